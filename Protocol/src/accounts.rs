@@ -0,0 +1,118 @@
+//! 登录界面磁贴对应的账户列表
+//!
+//! 软件把录入了面容的 Windows 账户写入注册表 `SOFTWARE\facewinunlock-tauri` 的
+//! [`ACCOUNTS_REG_VALUE`]，DLL 为每个账户显示一个磁贴。值是 REG_SZ，每行一个账户，
+//! 字段以制表符分隔：SID、登录用户名、显示名称。
+
+/// 账户列表在注册表中的值名称
+pub const ACCOUNTS_REG_VALUE: &str = "TILE_ACCOUNTS";
+
+/// 一个磁贴对应的 Windows 账户
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileAccount {
+    /// 账户的 SID 字符串，例如 `S-1-5-21-...-1001`
+    pub sid: String,
+    /// 解锁请求中的用户名，本地账户带 `.\` 前缀，联机账户是邮箱
+    pub user_name: String,
+    /// 磁贴上显示的名称
+    pub display_name: String,
+}
+
+impl TileAccount {
+    /// 解锁请求中的用户名是否属于这个账户，不区分大小写
+    pub fn matches(&self, user_name: &str) -> bool {
+        self.user_name.to_lowercase() == user_name.to_lowercase()
+    }
+}
+
+// 字段中不能出现分隔符
+fn clean(field: &str) -> String {
+    field.replace(['\t', '\r', '\n'], " ").trim().to_string()
+}
+
+/// 把账户列表编码成注册表中的文本
+pub fn encode_accounts(accounts: &[TileAccount]) -> String {
+    accounts
+        .iter()
+        .map(|account| {
+            format!(
+                "{}\t{}\t{}",
+                clean(&account.sid),
+                clean(&account.user_name),
+                clean(&account.display_name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 解析注册表中的账户列表，跳过格式错误的行
+pub fn parse_accounts(text: &str) -> Vec<TileAccount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.trim_end_matches('\r').split('\t');
+            let sid = fields.next()?.trim();
+            let user_name = fields.next()?.trim();
+            let display_name = fields.next().unwrap_or("").trim();
+            if !sid.starts_with("S-") || user_name.is_empty() {
+                return None;
+            }
+            Some(TileAccount {
+                sid: sid.to_string(),
+                user_name: user_name.to_string(),
+                // 没有显示名称时显示用户名
+                display_name: if display_name.is_empty() {
+                    user_name.to_string()
+                } else {
+                    display_name.to_string()
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(sid: &str, user_name: &str, display_name: &str) -> TileAccount {
+        TileAccount {
+            sid: sid.to_string(),
+            user_name: user_name.to_string(),
+            display_name: display_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn encode_parse_roundtrip() {
+        let accounts = vec![
+            account("S-1-5-21-1-2-3-1001", ".\\manson", "Manson 的电脑"),
+            account("S-1-5-21-1-2-3-1002", "someone@outlook.com", "Someone"),
+        ];
+        assert_eq!(parse_accounts(&encode_accounts(&accounts)), accounts);
+        assert!(parse_accounts("").is_empty());
+    }
+
+    #[test]
+    fn parse_skips_bad_lines() {
+        let text = "garbage\nS-1-5-21-1-2-3-1001\t.\\manson\r\n\tnobody\tNobody\nS-1-5-21-1-2-3-1002\tbad\tname\twith\ttabs";
+        let accounts = parse_accounts(text);
+        assert_eq!(
+            accounts,
+            vec![
+                account("S-1-5-21-1-2-3-1001", ".\\manson", ".\\manson"),
+                account("S-1-5-21-1-2-3-1002", "bad", "name"),
+            ]
+        );
+        // 显示名称中的制表符不会破坏格式
+        let encoded = encode_accounts(&[account("S-1-5-21-1", "a", "x\ty")]);
+        assert_eq!(parse_accounts(&encoded)[0].display_name, "x y");
+    }
+
+    #[test]
+    fn matches_ignores_case() {
+        let account = account("S-1-5-21-1-2-3-1001", ".\\Manson", "Manson");
+        assert!(account.matches(".\\manson"));
+        assert!(!account.matches("manson"));
+    }
+}
@@ -0,0 +1,144 @@
+//! 凭据的加密信封
+//!
+//! 握手成功后，双方用共享密钥和两个随机数各自推导出两个方向的会话密钥，
+//! 之后凭据等敏感消息放在 `Message::Sealed` 中以 ChaCha20-Poly1305 加密传输。
+//! 随机数是按方向递增的计数器，接收方只接受下一个期望的计数器值，重放和乱序都会被拒绝。
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    error::ProtocolError,
+    frame::PROTOCOL_VERSION,
+    handshake::{Handshake, PipeSecret},
+    message::{Message, MessageKind},
+};
+
+const CLIENT_TO_SERVER_LABEL: &[u8] = b"facewinunlock key client->server";
+const SERVER_TO_CLIENT_LABEL: &[u8] = b"facewinunlock key server->client";
+
+/// 握手之后的加密通道，每个连接一个
+pub struct SecureChannel {
+    send: ChaCha20Poly1305,
+    recv: ChaCha20Poly1305,
+    send_counter: u64,
+    recv_counter: u64,
+}
+
+impl SecureChannel {
+    /// 软件一侧
+    pub fn client(secret: &PipeSecret, handshake: &Handshake) -> Self {
+        Self::new(
+            secret,
+            handshake,
+            CLIENT_TO_SERVER_LABEL,
+            SERVER_TO_CLIENT_LABEL,
+        )
+    }
+
+    /// DLL 一侧
+    pub fn server(secret: &PipeSecret, handshake: &Handshake) -> Self {
+        Self::new(
+            secret,
+            handshake,
+            SERVER_TO_CLIENT_LABEL,
+            CLIENT_TO_SERVER_LABEL,
+        )
+    }
+
+    fn new(
+        secret: &PipeSecret,
+        handshake: &Handshake,
+        send_label: &[u8],
+        recv_label: &[u8],
+    ) -> Self {
+        let send_key = secret.derive_key(send_label, handshake);
+        let recv_key = secret.derive_key(recv_label, handshake);
+        Self {
+            send: ChaCha20Poly1305::new(Key::from_slice(send_key.as_slice())),
+            recv: ChaCha20Poly1305::new(Key::from_slice(recv_key.as_slice())),
+            send_counter: 0,
+            recv_counter: 0,
+        }
+    }
+
+    /// 加密一条消息，返回可以直接写入管道的 `Message::Sealed`
+    pub fn seal(&mut self, msg: &Message) -> Result<Message, ProtocolError> {
+        if let Message::Sealed { .. } = msg {
+            return Err(ProtocolError::UnexpectedMessage(MessageKind::Sealed));
+        }
+        let counter = self.send_counter;
+        let payload = Zeroizing::new(msg.encode_payload());
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(1 + payload.len()));
+        plaintext.push(msg.kind() as u8);
+        plaintext.extend_from_slice(&payload);
+
+        let ciphertext = self
+            .send
+            .encrypt(
+                &nonce(counter),
+                Payload {
+                    msg: &plaintext,
+                    aad: &aad(),
+                },
+            )
+            .map_err(|_| ProtocolError::PayloadTooLarge(plaintext.len() as u32))?;
+        self.send_counter += 1;
+        Ok(Message::Sealed {
+            counter,
+            ciphertext,
+        })
+    }
+
+    /// 解密 `Message::Sealed`，返回内层消息
+    pub fn open(&mut self, msg: &Message) -> Result<Message, ProtocolError> {
+        let Message::Sealed {
+            counter,
+            ciphertext,
+        } = msg
+        else {
+            // 握手之后敏感消息必须加密，明文一律拒绝
+            return Err(ProtocolError::UnexpectedMessage(msg.kind()));
+        };
+        if *counter != self.recv_counter {
+            return Err(ProtocolError::OutOfOrder {
+                expected: self.recv_counter,
+                actual: *counter,
+            });
+        }
+
+        let plaintext = Zeroizing::new(
+            self.recv
+                .decrypt(
+                    &nonce(*counter),
+                    Payload {
+                        msg: ciphertext,
+                        aad: &aad(),
+                    },
+                )
+                .map_err(|_| ProtocolError::DecryptionFailed)?,
+        );
+        self.recv_counter += 1;
+
+        let (&kind, payload) = plaintext.split_first().ok_or(ProtocolError::Truncated)?;
+        let kind = MessageKind::try_from(kind)?;
+        if kind == MessageKind::Sealed {
+            return Err(ProtocolError::UnexpectedMessage(kind));
+        }
+        Message::decode_payload(kind, payload)
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    Nonce::clone_from_slice(&nonce)
+}
+
+// 把协议版本绑定进认证标签，防止密文被拿到其他版本的协议中解析
+fn aad() -> [u8; 2] {
+    [PROTOCOL_VERSION, MessageKind::Sealed as u8]
+}
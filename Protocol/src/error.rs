@@ -0,0 +1,88 @@
+use std::fmt;
+
+use crate::message::MessageKind;
+
+/// 协议编解码过程中可能出现的错误
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// 底层读写失败（管道断开、超时等）
+    Io(std::io::Error),
+    /// 帧头魔数不匹配，说明对端不是本协议
+    BadMagic([u8; 2]),
+    /// 对端使用了不支持的协议版本
+    UnsupportedVersion(u8),
+    /// 未知的消息类型
+    UnknownKind(u8),
+    /// 负载长度超过上限
+    PayloadTooLarge(u32),
+    /// 校验和不一致，数据在传输中被截断或篡改
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// 数据不完整
+    Truncated,
+    /// 负载解析完成后仍有多余字节
+    TrailingBytes(usize),
+    /// 字符串不是合法的 UTF-8
+    InvalidUtf8,
+    /// 握手阶段收到了不该出现的消息
+    UnexpectedMessage(MessageKind),
+    /// 对端无法证明持有共享密钥
+    AuthenticationFailed,
+    /// 共享密钥格式错误
+    InvalidSecret,
+    /// 系统随机数生成器不可用
+    Random,
+    /// 密文无法通过认证，被篡改或密钥不一致
+    DecryptionFailed,
+    /// 加密消息的计数器不是期望值，可能是重放
+    OutOfOrder { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "管道读写失败: {}", e),
+            ProtocolError::BadMagic(magic) => write!(f, "帧头魔数错误: {:?}", magic),
+            ProtocolError::UnsupportedVersion(v) => write!(f, "不支持的协议版本: {}", v),
+            ProtocolError::UnknownKind(k) => write!(f, "未知的消息类型: {}", k),
+            ProtocolError::PayloadTooLarge(len) => write!(f, "负载长度超过上限: {} 字节", len),
+            ProtocolError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "校验和不一致，期望 {:#010x}，实际 {:#010x}",
+                expected, actual
+            ),
+            ProtocolError::Truncated => write!(f, "数据不完整"),
+            ProtocolError::TrailingBytes(n) => write!(f, "负载末尾有 {} 个多余字节", n),
+            ProtocolError::InvalidUtf8 => write!(f, "字符串不是合法的 UTF-8"),
+            ProtocolError::UnexpectedMessage(kind) => {
+                write!(f, "握手阶段收到意外的消息: {:?}", kind)
+            }
+            ProtocolError::AuthenticationFailed => write!(f, "对端身份验证失败"),
+            ProtocolError::InvalidSecret => write!(f, "共享密钥格式错误"),
+            ProtocolError::Random => write!(f, "生成随机数失败"),
+            ProtocolError::DecryptionFailed => write!(f, "解密失败，数据被篡改或密钥不一致"),
+            ProtocolError::OutOfOrder { expected, actual } => {
+                write!(f, "加密消息计数器错误，期望 {}，实际 {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(e: std::io::Error) -> Self {
+        // 读到一半对端关闭，统一视为数据不完整
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            ProtocolError::Truncated
+        } else {
+            ProtocolError::Io(e)
+        }
+    }
+}
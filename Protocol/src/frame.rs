@@ -0,0 +1,124 @@
+use std::io::{Read, Write};
+
+use crate::{
+    codec::crc32,
+    error::ProtocolError,
+    message::{Message, MessageKind},
+};
+
+/// 帧头魔数
+pub const MAGIC: [u8; 2] = *b"FW";
+/// 当前协议版本，不兼容的改动必须增加版本号
+pub const PROTOCOL_VERSION: u8 = 4;
+/// 帧头长度：魔数(2) + 版本(1) + 类型(1) + 负载长度(4) + 校验和(4)
+pub const HEADER_LEN: usize = 12;
+/// 单帧负载上限，防止对端发送超大长度耗尽内存
+pub const MAX_PAYLOAD_LEN: u32 = 64 * 1024;
+
+/// 帧头
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: u8,
+    pub kind: MessageKind,
+    pub length: u32,
+    pub checksum: u32,
+}
+
+impl FrameHeader {
+    /// 解析并校验帧头（不含校验和比对，校验和需要负载）
+    pub fn parse(buf: &[u8; HEADER_LEN]) -> Result<Self, ProtocolError> {
+        let magic = [buf[0], buf[1]];
+        if magic != MAGIC {
+            return Err(ProtocolError::BadMagic(magic));
+        }
+        let version = buf[2];
+        if version != PROTOCOL_VERSION {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+        let kind = MessageKind::try_from(buf[3])?;
+        let length = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        if length > MAX_PAYLOAD_LEN {
+            return Err(ProtocolError::PayloadTooLarge(length));
+        }
+        let checksum = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]);
+        Ok(Self {
+            version,
+            kind,
+            length,
+            checksum,
+        })
+    }
+}
+
+/// 将消息编码为完整的一帧
+/// 负载超过 MAX_PAYLOAD_LEN 时返回与解码相同的错误，避免写出对端必然拒绝的帧
+pub fn encode(msg: &Message) -> Result<Vec<u8>, ProtocolError> {
+    let payload = msg.encode_payload();
+    if payload.len() > MAX_PAYLOAD_LEN as usize {
+        return Err(ProtocolError::PayloadTooLarge(
+            u32::try_from(payload.len()).unwrap_or(u32::MAX),
+        ));
+    }
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&MAGIC);
+    frame.push(PROTOCOL_VERSION);
+    frame.push(msg.kind() as u8);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    let checksum = crc32(&[&frame[..8], &payload]);
+    frame.extend_from_slice(&checksum.to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// 从缓冲区头部解码一帧，返回消息和消耗的字节数
+/// 缓冲区不足一帧时返回 ProtocolError::Truncated
+pub fn decode(buf: &[u8]) -> Result<(Message, usize), ProtocolError> {
+    if buf.len() < HEADER_LEN {
+        return Err(ProtocolError::Truncated);
+    }
+    let mut header_buf = [0u8; HEADER_LEN];
+    header_buf.copy_from_slice(&buf[..HEADER_LEN]);
+    let header = FrameHeader::parse(&header_buf)?;
+
+    let total = HEADER_LEN + header.length as usize;
+    if buf.len() < total {
+        return Err(ProtocolError::Truncated);
+    }
+    let payload = &buf[HEADER_LEN..total];
+    let msg = decode_body(&header_buf, &header, payload)?;
+    Ok((msg, total))
+}
+
+/// 写入一帧，整帧一次性写出，避免对端读到半帧
+pub fn write_message<W: Write>(writer: &mut W, msg: &Message) -> Result<(), ProtocolError> {
+    let frame = encode(msg)?;
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// 阻塞读取一帧
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Message, ProtocolError> {
+    let mut header_buf = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header_buf)?;
+    let header = FrameHeader::parse(&header_buf)?;
+
+    let mut payload = vec![0u8; header.length as usize];
+    reader.read_exact(&mut payload)?;
+    decode_body(&header_buf, &header, &payload)
+}
+
+fn decode_body(
+    header_buf: &[u8; HEADER_LEN],
+    header: &FrameHeader,
+    payload: &[u8],
+) -> Result<Message, ProtocolError> {
+    let actual = crc32(&[&header_buf[..8], payload]);
+    if actual != header.checksum {
+        return Err(ProtocolError::ChecksumMismatch {
+            expected: header.checksum,
+            actual,
+        });
+    }
+    Message::decode_payload(header.kind, payload)
+}
@@ -0,0 +1,244 @@
+//! 管道两端的双向身份验证
+//!
+//! 软件在部署时生成一个 32 字节的共享密钥，写入只有 SYSTEM 和管理员可读的注册表项。
+//! 每次连接都按下面的顺序握手，任何一步失败都应立即断开管道：
+//!
+//! 1. 软件 -> DLL：`Hello { 客户端随机数 }`
+//! 2. DLL -> 软件：`Challenge { 服务端随机数, HMAC(密钥, "server" | 两个随机数) }`
+//! 3. 软件验证 DLL 的证明，再发送 `Proof { HMAC(密钥, "client" | 两个随机数) }`
+//! 4. DLL 验证软件的证明
+//!
+//! 两个方向使用不同的标签，抢先创建管道的进程无法把软件发来的证明原样反射回去；
+//! 随机数每次都不同，截获的证明也无法重放。握手之后的会话密钥见 [`crate::envelope`]。
+
+use std::io::{Read, Write};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    error::ProtocolError,
+    frame::{read_message, write_message, PROTOCOL_VERSION},
+    message::Message,
+};
+
+/// 随机数长度
+pub const NONCE_LEN: usize = 32;
+/// 证明（HMAC-SHA256）长度
+pub const PROOF_LEN: usize = 32;
+/// 共享密钥长度
+pub const SECRET_LEN: usize = 32;
+
+/// 共享密钥在 HKLM 下的注册表子项，部署时设置为仅 SYSTEM 和管理员可访问
+pub const SECRET_REG_PATH: &str = r"SOFTWARE\facewinunlock-tauri\Secret";
+/// 共享密钥的值名称，内容为十六进制字符串
+pub const SECRET_REG_VALUE: &str = "PIPE_SECRET";
+
+const SERVER_LABEL: &[u8] = b"facewinunlock pipe server";
+const CLIENT_LABEL: &[u8] = b"facewinunlock pipe client";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 安装时生成的共享密钥
+#[derive(Clone, PartialEq, Eq)]
+pub struct PipeSecret([u8; SECRET_LEN]);
+
+impl PipeSecret {
+    /// 用系统随机数生成新的密钥
+    pub fn generate() -> Result<Self, ProtocolError> {
+        Ok(Self(random_bytes()?))
+    }
+
+    pub fn from_bytes(bytes: [u8; SECRET_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// 从注册表中保存的十六进制字符串解析
+    pub fn from_hex(text: &str) -> Result<Self, ProtocolError> {
+        let text = text.trim();
+        if text.len() != SECRET_LEN * 2 || !text.is_ascii() {
+            return Err(ProtocolError::InvalidSecret);
+        }
+        let mut bytes = [0u8; SECRET_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)
+                .map_err(|_| ProtocolError::InvalidSecret)?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// 转为十六进制字符串，便于以 REG_SZ 保存
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn mac(
+        &self,
+        label: &[u8],
+        client_nonce: &[u8; NONCE_LEN],
+        server_nonce: &[u8; NONCE_LEN],
+    ) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC 接受任意长度的密钥");
+        mac.update(label);
+        mac.update(&[PROTOCOL_VERSION]);
+        mac.update(client_nonce);
+        mac.update(server_nonce);
+        mac
+    }
+
+    /// 从共享密钥和本次握手的随机数推导会话密钥
+    pub(crate) fn derive_key(&self, label: &[u8], handshake: &Handshake) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.proof(label, &handshake.client_nonce, &handshake.server_nonce))
+    }
+
+    fn proof(
+        &self,
+        label: &[u8],
+        client_nonce: &[u8; NONCE_LEN],
+        server_nonce: &[u8; NONCE_LEN],
+    ) -> [u8; PROOF_LEN] {
+        self.mac(label, client_nonce, server_nonce)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+
+    /// 常量时间比较，避免通过耗时猜测证明
+    fn verify(
+        &self,
+        label: &[u8],
+        client_nonce: &[u8; NONCE_LEN],
+        server_nonce: &[u8; NONCE_LEN],
+        proof: &[u8; PROOF_LEN],
+    ) -> Result<(), ProtocolError> {
+        self.mac(label, client_nonce, server_nonce)
+            .verify_slice(proof)
+            .map_err(|_| ProtocolError::AuthenticationFailed)
+    }
+}
+
+impl Drop for PipeSecret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for PipeSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 不在日志中输出密钥
+        f.write_str("PipeSecret(..)")
+    }
+}
+
+/// 握手成功后双方共同持有的随机数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub client_nonce: [u8; NONCE_LEN],
+    pub server_nonce: [u8; NONCE_LEN],
+}
+
+/// 生成一个握手用的随机数
+pub fn random_nonce() -> Result<[u8; NONCE_LEN], ProtocolError> {
+    random_bytes()
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], ProtocolError> {
+    let mut buf = [0u8; N];
+    getrandom::getrandom(&mut buf).map_err(|_| ProtocolError::Random)?;
+    Ok(buf)
+}
+
+/// 软件（管道客户端）一侧的握手
+///
+/// 返回 Ok 时 DLL 已经证明自己持有密钥，可以发送凭据。
+pub fn client_handshake<S: Read + Write>(
+    stream: &mut S,
+    secret: &PipeSecret,
+    client_nonce: [u8; NONCE_LEN],
+) -> Result<Handshake, ProtocolError> {
+    write_message(
+        stream,
+        &Message::Hello {
+            nonce: client_nonce,
+        },
+    )?;
+
+    let (server_nonce, server_proof) = match read_message(stream)? {
+        Message::Challenge { nonce, proof } => (nonce, proof),
+        other => return Err(ProtocolError::UnexpectedMessage(other.kind())),
+    };
+    // 先验证 DLL，再给出自己的证明
+    secret.verify(SERVER_LABEL, &client_nonce, &server_nonce, &server_proof)?;
+
+    let proof = secret.proof(CLIENT_LABEL, &client_nonce, &server_nonce);
+    write_message(stream, &Message::Proof { proof })?;
+
+    Ok(Handshake {
+        client_nonce,
+        server_nonce,
+    })
+}
+
+/// DLL（管道服务端）一侧的握手
+///
+/// 返回 Ok 时软件已经证明自己持有密钥，可以接受凭据。
+pub fn server_handshake<S: Read + Write>(
+    stream: &mut S,
+    secret: &PipeSecret,
+    server_nonce: [u8; NONCE_LEN],
+) -> Result<Handshake, ProtocolError> {
+    let client_nonce = match read_message(stream)? {
+        Message::Hello { nonce } => nonce,
+        other => return Err(ProtocolError::UnexpectedMessage(other.kind())),
+    };
+
+    let proof = secret.proof(SERVER_LABEL, &client_nonce, &server_nonce);
+    write_message(
+        stream,
+        &Message::Challenge {
+            nonce: server_nonce,
+            proof,
+        },
+    )?;
+
+    let client_proof = match read_message(stream)? {
+        Message::Proof { proof } => proof,
+        other => return Err(ProtocolError::UnexpectedMessage(other.kind())),
+    };
+    secret.verify(CLIENT_LABEL, &client_nonce, &server_nonce, &client_proof)?;
+
+    Ok(Handshake {
+        client_nonce,
+        server_nonce,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_hex_roundtrip() {
+        let secret = PipeSecret::generate().unwrap();
+        assert_eq!(PipeSecret::from_hex(&secret.to_hex()).unwrap(), secret);
+        assert!(matches!(
+            PipeSecret::from_hex("abcd"),
+            Err(ProtocolError::InvalidSecret)
+        ));
+        assert!(matches!(
+            PipeSecret::from_hex(&"zz".repeat(SECRET_LEN)),
+            Err(ProtocolError::InvalidSecret)
+        ));
+    }
+
+    #[test]
+    fn labels_make_proofs_directional() {
+        let secret = PipeSecret::from_bytes([7; SECRET_LEN]);
+        let (c, s) = ([1; NONCE_LEN], [2; NONCE_LEN]);
+        assert_ne!(
+            secret.proof(SERVER_LABEL, &c, &s),
+            secret.proof(CLIENT_LABEL, &c, &s)
+        );
+    }
+}
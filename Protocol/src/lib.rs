@@ -0,0 +1,43 @@
+//! FaceWinUnlock-Tauri 与 winlogon DLL 之间的命名管道协议
+//!
+//! 每一帧由 12 字节帧头和负载组成：
+//!
+//! | 偏移 | 长度 | 含义 |
+//! |------|------|------|
+//! | 0    | 2    | 魔数 `FW` |
+//! | 2    | 1    | 协议版本 |
+//! | 3    | 1    | 消息类型 |
+//! | 4    | 4    | 负载长度（小端） |
+//! | 8    | 4    | CRC-32，覆盖帧头前 8 字节和负载 |
+//!
+//! 连接建立后，双方先用 [`handshake`] 中的质询-应答互相证明持有安装时生成的共享密钥，
+//! 之后才允许传输凭据；凭据本身放在 [`envelope`] 的加密信封中。
+//!
+//! 本 crate 不依赖任何平台 API，两端各自把管道句柄包装成 `Read + Write` 后使用。
+
+pub mod accounts;
+mod codec;
+pub mod envelope;
+pub mod error;
+pub mod frame;
+pub mod handshake;
+pub mod logon;
+pub mod message;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod secret;
+
+pub use accounts::TileAccount;
+pub use envelope::SecureChannel;
+pub use error::ProtocolError;
+pub use frame::{decode, encode, read_message, write_message, PROTOCOL_VERSION};
+pub use handshake::{client_handshake, server_handshake, Handshake, PipeSecret};
+pub use logon::LogonOutcome;
+pub use message::{Message, MessageKind, StatusIcon};
+pub use secret::SecretString;
+
+/// 解锁管道名称
+pub const PIPE_NAME: &str = r"\\.\pipe\MansonWindowsUnlockRust";
+
+/// CredUI 提示框（例如 UAC 提权）等待确认身份时 DLL 触发的事件，软件收到后开始识别
+pub const CREDUI_EVENT_NAME: &str = r"Global\FaceWinUnlockCredUI";
@@ -0,0 +1,109 @@
+use std::fmt;
+
+// 常用的 NTSTATUS，取值见 ntstatus.h
+pub const STATUS_SUCCESS: i32 = 0;
+pub const STATUS_NO_SUCH_USER: i32 = 0xC000_0064_u32 as i32;
+pub const STATUS_WRONG_PASSWORD: i32 = 0xC000_006A_u32 as i32;
+pub const STATUS_LOGON_FAILURE: i32 = 0xC000_006D_u32 as i32;
+pub const STATUS_ACCOUNT_RESTRICTION: i32 = 0xC000_006E_u32 as i32;
+pub const STATUS_PASSWORD_EXPIRED: i32 = 0xC000_0071_u32 as i32;
+pub const STATUS_ACCOUNT_DISABLED: i32 = 0xC000_0072_u32 as i32;
+pub const STATUS_ACCOUNT_LOCKED_OUT: i32 = 0xC000_0234_u32 as i32;
+
+/// 对 ReportResult 收到的 NTSTATUS 的归类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogonOutcome {
+    /// 登录成功
+    Success,
+    /// 用户名或密码错误
+    BadPassword,
+    /// 账户已被锁定
+    AccountLocked,
+    /// 其他失败，保留原始状态码
+    Other { status: i32, substatus: i32 },
+}
+
+impl LogonOutcome {
+    pub fn from_ntstatus(status: i32, substatus: i32) -> Self {
+        if status == STATUS_SUCCESS {
+            return LogonOutcome::Success;
+        }
+        if status == STATUS_ACCOUNT_LOCKED_OUT || substatus == STATUS_ACCOUNT_LOCKED_OUT {
+            return LogonOutcome::AccountLocked;
+        }
+        match (status, substatus) {
+            (STATUS_WRONG_PASSWORD, _)
+            | (STATUS_LOGON_FAILURE, STATUS_SUCCESS)
+            | (STATUS_LOGON_FAILURE, STATUS_WRONG_PASSWORD)
+            | (STATUS_LOGON_FAILURE, STATUS_NO_SUCH_USER) => LogonOutcome::BadPassword,
+            _ => LogonOutcome::Other { status, substatus },
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        matches!(self, LogonOutcome::Success)
+    }
+
+    /// 写入解锁日志的简短标识
+    pub fn code(&self) -> String {
+        match self {
+            LogonOutcome::Success => String::from("success"),
+            LogonOutcome::BadPassword => String::from("bad_password"),
+            LogonOutcome::AccountLocked => String::from("account_locked"),
+            LogonOutcome::Other { status, substatus } => {
+                format!("{:#010x}/{:#010x}", *status as u32, *substatus as u32)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LogonOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogonOutcome::Success => write!(f, "登录成功"),
+            LogonOutcome::BadPassword => write!(f, "用户名或密码错误"),
+            LogonOutcome::AccountLocked => write!(f, "账户已被锁定"),
+            LogonOutcome::Other { status, substatus } => write!(
+                f,
+                "登录失败，状态码 {:#010x}，子状态码 {:#010x}",
+                *status as u32, *substatus as u32
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_common_statuses() {
+        assert_eq!(LogonOutcome::from_ntstatus(0, 0), LogonOutcome::Success);
+        assert_eq!(
+            LogonOutcome::from_ntstatus(STATUS_LOGON_FAILURE, STATUS_WRONG_PASSWORD),
+            LogonOutcome::BadPassword
+        );
+        assert_eq!(
+            LogonOutcome::from_ntstatus(STATUS_LOGON_FAILURE, STATUS_NO_SUCH_USER),
+            LogonOutcome::BadPassword
+        );
+        assert_eq!(
+            LogonOutcome::from_ntstatus(STATUS_ACCOUNT_LOCKED_OUT, 0),
+            LogonOutcome::AccountLocked
+        );
+        assert_eq!(
+            LogonOutcome::from_ntstatus(STATUS_ACCOUNT_RESTRICTION, STATUS_ACCOUNT_DISABLED),
+            LogonOutcome::Other {
+                status: STATUS_ACCOUNT_RESTRICTION,
+                substatus: STATUS_ACCOUNT_DISABLED
+            }
+        );
+    }
+
+    #[test]
+    fn other_code_keeps_raw_values() {
+        let outcome =
+            LogonOutcome::from_ntstatus(STATUS_ACCOUNT_RESTRICTION, STATUS_PASSWORD_EXPIRED);
+        assert_eq!(outcome.code(), "0xc000006e/0xc0000071");
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    codec::{PayloadReader, PayloadWriter},
+    error::ProtocolError,
+    handshake::{NONCE_LEN, PROOF_LEN},
+    secret::SecretString,
+};
+
+/// 管道上传输的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// 请求解锁，携带 Windows 凭据；只能放在 `Sealed` 中传输
+    Unlock {
+        username: String,
+        password: SecretString,
+    },
+    /// 取消本次解锁（例如用户已经手动解锁）
+    Cancel,
+    /// 状态提示，DLL 把文本、图标和进度显示在磁贴上
+    Status {
+        text: String,
+        icon: StatusIcon,
+        /// 识别进度（0-100），`None` 表示不显示进度
+        progress: Option<u8>,
+    },
+    /// 心跳，用于确认对端在线
+    Ping,
+    /// DLL 确认已完整收到解锁请求
+    Ack,
+    /// DLL 在 ReportResult 之后回传的登录结果（NTSTATUS 原值）
+    LogonResult { status: i32, substatus: i32 },
+    /// 握手第一步：软件发起，携带客户端随机数
+    Hello { nonce: [u8; NONCE_LEN] },
+    /// 握手第二步：DLL 回复服务端随机数，并证明自己持有共享密钥
+    Challenge {
+        nonce: [u8; NONCE_LEN],
+        proof: [u8; PROOF_LEN],
+    },
+    /// 握手第三步：软件证明自己持有共享密钥
+    Proof { proof: [u8; PROOF_LEN] },
+    /// 加密信封，内容是另一条消息，见 [`crate::envelope`]
+    Sealed { counter: u64, ciphertext: Vec<u8> },
+    /// 软件识别失败后等待用户唤醒，连接保持打开直到 DLL 回复 `Wake`
+    WatchWake,
+    /// DLL 通知软件用户已唤醒登录界面（按键、移动鼠标或点击磁贴），可以重新识别
+    Wake,
+}
+
+/// 状态提示的图标，DLL 据此绘制磁贴图片
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusIcon {
+    /// 不显示状态图标，只显示文本
+    #[default]
+    None = 0,
+    /// 摄像头已打开，正在识别
+    Scanning = 1,
+    /// 识别成功，正在登录
+    Success = 2,
+    /// 暂时无法解锁，例如冷却中
+    Warning = 3,
+    /// 识别失败或摄像头不可用
+    Error = 4,
+}
+
+impl From<u8> for StatusIcon {
+    /// 未知的图标按 `None` 处理，新版软件搭配旧版 DLL 时仍然能显示文本
+    fn from(v: u8) -> Self {
+        match v {
+            1 => StatusIcon::Scanning,
+            2 => StatusIcon::Success,
+            3 => StatusIcon::Warning,
+            4 => StatusIcon::Error,
+            _ => StatusIcon::None,
+        }
+    }
+}
+
+/// 负载中表示“不显示进度”的值
+const NO_PROGRESS: u8 = u8::MAX;
+
+/// 消息类型编号，写在帧头中
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Unlock = 1,
+    Cancel = 2,
+    Status = 3,
+    Ping = 4,
+    Ack = 5,
+    LogonResult = 6,
+    Hello = 7,
+    Challenge = 8,
+    Proof = 9,
+    Sealed = 10,
+    WatchWake = 11,
+    Wake = 12,
+}
+
+impl TryFrom<u8> for MessageKind {
+    type Error = ProtocolError;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        Ok(match v {
+            1 => MessageKind::Unlock,
+            2 => MessageKind::Cancel,
+            3 => MessageKind::Status,
+            4 => MessageKind::Ping,
+            5 => MessageKind::Ack,
+            6 => MessageKind::LogonResult,
+            7 => MessageKind::Hello,
+            8 => MessageKind::Challenge,
+            9 => MessageKind::Proof,
+            10 => MessageKind::Sealed,
+            11 => MessageKind::WatchWake,
+            12 => MessageKind::Wake,
+            other => return Err(ProtocolError::UnknownKind(other)),
+        })
+    }
+}
+
+impl Message {
+    /// 不带进度的状态提示
+    pub fn status(text: impl Into<String>, icon: StatusIcon) -> Self {
+        Message::Status {
+            text: text.into(),
+            icon,
+            progress: None,
+        }
+    }
+
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Message::Unlock { .. } => MessageKind::Unlock,
+            Message::Cancel => MessageKind::Cancel,
+            Message::Status { .. } => MessageKind::Status,
+            Message::Ping => MessageKind::Ping,
+            Message::Ack => MessageKind::Ack,
+            Message::LogonResult { .. } => MessageKind::LogonResult,
+            Message::Hello { .. } => MessageKind::Hello,
+            Message::Challenge { .. } => MessageKind::Challenge,
+            Message::Proof { .. } => MessageKind::Proof,
+            Message::Sealed { .. } => MessageKind::Sealed,
+            Message::WatchWake => MessageKind::WatchWake,
+            Message::Wake => MessageKind::Wake,
+        }
+    }
+
+    /// 序列化消息负载（不含帧头）
+    pub(crate) fn encode_payload(&self) -> Vec<u8> {
+        let mut w = PayloadWriter::new();
+        match self {
+            Message::Unlock { username, password } => {
+                w.str(username).str(password.expose());
+            }
+            Message::Status {
+                text,
+                icon,
+                progress,
+            } => {
+                // 文本放在最后，图标和进度是定长字段
+                w.u8(*icon as u8)
+                    .u8(progress.map_or(NO_PROGRESS, |p| p.min(100)))
+                    .str(text);
+            }
+            Message::LogonResult { status, substatus } => {
+                w.i32(*status).i32(*substatus);
+            }
+            Message::Hello { nonce } => {
+                w.array(nonce);
+            }
+            Message::Challenge { nonce, proof } => {
+                w.array(nonce).array(proof);
+            }
+            Message::Proof { proof } => {
+                w.array(proof);
+            }
+            Message::Sealed {
+                counter,
+                ciphertext,
+            } => {
+                w.u64(*counter).bytes(ciphertext);
+            }
+            Message::Cancel | Message::Ping | Message::Ack | Message::WatchWake | Message::Wake => {
+            }
+        }
+        w.finish()
+    }
+
+    /// 根据消息类型解析负载
+    pub(crate) fn decode_payload(kind: MessageKind, payload: &[u8]) -> Result<Self, ProtocolError> {
+        let mut r = PayloadReader::new(payload);
+        let msg = match kind {
+            MessageKind::Unlock => Message::Unlock {
+                username: r.string()?,
+                password: r.secret_string()?,
+            },
+            MessageKind::Cancel => Message::Cancel,
+            MessageKind::Status => {
+                let icon = StatusIcon::from(r.u8()?);
+                let progress = match r.u8()? {
+                    NO_PROGRESS => None,
+                    p => Some(p.min(100)),
+                };
+                Message::Status {
+                    text: r.string()?,
+                    icon,
+                    progress,
+                }
+            }
+            MessageKind::Ping => Message::Ping,
+            MessageKind::Ack => Message::Ack,
+            MessageKind::LogonResult => Message::LogonResult {
+                status: r.i32()?,
+                substatus: r.i32()?,
+            },
+            MessageKind::Hello => Message::Hello { nonce: r.array()? },
+            MessageKind::Challenge => Message::Challenge {
+                nonce: r.array()?,
+                proof: r.array()?,
+            },
+            MessageKind::Proof => Message::Proof { proof: r.array()? },
+            MessageKind::Sealed => Message::Sealed {
+                counter: r.u64()?,
+                ciphertext: r.bytes()?.to_vec(),
+            },
+            MessageKind::WatchWake => Message::WatchWake,
+            MessageKind::Wake => Message::Wake,
+        };
+        r.finish()?;
+        Ok(msg)
+    }
+}
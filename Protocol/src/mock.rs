@@ -0,0 +1,161 @@
+//! 测试用的内存管道和模拟 DLL
+//!
+//! [`MockPipeServer`] 按 `CPipeListener` 的顺序完成握手、接收状态提示和解锁请求，
+//! 并回复确认和预设的登录结果，软件一侧的解锁流程不需要 LogonUI 就能完整运行。
+
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    envelope::SecureChannel,
+    error::ProtocolError,
+    frame::{read_message, write_message},
+    handshake::{random_nonce, server_handshake, PipeSecret},
+    message::{Message, MessageKind},
+    secret::SecretString,
+};
+
+/// 内存中的双向管道，一端写入的数据由另一端读出；对端释放后读到 EOF
+pub struct Duplex {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+/// 创建一对相连的 [`Duplex`]
+pub fn duplex() -> (Duplex, Duplex) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+    (
+        Duplex {
+            tx: a_tx,
+            rx: a_rx,
+            pending: Vec::new(),
+        },
+        Duplex {
+            tx: b_tx,
+            rx: b_rx,
+            pending: Vec::new(),
+        },
+    )
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv() {
+                Ok(chunk) => self.pending = chunk,
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 模拟 DLL 在一个连接上收到的内容
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Received {
+    /// 依次收到的加密状态提示
+    pub statuses: Vec<String>,
+    /// 解锁请求中的用户名和密码，客户端取消或断开时为 None
+    pub unlock: Option<(String, SecretString)>,
+    /// 客户端是否在等待唤醒，模拟的 DLL 会立即回复 `Wake`
+    pub watched_wake: bool,
+}
+
+/// 模拟的 DLL，每个连接由一个线程处理
+pub struct MockPipeServer {
+    secret: PipeSecret,
+    /// 回传给软件的 (status, substatus)
+    logon_result: (i32, i32),
+}
+
+impl MockPipeServer {
+    /// 登录结果为成功的模拟 DLL
+    pub fn new(secret: PipeSecret) -> Self {
+        Self {
+            secret,
+            logon_result: (0, 0),
+        }
+    }
+
+    /// 设置回传的登录结果，用于模拟密码错误等情况
+    pub fn with_logon_result(mut self, status: i32, substatus: i32) -> Self {
+        self.logon_result = (status, substatus);
+        self
+    }
+
+    /// 接受一个连接，返回客户端一侧的流和处理线程
+    pub fn accept(&self) -> (Duplex, JoinHandle<Result<Received, ProtocolError>>) {
+        let (client, server) = duplex();
+        let secret = self.secret.clone();
+        let logon_result = self.logon_result;
+        let handle = thread::spawn(move || serve(server, &secret, logon_result));
+        (client, handle)
+    }
+}
+
+/// 按 DLL 的流程处理一个连接：握手，读到解锁请求后回复确认和登录结果；
+/// 收到 `WatchWake` 时立即回复 `Wake` 并结束，相当于用户马上点击了磁贴
+pub fn serve<S: Read + Write>(
+    mut stream: S,
+    secret: &PipeSecret,
+    (status, substatus): (i32, i32),
+) -> Result<Received, ProtocolError> {
+    let handshake = server_handshake(&mut stream, secret, random_nonce()?)?;
+    let mut channel = SecureChannel::server(secret, &handshake);
+    let mut received = Received::default();
+
+    loop {
+        let message = match read_message(&mut stream) {
+            Ok(message) => message,
+            // 客户端没有发送解锁请求就断开了
+            Err(ProtocolError::Truncated) => return Ok(received),
+            Err(e) => return Err(e),
+        };
+        match message {
+            sealed @ Message::Sealed { .. } => match channel.open(&sealed)? {
+                Message::Unlock { username, password } => {
+                    received.unlock = Some((username, password));
+                    break;
+                }
+                Message::Status { text, .. } => received.statuses.push(text),
+                other => return Err(ProtocolError::UnexpectedMessage(other.kind())),
+            },
+            Message::Cancel => return Ok(received),
+            Message::WatchWake => {
+                received.watched_wake = true;
+                write_message(&mut stream, &Message::Wake)?;
+                return Ok(received);
+            }
+            Message::Unlock { .. } => {
+                return Err(ProtocolError::UnexpectedMessage(MessageKind::Unlock))
+            }
+            Message::Status { .. } | Message::Ping => {}
+            other => return Err(ProtocolError::UnexpectedMessage(other.kind())),
+        }
+    }
+
+    write_message(&mut stream, &Message::Ack)?;
+    write_message(&mut stream, &Message::LogonResult { status, substatus })?;
+    Ok(received)
+}
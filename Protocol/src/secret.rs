@@ -0,0 +1,79 @@
+use std::fmt;
+
+use zeroize::{Zeroize, Zeroizing};
+
+/// 密码等敏感字符串，释放或清空时把内存清零
+///
+/// winlogon / LogonUI 进程长期驻留，普通 `String` 释放后内容仍留在堆上，
+/// 凭据相关的字段一律使用这个类型。
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// 取出明文，调用方不应再复制成普通 `String`
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 清零并清空
+    pub fn clear(&mut self) {
+        self.0.zeroize();
+    }
+
+    /// 转为以 0 结尾的 UTF-16，用于传给 Windows API，缓冲区释放时同样清零
+    pub fn to_wide(&self) -> Zeroizing<Vec<u16>> {
+        Zeroizing::new(self.0.encode_utf16().chain(std::iter::once(0)).collect())
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 不在日志中输出密码
+        f.write_str("SecretString(***)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_does_not_leak() {
+        let secret = SecretString::from("p@ssw0rd");
+        assert!(!format!("{:?}", secret).contains("p@ssw0rd"));
+    }
+
+    #[test]
+    fn clear_and_wide() {
+        let mut secret = SecretString::from("密码");
+        assert_eq!(secret.to_wide().as_slice(), &[0x5BC6, 0x7801, 0][..]);
+        secret.clear();
+        assert!(secret.is_empty());
+    }
+}
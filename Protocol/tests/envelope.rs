@@ -0,0 +1,159 @@
+use facewinunlock_protocol::{
+    encode,
+    handshake::{Handshake, NONCE_LEN, SECRET_LEN},
+    Message, MessageKind, PipeSecret, ProtocolError, SecretString, SecureChannel,
+};
+
+fn handshake(fill: u8) -> Handshake {
+    Handshake {
+        client_nonce: [fill; NONCE_LEN],
+        server_nonce: [fill.wrapping_add(1); NONCE_LEN],
+    }
+}
+
+fn channels() -> (SecureChannel, SecureChannel) {
+    let secret = PipeSecret::from_bytes([9; SECRET_LEN]);
+    (
+        SecureChannel::client(&secret, &handshake(1)),
+        SecureChannel::server(&secret, &handshake(1)),
+    )
+}
+
+fn unlock() -> Message {
+    Message::Unlock {
+        username: String::from(".\\manson"),
+        password: SecretString::from("p@ssw0rd-明文"),
+    }
+}
+
+#[test]
+fn sealed_unlock_roundtrip() {
+    let (mut client, mut server) = channels();
+    for _ in 0..3 {
+        let sealed = client.seal(&unlock()).unwrap();
+        assert_eq!(server.open(&sealed).unwrap(), unlock());
+    }
+    // 反方向使用独立的密钥和计数器
+    let sealed = server
+        .seal(&Message::LogonResult {
+            status: 0,
+            substatus: 0,
+        })
+        .unwrap();
+    assert!(matches!(
+        client.open(&sealed).unwrap(),
+        Message::LogonResult { status: 0, .. }
+    ));
+}
+
+#[test]
+fn password_is_not_visible_on_the_wire() {
+    let (mut client, _) = channels();
+    let frame = encode(&client.seal(&unlock()).unwrap()).unwrap();
+    let needle = "p@ssw0rd".as_bytes();
+    assert!(!frame.windows(needle.len()).any(|w| w == needle));
+}
+
+#[test]
+fn tampered_ciphertext_is_rejected() {
+    let (mut client, mut server) = channels();
+    let Message::Sealed {
+        counter,
+        mut ciphertext,
+    } = client.seal(&unlock()).unwrap()
+    else {
+        panic!("期望收到 Sealed");
+    };
+    ciphertext[0] ^= 1;
+    assert!(matches!(
+        server.open(&Message::Sealed {
+            counter,
+            ciphertext
+        }),
+        Err(ProtocolError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn replayed_or_reordered_messages_are_rejected() {
+    let (mut client, mut server) = channels();
+    let first = client.seal(&unlock()).unwrap();
+    let second = client.seal(&unlock()).unwrap();
+
+    assert!(matches!(
+        server.open(&second),
+        Err(ProtocolError::OutOfOrder {
+            expected: 0,
+            actual: 1
+        })
+    ));
+    server.open(&first).unwrap();
+    assert!(matches!(
+        server.open(&first),
+        Err(ProtocolError::OutOfOrder {
+            expected: 1,
+            actual: 0
+        })
+    ));
+    server.open(&second).unwrap();
+}
+
+#[test]
+fn forged_counter_fails_authentication() {
+    // 改计数器对上接收方的期望值，密文仍然无法通过认证
+    let (mut client, mut server) = channels();
+    let _ = client.seal(&unlock()).unwrap();
+    let Message::Sealed { ciphertext, .. } = client.seal(&unlock()).unwrap() else {
+        panic!("期望收到 Sealed");
+    };
+    assert!(matches!(
+        server.open(&Message::Sealed {
+            counter: 0,
+            ciphertext
+        }),
+        Err(ProtocolError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn other_session_cannot_open() {
+    let secret = PipeSecret::from_bytes([9; SECRET_LEN]);
+    let mut client = SecureChannel::client(&secret, &handshake(1));
+    let mut other_session = SecureChannel::server(&secret, &handshake(5));
+    let mut wrong_secret =
+        SecureChannel::server(&PipeSecret::from_bytes([8; SECRET_LEN]), &handshake(1));
+    let sealed = client.seal(&unlock()).unwrap();
+    assert!(matches!(
+        other_session.open(&sealed),
+        Err(ProtocolError::DecryptionFailed)
+    ));
+    assert!(matches!(
+        wrong_secret.open(&sealed),
+        Err(ProtocolError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn own_messages_cannot_be_reflected() {
+    // 软件发出的密文被原样发回，软件自己也打不开
+    let (mut client, _) = channels();
+    let sealed = client.seal(&unlock()).unwrap();
+    assert!(matches!(
+        client.open(&sealed),
+        Err(ProtocolError::DecryptionFailed)
+    ));
+}
+
+#[test]
+fn plaintext_and_nested_envelopes_are_rejected() {
+    let (mut client, mut server) = channels();
+    assert!(matches!(
+        server.open(&unlock()),
+        Err(ProtocolError::UnexpectedMessage(MessageKind::Unlock))
+    ));
+    let sealed = client.seal(&unlock()).unwrap();
+    assert!(matches!(
+        client.seal(&sealed),
+        Err(ProtocolError::UnexpectedMessage(MessageKind::Sealed))
+    ));
+}
@@ -0,0 +1,186 @@
+use std::{
+    io::{self, Read, Write},
+    thread,
+};
+
+use facewinunlock_protocol::{
+    client_handshake, decode,
+    handshake::{random_nonce, NONCE_LEN, SECRET_LEN},
+    mock::duplex,
+    read_message, server_handshake, write_message, Message, MessageKind, PipeSecret, ProtocolError,
+    SecretString,
+};
+
+/// 记录写出数据的包装，模拟截获管道流量
+struct Recorder<S> {
+    inner: S,
+    written: Vec<u8>,
+}
+
+impl<S: Read> Read for Recorder<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Recorder<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn secret(fill: u8) -> PipeSecret {
+    PipeSecret::from_bytes([fill; SECRET_LEN])
+}
+
+#[test]
+fn mutual_handshake_succeeds_with_shared_secret() {
+    let (mut client, mut server) = duplex();
+    let server_thread = thread::spawn(move || {
+        let result = server_handshake(&mut server, &secret(1), [2; NONCE_LEN]);
+        // 握手成功后才读取凭据
+        let next = read_message(&mut server).unwrap();
+        (result.unwrap(), next)
+    });
+
+    let handshake = client_handshake(&mut client, &secret(1), [1; NONCE_LEN]).unwrap();
+    write_message(&mut client, &Message::Ping).unwrap();
+
+    let (server_view, next) = server_thread.join().unwrap();
+    assert_eq!(handshake, server_view);
+    assert_eq!(handshake.client_nonce, [1; NONCE_LEN]);
+    assert_eq!(handshake.server_nonce, [2; NONCE_LEN]);
+    assert_eq!(next, Message::Ping);
+}
+
+#[test]
+fn client_rejects_server_without_secret() {
+    // 抢先创建管道的进程不知道密钥，软件在发送任何证明或凭据之前就断开
+    let (mut client, mut server) = duplex();
+    let squatter = thread::spawn(move || {
+        let result = server_handshake(&mut server, &secret(9), [2; NONCE_LEN]);
+        // 软件断开后什么也收不到
+        assert!(matches!(result, Err(ProtocolError::Truncated)));
+    });
+
+    let result = client_handshake(&mut client, &secret(1), [1; NONCE_LEN]);
+    assert!(matches!(result, Err(ProtocolError::AuthenticationFailed)));
+    drop(client);
+    squatter.join().unwrap();
+}
+
+#[test]
+fn server_rejects_client_without_secret() {
+    let (mut client, mut server) = duplex();
+    let server_thread =
+        thread::spawn(move || server_handshake(&mut server, &secret(1), [2; NONCE_LEN]));
+
+    // 伪造的客户端收到 Challenge 后随便给一个证明
+    write_message(
+        &mut client,
+        &Message::Hello {
+            nonce: [1; NONCE_LEN],
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        read_message(&mut client).unwrap(),
+        Message::Challenge { .. }
+    ));
+    write_message(&mut client, &Message::Proof { proof: [0; 32] }).unwrap();
+
+    assert!(matches!(
+        server_thread.join().unwrap(),
+        Err(ProtocolError::AuthenticationFailed)
+    ));
+}
+
+#[test]
+fn server_proof_cannot_be_reflected_as_client_proof() {
+    let (mut client, mut server) = duplex();
+    let server_thread =
+        thread::spawn(move || server_handshake(&mut server, &secret(1), [2; NONCE_LEN]));
+
+    write_message(
+        &mut client,
+        &Message::Hello {
+            nonce: [1; NONCE_LEN],
+        },
+    )
+    .unwrap();
+    let Message::Challenge { proof, .. } = read_message(&mut client).unwrap() else {
+        panic!("期望收到 Challenge");
+    };
+    // 把 DLL 的证明原样发回去
+    write_message(&mut client, &Message::Proof { proof }).unwrap();
+
+    assert!(matches!(
+        server_thread.join().unwrap(),
+        Err(ProtocolError::AuthenticationFailed)
+    ));
+}
+
+#[test]
+fn recorded_proof_cannot_be_replayed() {
+    // 截获一次合法握手中软件写出的全部数据
+    let (client, mut server) = duplex();
+    let mut client = Recorder {
+        inner: client,
+        written: Vec::new(),
+    };
+    let server_thread =
+        thread::spawn(move || server_handshake(&mut server, &secret(1), [2; NONCE_LEN]));
+    client_handshake(&mut client, &secret(1), [1; NONCE_LEN]).unwrap();
+    server_thread.join().unwrap().unwrap();
+
+    let (_, used) = decode(&client.written).unwrap();
+    let (recorded, _) = decode(&client.written[used..]).unwrap();
+    assert_eq!(recorded.kind(), MessageKind::Proof);
+
+    // DLL 换了新的随机数，旧证明失效
+    let (mut attacker, mut server) = duplex();
+    let server_thread =
+        thread::spawn(move || server_handshake(&mut server, &secret(1), [3; NONCE_LEN]));
+    write_message(
+        &mut attacker,
+        &Message::Hello {
+            nonce: [1; NONCE_LEN],
+        },
+    )
+    .unwrap();
+    let _ = read_message(&mut attacker).unwrap();
+    write_message(&mut attacker, &recorded).unwrap();
+    assert!(matches!(
+        server_thread.join().unwrap(),
+        Err(ProtocolError::AuthenticationFailed)
+    ));
+}
+
+#[test]
+fn credentials_before_handshake_are_rejected() {
+    // 旧版本软件或恶意进程直接发送凭据
+    let (mut client, mut server) = duplex();
+    write_message(
+        &mut client,
+        &Message::Unlock {
+            username: String::from("user"),
+            password: SecretString::from("secret"),
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        server_handshake(&mut server, &secret(1), [2; NONCE_LEN]),
+        Err(ProtocolError::UnexpectedMessage(MessageKind::Unlock))
+    ));
+}
+
+#[test]
+fn random_nonces_differ() {
+    assert_ne!(random_nonce().unwrap(), random_nonce().unwrap());
+}
@@ -0,0 +1,106 @@
+use facewinunlock_protocol::{
+    client_handshake,
+    handshake::{NONCE_LEN, SECRET_LEN},
+    logon::STATUS_WRONG_PASSWORD,
+    mock::{Duplex, MockPipeServer},
+    read_message, write_message, LogonOutcome, Message, PipeSecret, SecretString, SecureChannel,
+    StatusIcon,
+};
+
+fn secret() -> PipeSecret {
+    PipeSecret::from_bytes([5; SECRET_LEN])
+}
+
+// 按软件的流程完成握手，返回加密通道
+fn connect(client: &mut Duplex) -> SecureChannel {
+    let handshake = client_handshake(client, &secret(), [1; NONCE_LEN]).unwrap();
+    SecureChannel::client(&secret(), &handshake)
+}
+
+fn read_outcome(client: &mut Duplex) -> LogonOutcome {
+    assert_eq!(read_message(client).unwrap(), Message::Ack);
+    match read_message(client).unwrap() {
+        Message::LogonResult { status, substatus } => {
+            LogonOutcome::from_ntstatus(status, substatus)
+        }
+        other => panic!("期望登录结果，收到 {:?}", other.kind()),
+    }
+}
+
+#[test]
+fn records_statuses_and_credentials() {
+    let server = MockPipeServer::new(secret());
+    let (mut client, handle) = server.accept();
+    let mut channel = connect(&mut client);
+
+    let status = Message::status("请向左转头", StatusIcon::None);
+    write_message(&mut client, &channel.seal(&status).unwrap()).unwrap();
+    let unlock = Message::Unlock {
+        username: String::from(".\\manson"),
+        password: SecretString::from("p@ssw0rd"),
+    };
+    write_message(&mut client, &channel.seal(&unlock).unwrap()).unwrap();
+
+    assert!(read_outcome(&mut client).is_success());
+    let received = handle.join().unwrap().unwrap();
+    assert_eq!(received.statuses, vec![String::from("请向左转头")]);
+    assert_eq!(
+        received.unlock,
+        Some((String::from(".\\manson"), SecretString::from("p@ssw0rd")))
+    );
+}
+
+#[test]
+fn reports_configured_logon_result() {
+    let server = MockPipeServer::new(secret()).with_logon_result(STATUS_WRONG_PASSWORD, 0);
+    let (mut client, handle) = server.accept();
+    let mut channel = connect(&mut client);
+
+    let unlock = Message::Unlock {
+        username: String::from("null"),
+        password: SecretString::from("null"),
+    };
+    write_message(&mut client, &channel.seal(&unlock).unwrap()).unwrap();
+
+    assert!(!read_outcome(&mut client).is_success());
+    assert!(handle.join().unwrap().unwrap().unlock.is_some());
+}
+
+#[test]
+fn disconnect_without_request_is_not_an_error() {
+    let server = MockPipeServer::new(secret());
+    let (mut client, handle) = server.accept();
+    connect(&mut client);
+    drop(client);
+
+    let received = handle.join().unwrap().unwrap();
+    assert!(received.unlock.is_none());
+    assert!(received.statuses.is_empty());
+}
+
+#[test]
+fn plaintext_unlock_is_rejected() {
+    let server = MockPipeServer::new(secret());
+    let (mut client, handle) = server.accept();
+    connect(&mut client);
+    let unlock = Message::Unlock {
+        username: String::from(".\\manson"),
+        password: SecretString::from("p@ssw0rd"),
+    };
+    write_message(&mut client, &unlock).unwrap();
+
+    assert!(handle.join().unwrap().is_err());
+}
+
+#[test]
+fn watch_wake_is_answered() {
+    let server = MockPipeServer::new(secret());
+    let (mut client, handle) = server.accept();
+    connect(&mut client);
+    write_message(&mut client, &Message::WatchWake).unwrap();
+
+    assert_eq!(read_message(&mut client).unwrap(), Message::Wake);
+    let received = handle.join().unwrap().unwrap();
+    assert!(received.watched_wake);
+    assert!(received.unlock.is_none());
+}
@@ -0,0 +1,258 @@
+use std::io::Cursor;
+
+use facewinunlock_protocol::{
+    decode, encode,
+    frame::{HEADER_LEN, MAX_PAYLOAD_LEN},
+    read_message, write_message, Message, ProtocolError, SecretString, StatusIcon,
+    PROTOCOL_VERSION,
+};
+
+fn samples() -> Vec<Message> {
+    vec![
+        Message::Unlock {
+            username: String::from(".\\manson"),
+            password: SecretString::from("p@ssw0rd"),
+        },
+        Message::Unlock {
+            username: String::from("DOMAIN\\用户"),
+            // 超过旧实现 256 个 u16 缓冲区的长密码
+            password: SecretString::from("长密码-".repeat(200)),
+        },
+        Message::Unlock {
+            username: String::new(),
+            password: SecretString::default(),
+        },
+        Message::Cancel,
+        Message::Status {
+            text: String::from("正在识别面容……"),
+            icon: StatusIcon::Scanning,
+            progress: Some(40),
+        },
+        Message::status("面容解锁失败次数过多，请 30 秒后再试", StatusIcon::Warning),
+        Message::status("", StatusIcon::None),
+        Message::Ping,
+        Message::Ack,
+        Message::WatchWake,
+        Message::Wake,
+        Message::LogonResult {
+            status: 0,
+            substatus: 0,
+        },
+        Message::LogonResult {
+            status: 0xC000_006D_u32 as i32,
+            substatus: 0xC000_006A_u32 as i32,
+        },
+        Message::Hello { nonce: [0xA5; 32] },
+        Message::Challenge {
+            nonce: [1; 32],
+            proof: [2; 32],
+        },
+        Message::Proof { proof: [0xFF; 32] },
+        Message::Sealed {
+            counter: 7,
+            ciphertext: vec![0xAB; 48],
+        },
+    ]
+}
+
+#[test]
+fn encode_decode_roundtrip() {
+    for msg in samples() {
+        let frame = encode(&msg).unwrap();
+        let (decoded, used) = decode(&frame).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(used, frame.len());
+    }
+}
+
+#[test]
+fn stream_roundtrip_multiple_frames() {
+    let mut buf = Vec::new();
+    for msg in samples() {
+        write_message(&mut buf, &msg).unwrap();
+    }
+
+    let mut cursor = Cursor::new(buf);
+    for msg in samples() {
+        assert_eq!(read_message(&mut cursor).unwrap(), msg);
+    }
+    // 流已读完
+    assert!(matches!(
+        read_message(&mut cursor),
+        Err(ProtocolError::Truncated)
+    ));
+}
+
+#[test]
+fn header_layout() {
+    let frame = encode(&Message::Ping).unwrap();
+    assert_eq!(frame.len(), HEADER_LEN);
+    assert_eq!(&frame[..2], b"FW");
+    assert_eq!(frame[2], PROTOCOL_VERSION);
+    assert_eq!(&frame[4..8], &0u32.to_le_bytes());
+}
+
+#[test]
+fn partial_frame_is_truncated() {
+    let frame = encode(&Message::Unlock {
+        username: String::from("user"),
+        password: SecretString::from("secret"),
+    })
+    .unwrap();
+    for len in 0..frame.len() {
+        assert!(
+            matches!(decode(&frame[..len]), Err(ProtocolError::Truncated)),
+            "长度 {} 应当判定为不完整",
+            len
+        );
+        let mut cursor = Cursor::new(&frame[..len]);
+        assert!(matches!(
+            read_message(&mut cursor),
+            Err(ProtocolError::Truncated)
+        ));
+    }
+}
+
+#[test]
+fn corrupted_payload_fails_checksum() {
+    let mut frame = encode(&Message::status("hello", StatusIcon::None)).unwrap();
+    let last = frame.len() - 1;
+    frame[last] ^= 0xFF;
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::ChecksumMismatch { .. })
+    ));
+}
+
+#[test]
+fn corrupted_kind_fails_checksum() {
+    // Cancel 和 Ping 都没有负载，只改类型也必须被校验和发现
+    let mut frame = encode(&Message::Cancel).unwrap();
+    frame[3] = Message::Ping.kind() as u8;
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::ChecksumMismatch { .. })
+    ));
+}
+
+#[test]
+fn rejects_bad_magic_and_version() {
+    let mut frame = encode(&Message::Ping).unwrap();
+    frame[0] = b'X';
+    assert!(matches!(decode(&frame), Err(ProtocolError::BadMagic(_))));
+
+    let mut frame = encode(&Message::Ping).unwrap();
+    frame[2] = PROTOCOL_VERSION + 1;
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::UnsupportedVersion(v)) if v == PROTOCOL_VERSION + 1
+    ));
+}
+
+#[test]
+fn rejects_unknown_kind() {
+    let mut frame = encode(&Message::Ping).unwrap();
+    frame[3] = 0xEE;
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::UnknownKind(0xEE))
+    ));
+}
+
+#[test]
+fn encode_rejects_oversized_payload() {
+    let text = "x".repeat(MAX_PAYLOAD_LEN as usize);
+    let msg = Message::status(&text, StatusIcon::None);
+    assert!(matches!(
+        encode(&msg),
+        Err(ProtocolError::PayloadTooLarge(_))
+    ));
+    let mut out = Vec::new();
+    assert!(matches!(
+        write_message(&mut out, &msg),
+        Err(ProtocolError::PayloadTooLarge(_))
+    ));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn rejects_oversized_length() {
+    let mut frame = encode(&Message::Ping).unwrap();
+    frame[4..8].copy_from_slice(&(MAX_PAYLOAD_LEN + 1).to_le_bytes());
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::PayloadTooLarge(_))
+    ));
+}
+
+#[test]
+fn rejects_trailing_payload_bytes() {
+    // 手工拼一个负载多出一个字节的 Ping 帧，并重新计算校验和
+    let mut frame = encode(&Message::status("x", StatusIcon::None)).unwrap();
+    frame[3] = Message::Ping.kind() as u8;
+    let payload = frame[HEADER_LEN..].to_vec();
+    let checksum = crc32_reference(&[&frame[..8], &payload]);
+    frame[8..12].copy_from_slice(&checksum.to_le_bytes());
+    assert!(matches!(
+        decode(&frame),
+        Err(ProtocolError::TrailingBytes(_))
+    ));
+}
+
+#[test]
+fn rejects_invalid_utf8() {
+    let mut frame = encode(&Message::status("ab", StatusIcon::None)).unwrap();
+    let len = frame.len();
+    frame[len - 2] = 0xFF;
+    frame[len - 1] = 0xFE;
+    let checksum = crc32_reference(&[&frame[..8], &frame[HEADER_LEN..]]);
+    frame[8..12].copy_from_slice(&checksum.to_le_bytes());
+    assert!(matches!(decode(&frame), Err(ProtocolError::InvalidUtf8)));
+}
+
+#[test]
+fn status_tolerates_newer_icons() {
+    // 图标和进度在文本之前，改完重新计算校验和
+    let mut frame = encode(&Message::status("hi", StatusIcon::Error)).unwrap();
+    frame[HEADER_LEN] = 0x7F;
+    frame[HEADER_LEN + 1] = 150;
+    let checksum = crc32_reference(&[&frame[..8], &frame[HEADER_LEN..]]);
+    frame[8..12].copy_from_slice(&checksum.to_le_bytes());
+    let (decoded, _) = decode(&frame).unwrap();
+    assert_eq!(
+        decoded,
+        Message::Status {
+            text: String::from("hi"),
+            icon: StatusIcon::None,
+            progress: Some(100),
+        }
+    );
+}
+
+// 独立实现的 CRC-32，用于构造测试数据
+fn crc32_reference(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for part in parts {
+        for &b in part.iter() {
+            crc ^= b as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+    }
+    !crc
+}
+
+#[test]
+fn crc_matches_known_vector() {
+    // "123456789" 的 CRC-32 标准校验值
+    assert_eq!(crc32_reference(&[b"123456789"]), 0xCBF4_3926);
+    // 帧中的校验和与参考实现一致
+    let frame = encode(&Message::Ping).unwrap();
+    let stored = u32::from_le_bytes([frame[8], frame[9], frame[10], frame[11]]);
+    assert_eq!(stored, crc32_reference(&[&frame[..8]]));
+}
@@ -1,13 +1,18 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread};
+use std::{io::{Read, Write}, sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex}, thread, time::Duration};
+use facewinunlock_protocol::{handshake::{random_nonce, SECRET_REG_PATH, SECRET_REG_VALUE}, read_message, server_handshake, write_message, LogonOutcome, CREDUI_EVENT_NAME, Message, MessageKind, PipeSecret, PIPE_NAME, ProtocolError, SecretString, SecureChannel};
 use windows::Win32::{
-    Foundation::CloseHandle, 
-    Storage::FileSystem::{ReadFile, PIPE_ACCESS_INBOUND}, 
-    System::
-        Pipes::{ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_MESSAGE, PIPE_TYPE_MESSAGE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT}
-    , UI::Shell::ICredentialProviderEvents
+    Foundation::{CloseHandle, HANDLE, HLOCAL, LocalFree},
+    Security::{Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1}, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES},
+    Storage::FileSystem::{FlushFileBuffers, ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+    System::{
+        Pipes::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, GetNamedPipeClientProcessId, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT},
+        Threading::{CreateEventW, OpenProcess, QueryFullProcessImageNameW, SetEvent, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION},
+    },
+    UI::Shell::ICredentialProviderEvents
 };
+use windows_core::{PCWSTR, PWSTR};
 
-use crate::SharedCredentials;
+use crate::{read_facewinunlock_registry, read_registry_string, CTileStatus::TileStatus, CWakeSignal::WakeSignal, SharedCredentials};
 
 // 包装 COM 接口，使其可以跨线程传输
 #[derive(Clone)]
@@ -16,14 +21,52 @@ struct SendableEvents(pub ICredentialProviderEvents);
 unsafe impl Send for SendableEvents {}
 unsafe impl Sync for SendableEvents {}
 
+// 把管道句柄包装成 Read + Write，交给协议库读写完整的帧
+struct PipeStream(HANDLE);
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0u32;
+        unsafe { ReadFile(self.0, Some(buf), Some(&mut read), None)? };
+        Ok(read as usize)
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut written = 0u32;
+        unsafe { WriteFile(self.0, Some(buf), Some(&mut written), None)? };
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        unsafe { FlushFileBuffers(self.0)? };
+        Ok(())
+    }
+}
+
+// 等待 ReportResult 的最长时间
+const LOGON_RESULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 软件等待唤醒时，检查监听是否已停止的间隔
+const WAKE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// 管道安全描述符：不继承父级权限，只允许 SYSTEM 和管理员（需提升权限）打开
+const PIPE_SDDL: PCWSTR = windows_core::w!("D:P(A;;GA;;;SY)(A;;GA;;;BA)");
+
+// 上一个监听线程可能还没来得及关闭管道，创建失败时重试的次数和间隔
+const CREATE_PIPE_RETRIES: u32 = 10;
+const CREATE_PIPE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct CPipeListener {
     pub is_unlocked: AtomicBool,
     pub running: Arc<AtomicBool>,
 }
 
 impl CPipeListener {
-    pub fn start(provider_events: ICredentialProviderEvents, advise_context: usize, shared_creds_clone: Arc<Mutex<SharedCredentials>>) -> Arc<Self> {
-        info!("CPipeListener::start - 启动管道监听");
+    /// 启动管道监听，prompt 为 true 表示 CredUI 提示框，管道创建好之后通知软件开始识别
+    pub fn start(provider_events: ICredentialProviderEvents, advise_context: usize, shared_creds_clone: Arc<Mutex<SharedCredentials>>, prompt: bool) -> Arc<Self> {
+        info!("CPipeListener::start - 启动管道监听，CredUI: {}", prompt);
 
         let running = Arc::new(AtomicBool::new(true));
         let running_clone = running.clone();
@@ -35,31 +78,50 @@ impl CPipeListener {
 
         let sendable_events = SendableEvents(provider_events);
         let listener_clone = listener.clone();
+        let tile_status = shared_creds_clone.lock().unwrap().tile_status.clone();
+        let wake = shared_creds_clone.lock().unwrap().wake.clone();
 
         thread::spawn(move || {
             info!("CPipeListener::start - 进入管道监听线程");
             let events_wrapper = sendable_events;
-            let pipe_name = windows_core::w!(r"\\.\pipe\MansonWindowsUnlockRust");
+            let pipe_name_wide: Vec<u16> = PIPE_NAME.encode_utf16().chain(Some(0)).collect();
+            let pipe_name = PCWSTR(pipe_name_wide.as_ptr());
+            // 锁屏时软件自己开始识别，CredUI 提示框需要通知一次
+            let mut announce = prompt;
             unsafe {
                 while running_clone.load(Ordering::SeqCst) {
-                    // 创建命名管道 
-                    let h_pipe = CreateNamedPipeW(
-                        pipe_name,
-                        PIPE_ACCESS_INBOUND,
-                        PIPE_TYPE_MESSAGE | PIPE_READMODE_MESSAGE | PIPE_WAIT,
-                        PIPE_UNLIMITED_INSTANCES,
-                        512, 512, 0,
-                        None // 先使用默认权限，如果创建失败，则使用 create_everyone_full_access_sa
-                    );
+                    // 创建命名管道
+                    // 消息有帧头和长度，使用字节模式即可，不再依赖管道的消息边界
+                    // FILE_FLAG_FIRST_PIPE_INSTANCE：如果同名管道已被其他进程抢先创建，直接失败，防止凭据被截获
+                    let mut h_pipe = create_secure_pipe(pipe_name);
+                    let mut retries = 0;
+                    while let Err(e) = &h_pipe {
+                        if retries >= CREATE_PIPE_RETRIES || !running_clone.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        warn!("创建管道失败，稍后重试：{:?}", e);
+                        retries += 1;
+                        thread::sleep(CREATE_PIPE_RETRY_INTERVAL);
+                        h_pipe = create_secure_pipe(pipe_name);
+                    }
 
-                    if h_pipe.is_invalid() {
-                        error!("创建管道失败");
-                        break;
+                    let h_pipe = match h_pipe {
+                        Ok(h) => h,
+                        Err(e) => {
+                            error!("创建管道失败，同名管道可能已被其他进程占用：{:?}", e);
+                            break;
+                        }
+                    };
+
+                    // 管道已经可以连接，软件收到通知后不会因为管道还没创建而连接失败
+                    if announce {
+                        announce = false;
+                        signal_credui_prompt();
                     }
 
                     // 使命名管道服务器进程能够等待客户端进程连接到命名管道的实例
                     let f_connected = ConnectNamedPipe(
-                        h_pipe, 
+                        h_pipe,
                         None // 创建管道时未指定FILE_FLAG_OVERLAPPED，使用同步模式，函数会阻塞线程，直到客户端连接成功或发生错误才返回
                     );
 
@@ -72,46 +134,86 @@ impl CPipeListener {
                     if !running_clone.load(Ordering::SeqCst) {
                         // 防止在退出时误读数据
                         let _ = CloseHandle(h_pipe);
-                        break; 
+                        break;
                     }
 
-                    let mut buf = [0u16; 256];
-                    let mut read = 0;
-
-                    // 获取 buf 的字节切片视图 (&mut [u8])
-                    // buf 的字节长度是 256 * 2 (每个 u16 占两个字节)
-                    let byte_slice = std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 2);
-                    
-                    // 读取用户名
-                    if ReadFile(h_pipe, Some(byte_slice), Some(&mut read), None).is_ok() {
-                        let user = String::from_utf16_lossy(&buf[.. (read as usize / 2)]);
-                        let mut creds = shared_creds_clone.lock().unwrap();
-                        creds.username = user.trim_matches('\0').to_string();
+                    // 管道权限只放行 SYSTEM 和管理员，这里再确认对端就是部署时登记的软件
+                    if let Err(e) = verify_client_process(h_pipe) {
+                        warn!("拒绝未登记的管道客户端：{}", e);
+                        close_pipe(h_pipe);
+                        continue;
                     }
 
-                    // 读取密码前重置一下缓冲区
-                    read = 0;
+                    let mut stream = PipeStream(h_pipe);
+                    // 质询-应答，确认对端持有共享密钥后才接受凭据
+                    let mut channel = match authenticate_client(&mut stream) {
+                        Ok(channel) => channel,
+                        Err(e) => {
+                            warn!("管道握手失败：{}", e);
+                            close_pipe(h_pipe);
+                            continue;
+                        }
+                    };
 
-                    // 读取密码
-                    if ReadFile(h_pipe, Some(byte_slice), Some(&mut read), None).is_ok() {
-                        let pass = String::from_utf16_lossy(&buf[.. (read as usize / 2)]);
-                        let mut creds = shared_creds_clone.lock().unwrap();
-                        creds.password = pass.trim_matches('\0').to_string();
+                    let request = read_unlock_request(&mut stream, &mut channel, &tile_status, &wake, &running_clone);
+                    // 本次连接显示的提示到此为止，软件取消时留下的识别失败原因继续显示
+                    if !matches!(request, Ok(None)) {
+                        tile_status.clear();
+                    }
+                    let (username, password) = match request {
+                        Ok(Some(creds)) => creds,
+                        Ok(None) => {
+                            // 客户端取消或已经通知了唤醒，等待下一个连接
+                            close_pipe(h_pipe);
+                            continue;
+                        }
+                        Err(e) => {
+                            // 帧不完整或校验失败，丢弃整条请求，不写入半截凭据
+                            warn!("读取解锁请求失败：{}", e);
+                            close_pipe(h_pipe);
+                            continue;
+                        }
+                    };
+
+                    // 告诉软件凭据已完整收到
+                    if let Err(e) = write_message(&mut stream, &Message::Ack) {
+                        warn!("发送确认消息失败：{}", e);
+                        close_pipe(h_pipe);
+                        continue;
                     }
 
-                    // 准备就绪
+                    // 一次性写入完整凭据并标记就绪
+                    let (result_sender, result_receiver) = mpsc::channel();
                     {
                         let mut creds = shared_creds_clone.lock().unwrap();
+                        creds.username = username;
+                        creds.password = password;
                         creds.is_ready = true;
+                        creds.result_sender = Some(result_sender);
                     }
 
                     running_clone.store(false, Ordering::SeqCst);
                     listener_clone.is_unlocked.store(true, Ordering::SeqCst);
-                    
+
                     // 通知 UI 刷新，触发 GetCredentialCount
                     let _ = events_wrapper.0.CredentialsChanged(advise_context);
-                    
-                    let _ = CloseHandle(h_pipe);
+
+                    // 等待 ReportResult 给出最终结果，再回传给软件
+                    match result_receiver.recv_timeout(LOGON_RESULT_TIMEOUT) {
+                        Ok((status, substatus)) => {
+                            info!("回传登录结果: {}", LogonOutcome::from_ntstatus(status, substatus));
+                            // write_message 内部会 FlushFileBuffers，等待软件读完后才返回
+                            if let Err(e) = write_message(&mut stream, &Message::LogonResult { status, substatus }) {
+                                warn!("回传登录结果失败：{}", e);
+                            }
+                        }
+                        Err(_) => {
+                            warn!("等待登录结果超时，关闭管道");
+                            shared_creds_clone.lock().unwrap().result_sender = None;
+                        }
+                    }
+
+                    close_pipe(h_pipe);
 
                     break;
                 }
@@ -122,8 +224,170 @@ impl CPipeListener {
     }
 }
 
+// 按 PIPE_SDDL 创建只允许 SYSTEM 和管理员访问的管道
+unsafe fn create_secure_pipe(pipe_name: PCWSTR) -> windows::core::Result<HANDLE> {
+    let mut sd = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(PIPE_SDDL, SDDL_REVISION_1, &mut sd, None)?;
+        let sa = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: sd.0,
+            bInheritHandle: false.into(),
+        };
+
+        let h_pipe = CreateNamedPipeW(
+            pipe_name,
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE, // 双向管道，需要回传确认和登录结果
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+            PIPE_UNLIMITED_INSTANCES,
+            4096, 4096, 0,
+            Some(&sa)
+        );
+        let error = windows::core::Error::from_thread();
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+
+        if h_pipe.is_invalid() {
+            return Err(error);
+        }
+        Ok(h_pipe)
+    }
+}
+
+// 通知软件 CredUI 提示框正在等待确认身份
+// 事件的权限与管道相同，没有管理员权限的进程弹出的提示框无法打开事件，也无法通过管道握手
+fn signal_credui_prompt() {
+    let name: Vec<u16> = CREDUI_EVENT_NAME.encode_utf16().chain(Some(0)).collect();
+    let mut sd = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        if let Err(e) = ConvertStringSecurityDescriptorToSecurityDescriptorW(PIPE_SDDL, SDDL_REVISION_1, &mut sd, None) {
+            warn!("创建 CredUI 事件的安全描述符失败：{:?}", e);
+            return;
+        }
+        let sa = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: sd.0,
+            bInheritHandle: false.into(),
+        };
+        // 软件启动时已经创建了事件，这里打开同一个事件
+        let event = CreateEventW(Some(&sa), false, false, PCWSTR(name.as_ptr()));
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        match event {
+            Ok(event) => {
+                if let Err(e) = SetEvent(event) {
+                    warn!("通知软件识别失败：{:?}", e);
+                } else {
+                    info!("已通知软件为 CredUI 提示框识别");
+                }
+                let _ = CloseHandle(event);
+            }
+            Err(e) => warn!("打开 CredUI 事件失败，提示框所在进程可能没有管理员权限：{:?}", e),
+        }
+    }
+}
+
+// 断开并关闭管道
+unsafe fn close_pipe(h_pipe: HANDLE) {
+    unsafe {
+        let _ = DisconnectNamedPipe(h_pipe);
+        let _ = CloseHandle(h_pipe);
+    }
+}
+
+// 校验客户端进程的映像路径是否为部署时写入注册表的 APP_PATH
+unsafe fn verify_client_process(h_pipe: HANDLE) -> Result<(), String> {
+    let mut pid = 0u32;
+    unsafe { GetNamedPipeClientProcessId(h_pipe, &mut pid) }
+        .map_err(|e| format!("获取客户端进程ID失败：{:?}", e))?;
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }
+        .map_err(|e| format!("打开客户端进程 {} 失败：{:?}", pid, e))?;
+    let mut buffer = [0u16; 1024];
+    let mut len = buffer.len() as u32;
+    let result = unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut len) };
+    unsafe { let _ = CloseHandle(process); }
+    result.map_err(|e| format!("获取客户端进程路径失败：{:?}", e))?;
+    let image_path = String::from_utf16_lossy(&buffer[..len as usize]);
+
+    let app_path = read_facewinunlock_registry("APP_PATH")
+        .map_err(|e| format!("读取 APP_PATH 失败：{:?}", e))?;
+
+    // 注册表中的路径可能带有 \\?\ 前缀
+    let normalize = |path: &str| path.trim_start_matches(r"\\?\").to_lowercase();
+    if normalize(&image_path) != normalize(&app_path) {
+        return Err(format!("客户端进程 {} ({}) 不是已登记的软件", pid, image_path));
+    }
+    Ok(())
+}
+
+// 读取共享密钥并完成握手，返回本次连接的加密通道
+fn authenticate_client(stream: &mut PipeStream) -> Result<SecureChannel, String> {
+    // 每次连接都重新读取，软件重新部署后无需重启 LogonUI
+    let secret = read_registry_string(SECRET_REG_PATH, SECRET_REG_VALUE)
+        .map_err(|e| format!("读取管道密钥失败：{:?}", e))?;
+    let secret = PipeSecret::from_hex(&secret).map_err(|e| e.to_string())?;
+    let nonce = random_nonce().map_err(|e| e.to_string())?;
+    let handshake = server_handshake(stream, &secret, nonce).map_err(|e| e.to_string())?;
+    Ok(SecureChannel::server(&secret, &handshake))
+}
+
+// 读取一个连接上的消息，直到收到解锁请求
+// 加密的状态信息会显示在磁贴上，例如识别进度、动作验证的提示和识别失败的原因
+// 软件识别失败后发送 WatchWake 等待用户唤醒，磁贴被选中时回复 Wake
+// 返回 None 表示客户端取消了本次解锁（识别失败时先发送失败原因再取消），或者本次连接只用于等待唤醒
+fn read_unlock_request(stream: &mut PipeStream, channel: &mut SecureChannel, tile_status: &TileStatus, wake: &WakeSignal, running: &AtomicBool) -> Result<Option<(String, SecretString)>, ProtocolError> {
+    loop {
+        match read_message(stream)? {
+            sealed @ Message::Sealed { .. } => match channel.open(&sealed)? {
+                Message::Unlock { username, password } => {
+                    info!("收到解锁请求，用户名: {}", username);
+                    return Ok(Some((username, password)));
+                }
+                Message::Status { text, icon, progress } => {
+                    info!("在磁贴上显示提示: {} ({:?}, 进度 {:?})", text, icon, progress);
+                    tile_status.update(&text, icon, progress);
+                }
+                other => {
+                    warn!("忽略加密信封中的意外消息: {:?}", other.kind());
+                }
+            },
+            Message::Unlock { .. } => {
+                // 凭据必须加密传输，明文请求直接断开
+                return Err(ProtocolError::UnexpectedMessage(MessageKind::Unlock));
+            }
+            Message::Cancel => {
+                info!("客户端取消了解锁请求");
+                return Ok(None);
+            }
+            Message::Status { text, .. } => {
+                info!("收到状态信息: {}", text);
+            }
+            Message::Ping => {
+                info!("收到心跳");
+            }
+            Message::WatchWake => {
+                info!("软件等待用户唤醒登录界面");
+                wake.reset();
+                // 监听停止（例如用户手动解锁）后断开，软件会读到管道关闭
+                while running.load(Ordering::SeqCst) {
+                    if wake.wait(WAKE_POLL_INTERVAL) {
+                        info!("用户唤醒了登录界面，通知软件重新识别");
+                        // 上次识别失败的原因不再显示
+                        tile_status.clear();
+                        write_message(stream, &Message::Wake)?;
+                        break;
+                    }
+                }
+                return Ok(None);
+            }
+            other => {
+                warn!("忽略不应由软件发送的消息: {:?}", other.kind());
+            }
+        }
+    }
+}
+
 impl Drop for CPipeListener {
     fn drop(&mut self) {
         info!("销毁一个 CPipeListener");
     }
-}
\ No newline at end of file
+}
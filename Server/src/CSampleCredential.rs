@@ -1,32 +1,212 @@
 // 引入必要的同步原语和Win32 API
-use std::sync::{Arc, Mutex};
+use std::{ffi::c_void, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
 use windows::Win32::{
-    Foundation::{ERROR_NOT_READY, E_NOTIMPL, STATUS_SUCCESS}, Graphics::Gdi::HBITMAP, Security::Credentials::{CredPackAuthenticationBufferW, CRED_PACK_FLAGS}, System::Com::CoTaskMemAlloc, UI::Shell::{
-        ICredentialProviderCredential, ICredentialProviderCredentialEvents, ICredentialProviderCredential_Impl, CPFIS_NONE, CPFS_DISPLAY_IN_BOTH, CPGSR_RETURN_CREDENTIAL_FINISHED, CPSI_ERROR, CPSI_NONE, CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION, CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE, CREDENTIAL_PROVIDER_FIELD_STATE, CREDENTIAL_PROVIDER_GET_SERIALIZATION_RESPONSE, CREDENTIAL_PROVIDER_STATUS_ICON
+    Foundation::{ERROR_NOT_READY, E_INVALIDARG, E_NOTIMPL, E_POINTER, NTSTATUS, STATUS_SUCCESS, S_FALSE, S_OK}, Graphics::Gdi::HBITMAP, Security::Credentials::{CredPackAuthenticationBufferW, CRED_PACK_FLAGS}, System::Com::CoTaskMemAlloc, UI::Shell::{
+        ICredentialProviderCredential, ICredentialProviderCredential2, ICredentialProviderCredential2_Impl, ICredentialProviderCredential2_Vtbl, ICredentialProviderCredentialEvents, ICredentialProviderCredential_Impl, ICredentialProviderCredential_Vtbl, CPFIS_FOCUSED, CPFIS_NONE, CPFS_DISPLAY_IN_BOTH, CPFS_DISPLAY_IN_SELECTED_TILE, CPFS_HIDDEN, CPFT_COMMAND_LINK, CPFT_LARGE_TEXT, CPFT_PASSWORD_TEXT, CPFT_SMALL_TEXT, CPFT_SUBMIT_BUTTON, CPFT_TILE_IMAGE, CPUS_CREDUI, CPGSR_RETURN_CREDENTIAL_FINISHED, CPSI_ERROR, CPSI_NONE, CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION, CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE, CREDENTIAL_PROVIDER_FIELD_STATE, CREDENTIAL_PROVIDER_FIELD_TYPE, CREDENTIAL_PROVIDER_GET_SERIALIZATION_RESPONSE, CREDENTIAL_PROVIDER_STATUS_ICON, CREDENTIAL_PROVIDER_USAGE_SCENARIO
     }
 };
-use windows_core::{implement, BOOL, PCWSTR, PWSTR};
-use crate::{dll_add_ref, dll_release, CLSID_SampleProvider, SharedCredentials};
+use facewinunlock_protocol::{SecretString, TileAccount};
+use windows_core::{implement, AsImpl, IUnknownImpl, Interface, BOOL, HRESULT, PCWSTR, PWSTR};
+use crate::{dll_add_ref, dll_release, read_facewinunlock_registry, CLSID_SampleProvider, CTileStatus::{IMAGE_FIELD_ID, STATUS_FIELD_ID}, SharedCredentials};
+
+/// 磁贴的默认标题，没有对应账户时显示
+const DEFAULT_TITLE: &str = "FaceWinUnlock-Tauri 面容解锁";
+/// 密码框所在的字段ID，面容解锁失败后可以直接在磁贴上输入密码
+pub const PASSWORD_FIELD_ID: u32 = 3;
+/// 提交按钮所在的字段ID，显示在密码框旁边
+pub const SUBMIT_FIELD_ID: u32 = 4;
+/// “重新识别面容”链接所在的字段ID
+pub const RETRY_FIELD_ID: u32 = 5;
+/// 字段总数
+pub const FIELD_COUNT: u32 = 6;
+/// 重新识别链接的文字
+const RETRY_LABEL: &str = "重新识别面容";
+
+/// 各字段的类型和标签，下标就是字段ID
+pub const FIELD_DESCRIPTORS: [(CREDENTIAL_PROVIDER_FIELD_TYPE, &str); FIELD_COUNT as usize] = [
+    (CPFT_TILE_IMAGE, "框架图标"),  // 字段0: 图标
+    (CPFT_LARGE_TEXT, "WinLogon基础框架加载成功！"),  // 字段1: 文本
+    (CPFT_SMALL_TEXT, "状态"),  // 字段2: 软件发送的提示
+    (CPFT_PASSWORD_TEXT, "密码"),  // 字段3: 面容解锁失败时手动输入密码
+    (CPFT_SUBMIT_BUTTON, "提交"),  // 字段4: 密码框旁的提交按钮
+    (CPFT_COMMAND_LINK, RETRY_LABEL),  // 字段5: 通知软件再识别一次
+];
+
+/// ReportResult 对登录结果的处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultForwarding {
+    /// 把结果回传给软件
+    pub forward: bool,
+    /// 清空共享的面容凭据，避免再次提交被拒绝的密码
+    pub clear_credentials: bool,
+}
+
+/// 只有面容解锁提交的凭据的结果才回传给软件，手动输入密码的结果与软件无关
+pub fn should_forward_result(manual: bool, status: NTSTATUS) -> ResultForwarding {
+    ResultForwarding {
+        forward: !manual,
+        clear_credentials: !manual && status != STATUS_SUCCESS,
+    }
+}
 
 /// 凭据实现类，代表登录界面上的一个磁贴
-/// 每个凭据对应一个可选择的登录选项
-#[implement(ICredentialProviderCredential)]
+/// 每个凭据对应一个录入了面容的账户，软件没有发布账户列表时只有一个不属于任何账户的磁贴
+#[implement(ICredentialProviderCredential2)]
 pub struct SampleCredential {
     // 用于接收系统事件通知的接口（互斥锁保护线程安全）
     events: Mutex<Option<ICredentialProviderCredentialEvents>>,
     shared_creds: Arc<Mutex<SharedCredentials>>,
-    auth_package_id: u32
+    auth_package_id: u32,
+    // 磁贴对应的账户，None 表示不属于任何账户
+    account: Option<TileAccount>,
+    // 使用场景，CredUI 提示框不会调用 ReportResult
+    usage_scenario: CREDENTIAL_PROVIDER_USAGE_SCENARIO,
+    // CredPackAuthenticationBufferW 的标志，由 CredUI 调用方的 CREDUIWIN_* 标志决定
+    pack_flags: CRED_PACK_FLAGS,
+    // 用户在密码框中输入的密码，取消选中或登录失败时清空
+    typed_password: Mutex<SecretString>,
+    // 最近一次提交的是手动输入的密码，ReportResult 据此选择提示，并且不把结果回传给软件
+    manual_submit: AtomicBool,
 }
 
 impl SampleCredential {
     /// 创建新的凭据实例
-    pub fn new(shared_creds: Arc<Mutex<SharedCredentials>>, auth_package_id: u32) -> Self {
-        info!("SampleCredential::new - 创建凭据实例");
+    pub fn new(
+        shared_creds: Arc<Mutex<SharedCredentials>>,
+        auth_package_id: u32,
+        account: Option<TileAccount>,
+        usage_scenario: CREDENTIAL_PROVIDER_USAGE_SCENARIO,
+        pack_flags: CRED_PACK_FLAGS
+    ) -> Self {
+        info!("SampleCredential::new - 创建凭据实例，账户: {:?}", account.as_ref().map(|a| &a.user_name));
         dll_add_ref(); // 增加DLL引用计数
         Self { 
             events: Mutex::new(None),
             shared_creds: shared_creds,
-            auth_package_id: auth_package_id
+            auth_package_id: auth_package_id,
+            account: account,
+            usage_scenario: usage_scenario,
+            pack_flags: pack_flags,
+            typed_password: Mutex::new(SecretString::default()),
+            manual_submit: AtomicBool::new(false),
+        }
+    }
+
+    /// 转换为返回给 LogonUI 的接口，GetUserSid 由 USER_SID_VTABLE 中的 get_user_sid 处理
+    pub fn into_interface(self) -> ICredentialProviderCredential2 {
+        unsafe { install_user_sid_vtable(self.into(), &USER_SID_VTABLE) }
+    }
+
+    /// 磁贴对应账户的 SID，由调用方用 CoTaskMemFree 释放，不属于任何账户时返回 None
+    fn user_sid(&self) -> windows_core::Result<Option<PWSTR>> {
+        let Some(account) = &self.account else {
+            // 不属于任何账户的磁贴显示在“其他用户”下
+            info!("SampleCredential::GetUserSid - 磁贴不属于任何账户");
+            return Ok(None);
+        };
+        info!("SampleCredential::GetUserSid - {}", account.sid);
+        unsafe {
+            let utf16 = to_wide_vec(&account.sid);
+            let ptr = CoTaskMemAlloc(utf16.len() * 2);
+            if ptr.is_null() {
+                error!("SampleCredential::GetUserSid - 内存分配失败");
+                return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+            }
+            std::ptr::copy_nonoverlapping(utf16.as_ptr(), ptr as *mut u16, utf16.len());
+            Ok(Some(PWSTR(ptr as *mut _)))
+        }
+    }
+
+    /// 解锁请求是否可以由这个磁贴提交：账户的磁贴只提交该账户的凭据
+    pub fn accepts(account: Option<&TileAccount>, username: &str) -> bool {
+        account.map_or(true, |account| account.matches(username))
+    }
+}
+
+/// 不属于任何账户的磁贴需要返回 S_FALSE 并把 SID 置空，windows-rs 生成的 GetUserSid 只能返回 S_OK 加出参，
+/// 或者返回错误码但不写出参，所以凭据对象使用这个虚表：GetUserSid 换成 get_user_sid，其余方法仍然是生成的实现
+static USER_SID_VTABLE: ICredentialProviderCredential2_Vtbl = user_sid_vtable::<SampleCredential_Impl>(get_user_sid);
+
+/// GetUserSid 在虚表中的函数类型
+pub type GetUserSidFn = unsafe extern "system" fn(*mut c_void, *mut PWSTR) -> HRESULT;
+
+/// 用 #[implement] 生成的方法构造虚表，只替换 GetUserSid
+/// 对象只实现一个接口，接口指针前面只有 identity 指针，偏移与 #[implement] 生成的虚表一样是 -1
+pub const fn user_sid_vtable<Identity: ICredentialProviderCredential_Impl>(get_user_sid: GetUserSidFn) -> ICredentialProviderCredential2_Vtbl {
+    ICredentialProviderCredential2_Vtbl {
+        base__: ICredentialProviderCredential_Vtbl::new::<Identity, -1>(),
+        GetUserSid: get_user_sid,
+    }
+}
+
+/// 让对象的接口指针指向 vtbl，QueryInterface 和事件通知拿到的是同一个接口指针，也使用新的虚表
+/// vtbl 必须由 user_sid_vtable 按这个对象的实现类型构造
+pub unsafe fn install_user_sid_vtable(
+    cred: ICredentialProviderCredential2,
+    vtbl: &'static ICredentialProviderCredential2_Vtbl
+) -> ICredentialProviderCredential2 {
+    unsafe {
+        *(cred.as_raw() as *mut *const ICredentialProviderCredential2_Vtbl) = vtbl;
+    }
+    cred
+}
+
+/// GetUserSid 的公共部分：检查出参并先置空，再把实现对象和出参交给 get
+pub unsafe fn user_sid_with<T>(this: *mut c_void, sid: *mut PWSTR, get: impl FnOnce(&T, *mut PWSTR) -> HRESULT) -> HRESULT
+where
+    ICredentialProviderCredential2: AsImpl<T>,
+{
+    unsafe {
+        if sid.is_null() {
+            return E_POINTER;
+        }
+        sid.write(PWSTR::null());
+        match ICredentialProviderCredential2::from_raw_borrowed(&this) {
+            Some(cred) => get(cred.as_impl(), sid),
+            None => E_POINTER,
+        }
+    }
+}
+
+/// 返回磁贴对应账户的 SID，不属于任何账户时返回 S_FALSE 和空 SID
+unsafe extern "system" fn get_user_sid(this: *mut c_void, sid: *mut PWSTR) -> HRESULT {
+    unsafe {
+        user_sid_with(this, sid, |cred: &SampleCredential, sid| match cred.user_sid() {
+            Ok(Some(value)) => {
+                sid.write(value);
+                S_OK
+            }
+            Ok(None) => S_FALSE,
+            Err(e) => e.into(),
+        })
+    }
+}
+
+impl SampleCredential_Impl {
+    // 事件通知和磁贴状态使用的凭据接口
+    fn credential_interface(&self) -> ICredentialProviderCredential {
+        self.to_interface::<ICredentialProviderCredential2>().into()
+    }
+
+    // 密码框和提交按钮只在账户的磁贴上显示，不属于任何账户的磁贴不知道用哪个用户名登录
+    fn shows_password(&self) -> bool {
+        self.account.is_some()
+    }
+
+    // 重新识别通过唤醒通知软件，软件只在开启了“唤醒时再次识别”时等待唤醒
+    // CredUI 提示框只识别一次，不显示这个链接
+    fn shows_retry(&self) -> bool {
+        self.usage_scenario != CPUS_CREDUI
+            && read_facewinunlock_registry("RESCAN_ON_WAKE").map_or(true, |value| value.as_str() != "0")
+    }
+
+    // 清空保存的输入和磁贴上的密码框
+    fn clear_password(&self) {
+        self.typed_password.lock().unwrap().clear();
+        if let Some(events) = self.events.lock().unwrap().clone() {
+            unsafe {
+                let _ = events.SetFieldString(&self.credential_interface(), PASSWORD_FIELD_ID, windows_core::w!(""));
+            }
         }
     }
 }
@@ -45,6 +225,11 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         info!("SampleCredential::Advise - 注册事件通知");
         let mut events = self.events.lock().unwrap();
         *events = pcpce.clone(); // 保存事件接口
+        // 管道线程收到提示时通过事件接口刷新磁贴上的状态文字
+        if let Some(events) = events.clone() {
+            let tile_status = self.shared_creds.lock().unwrap().tile_status.clone();
+            tile_status.attach(events, self.credential_interface(), STATUS_FIELD_ID, Some(IMAGE_FIELD_ID));
+        }
         Ok(())
     }
 
@@ -53,18 +238,23 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         info!("SampleCredential::UnAdvise - 取消事件通知");
         let mut events = self.events.lock().unwrap();
         *events = None; // 清除事件接口
+        self.shared_creds.lock().unwrap().tile_status.detach(&self.credential_interface());
         Ok(())
     }
 
     /// 当凭据磁贴被选中时调用
     fn SetSelected(&self) -> windows_core::Result<BOOL> {
         info!("SampleCredential::SetSelected - 磁贴被选中");
+        // 用户已经在登录界面前，识别失败后等待的软件可以重新识别
+        self.shared_creds.lock().unwrap().wake.notify();
         Ok(true.into()) // 返回true表示处理成功
     }
 
     /// 当凭据磁贴被取消选中时调用
     fn SetDeselected(&self) -> windows_core::Result<()> {
         info!("SampleCredential::SetDeselected - 磁贴被取消选中");
+        // 离开磁贴时不保留输入了一半的密码
+        self.clear_password();
         Ok(())
     }
 
@@ -79,18 +269,22 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         pcpfis: *mut CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE
     ) -> windows_core::Result<()> {
         info!("SampleCredential::GetFieldState - 获取字段 {} 的状态", dwfieldid);
-        unsafe {
-            match dwfieldid {
-                // 字段0: 图标，字段1: 文本
-                0 | 1 => {  
-                    *pcpfs = CPFS_DISPLAY_IN_BOTH; // 在磁贴和详细视图中都显示
-                    *pcpfis = CPFIS_NONE;          // 非交互元素（不能点击或编辑）
-                }
-                _ => {
-                    error!("SampleCredential::GetFieldState - 无效的字段ID: {}", dwfieldid);
-                    return Err(windows::Win32::Foundation::E_INVALIDARG.into());
-                }
+        let (state, interactive) = match dwfieldid {
+            // 字段0: 图标，字段1: 文本，字段2: 状态文字，在磁贴和详细视图中都显示，不能点击或编辑
+            IMAGE_FIELD_ID | 1 | STATUS_FIELD_ID => (CPFS_DISPLAY_IN_BOTH, CPFIS_NONE),
+            // 选中磁贴后可以直接输入密码
+            PASSWORD_FIELD_ID if self.shows_password() => (CPFS_DISPLAY_IN_SELECTED_TILE, CPFIS_FOCUSED),
+            SUBMIT_FIELD_ID if self.shows_password() => (CPFS_DISPLAY_IN_SELECTED_TILE, CPFIS_NONE),
+            RETRY_FIELD_ID if self.shows_retry() => (CPFS_DISPLAY_IN_SELECTED_TILE, CPFIS_NONE),
+            PASSWORD_FIELD_ID | SUBMIT_FIELD_ID | RETRY_FIELD_ID => (CPFS_HIDDEN, CPFIS_NONE),
+            _ => {
+                error!("SampleCredential::GetFieldState - 无效的字段ID: {}", dwfieldid);
+                return Err(E_INVALIDARG.into());
             }
+        };
+        unsafe {
+            *pcpfs = state;
+            *pcpfis = interactive;
         }
         Ok(())
     }
@@ -99,8 +293,18 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
     /// dwfieldid: 字段ID
     fn GetStringValue(&self, dwfieldid: u32) -> windows_core::Result<PWSTR> {
         info!("SampleCredential::GetStringValue - 获取字段 {} 的文本内容", dwfieldid);
+        let status;
         let val = match dwfieldid {
-            1 => "FaceWinUnlock-Tauri-请勿点击此磁贴",  // 字段1的文本内容
+            // 字段1显示账户名称
+            1 => self.account.as_ref().map_or(DEFAULT_TITLE, |account| account.display_name.as_str()),
+            STATUS_FIELD_ID => {
+                // 字段2显示软件通过管道发送的提示
+                status = self.shared_creds.lock().unwrap().tile_status.text();
+                status.as_str()
+            }
+            // 密码框不回显已经输入的密码
+            PASSWORD_FIELD_ID => "",
+            RETRY_FIELD_ID => RETRY_LABEL,
             _ => {
                 warn!("SampleCredential::GetStringValue - 字段 {} 无文本内容", dwfieldid);
                 ""
@@ -122,10 +326,14 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
     }
 
     /// 获取图标字段的位图
-    /// _dwfieldid: 字段ID（这里是0）
-    fn GetBitmapValue(&self, _dwfieldid: u32) -> windows_core::Result<HBITMAP> {
+    /// dwfieldid: 字段ID（这里是0）
+    fn GetBitmapValue(&self, dwfieldid: u32) -> windows_core::Result<HBITMAP> {
         info!("SampleCredential::GetBitmapValue - 获取图标字段的位图");
-        Ok(HBITMAP::default())  // 返回默认图标
+        if dwfieldid != IMAGE_FIELD_ID {
+            return Err(windows::Win32::Foundation::E_INVALIDARG.into());
+        }
+        // 按软件最近发送的状态绘制图标和识别进度，位图由 LogonUI 释放
+        self.shared_creds.lock().unwrap().tile_status.bitmap()
     }
 
     /// 获取复选框字段的值（未实现）
@@ -134,10 +342,13 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         Err(E_NOTIMPL.into())
     }
 
-    /// 获取提交按钮字段的值（未实现）
-    fn GetSubmitButtonValue(&self, _dwfieldid: u32) -> windows_core::Result<u32> {
-        info!("SampleCredential::GetSubmitButtonValue - 未实现的接口");
-        Err(E_NOTIMPL.into())
+    /// 获取提交按钮旁边的字段
+    fn GetSubmitButtonValue(&self, dwfieldid: u32) -> windows_core::Result<u32> {
+        info!("SampleCredential::GetSubmitButtonValue - 获取字段 {} 旁边的字段", dwfieldid);
+        if dwfieldid != SUBMIT_FIELD_ID {
+            return Err(E_INVALIDARG.into());
+        }
+        Ok(PASSWORD_FIELD_ID) // 提交按钮显示在密码框旁边
     }
 
     /// 获取下拉框字段的选项数量（未实现）
@@ -152,10 +363,15 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         Err(E_NOTIMPL.into())
     }
 
-    /// 设置文本字段的值（未实现）
-    fn SetStringValue(&self, _dwfieldid: u32, _psz: &windows_core::PCWSTR) -> windows_core::Result<()> {
-        info!("SampleCredential::SetStringValue - 未实现的接口");
-        Err(E_NOTIMPL.into())
+    /// 用户在密码框中输入时调用，不记录输入的内容
+    fn SetStringValue(&self, dwfieldid: u32, psz: &windows_core::PCWSTR) -> windows_core::Result<()> {
+        if dwfieldid != PASSWORD_FIELD_ID {
+            error!("SampleCredential::SetStringValue - 字段 {} 不能编辑", dwfieldid);
+            return Err(E_INVALIDARG.into());
+        }
+        let value = if psz.is_null() { String::new() } else { unsafe { psz.to_string() }.map_err(|_| windows_core::Error::from(E_INVALIDARG))? };
+        *self.typed_password.lock().unwrap() = SecretString::from(value);
+        Ok(())
     }
 
     /// 设置复选框字段的值（未实现）
@@ -170,10 +386,15 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         Err(E_NOTIMPL.into())
     }
 
-    /// 命令链接被点击（未实现）
-    fn CommandLinkClicked(&self, _dwfieldid: u32) -> windows_core::Result<()> {
-        info!("SampleCredential::CommandLinkClicked - 未实现的接口");
-        Err(E_NOTIMPL.into())
+    /// 点击“重新识别面容”
+    fn CommandLinkClicked(&self, dwfieldid: u32) -> windows_core::Result<()> {
+        info!("SampleCredential::CommandLinkClicked - 点击字段 {}", dwfieldid);
+        if dwfieldid != RETRY_FIELD_ID {
+            return Err(E_INVALIDARG.into());
+        }
+        // 与选中磁贴相同，识别失败后等待唤醒的软件会再识别一次
+        self.shared_creds.lock().unwrap().wake.notify();
+        Ok(())
     }
 
     /// 序列化凭据信息（登录时调用）
@@ -185,66 +406,44 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
         _pcpsioptionalstatusicon: *mut CREDENTIAL_PROVIDER_STATUS_ICON
     ) -> windows_core::Result<()> {
         info!("SampleCredential::GetSerialization - 序列化凭据");
-        unsafe {
+        // 软件识别通过时使用管道收到的凭据，否则使用用户在密码框中输入的密码
+        let (full_username, password, manual) = {
             let creds = self.shared_creds.lock().unwrap();
-            if !creds.is_ready {
-                error!("SampleCredential::GetSerialization - 凭据未就绪");
-                return Err(ERROR_NOT_READY.into());
+            // 只在识别出的账户的磁贴上使用收到的凭据，避免用户点击其他账户的磁贴时提交这个账户的凭据
+            if creds.is_ready && SampleCredential::accepts(self.account.as_ref(), &creds.username) {
+                (creds.full_username(), creds.password.clone(), false)
+            } else {
+                let typed = self.typed_password.lock().unwrap();
+                match &self.account {
+                    Some(account) if !typed.is_empty() => (account.user_name.clone(), typed.clone(), true),
+                    _ => {
+                        if creds.is_ready {
+                            warn!("SampleCredential::GetSerialization - 凭据不属于这个磁贴的账户");
+                        } else {
+                            error!("SampleCredential::GetSerialization - 凭据未就绪");
+                        }
+                        return Err(ERROR_NOT_READY.into());
+                    }
+                }
             }
+        };
+        self.manual_submit.store(manual, Ordering::SeqCst);
+        info!("SampleCredential::GetSerialization - 用户名: {}，手动输入密码: {}", full_username, manual);
 
-            // 设置响应为：成功，准备好序列化数据了
-            *pcpgsr = CPGSR_RETURN_CREDENTIAL_FINISHED;
+        unsafe { serialize_credentials(&full_username, &password, self.pack_flags, self.auth_package_id, pcpgsr, pcpcs)?; }
 
-            // 准备拼接后的用户名 (Domain\User)
-            let full_username = if creds.domain.is_empty() || creds.domain == "." {
-                creds.username.clone()
+        // CredUI 提示框不会调用 ReportResult，凭据交给提示框即视为完成，密码是否正确由提示框的调用方校验
+        if self.usage_scenario == CPUS_CREDUI {
+            if manual {
+                self.typed_password.lock().unwrap().clear();
             } else {
-                format!("{}\\{}", creds.domain, creds.username)
-            };
-
-            // 获取管道收到的用户名和密码
-            let v_username = to_wide_vec(&full_username);
-            let v_password = to_wide_vec(&creds.password);
-
-            // 转换成 PCWSTR (指向 u16 数组开头的指针)
-            let pwz_username = PCWSTR(v_username.as_ptr());
-            let pwz_password = PCWSTR(v_password.as_ptr());
-
-            // 调用 LSA 序列化开始
-
-            let mut auth_buffer_size: u32 = 0;
-
-            // 使用系统 API 打包 Kerberos 凭据
-            // 第一次调用获取长度
-            let _ = CredPackAuthenticationBufferW(
-                CRED_PACK_FLAGS(0), // 默认传 0
-                pwz_username,
-                pwz_password,
-                None, // 第一次传 None
-                &mut auth_buffer_size
-            );
-
-            // 分配 COM 内存，系统会自动释放这块内存
-            let out_buf = CoTaskMemAlloc(auth_buffer_size as usize) as *mut u8;
-
-            // 第二次调用真正打包
-            CredPackAuthenticationBufferW(
-                CRED_PACK_FLAGS(0),
-                pwz_username,
-                pwz_password,
-                Some(out_buf), // 传入分配好的指针
-                &mut auth_buffer_size
-            )?;
-
-            // 填充返回给 Windows 的结构体
-            *pcpgsr = CPGSR_RETURN_CREDENTIAL_FINISHED;
-            (*pcpcs).clsidCredentialProvider = CLSID_SampleProvider; 
-            (*pcpcs).cbSerialization = auth_buffer_size;
-            (*pcpcs).rgbSerialization = out_buf;
-
-            // 重点：AuthenticationPackage 需要通过 LsaLookupAuthenticationPackage 获取
-            // 通常在 Provider 初始化时获取一次。
-            (*pcpcs).ulAuthenticationPackage = self.auth_package_id;
+                let mut creds = self.shared_creds.lock().unwrap();
+                if let Some(sender) = creds.result_sender.take() {
+                    let _ = sender.send((STATUS_SUCCESS.0, STATUS_SUCCESS.0));
+                }
+                creds.password.clear();
+                creds.is_ready = false;
+            }
         }
         Ok(())
     }
@@ -253,22 +452,45 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
     fn ReportResult(
         &self, 
         ntsstatus: windows::Win32::Foundation::NTSTATUS, 
-        _ntssubstatus: windows::Win32::Foundation::NTSTATUS, 
+        ntssubstatus: windows::Win32::Foundation::NTSTATUS, 
         ppszoptionalstatustext: *mut PWSTR, 
         pcpsioptionalstatusicon: *mut CREDENTIAL_PROVIDER_STATUS_ICON
     ) -> windows_core::Result<()> {
-        info!("SampleCredential::ReportResult - 报告登录结果");
+        info!("SampleCredential::ReportResult - 报告登录结果: {:#010x}, 子状态: {:#010x}", ntsstatus.0 as u32, ntssubstatus.0 as u32);
+        let manual = self.manual_submit.swap(false, Ordering::SeqCst);
+        let forwarding = should_forward_result(manual, ntsstatus);
+        if forwarding.forward {
+            // 把结果交给管道线程，由它回传给软件；手动输入密码的结果与软件无关
+            let mut creds = self.shared_creds.lock().unwrap();
+            if let Some(sender) = creds.result_sender.take() {
+                if sender.send((ntsstatus.0, ntssubstatus.0)).is_err() {
+                    warn!("SampleCredential::ReportResult - 管道线程已退出，登录结果无法回传");
+                }
+            }
+        }
+        if ntsstatus != STATUS_SUCCESS {
+            // 登录失败时清空密码框，用户可以直接重新输入
+            self.clear_password();
+        }
         unsafe {
             if ntsstatus != STATUS_SUCCESS {
                 // 如果登录失败
-                let mut creds = self.shared_creds.lock().unwrap();
-                // 清空错误凭据
-                creds.username.clear();
-                creds.password.clear();
-                creds.is_ready = false;
+                if forwarding.clear_credentials {
+                    let mut creds = self.shared_creds.lock().unwrap();
+                    // 清空错误凭据
+                    creds.username.clear();
+                    creds.password.clear();
+                    creds.is_ready = false;
+                }
 
-                // 设置错误提示文本
-                let error_text = "用户名或密码错误，请点击自己账户，手动输入密码进入系统。";
+                // 设置错误提示文本，面容解锁失败时引导用户在同一个磁贴上输入密码或重新识别
+                let error_text = if manual {
+                    "登录失败，请检查密码后重新输入。"
+                } else if self.shows_password() {
+                    "面容解锁失败，请在下方输入密码，或点击“重新识别面容”。"
+                } else {
+                    "面容解锁失败，请点击自己的账户，手动输入密码进入系统。"
+                };
                 let utf16: Vec<u16> = error_text.encode_utf16().chain(Some(0)).collect();
                 let ptr = windows::Win32::System::Com::CoTaskMemAlloc(utf16.len() * 2);
                 if !ptr.is_null() {
@@ -287,7 +509,182 @@ impl ICredentialProviderCredential_Impl for SampleCredential_Impl {
     }
 }
 
+impl ICredentialProviderCredential2_Impl for SampleCredential_Impl {
+    /// 返回磁贴对应账户的 SID，LogonUI 据此把磁贴放到该用户的头像下
+    /// LogonUI 通过 USER_SID_VTABLE 调用 get_user_sid，不经过这里
+    fn GetUserSid(&self) -> windows_core::Result<PWSTR> {
+        self.user_sid().map(|sid| sid.unwrap_or(PWSTR::null()))
+    }
+}
+
+/// 把用户名和密码打包成 LogonUI 需要的序列化凭据，面容解锁的磁贴和包装的系统密码磁贴共用
+pub unsafe fn serialize_credentials(
+    full_username: &str,
+    password: &SecretString,
+    pack_flags: CRED_PACK_FLAGS,
+    auth_package_id: u32,
+    pcpgsr: *mut CREDENTIAL_PROVIDER_GET_SERIALIZATION_RESPONSE,
+    pcpcs: *mut CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION
+) -> windows_core::Result<()> {
+    unsafe {
+        // 设置响应为：成功，准备好序列化数据了
+        *pcpgsr = CPGSR_RETURN_CREDENTIAL_FINISHED;
+
+        // 获取用户名和密码
+        let v_username = to_wide_vec(full_username);
+        // 密码的 UTF-16 副本在函数返回时清零
+        let v_password = password.to_wide();
+
+        // 转换成 PCWSTR (指向 u16 数组开头的指针)
+        let pwz_username = PCWSTR(v_username.as_ptr());
+        let pwz_password = PCWSTR(v_password.as_ptr());
+
+        // 调用 LSA 序列化开始
+
+        let mut auth_buffer_size: u32 = 0;
+
+        // 使用系统 API 打包 Kerberos 凭据
+        // 第一次调用获取长度
+        let _ = CredPackAuthenticationBufferW(
+            pack_flags, // 登录和解锁为 0，CredUI 按调用方要求的格式打包
+            pwz_username,
+            pwz_password,
+            None, // 第一次传 None
+            &mut auth_buffer_size
+        );
+
+        // 分配 COM 内存，系统会自动释放这块内存
+        let out_buf = CoTaskMemAlloc(auth_buffer_size as usize) as *mut u8;
+
+        // 第二次调用真正打包
+        CredPackAuthenticationBufferW(
+            pack_flags,
+            pwz_username,
+            pwz_password,
+            Some(out_buf), // 传入分配好的指针
+            &mut auth_buffer_size
+        )?;
+
+        // 填充返回给 Windows 的结构体
+        *pcpgsr = CPGSR_RETURN_CREDENTIAL_FINISHED;
+        (*pcpcs).clsidCredentialProvider = CLSID_SampleProvider; 
+        (*pcpcs).cbSerialization = auth_buffer_size;
+        (*pcpcs).rgbSerialization = out_buf;
+
+        // 重点：AuthenticationPackage 需要通过 LsaLookupAuthenticationPackage 获取
+        // 通常在 Provider 初始化时获取一次。
+        (*pcpcs).ulAuthenticationPackage = auth_package_id;
+    }
+    Ok(())
+}
+
 // 将 String 转换为符合 Win32 要求的 UTF-16 向量（带 null 结尾）
 fn to_wide_vec(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use windows::Win32::{
+        Security::Credentials::STATUS_LOGON_FAILURE, System::Com::CoTaskMemFree, UI::Shell::CPUS_LOGON,
+    };
+
+    /// 录入了面容的测试账户
+    pub fn account() -> TileAccount {
+        TileAccount {
+            sid: String::from("S-1-5-21-1-2-3-1001"),
+            user_name: String::from(".\\tester"),
+            display_name: String::from("tester"),
+        }
+    }
+
+    /// 锁屏场景下的凭据，已经换上 USER_SID_VTABLE
+    pub fn credential(account: Option<TileAccount>) -> ICredentialProviderCredential2 {
+        SampleCredential::new(
+            Arc::new(Mutex::new(SharedCredentials::new())),
+            0,
+            account,
+            CPUS_LOGON,
+            CRED_PACK_FLAGS(0)
+        ).into_interface()
+    }
+
+    /// 通过虚表调用 GetUserSid，返回 HRESULT 和 SID 字符串
+    pub fn call_get_user_sid(cred: &ICredentialProviderCredential2) -> (HRESULT, Option<String>) {
+        // 出参先填入非空值，检查 GetUserSid 是否置空
+        let mut sid = PWSTR(8 as *mut u16);
+        unsafe {
+            let hr = (cred.vtable().GetUserSid)(cred.as_raw(), &mut sid);
+            if sid.is_null() {
+                return (hr, None);
+            }
+            let value = sid.to_string().ok();
+            CoTaskMemFree(Some(sid.0 as *const _));
+            (hr, value)
+        }
+    }
+
+    #[test]
+    fn field_ids_match_descriptors() {
+        assert_eq!(FIELD_DESCRIPTORS.len(), FIELD_COUNT as usize);
+        assert_eq!(FIELD_DESCRIPTORS[IMAGE_FIELD_ID as usize].0, CPFT_TILE_IMAGE);
+        assert_eq!(FIELD_DESCRIPTORS[STATUS_FIELD_ID as usize].0, CPFT_SMALL_TEXT);
+        assert_eq!(FIELD_DESCRIPTORS[PASSWORD_FIELD_ID as usize].0, CPFT_PASSWORD_TEXT);
+        assert_eq!(FIELD_DESCRIPTORS[SUBMIT_FIELD_ID as usize].0, CPFT_SUBMIT_BUTTON);
+        assert_eq!(FIELD_DESCRIPTORS[RETRY_FIELD_ID as usize].0, CPFT_COMMAND_LINK);
+    }
+
+    #[test]
+    fn only_face_results_are_forwarded() {
+        assert_eq!(
+            should_forward_result(false, STATUS_SUCCESS),
+            ResultForwarding { forward: true, clear_credentials: false }
+        );
+        assert_eq!(
+            should_forward_result(false, STATUS_LOGON_FAILURE),
+            ResultForwarding { forward: true, clear_credentials: true }
+        );
+        // 手动输入密码的结果不回传，也不影响软件发来的凭据
+        for status in [STATUS_SUCCESS, STATUS_LOGON_FAILURE] {
+            assert_eq!(
+                should_forward_result(true, status),
+                ResultForwarding { forward: false, clear_credentials: false }
+            );
+        }
+    }
+
+    #[test]
+    fn get_user_sid_is_the_last_slot() {
+        // 虚表依次是 IUnknown 的 3 个方法、ICredentialProviderCredential 的 18 个方法和 GetUserSid
+        let slot = std::mem::size_of::<usize>();
+        assert_eq!(std::mem::size_of::<ICredentialProviderCredential_Vtbl>(), 21 * slot);
+        assert_eq!(std::mem::offset_of!(ICredentialProviderCredential2_Vtbl, GetUserSid), 21 * slot);
+        assert_eq!(std::mem::size_of::<ICredentialProviderCredential2_Vtbl>(), 22 * slot);
+    }
+
+    #[test]
+    fn user_sid_vtable_keeps_the_generated_methods() {
+        for (account, password_state) in [(None, CPFS_HIDDEN), (Some(account()), CPFS_DISPLAY_IN_SELECTED_TILE)] {
+            let cred = credential(account);
+            assert!(std::ptr::eq(cred.vtable(), &USER_SID_VTABLE));
+            // 偏移不对时生成的方法会读到错误的对象，字段状态与账户不符
+            let mut state = CPFS_HIDDEN;
+            let mut interactive = CPFIS_NONE;
+            unsafe { cred.GetFieldState(PASSWORD_FIELD_ID, &mut state, &mut interactive) }.unwrap();
+            assert_eq!(state, password_state);
+            // QueryInterface 返回同一个接口指针，LogonUI 比较凭据时能认出它
+            let base = cred.cast::<ICredentialProviderCredential>().unwrap();
+            assert_eq!(base.as_raw(), cred.as_raw());
+        }
+    }
+
+    #[test]
+    fn tile_without_account_returns_false_and_null_sid() {
+        assert_eq!(call_get_user_sid(&credential(None)), (S_FALSE, None));
+        assert_eq!(
+            call_get_user_sid(&credential(Some(account()))),
+            (S_OK, Some(account().sid))
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// 凭据提供程序过滤器：开启包装模式后隐藏系统密码提供程序，它的磁贴改由本程序包装显示
+use windows::Win32::{Foundation::E_NOTIMPL, System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER}, UI::Shell::{ICredentialProvider, ICredentialProviderFilter, ICredentialProviderFilter_Impl, PasswordCredentialProvider, CPUS_LOGON, CPUS_UNLOCK_WORKSTATION, CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION, CREDENTIAL_PROVIDER_USAGE_SCENARIO}};
+use windows_core::{implement, BOOL, GUID};
+use crate::{dll_add_ref, dll_release, read_facewinunlock_registry, CLSID_SampleProvider};
+
+/// 是否包装系统密码提供程序：软件设置中开启（注册表中为 "1"），并且是登录或锁屏界面，只有这两个场景显示用户磁贴
+pub fn wraps(cpus: CREDENTIAL_PROVIDER_USAGE_SCENARIO, wrap_setting: Option<&str>) -> bool {
+    matches!(cpus, CPUS_LOGON | CPUS_UNLOCK_WORKSTATION) && wrap_setting == Some("1")
+}
+
+/// 这个场景是否包装系统密码提供程序，设置从注册表读取
+pub fn wraps_scenario(cpus: CREDENTIAL_PROVIDER_USAGE_SCENARIO) -> bool {
+    let wrap_setting = read_facewinunlock_registry("WRAP_PASSWORD_PROVIDER").ok();
+    wraps(cpus, wrap_setting.as_deref())
+}
+
+/// 创建系统密码提供程序的实例，本程序包装它的磁贴
+pub fn create_password_provider() -> windows_core::Result<ICredentialProvider> {
+    unsafe { CoCreateInstance(&PasswordCredentialProvider, None, CLSCTX_INPROC_SERVER) }
+}
+
+/// 凭据提供程序过滤器，LogonUI 枚举凭据提供程序之前调用
+#[implement(ICredentialProviderFilter)]
+pub struct SampleFilter;
+
+impl SampleFilter {
+    pub fn new() -> Self {
+        info!("SampleFilter::new - 创建过滤器实例");
+        dll_add_ref(); // 增加DLL引用计数
+        Self
+    }
+}
+
+impl Drop for SampleFilter {
+    fn drop(&mut self) {
+        info!("SampleFilter::drop - 销毁过滤器实例");
+        dll_release(); // 减少DLL引用计数
+    }
+}
+
+impl ICredentialProviderFilter_Impl for SampleFilter_Impl {
+    /// 决定哪些凭据提供程序可以显示
+    /// rgclsidproviders: 所有凭据提供程序的CLSID
+    /// rgballow: 输入输出参数，对应的凭据提供程序是否显示
+    fn Filter(
+        &self,
+        cpus: CREDENTIAL_PROVIDER_USAGE_SCENARIO,
+        _dwflags: u32,
+        rgclsidproviders: *const GUID,
+        rgballow: *mut BOOL,
+        cproviders: u32
+    ) -> windows_core::Result<()> {
+        if !wraps_scenario(cpus) || rgclsidproviders.is_null() || rgballow.is_null() {
+            return Ok(());
+        }
+        let providers = unsafe { std::slice::from_raw_parts(rgclsidproviders, cproviders as usize) };
+        let allow = unsafe { std::slice::from_raw_parts_mut(rgballow, cproviders as usize) };
+
+        // 本程序被其他过滤器隐藏时不能包装，保留系统密码磁贴
+        let wrapper_allowed = providers.iter().zip(allow.iter()).any(|(clsid, allowed)| *clsid == CLSID_SampleProvider && allowed.as_bool());
+        if !wrapper_allowed {
+            info!("SampleFilter::Filter - 本程序不显示，保留系统密码提供程序");
+            return Ok(());
+        }
+        // 先确认能创建系统密码提供程序，否则隐藏后用户将无法输入密码
+        if let Err(e) = create_password_provider() {
+            warn!("SampleFilter::Filter - 无法创建系统密码提供程序，不隐藏它: {:?}", e);
+            return Ok(());
+        }
+
+        for (clsid, allowed) in providers.iter().zip(allow.iter_mut()) {
+            if *clsid == PasswordCredentialProvider {
+                info!("SampleFilter::Filter - 隐藏系统密码提供程序，由本程序包装显示");
+                *allowed = BOOL::from(false);
+            }
+        }
+        Ok(())
+    }
+
+    /// 远程桌面传入的凭据不做处理
+    fn UpdateRemoteCredential(
+        &self,
+        _pcpcsin: *const CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION,
+        _pcpcsout: *mut CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION
+    ) -> windows_core::Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::UI::Shell::{CPUS_CHANGE_PASSWORD, CPUS_CREDUI};
+
+    #[test]
+    fn wraps_only_logon_and_unlock_when_enabled() {
+        assert!(wraps(CPUS_LOGON, Some("1")));
+        assert!(wraps(CPUS_UNLOCK_WORKSTATION, Some("1")));
+        // CredUI 提示框和修改密码界面不显示用户磁贴
+        assert!(!wraps(CPUS_CREDUI, Some("1")));
+        assert!(!wraps(CPUS_CHANGE_PASSWORD, Some("1")));
+        // 未开启、设置缺失或者不是 "1" 时不包装
+        for setting in [None, Some("0"), Some(""), Some("true")] {
+            assert!(!wraps(CPUS_LOGON, setting));
+        }
+    }
+}
@@ -1,11 +1,12 @@
 // 引入必要的Win32 API和同步原语
-use windows::Win32::{Foundation::{HANDLE, STATUS_SUCCESS}, Security::Authentication::Identity::{LsaConnectUntrusted, LsaDeregisterLogonProcess, LsaLookupAuthenticationPackage, LSA_STRING}, Storage::FileSystem::{CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_GENERIC_READ, FILE_SHARE_NONE, OPEN_EXISTING}, UI::Shell::*};
+use windows::Win32::{Foundation::{CloseHandle, E_NOTIMPL, HANDLE, STATUS_SUCCESS}, Security::{Authentication::Identity::{LsaConnectUntrusted, LsaDeregisterLogonProcess, LsaLookupAuthenticationPackage, LSA_STRING}, Credentials::{CREDUIWIN_AUTHPACKAGE_ONLY, CREDUIWIN_FLAGS, CREDUIWIN_GENERIC, CREDUIWIN_PACK_32_WOW, CRED_PACK_FLAGS, CRED_PACK_GENERIC_CREDENTIALS, CRED_PACK_WOW_BUFFER}}, Storage::FileSystem::{CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_GENERIC_READ, FILE_SHARE_NONE, OPEN_EXISTING}, UI::Shell::*};
 use std::sync::{atomic::Ordering, Arc, Mutex};
-use crate::{dll_add_ref, dll_release, read_facewinunlock_registry, CPipeListener::CPipeListener, CSampleCredential::SampleCredential, SharedCredentials};
-use windows_core::{implement, BOOL, PSTR, PWSTR};
+use facewinunlock_protocol::{accounts::{parse_accounts, ACCOUNTS_REG_VALUE}, TileAccount, PIPE_NAME};
+use crate::{dll_add_ref, dll_release, read_facewinunlock_registry, CPipeListener::CPipeListener, CSampleCredential::{SampleCredential, FIELD_COUNT, FIELD_DESCRIPTORS}, CSampleFilter::{create_password_provider, wraps_scenario}, CWrappedCredential::WrappedCredential, SharedCredentials};
+use windows_core::{implement, Interface, BOOL, PCWSTR, PSTR, PWSTR};
 
 /// 凭据提供程序主类，负责管理凭据和与系统交互
-#[implement(ICredentialProvider)]
+#[implement(ICredentialProvider, ICredentialProviderSetUserArray)]
 pub struct SampleProvider {
     // 内部状态（使用互斥锁保证线程安全）
     inner: Mutex<ProviderInner>,
@@ -13,12 +14,18 @@ pub struct SampleProvider {
 
 /// 凭据提供程序的内部状态
 struct ProviderInner {
-    usage_scenario: CREDENTIAL_PROVIDER_USAGE_SCENARIO, // 使用场景（登录、解锁等）
+    usage_scenario: CREDENTIAL_PROVIDER_USAGE_SCENARIO, // 使用场景（登录、解锁、CredUI）
+    cred_ui_flags: CREDUIWIN_FLAGS, // CredUI 调用方传入的 CREDUIWIN_* 标志，其他场景为 0
+    serialization_package: Option<u32>, // CredUI 调用方通过 SetSerialization 指定的认证包
     events: Option<ICredentialProviderEvents>, // 系统事件接口
     advise_context: usize, // 通知上下文ID
     listener: Option<Arc<CPipeListener>>, // 管道监听器实例
     pub shared_creds: Arc<Mutex<SharedCredentials>>, // 共享的凭据列表
-    pub auth_package_id: u32 // 认证包ID
+    pub auth_package_id: u32, // 认证包ID
+    user_sids: Option<Vec<String>>, // LogonUI 显示的用户的 SID，SetUserArray 时记录
+    tiles: Vec<Option<TileAccount>>, // 当前的磁贴，GetCredentialCount 时刷新，None 表示不属于任何账户的磁贴
+    wrapped: Option<ICredentialProvider>, // 包装模式下的系统密码提供程序，它的磁贴代替本程序的磁贴
+    wrapped_credentials: Vec<ICredentialProviderCredential>, // 包装后的系统磁贴，GetCredentialCount 时刷新
 }
 
 impl SampleProvider {
@@ -28,12 +35,7 @@ impl SampleProvider {
         dll_add_ref(); // 增加DLL引用计数
 
         // 创建共享的凭据列表实例
-        let shared = Arc::new(Mutex::new(SharedCredentials {
-            username: String::new(),
-            password: String::new(),
-            domain: String::from("."),
-            is_ready: false,
-        }));
+        let shared = Arc::new(Mutex::new(SharedCredentials::new()));
 
         // 获取认证包ID
         let auth_id = retrieve_negotiate_auth_package().unwrap_or(0);
@@ -41,16 +43,87 @@ impl SampleProvider {
         Self {
             inner: Mutex::new(ProviderInner {
                 usage_scenario: CPUS_LOGON, // 默认场景为登录
+                cred_ui_flags: CREDUIWIN_FLAGS(0),
+                serialization_package: None,
                 events: None,
                 advise_context: 0,
                 listener: None,
                 shared_creds: shared,
-                auth_package_id: auth_id
+                auth_package_id: auth_id,
+                user_sids: None,
+                tiles: Vec::new(),
+                wrapped: None,
+                wrapped_credentials: Vec::new(),
             }),
         }
     }
 }
 
+impl ProviderInner {
+    // GetSerialization 打包凭据时使用的标志，CredUI 的调用方通过 CREDUIWIN_* 指定凭据格式
+    fn pack_flags(&self) -> CRED_PACK_FLAGS {
+        let mut flags = CRED_PACK_FLAGS(0);
+        if self.usage_scenario == CPUS_CREDUI {
+            if self.cred_ui_flags.contains(CREDUIWIN_GENERIC) {
+                flags |= CRED_PACK_GENERIC_CREDENTIALS; // 调用方要明文的用户名和密码
+            }
+            if self.cred_ui_flags.contains(CREDUIWIN_PACK_32_WOW) {
+                flags |= CRED_PACK_WOW_BUFFER; // 调用方是 64 位系统上的 32 位程序
+            }
+        }
+        flags
+    }
+
+    // 调用方只接受指定认证包的凭据，而我们使用的 Negotiate 不是这个认证包
+    fn package_rejected(&self) -> bool {
+        self.usage_scenario == CPUS_CREDUI
+            && self.cred_ui_flags.contains(CREDUIWIN_AUTHPACKAGE_ONLY)
+            && self.serialization_package.is_some_and(|package| package != self.auth_package_id)
+    }
+
+    // 包装模式：枚举系统密码提供程序的磁贴，录入了面容的用户磁贴上追加状态文字
+    // 返回 (数量, 默认索引, 自动登录)
+    fn enumerate_wrapped(&mut self, wrapped: &ICredentialProvider) -> windows_core::Result<(u32, u32, bool)> {
+        let mut count = 0u32;
+        let mut default = CREDENTIAL_PROVIDER_NO_DEFAULT;
+        let mut autologon = BOOL::from(false);
+        unsafe { wrapped.GetCredentialCount(&mut count, &mut default, &mut autologon)?; }
+        // 状态文字排在系统字段之后
+        let status_field = unsafe { wrapped.GetFieldDescriptorCount()? };
+
+        let accounts = load_tile_accounts(self.user_sids.as_deref());
+        let mut credentials = Vec::new();
+        let mut tiles = Vec::new();
+        for index in 0..count {
+            let credential = unsafe { wrapped.GetCredentialAt(index)? };
+            let account = WrappedCredential::user_sid(&credential)
+                .and_then(|sid| accounts.iter().find(|account| account.sid.eq_ignore_ascii_case(&sid)).cloned());
+            let wrapper: ICredentialProviderCredential2 = WrappedCredential::new(
+                credential,
+                status_field,
+                account.clone(),
+                self.shared_creds.clone(),
+                self.auth_package_id,
+            ).into_interface();
+            credentials.push(wrapper.into());
+            tiles.push(account);
+        }
+
+        let mut result = (count, default, autologon.as_bool());
+        if self.listener.as_ref().is_some_and(|l| l.is_unlocked.load(Ordering::SeqCst)) {
+            // 管道已经收到了凭据，在识别出的用户的系统磁贴上自动登录
+            let username = self.shared_creds.lock().unwrap().username.clone();
+            match tiles.iter().position(|tile| WrappedCredential::accepts(tile.as_ref(), &username)) {
+                Some(index) => result = (count, index as u32, true),
+                None => warn!("系统密码提供程序没有 {} 的磁贴，无法自动登录", username),
+            }
+        }
+        self.wrapped_credentials = credentials;
+        self.tiles = tiles;
+        Ok(result)
+    }
+}
+
 /// 实现Drop trait，在对象销毁时减少引用计数
 impl Drop for SampleProvider {
     fn drop(&mut self) {
@@ -60,16 +133,20 @@ impl Drop for SampleProvider {
             // 如果有监听线程，并且正在运行中
             if listener.running.swap(false, Ordering::SeqCst) { 
                 info!("SampleProvider::drop - 安全关闭监听线程……");
+                // 连接一次管道，让阻塞在 ConnectNamedPipe 的线程返回并检查 running
+                let pipe_name: Vec<u16> = PIPE_NAME.encode_utf16().chain(Some(0)).collect();
                 unsafe {
-                    let _ = CreateFileW(
-                        windows_core::w!(r"\\.\pipe\MansonWindowsUnlockRust"),
+                    if let Ok(handle) = CreateFileW(
+                        PCWSTR(pipe_name.as_ptr()),
                         FILE_GENERIC_READ.0,
                         FILE_SHARE_NONE,
                         None,
                         OPEN_EXISTING,
                         FILE_FLAGS_AND_ATTRIBUTES(0),
                         None
-                    );
+                    ) {
+                        let _ = CloseHandle(handle);
+                    }
                 }
                 // 给线程一个微小的调度时间窗口
                 std::thread::yield_now();
@@ -84,19 +161,63 @@ impl Drop for SampleProvider {
 /// 实现ICredentialProvider接口，这是凭据提供程序的核心接口
 impl ICredentialProvider_Impl for SampleProvider_Impl {
     /// 设置凭据提供程序的使用场景
-    /// cpus: 使用场景（登录、解锁、切换用户等）
-    /// _dwflags: 附加标志
-    fn SetUsageScenario(&self, cpus: CREDENTIAL_PROVIDER_USAGE_SCENARIO, _dwflags: u32) -> windows_core::Result<()> {
-        info!("SampleProvider::SetUsageScenario - 设置使用场景: {:?}", cpus);
+    /// cpus: 使用场景（登录、解锁、CredUI 等）
+    /// dwflags: CredUI 场景下为调用方传入的 CREDUIWIN_* 标志
+    fn SetUsageScenario(&self, cpus: CREDENTIAL_PROVIDER_USAGE_SCENARIO, dwflags: u32) -> windows_core::Result<()> {
+        info!("SampleProvider::SetUsageScenario - 设置使用场景: {:?}，标志: {:#x}", cpus, dwflags);
+        match cpus {
+            // 登录和锁屏界面：软件识别通过后发送凭据，在识别出的账户磁贴上自动登录
+            CPUS_LOGON | CPUS_UNLOCK_WORKSTATION => {}
+            // CredUI 提示框（UAC 提权、凭据对话框）：通知软件识别，通过后代为填写凭据
+            CPUS_CREDUI => {}
+            // 修改密码需要用户输入旧密码和新密码，面容解锁不参与
+            CPUS_CHANGE_PASSWORD => {
+                info!("SampleProvider::SetUsageScenario - 修改密码场景不显示面容解锁");
+                return Err(E_NOTIMPL.into());
+            }
+            _ => {
+                info!("SampleProvider::SetUsageScenario - 不支持的使用场景");
+                return Err(E_NOTIMPL.into());
+            }
+        }
+        // 软件开启了包装模式时，过滤器已经隐藏了系统密码提供程序，由本程序创建并包装它
+        let wrapped = if wraps_scenario(cpus) {
+            match create_password_provider().and_then(|provider| unsafe { provider.SetUsageScenario(cpus, dwflags) }.map(|_| provider)) {
+                Ok(provider) => {
+                    info!("SampleProvider::SetUsageScenario - 包装系统密码提供程序");
+                    Some(provider)
+                }
+                Err(e) => {
+                    warn!("SampleProvider::SetUsageScenario - 无法包装系统密码提供程序，显示本程序的磁贴: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
         let mut inner = self.inner.lock().unwrap();
         inner.usage_scenario = cpus; // 保存使用场景
+        inner.cred_ui_flags = if cpus == CPUS_CREDUI { CREDUIWIN_FLAGS(dwflags) } else { CREDUIWIN_FLAGS(0) };
+        inner.wrapped = wrapped;
         Ok(())
     }
 
-    /// 设置序列化的凭据信息（用于预填充凭据，这里空实现）
-    /// _pcpcs: 序列化的凭据数据
-    fn SetSerialization(&self, _pcpcs: *const CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION) -> windows_core::Result<()> {
-        info!("SampleProvider::SetSerialization - 空实现");
+    /// 设置序列化的凭据信息，CredUI 的调用方用它指定认证包或预填用户名
+    /// pcpcs: 序列化的凭据数据
+    fn SetSerialization(&self, pcpcs: *const CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION) -> windows_core::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(wrapped) = &inner.wrapped {
+            // 例如远程桌面传入的凭据，交给系统密码提供程序处理
+            return unsafe { wrapped.SetSerialization(pcpcs) };
+        }
+        if inner.usage_scenario != CPUS_CREDUI || pcpcs.is_null() {
+            info!("SampleProvider::SetSerialization - 忽略");
+            return Ok(());
+        }
+        // 只记录认证包，预填的用户名由磁贴对应的账户决定
+        let package = unsafe { (*pcpcs).ulAuthenticationPackage };
+        info!("SampleProvider::SetSerialization - CredUI 指定认证包: {}", package);
+        inner.serialization_package = Some(package);
         Ok(())
     }
 
@@ -108,10 +229,20 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
         let mut inner = self.inner.lock().unwrap();
         inner.events = pcpe.clone(); // 保存事件接口
         inner.advise_context = upadvisecontext; // 保存上下文ID
+        // 系统密码提供程序直接使用同一个事件接口，它通知磁贴变化时 LogonUI 重新枚举本程序
+        if let Some(wrapped) = &inner.wrapped {
+            unsafe { wrapped.Advise(pcpe.as_ref(), upadvisecontext)?; }
+        }
+
+        if inner.package_rejected() {
+            info!("SampleProvider::Advise - 调用方不接受 Negotiate 认证包，不启动管道监听");
+            return Ok(());
+        }
 
-        // 启动管道监听，传入系统事件接口
+        // 启动管道监听，传入系统事件接口；CredUI 提示框由这里通知软件开始识别
         if let Some(events) = &inner.events {
-            inner.listener = Some(CPipeListener::start(events.clone(), upadvisecontext, inner.shared_creds.clone()));
+            let prompt = inner.usage_scenario == CPUS_CREDUI;
+            inner.listener = Some(CPipeListener::start(events.clone(), upadvisecontext, inner.shared_creds.clone(), prompt));
         }
 
         Ok(())
@@ -123,12 +254,21 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
         let mut inner = self.inner.lock().unwrap();
         inner.events = None; // 清除事件接口
         inner.advise_context = 0; // 重置上下文ID
+        if let Some(wrapped) = &inner.wrapped {
+            unsafe { wrapped.UnAdvise()?; }
+        }
         Ok(())
     }
 
     /// 获取字段描述符的数量
     fn GetFieldDescriptorCount(&self) -> windows_core::Result<u32> {
-        let count = 2; // 我们定义了2个字段：图标和文本
+        if let Some(wrapped) = &self.inner.lock().unwrap().wrapped {
+            // 系统密码提供程序的字段，加上追加的状态文字
+            let count = unsafe { wrapped.GetFieldDescriptorCount()? } + 1;
+            info!("SampleProvider::GetFieldDescriptorCount - 包装模式字段数量: {}", count);
+            return Ok(count);
+        }
+        let count = FIELD_COUNT; // 图标、文本、状态文字，以及识别失败后使用的密码框、提交按钮和重新识别链接
         info!("SampleProvider::GetFieldDescriptorCount - 字段数量: {}", count);
         Ok(count)
     }
@@ -137,42 +277,24 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
     /// dwindex: 字段索引
     fn GetFieldDescriptorAt(&self, dwindex: u32) -> windows_core::Result<*mut CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR> {
         info!("SampleProvider::GetFieldDescriptorAt - 获取字段 {} 的描述符", dwindex);
-        unsafe {
-            // 分配字段描述符的内存（使用CoTaskMemAlloc，系统会负责释放）
-            let size = std::mem::size_of::<CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR>();
-            let ptr = windows::Win32::System::Com::CoTaskMemAlloc(size) as *mut CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR;
-            if ptr.is_null() {
-                error!("SampleProvider::GetFieldDescriptorAt - 内存分配失败");
-                return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+        if let Some(wrapped) = &self.inner.lock().unwrap().wrapped {
+            let wrapped_count = unsafe { wrapped.GetFieldDescriptorCount()? };
+            if dwindex < wrapped_count {
+                return unsafe { wrapped.GetFieldDescriptorAt(dwindex) };
             }
-    
-            // 根据索引设置字段类型和标签
-            let (ft, label) = match dwindex {
-                0 => (CPFT_TILE_IMAGE, "框架图标"),  // 字段0: 图标
-                1 => (CPFT_LARGE_TEXT, "WinLogon基础框架加载成功！"),  // 字段1: 文本
-                _ => {
-                    error!("SampleProvider::GetFieldDescriptorAt - 无效的字段索引: {}", dwindex);
-                    return Err(windows::Win32::Foundation::E_INVALIDARG.into());
-                }
-            };
-    
-            // 转换标签为UTF-16并分配内存
-            let label_u16: Vec<u16> = label.encode_utf16().chain(Some(0)).collect();
-            let label_ptr = windows::Win32::System::Com::CoTaskMemAlloc(label_u16.len() * 2) as *mut u16;
-            if label_ptr.is_null() {
-                error!("SampleProvider::GetFieldDescriptorAt - 标签内存分配失败");
-                windows::Win32::System::Com::CoTaskMemFree(Some(ptr as *mut _)); // 释放之前分配的内存
-                return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+            if dwindex == wrapped_count {
+                return alloc_field_descriptor(dwindex, CPFT_SMALL_TEXT, "面容解锁状态"); // 追加在系统字段之后的状态文字
             }
-            std::ptr::copy_nonoverlapping(label_u16.as_ptr(), label_ptr, label_u16.len());
-    
-            // 设置字段描述符的属性
-            (*ptr).dwFieldID = dwindex;
-            (*ptr).cpft = ft;
-            (*ptr).pszLabel = PWSTR(label_ptr);
-    
-            Ok(ptr)
+            error!("SampleProvider::GetFieldDescriptorAt - 无效的字段索引: {}", dwindex);
+            return Err(windows::Win32::Foundation::E_INVALIDARG.into());
         }
+
+        // 根据索引设置字段类型和标签
+        let Some(&(ft, label)) = FIELD_DESCRIPTORS.get(dwindex as usize) else {
+            error!("SampleProvider::GetFieldDescriptorAt - 无效的字段索引: {}", dwindex);
+            return Err(windows::Win32::Foundation::E_INVALIDARG.into());
+        };
+        alloc_field_descriptor(dwindex, ft, label)
     }
 
     /// 获取凭据的数量和默认凭据
@@ -186,7 +308,17 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
         pbautologonwithdefault: *mut BOOL
     ) -> windows_core::Result<()> {
         info!("SampleProvider::GetCredentialCount - 获取凭据数量");
-        let inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(wrapped) = inner.wrapped.clone() {
+            let (count, default, autologon) = inner.enumerate_wrapped(&wrapped)?;
+            unsafe {
+                *pdwcount = count;
+                *pdwdefault = default;
+                *pbautologonwithdefault = BOOL::from(autologon);
+            }
+            info!("SampleProvider::GetCredentialCount - 包装模式凭据数量: {}，默认索引: {}，自动登录: {}", count, default, autologon);
+            return Ok(());
+        }
         let mut show_tile = true;
         if let Ok(result) = read_facewinunlock_registry("SHOW_TILE") {
             if result.as_str() == "0" {
@@ -198,23 +330,44 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
 
         info!( "是否显示图标: {}", show_tile);
 
-        unsafe {
-            // 如果管道已经收到了数据，告诉系统我们要自动登录
-            if let Some(l) = &inner.listener {
-                if l.is_unlocked.load(Ordering::SeqCst) {
-                    *pdwcount = 1;
-                    *pdwdefault = 0;
-                    *pbautologonwithdefault = BOOL::from(true); // 触发自动登录
+        // 每个录入了面容的账户一个磁贴，软件没有发布账户列表时只有一个不属于任何账户的磁贴
+        let mut tiles: Vec<Option<TileAccount>> = load_tile_accounts(inner.user_sids.as_deref()).into_iter().map(Some).collect();
+        if tiles.is_empty() {
+            tiles.push(None);
+        }
+        // 调用方只接受其他认证包的凭据，不显示磁贴
+        if inner.package_rejected() {
+            tiles.clear();
+        }
+
+        let unlocked = inner.listener.as_ref().is_some_and(|l| l.is_unlocked.load(Ordering::SeqCst));
+        let (count, default, autologon) = if unlocked {
+            // 管道已经收到了凭据，在识别出的账户的磁贴上自动登录
+            let username = inner.shared_creds.lock().unwrap().username.clone();
+            let index = match tiles.iter().position(|tile| SampleCredential::accepts(tile.as_ref(), &username)) {
+                Some(index) => index,
+                None => {
+                    // 账户列表过期，没有这个账户的磁贴，临时加一个不属于任何账户的磁贴登录
+                    warn!("账户列表中没有 {}，使用不属于任何账户的磁贴登录", username);
+                    tiles.push(None);
+                    tiles.len() - 1
                 }
-            } else {
-                *pdwcount = if show_tile { 1 } else { 0 };
-                // 初始化默认凭据索引和自动登录开关
-                *pdwdefault = 0;
-                // 默认关闭自动登录
-                *pbautologonwithdefault = BOOL::from(false);
-            }
+            };
+            (tiles.len() as u32, index as u32, true)
+        } else if show_tile {
+            // 不抢占默认选中的磁贴
+            (tiles.len() as u32, CREDENTIAL_PROVIDER_NO_DEFAULT, false)
+        } else {
+            (0, CREDENTIAL_PROVIDER_NO_DEFAULT, false)
+        };
+        inner.tiles = tiles;
+
+        unsafe {
+            *pdwcount = count;
+            *pdwdefault = default;
+            *pbautologonwithdefault = BOOL::from(autologon); // 收到凭据时触发自动登录
         }
-        info!("SampleProvider::GetCredentialCount - 凭据数量: 1，默认索引: 0");
+        info!("SampleProvider::GetCredentialCount - 凭据数量: {}，默认索引: {}，自动登录: {}", count, default, autologon);
         Ok(())
     }
 
@@ -222,10 +375,22 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
     /// dwindex: 凭据索引
     fn GetCredentialAt(&self, dwindex: u32) -> windows_core::Result<ICredentialProviderCredential> {
         info!("SampleProvider::GetCredentialAt - 获取凭据，索引: {}", dwindex);
-        if dwindex == 0 {
-            let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock().unwrap();
+        if inner.wrapped.is_some() {
+            return inner.wrapped_credentials.get(dwindex as usize).cloned().ok_or_else(|| {
+                error!("SampleProvider::GetCredentialAt - 无效的凭据索引: {}", dwindex);
+                windows::core::Error::from_hresult(windows::Win32::Foundation::E_INVALIDARG)
+            });
+        }
+        if let Some(account) = inner.tiles.get(dwindex as usize) {
             // 创建凭据实例并转换为接口返回，并传递收到的用户名和密码
-            let cred = SampleCredential::new(inner.shared_creds.clone(), inner.auth_package_id);
+            let cred: ICredentialProviderCredential2 = SampleCredential::new(
+                inner.shared_creds.clone(),
+                inner.auth_package_id,
+                account.clone(),
+                inner.usage_scenario,
+                inner.pack_flags(),
+            ).into_interface();
             Ok(cred.into())
         } else {
             error!("SampleProvider::GetCredentialAt - 无效的凭据索引: {}", dwindex);
@@ -234,6 +399,76 @@ impl ICredentialProvider_Impl for SampleProvider_Impl {
     }
 }
 
+/// 接收 LogonUI 显示的用户列表
+/// 实现这个接口后 LogonUI 把本程序当作 V2 凭据提供程序，按 GetUserSid 把磁贴放到对应用户的头像下
+impl ICredentialProviderSetUserArray_Impl for SampleProvider_Impl {
+    fn SetUserArray(&self, users: windows_core::Ref<ICredentialProviderUserArray>) -> windows_core::Result<()> {
+        let users = users.ok()?;
+        let mut sids = Vec::new();
+        unsafe {
+            for index in 0..users.GetCount()? {
+                let sid = users.GetAt(index)?.GetSid()?;
+                sids.push(sid.to_string().unwrap_or_default());
+                windows::Win32::System::Com::CoTaskMemFree(Some(sid.0 as *const _));
+            }
+        }
+        info!("SampleProvider::SetUserArray - LogonUI 显示 {} 个用户", sids.len());
+        let mut inner = self.inner.lock().unwrap();
+        // 系统密码提供程序同样按用户列表显示磁贴
+        if let Some(set_user_array) = inner.wrapped.as_ref().and_then(|wrapped| wrapped.cast::<ICredentialProviderSetUserArray>().ok()) {
+            unsafe { set_user_array.SetUserArray(users)?; }
+        }
+        inner.user_sids = Some(sids);
+        Ok(())
+    }
+}
+
+// 分配字段描述符，内存和标签都使用 CoTaskMemAlloc 分配，由系统负责释放
+fn alloc_field_descriptor(dwindex: u32, ft: CREDENTIAL_PROVIDER_FIELD_TYPE, label: &str) -> windows_core::Result<*mut CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR> {
+    unsafe {
+        let size = std::mem::size_of::<CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR>();
+        let ptr = windows::Win32::System::Com::CoTaskMemAlloc(size) as *mut CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR;
+        if ptr.is_null() {
+            error!("SampleProvider::GetFieldDescriptorAt - 内存分配失败");
+            return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+        }
+
+        // 转换标签为UTF-16并分配内存
+        let label_u16: Vec<u16> = label.encode_utf16().chain(Some(0)).collect();
+        let label_ptr = windows::Win32::System::Com::CoTaskMemAlloc(label_u16.len() * 2) as *mut u16;
+        if label_ptr.is_null() {
+            error!("SampleProvider::GetFieldDescriptorAt - 标签内存分配失败");
+            windows::Win32::System::Com::CoTaskMemFree(Some(ptr as *mut _)); // 释放之前分配的内存
+            return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+        }
+        std::ptr::copy_nonoverlapping(label_u16.as_ptr(), label_ptr, label_u16.len());
+
+        // 写入完整的字段描述符，未使用的 guidFieldType 置零
+        ptr.write(CREDENTIAL_PROVIDER_FIELD_DESCRIPTOR {
+            dwFieldID: dwindex,
+            cpft: ft,
+            pszLabel: PWSTR(label_ptr),
+            ..Default::default()
+        });
+        Ok(ptr)
+    }
+}
+
+// 读取软件发布的账户列表，LogonUI 给出了用户列表时只保留其中的账户
+fn load_tile_accounts(user_sids: Option<&[String]>) -> Vec<TileAccount> {
+    let accounts = match read_facewinunlock_registry(ACCOUNTS_REG_VALUE) {
+        Ok(text) => parse_accounts(&text),
+        Err(e) => {
+            info!("没有读取到账户列表: {:?}", e);
+            return Vec::new();
+        }
+    };
+    match user_sids {
+        Some(sids) => accounts.into_iter().filter(|account| sids.iter().any(|sid| sid.eq_ignore_ascii_case(&account.sid))).collect(),
+        None => accounts,
+    }
+}
+
 // 获取Negotiate AuthPackage ID
 pub fn retrieve_negotiate_auth_package() -> windows_core::Result<u32> {
     info!("正在获取 AuthPackage ID...");
@@ -0,0 +1,123 @@
+use std::{f32::consts::TAU, ffi::c_void};
+use facewinunlock_protocol::StatusIcon;
+use windows::Win32::Graphics::Gdi::{CreateDIBSection, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP};
+
+/// 磁贴图片的边长，LogonUI 会按显示大小缩放
+pub const IMAGE_SIZE: i32 = 192;
+
+// 颜色都是预乘 alpha 的 0xAARRGGBB，和 32 位 DIB 的 BGRA 内存布局一致
+const TRANSPARENT: u32 = 0x0000_0000;
+const GLYPH: u32 = 0xFFFF_FFFF;
+// 进度环未完成的部分
+const TRACK: u32 = 0x5555_5555;
+// 进度环的宽度和它与圆形底色之间的间隙
+const RING_WIDTH: f32 = 10.0;
+const RING_GAP: f32 = 6.0;
+// 图标笔画的粗细，相对于圆形底色的半径
+const STROKE: f32 = 0.12;
+
+// 每种状态的底色
+fn icon_color(icon: StatusIcon) -> u32 {
+    match icon {
+        StatusIcon::None => 0xFF5A_6A7A,
+        StatusIcon::Scanning => 0xFF2D_7DD2,
+        StatusIcon::Success => 0xFF2E_A043,
+        StatusIcon::Warning => 0xFFE0_A01E,
+        StatusIcon::Error => 0xFFD1_3B3B,
+    }
+}
+
+// 点 (x, y) 到线段的距离
+fn segment_distance(x: f32, y: f32, (x1, y1): (f32, f32), (x2, y2): (f32, f32)) -> f32 {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let t = (((x - x1) * dx + (y - y1) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    let (px, py) = (x1 + t * dx - x, y1 + t * dy - y);
+    (px * px + py * py).sqrt()
+}
+
+fn on_segment(x: f32, y: f32, from: (f32, f32), to: (f32, f32)) -> bool {
+    segment_distance(x, y, from, to) <= STROKE / 2.0
+}
+
+fn in_circle(x: f32, y: f32, (cx, cy): (f32, f32), radius: f32) -> bool {
+    (x - cx).powi(2) + (y - cy).powi(2) <= radius * radius
+}
+
+// (x, y) 是相对于圆形底色的坐标，圆心为原点，半径为 1，y 轴向下
+fn in_glyph(icon: StatusIcon, x: f32, y: f32) -> bool {
+    let r = (x * x + y * y).sqrt();
+    match icon {
+        // 笑脸：两只眼睛和下半圆的嘴
+        StatusIcon::None => {
+            in_circle(x, y, (-0.3, -0.2), 0.1)
+                || in_circle(x, y, (0.3, -0.2), 0.1)
+                || (y > 0.15 && (r - 0.45).abs() <= STROKE / 2.0)
+        }
+        // 镜头：圆环和中间的圆点
+        StatusIcon::Scanning => (r - 0.5).abs() <= STROKE / 2.0 || r <= 0.15,
+        // 对勾
+        StatusIcon::Success => on_segment(x, y, (-0.45, 0.0), (-0.1, 0.35)) || on_segment(x, y, (-0.1, 0.35), (0.5, -0.3)),
+        // 感叹号
+        StatusIcon::Warning => on_segment(x, y, (0.0, -0.5), (0.0, 0.15)) || in_circle(x, y, (0.0, 0.42), 0.09),
+        // 叉号
+        StatusIcon::Error => on_segment(x, y, (-0.4, -0.4), (0.4, 0.4)) || on_segment(x, y, (-0.4, 0.4), (0.4, -0.4)),
+    }
+}
+
+/// 绘制磁贴图片：圆形底色和中间的图标表示状态，外圈从正上方顺时针显示识别进度
+/// 返回 IMAGE_SIZE * IMAGE_SIZE 个像素，从上到下逐行排列
+pub fn render(icon: StatusIcon, progress: Option<u8>) -> Vec<u32> {
+    let size = IMAGE_SIZE as usize;
+    let center = IMAGE_SIZE as f32 / 2.0;
+    let outer = center - 2.0;
+    let inner = outer - RING_WIDTH - RING_GAP;
+    let color = icon_color(icon);
+
+    let mut pixels = vec![TRANSPARENT; size * size];
+    for y in 0..size {
+        for x in 0..size {
+            let px = x as f32 + 0.5 - center;
+            let py = y as f32 + 0.5 - center;
+            let dist = (px * px + py * py).sqrt();
+            pixels[y * size + x] = if dist <= inner {
+                if in_glyph(icon, px / inner, py / inner) { GLYPH } else { color }
+            } else if dist >= outer - RING_WIDTH && dist <= outer {
+                match progress {
+                    Some(percent) => {
+                        // 正上方为 0，顺时针增加到 1
+                        let turn = (px.atan2(-py) / TAU + 1.0) % 1.0;
+                        if turn * 100.0 < percent as f32 { color } else { TRACK }
+                    }
+                    None => TRANSPARENT,
+                }
+            } else {
+                TRANSPARENT
+            };
+        }
+    }
+    pixels
+}
+
+/// 创建磁贴图片的位图，调用方负责释放
+pub fn create_bitmap(icon: StatusIcon, progress: Option<u8>) -> windows::core::Result<HBITMAP> {
+    let pixels = render(icon, progress);
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: IMAGE_SIZE,
+            // 高度为负表示从上到下的位图，和 render 的像素顺序一致
+            biHeight: -IMAGE_SIZE,
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bits: *mut c_void = std::ptr::null_mut();
+    unsafe {
+        let bitmap = CreateDIBSection(None, &info, DIB_RGB_COLORS, &mut bits, None, 0)?;
+        std::ptr::copy_nonoverlapping(pixels.as_ptr(), bits as *mut u32, pixels.len());
+        Ok(bitmap)
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Mutex;
+use facewinunlock_protocol::StatusIcon;
+use windows::Win32::{Graphics::Gdi::{DeleteObject, HBITMAP}, UI::Shell::{ICredentialProviderCredential, ICredentialProviderCredentialEvents}};
+use windows_core::PCWSTR;
+use crate::CStatusImage::create_bitmap;
+
+/// 磁贴图片所在的字段ID，显示状态图标和识别进度
+pub const IMAGE_FIELD_ID: u32 = 0;
+/// 状态文字所在的字段ID，字段0是图标，字段1是标题
+pub const STATUS_FIELD_ID: u32 = 2;
+
+// 凭据的事件接口和凭据本身，SetFieldString 需要两者一起传入
+#[derive(Clone)]
+struct AttachedCredential {
+    events: ICredentialProviderCredentialEvents,
+    credential: ICredentialProviderCredential,
+    // 包装系统密码磁贴时，状态文字排在系统字段之后，并且没有可以绘制的图片
+    status_field: u32,
+    image_field: Option<u32>,
+}
+// 管道线程需要通过它刷新磁贴，声明这是安全的
+unsafe impl Send for AttachedCredential {}
+unsafe impl Sync for AttachedCredential {}
+
+// 最近一次交给 SetFieldBitmap 的位图，换成新位图或释放 TileStatus 时删除
+struct OwnedBitmap(HBITMAP);
+unsafe impl Send for OwnedBitmap {}
+
+impl Drop for OwnedBitmap {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = DeleteObject(self.0.into());
+        }
+    }
+}
+
+// 软件最近一次发送的状态
+#[derive(Clone, Default)]
+struct State {
+    text: String,
+    icon: StatusIcon,
+    progress: Option<u8>,
+}
+
+/// 磁贴上的状态，软件通过管道发送的提示（例如动作验证、冷却倒计时）显示在这里
+/// 文字显示在状态字段，图标和识别进度绘制在磁贴图片上
+pub struct TileStatus {
+    state: Mutex<State>,
+    // 凭据 Advise 之后才能主动刷新磁贴，每个账户的磁贴各有一个凭据
+    attached: Mutex<Vec<AttachedCredential>>,
+    bitmap: Mutex<Option<OwnedBitmap>>,
+}
+
+impl TileStatus {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            attached: Mutex::new(Vec::new()),
+            bitmap: Mutex::new(None),
+        }
+    }
+
+    /// 当前的状态文字，GetStringValue 时使用
+    pub fn text(&self) -> String {
+        self.state.lock().unwrap().text.clone()
+    }
+
+    /// 按当前状态绘制磁贴图片，GetBitmapValue 时使用，位图由调用方释放
+    pub fn bitmap(&self) -> windows::core::Result<HBITMAP> {
+        let state = self.state.lock().unwrap().clone();
+        create_bitmap(state.icon, state.progress)
+    }
+
+    /// 凭据 Advise 时记录事件接口，以及状态文字和图片所在的字段
+    pub fn attach(&self, events: ICredentialProviderCredentialEvents, credential: ICredentialProviderCredential, status_field: u32, image_field: Option<u32>) {
+        let mut attached = self.attached.lock().unwrap();
+        attached.retain(|item| item.credential != credential);
+        attached.push(AttachedCredential { events, credential, status_field, image_field });
+    }
+
+    /// 凭据 UnAdvise 时清除事件接口
+    pub fn detach(&self, credential: &ICredentialProviderCredential) {
+        self.attached.lock().unwrap().retain(|item| item.credential != *credential);
+    }
+
+    /// 清除状态，恢复默认的磁贴
+    pub fn clear(&self) {
+        self.update("", StatusIcon::None, None);
+    }
+
+    /// 更新状态，立即刷新所有已经 Advise 的磁贴的文字和图片
+    pub fn update(&self, text: &str, icon: StatusIcon, progress: Option<u8>) {
+        let changed_image = {
+            let mut state = self.state.lock().unwrap();
+            let changed_image = state.icon != icon || state.progress != progress;
+            *state = State { text: text.to_string(), icon, progress };
+            changed_image
+        };
+
+        // 先复制出接口再调用，避免 LogonUI 回调 GetStringValue 时死锁
+        let attached = self.attached.lock().unwrap().clone();
+        if attached.is_empty() {
+            info!("磁贴还没有显示，状态稍后由 GetStringValue 和 GetBitmapValue 读取: {}", text);
+            return;
+        }
+        let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+        for item in &attached {
+            let result = unsafe {
+                item.events.SetFieldString(&item.credential, item.status_field, PCWSTR(wide.as_ptr()))
+            };
+            if let Err(e) = result {
+                warn!("刷新磁贴状态文字失败: {:?}", e);
+            }
+        }
+
+        // 图标和进度都没变时不重绘图片
+        if !changed_image {
+            return;
+        }
+        let bitmap = match create_bitmap(icon, progress) {
+            Ok(bitmap) => bitmap,
+            Err(e) => {
+                warn!("绘制磁贴图片失败: {:?}", e);
+                return;
+            }
+        };
+        for item in &attached {
+            let Some(image_field) = item.image_field else {
+                continue;
+            };
+            let result = unsafe {
+                item.events.SetFieldBitmap(&item.credential, image_field, bitmap)
+            };
+            if let Err(e) = result {
+                warn!("刷新磁贴图片失败: {:?}", e);
+            }
+        }
+        // LogonUI 可能在下次重绘前仍然引用这张位图，保留到换成新位图为止
+        *self.bitmap.lock().unwrap() = Some(OwnedBitmap(bitmap));
+    }
+}
@@ -0,0 +1,34 @@
+use std::{sync::{Condvar, Mutex}, time::Duration};
+
+/// 用户唤醒登录界面的信号，磁贴被选中时触发，管道线程据此通知软件重新识别
+pub struct WakeSignal {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl WakeSignal {
+    pub fn new() -> Self {
+        Self {
+            woken: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// 磁贴被选中（按键、移动鼠标后显示磁贴或用户点击磁贴）时调用
+    pub fn notify(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+
+    /// 清除之前的唤醒，只响应之后的
+    pub fn reset(&self) {
+        *self.woken.lock().unwrap() = false;
+    }
+
+    /// 最多等待 timeout，返回期间是否被唤醒
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let guard = self.woken.lock().unwrap();
+        let (mut woken, _) = self.condvar.wait_timeout_while(guard, timeout, |woken| !*woken).unwrap();
+        std::mem::replace(&mut *woken, false)
+    }
+}
@@ -0,0 +1,425 @@
+// 包装系统密码提供程序的凭据：磁贴和密码框由系统提供，面容解锁的状态文字追加在系统字段之后
+use std::{ffi::c_void, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
+use windows::Win32::{
+    Foundation::{E_INVALIDARG, HWND, NTSTATUS, STATUS_SUCCESS, S_FALSE}, Graphics::Gdi::HBITMAP, Security::Credentials::CRED_PACK_FLAGS, UI::Shell::{
+        ICredentialProviderCredential, ICredentialProviderCredential2, ICredentialProviderCredential2_Impl, ICredentialProviderCredential2_Vtbl, ICredentialProviderCredentialEvents, ICredentialProviderCredentialEvents_Impl, ICredentialProviderCredential_Impl, CPFIS_NONE, CPFS_DISPLAY_IN_SELECTED_TILE, CPFS_HIDDEN, CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION, CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE, CREDENTIAL_PROVIDER_FIELD_STATE, CREDENTIAL_PROVIDER_GET_SERIALIZATION_RESPONSE, CREDENTIAL_PROVIDER_STATUS_ICON
+    }
+};
+use facewinunlock_protocol::TileAccount;
+use windows_core::{implement, Interface, IUnknownImpl, Ref, BOOL, HRESULT, PCWSTR, PWSTR};
+use crate::{dll_add_ref, dll_release, CSampleCredential::{install_user_sid_vtable, serialize_credentials, user_sid_vtable, user_sid_with}, SharedCredentials};
+
+/// 包装的凭据，代表系统密码提供程序的一个用户磁贴
+/// 编号小于 status_field 的字段都属于系统凭据，原样转发；status_field 是面容解锁的状态文字
+#[implement(ICredentialProviderCredential2)]
+pub struct WrappedCredential {
+    inner: ICredentialProviderCredential,
+    // 状态文字的字段ID，等于系统密码提供程序的字段数量
+    status_field: u32,
+    // 磁贴对应的录入了面容的账户，None 表示这个用户没有录入面容，只显示系统磁贴
+    account: Option<TileAccount>,
+    events: Mutex<Option<ICredentialProviderCredentialEvents>>,
+    // 交给系统凭据的事件接口，UnAdvise 时释放
+    inner_events: Mutex<Option<ICredentialProviderCredentialEvents>>,
+    shared_creds: Arc<Mutex<SharedCredentials>>,
+    auth_package_id: u32,
+    // 最近一次提交的是管道收到的凭据，ReportResult 据此把结果回传给软件
+    face_submit: AtomicBool,
+}
+
+impl WrappedCredential {
+    /// 包装系统密码提供程序的凭据
+    pub fn new(
+        inner: ICredentialProviderCredential,
+        status_field: u32,
+        account: Option<TileAccount>,
+        shared_creds: Arc<Mutex<SharedCredentials>>,
+        auth_package_id: u32
+    ) -> Self {
+        info!("WrappedCredential::new - 包装系统凭据，账户: {:?}", account.as_ref().map(|a| &a.user_name));
+        dll_add_ref(); // 增加DLL引用计数
+        Self {
+            inner: inner,
+            status_field: status_field,
+            account: account,
+            events: Mutex::new(None),
+            inner_events: Mutex::new(None),
+            shared_creds: shared_creds,
+            auth_package_id: auth_package_id,
+            face_submit: AtomicBool::new(false),
+        }
+    }
+
+    /// 转换为返回给 LogonUI 的接口，GetUserSid 由 USER_SID_VTABLE 中的 get_user_sid 处理
+    pub fn into_interface(self) -> ICredentialProviderCredential2 {
+        unsafe { install_user_sid_vtable(self.into(), &USER_SID_VTABLE) }
+    }
+
+    /// 系统凭据对应用户的 SID，取不到时返回 None（例如“其他用户”磁贴）
+    pub fn user_sid(inner: &ICredentialProviderCredential) -> Option<String> {
+        let inner = inner.cast::<ICredentialProviderCredential2>().ok()?;
+        unsafe {
+            let sid = inner.GetUserSid().ok()?;
+            if sid.is_null() {
+                return None;
+            }
+            let result = sid.to_string().ok();
+            windows::Win32::System::Com::CoTaskMemFree(Some(sid.0 as *const _));
+            result
+        }
+    }
+
+    /// 字段是否属于系统凭据，系统凭据的字段编号从 0 开始，状态文字排在它们之后
+    pub fn forwards_to_inner(dwfieldid: u32, status_field: u32) -> bool {
+        dwfieldid < status_field
+    }
+
+    /// 管道收到的凭据是否属于这个磁贴的用户，没有录入面容的用户不使用面容解锁
+    pub fn accepts(account: Option<&TileAccount>, username: &str) -> bool {
+        account.is_some_and(|account| account.matches(username))
+    }
+}
+
+/// 与面容解锁的凭据相同，windows-rs 生成的 GetUserSid 无法原样返回系统凭据的 S_FALSE，换成 get_user_sid
+static USER_SID_VTABLE: ICredentialProviderCredential2_Vtbl = user_sid_vtable::<WrappedCredential_Impl>(get_user_sid);
+
+/// 原样返回系统凭据的 HRESULT 和 SID，“其他用户”磁贴仍然是 S_FALSE 和空 SID
+unsafe extern "system" fn get_user_sid(this: *mut c_void, sid: *mut PWSTR) -> HRESULT {
+    unsafe {
+        user_sid_with(this, sid, |cred: &WrappedCredential, sid| {
+            match cred.inner.cast::<ICredentialProviderCredential2>() {
+                Ok(inner) => (inner.vtable().GetUserSid)(inner.as_raw(), sid),
+                // 旧版本的系统凭据不属于任何用户
+                Err(_) => S_FALSE,
+            }
+        })
+    }
+}
+
+impl WrappedCredential_Impl {
+    // 事件通知和磁贴状态使用的凭据接口
+    fn credential_interface(&self) -> ICredentialProviderCredential {
+        self.to_interface::<ICredentialProviderCredential2>().into()
+    }
+
+    // 字段是否属于系统凭据
+    fn is_inner_field(&self, dwfieldid: u32) -> bool {
+        WrappedCredential::forwards_to_inner(dwfieldid, self.status_field)
+    }
+}
+
+impl Drop for WrappedCredential {
+    fn drop(&mut self) {
+        info!("WrappedCredential::drop - 销毁包装的凭据");
+        dll_release(); // 减少DLL引用计数，与new中的dll_add_ref()对应
+    }
+}
+
+impl ICredentialProviderCredential_Impl for WrappedCredential_Impl {
+    /// 系统凭据的事件经过 WrappedEvents 转发，LogonUI 看到的是包装后的凭据；状态文字直接使用 LogonUI 的事件接口
+    fn Advise(&self, pcpce: windows_core::Ref<ICredentialProviderCredentialEvents>) -> windows_core::Result<()> {
+        info!("WrappedCredential::Advise - 注册事件通知");
+        let inner_events: Option<ICredentialProviderCredentialEvents> = pcpce
+            .as_ref()
+            .map(|events| WrappedEvents::new(events.clone(), self.credential_interface()).into());
+        unsafe { self.inner.Advise(inner_events.as_ref())?; }
+        *self.inner_events.lock().unwrap() = inner_events;
+        let mut events = self.events.lock().unwrap();
+        *events = pcpce.clone();
+        // 只在录入了面容的用户磁贴上显示状态文字，系统磁贴没有可以绘制的图标字段
+        if let (Some(events), Some(_)) = (events.clone(), &self.account) {
+            let tile_status = self.shared_creds.lock().unwrap().tile_status.clone();
+            tile_status.attach(events, self.credential_interface(), self.status_field, None);
+        }
+        Ok(())
+    }
+
+    fn UnAdvise(&self) -> windows_core::Result<()> {
+        info!("WrappedCredential::UnAdvise - 取消事件通知");
+        *self.events.lock().unwrap() = None;
+        self.shared_creds.lock().unwrap().tile_status.detach(&self.credential_interface());
+        let result = unsafe { self.inner.UnAdvise() };
+        // 事件接口持有包装的凭据，必须在这里释放，否则两者互相引用无法销毁
+        *self.inner_events.lock().unwrap() = None;
+        result
+    }
+
+    fn SetSelected(&self) -> windows_core::Result<BOOL> {
+        info!("WrappedCredential::SetSelected - 磁贴被选中");
+        if self.account.is_some() {
+            // 与面容解锁的磁贴相同，用户已经在登录界面前，识别失败后等待的软件可以重新识别
+            self.shared_creds.lock().unwrap().wake.notify();
+        }
+        unsafe { self.inner.SetSelected() }
+    }
+
+    fn SetDeselected(&self) -> windows_core::Result<()> {
+        unsafe { self.inner.SetDeselected() }
+    }
+
+    fn GetFieldState(
+        &self,
+        dwfieldid: u32,
+        pcpfs: *mut CREDENTIAL_PROVIDER_FIELD_STATE,
+        pcpfis: *mut CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE
+    ) -> windows_core::Result<()> {
+        if self.is_inner_field(dwfieldid) {
+            return unsafe { self.inner.GetFieldState(dwfieldid, pcpfs, pcpfis) };
+        }
+        if dwfieldid != self.status_field {
+            error!("WrappedCredential::GetFieldState - 无效的字段ID: {}", dwfieldid);
+            return Err(E_INVALIDARG.into());
+        }
+        // 状态文字显示在选中的用户磁贴上，密码框的下方
+        let state = if self.account.is_some() { CPFS_DISPLAY_IN_SELECTED_TILE } else { CPFS_HIDDEN };
+        unsafe {
+            *pcpfs = state;
+            *pcpfis = CPFIS_NONE;
+        }
+        Ok(())
+    }
+
+    fn GetStringValue(&self, dwfieldid: u32) -> windows_core::Result<PWSTR> {
+        if self.is_inner_field(dwfieldid) {
+            return unsafe { self.inner.GetStringValue(dwfieldid) };
+        }
+        if dwfieldid != self.status_field {
+            return Err(E_INVALIDARG.into());
+        }
+        // 显示软件通过管道发送的提示
+        let status = self.shared_creds.lock().unwrap().tile_status.text();
+        unsafe {
+            let utf16: Vec<u16> = status.encode_utf16().chain(Some(0)).collect();
+            let ptr = windows::Win32::System::Com::CoTaskMemAlloc(utf16.len() * 2);
+            if ptr.is_null() {
+                error!("WrappedCredential::GetStringValue - 内存分配失败");
+                return Err(windows::Win32::Foundation::E_OUTOFMEMORY.into());
+            }
+            std::ptr::copy_nonoverlapping(utf16.as_ptr(), ptr as *mut u16, utf16.len());
+            Ok(PWSTR(ptr as *mut _))
+        }
+    }
+
+    fn GetBitmapValue(&self, dwfieldid: u32) -> windows_core::Result<HBITMAP> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.GetBitmapValue(dwfieldid) }
+    }
+
+    fn GetCheckboxValue(&self, dwfieldid: u32, pbchecked: *mut BOOL, ppszlabel: *mut PWSTR) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.GetCheckboxValue(dwfieldid, pbchecked, ppszlabel) }
+    }
+
+    fn GetSubmitButtonValue(&self, dwfieldid: u32) -> windows_core::Result<u32> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.GetSubmitButtonValue(dwfieldid) }
+    }
+
+    fn GetComboBoxValueCount(&self, dwfieldid: u32, pcitems: *mut u32, pdwselecteditem: *mut u32) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.GetComboBoxValueCount(dwfieldid, pcitems, pdwselecteditem) }
+    }
+
+    fn GetComboBoxValueAt(&self, dwfieldid: u32, dwitem: u32) -> windows_core::Result<PWSTR> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.GetComboBoxValueAt(dwfieldid, dwitem) }
+    }
+
+    fn SetStringValue(&self, dwfieldid: u32, psz: &PCWSTR) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.SetStringValue(dwfieldid, *psz) }
+    }
+
+    fn SetCheckboxValue(&self, dwfieldid: u32, bchecked: BOOL) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.SetCheckboxValue(dwfieldid, bchecked.as_bool()) }
+    }
+
+    fn SetComboBoxSelectedValue(&self, dwfieldid: u32, dwselecteditem: u32) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.SetComboBoxSelectedValue(dwfieldid, dwselecteditem) }
+    }
+
+    fn CommandLinkClicked(&self, dwfieldid: u32) -> windows_core::Result<()> {
+        if !self.is_inner_field(dwfieldid) {
+            return Err(E_INVALIDARG.into());
+        }
+        unsafe { self.inner.CommandLinkClicked(dwfieldid) }
+    }
+
+    /// 软件识别通过时提交管道收到的凭据，否则交给系统凭据提交用户输入的密码
+    fn GetSerialization(
+        &self,
+        pcpgsr: *mut CREDENTIAL_PROVIDER_GET_SERIALIZATION_RESPONSE,
+        pcpcs: *mut CREDENTIAL_PROVIDER_CREDENTIAL_SERIALIZATION,
+        ppszoptionalstatustext: *mut PWSTR,
+        pcpsioptionalstatusicon: *mut CREDENTIAL_PROVIDER_STATUS_ICON
+    ) -> windows_core::Result<()> {
+        let face = {
+            let creds = self.shared_creds.lock().unwrap();
+            if creds.is_ready && WrappedCredential::accepts(self.account.as_ref(), &creds.username) {
+                Some((creds.full_username(), creds.password.clone()))
+            } else {
+                None
+            }
+        };
+        self.face_submit.store(face.is_some(), Ordering::SeqCst);
+        match face {
+            Some((full_username, password)) => {
+                info!("WrappedCredential::GetSerialization - 提交面容解锁的凭据，用户名: {}", full_username);
+                unsafe { serialize_credentials(&full_username, &password, CRED_PACK_FLAGS(0), self.auth_package_id, pcpgsr, pcpcs) }
+            }
+            None => {
+                info!("WrappedCredential::GetSerialization - 由系统凭据提交");
+                unsafe { self.inner.GetSerialization(pcpgsr, pcpcs, ppszoptionalstatustext, pcpsioptionalstatusicon) }
+            }
+        }
+    }
+
+    /// 登录结果交给系统凭据显示提示，面容解锁提交的结果同时回传给软件
+    fn ReportResult(
+        &self,
+        ntsstatus: NTSTATUS,
+        ntssubstatus: NTSTATUS,
+        ppszoptionalstatustext: *mut PWSTR,
+        pcpsioptionalstatusicon: *mut CREDENTIAL_PROVIDER_STATUS_ICON
+    ) -> windows_core::Result<()> {
+        info!("WrappedCredential::ReportResult - 报告登录结果: {:#010x}, 子状态: {:#010x}", ntsstatus.0 as u32, ntssubstatus.0 as u32);
+        if self.face_submit.swap(false, Ordering::SeqCst) {
+            let mut creds = self.shared_creds.lock().unwrap();
+            if let Some(sender) = creds.result_sender.take() {
+                if sender.send((ntsstatus.0, ntssubstatus.0)).is_err() {
+                    warn!("WrappedCredential::ReportResult - 管道线程已退出，登录结果无法回传");
+                }
+            }
+            if ntsstatus != STATUS_SUCCESS {
+                // 清空错误凭据，用户可以直接在系统密码框中输入密码
+                creds.username.clear();
+                creds.password.clear();
+                creds.is_ready = false;
+            }
+        }
+        unsafe { self.inner.ReportResult(ntsstatus, ntssubstatus, ppszoptionalstatustext, pcpsioptionalstatusicon) }
+    }
+}
+
+impl ICredentialProviderCredential2_Impl for WrappedCredential_Impl {
+    /// 返回系统凭据的 SID，磁贴仍然显示在原来的用户头像下
+    /// LogonUI 通过 USER_SID_VTABLE 调用 get_user_sid，不经过这里
+    fn GetUserSid(&self) -> windows_core::Result<PWSTR> {
+        match self.inner.cast::<ICredentialProviderCredential2>() {
+            Ok(inner) => unsafe { inner.GetUserSid() },
+            Err(_) => Ok(PWSTR::null()),
+        }
+    }
+}
+
+/// 交给系统凭据的事件接口
+/// 系统凭据通知字段变化时传入的是它自己，LogonUI 不认识这个凭据，转发时换成包装后的凭据
+#[implement(ICredentialProviderCredentialEvents)]
+struct WrappedEvents {
+    events: ICredentialProviderCredentialEvents,
+    wrapper: ICredentialProviderCredential,
+}
+
+impl WrappedEvents {
+    fn new(events: ICredentialProviderCredentialEvents, wrapper: ICredentialProviderCredential) -> Self {
+        dll_add_ref(); // 增加DLL引用计数
+        Self { events, wrapper }
+    }
+}
+
+impl Drop for WrappedEvents {
+    fn drop(&mut self) {
+        dll_release(); // 减少DLL引用计数，与new中的dll_add_ref()对应
+    }
+}
+
+impl ICredentialProviderCredentialEvents_Impl for WrappedEvents_Impl {
+    fn SetFieldState(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, cpfs: CREDENTIAL_PROVIDER_FIELD_STATE) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldState(&self.wrapper, dwfieldid, cpfs) }
+    }
+
+    fn SetFieldInteractiveState(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, cpfis: CREDENTIAL_PROVIDER_FIELD_INTERACTIVE_STATE) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldInteractiveState(&self.wrapper, dwfieldid, cpfis) }
+    }
+
+    fn SetFieldString(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, psz: &PCWSTR) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldString(&self.wrapper, dwfieldid, *psz) }
+    }
+
+    fn SetFieldCheckbox(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, bchecked: BOOL, pszlabel: &PCWSTR) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldCheckbox(&self.wrapper, dwfieldid, bchecked.as_bool(), *pszlabel) }
+    }
+
+    fn SetFieldBitmap(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, hbmp: HBITMAP) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldBitmap(&self.wrapper, dwfieldid, hbmp) }
+    }
+
+    fn SetFieldComboBoxSelectedItem(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, dwselecteditem: u32) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldComboBoxSelectedItem(&self.wrapper, dwfieldid, dwselecteditem) }
+    }
+
+    fn DeleteFieldComboBoxItem(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, dwitem: u32) -> windows_core::Result<()> {
+        unsafe { self.events.DeleteFieldComboBoxItem(&self.wrapper, dwfieldid, dwitem) }
+    }
+
+    fn AppendFieldComboBoxItem(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, pszitem: &PCWSTR) -> windows_core::Result<()> {
+        unsafe { self.events.AppendFieldComboBoxItem(&self.wrapper, dwfieldid, *pszitem) }
+    }
+
+    fn SetFieldSubmitButton(&self, _pcpc: Ref<ICredentialProviderCredential>, dwfieldid: u32, dwadjacentto: u32) -> windows_core::Result<()> {
+        unsafe { self.events.SetFieldSubmitButton(&self.wrapper, dwfieldid, dwadjacentto) }
+    }
+
+    fn OnCreatingWindow(&self) -> windows_core::Result<HWND> {
+        unsafe { self.events.OnCreatingWindow() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CSampleCredential::{tests::{account, call_get_user_sid, credential}, FIELD_COUNT};
+    use windows::Win32::Foundation::S_OK;
+
+    fn wrap(inner: ICredentialProviderCredential2) -> ICredentialProviderCredential2 {
+        WrappedCredential::new(inner.into(), FIELD_COUNT, None, Arc::new(Mutex::new(SharedCredentials::new())), 0).into_interface()
+    }
+
+    #[test]
+    fn fields_before_status_go_to_inner() {
+        assert!(WrappedCredential::forwards_to_inner(0, 3));
+        assert!(WrappedCredential::forwards_to_inner(2, 3));
+        // 状态文字和之后的编号由包装的凭据处理
+        assert!(!WrappedCredential::forwards_to_inner(3, 3));
+        assert!(!WrappedCredential::forwards_to_inner(4, 3));
+        // 系统凭据没有字段时全部由包装的凭据处理
+        assert!(!WrappedCredential::forwards_to_inner(0, 0));
+    }
+
+    #[test]
+    fn get_user_sid_forwards_inner_result() {
+        // “其他用户”磁贴的 S_FALSE 和空 SID 原样返回，与面容解锁的磁贴一致
+        let other = wrap(credential(None));
+        assert!(std::ptr::eq(other.vtable(), &USER_SID_VTABLE));
+        assert_eq!(call_get_user_sid(&other), (S_FALSE, None));
+        assert_eq!(call_get_user_sid(&wrap(credential(Some(account())))), (S_OK, Some(account().sid)));
+    }
+}
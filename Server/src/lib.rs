@@ -9,11 +9,14 @@ use std::fs::File;
 use std::ffi::{c_void, OsStr};
 use std::os::windows::ffi::OsStrExt;
 use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use facewinunlock_protocol::SecretString;
 
 // Windows基础类型和COM接口
 use windows::Win32::Foundation::{CLASS_E_CLASSNOTAVAILABLE, CLASS_E_NOAGGREGATION, E_INVALIDARG, HINSTANCE, S_FALSE, S_OK};
 use windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
-use windows::Win32::UI::Shell::ICredentialProvider;
+use windows::Win32::UI::Shell::{ICredentialProvider, ICredentialProviderFilter};
 use windows_core::{implement, Ref, BOOL, GUID, PCWSTR};
 use windows::core::{Interface, HRESULT};
 use windows::Win32::System::Com::{IClassFactory, IClassFactory_Impl};
@@ -21,9 +24,17 @@ use windows::Win32::System::Com::{IClassFactory, IClassFactory_Impl};
 // 导入凭据提供程序和凭据的实现模块
 pub mod CSampleProvider;
 pub mod CSampleCredential;
+pub mod CSampleFilter;
+pub mod CWrappedCredential;
 pub mod CPipeListener;
+pub mod CStatusImage;
+pub mod CTileStatus;
+pub mod CWakeSignal;
 
 use CSampleProvider::SampleProvider;
+use CSampleFilter::SampleFilter;
+use CTileStatus::TileStatus;
+use CWakeSignal::WakeSignal;
 
 // 全局引用计数器，用于管理DLL的生命周期
 // 当引用计数为0时，系统可以安全卸载DLL
@@ -41,9 +52,16 @@ pub fn dll_release() {
     info!("DLL引用计数减少，当前计数: {}", new_count);
 }
 
+/// 软件的注册表路径
+pub const APP_REG_PATH: &str = "SOFTWARE\\facewinunlock-tauri";
+
 /// 读取注册表数据
 pub fn read_facewinunlock_registry(key_name: &str) -> windows::core::Result<String> {
-    let reg_path = "SOFTWARE\\facewinunlock-tauri";
+    read_registry_string(APP_REG_PATH, key_name)
+}
+
+/// 读取 HKLM 下指定子项中的 REG_SZ 值
+pub fn read_registry_string(reg_path: &str, key_name: &str) -> windows::core::Result<String> {
     // 打开HKLM下的注册表项
     let mut hkey: HKEY = HKEY::default();
 
@@ -122,18 +140,56 @@ pub fn read_facewinunlock_registry(key_name: &str) -> windows::core::Result<Stri
 // 8a7b9c6d-4e5f-89a0-8b7c-6d5e4f3e2d1c
 pub const CLSID_SampleProvider: GUID = GUID::from_u128(0x8a7b9c6d_4e5f_89a0_8b7c_6d5e4f3e2d1c);
 
+// 凭据提供程序过滤器的GUID，包装系统密码提供程序时隐藏它
+// 8a7b9c6d-4e5f-89a0-8b7c-6d5e4f3e2d1d
+pub const CLSID_SampleFilter: GUID = GUID::from_u128(0x8a7b9c6d_4e5f_89a0_8b7c_6d5e4f3e2d1d);
+
 // 共享的凭据信息
 pub struct SharedCredentials {
     pub username: String,
-    pub password: String,
+    // 释放或清空时清零，避免密码残留在 LogonUI 的堆内存中
+    pub password: SecretString,
     pub domain: String,
     pub is_ready: bool,
+    // ReportResult 通过它把登录结果 (NTSTATUS, 子状态) 交给管道线程回传给软件
+    pub result_sender: Option<Sender<(i32, i32)>>,
+    // 磁贴上的状态文字，管道线程收到提示后刷新
+    pub tile_status: Arc<TileStatus>,
+    // 磁贴被选中时触发，管道线程通知等待唤醒的软件
+    pub wake: Arc<WakeSignal>,
+}
+
+impl SharedCredentials {
+    /// 尚未收到凭据的共享信息，本机账户的域为 "."
+    pub fn new() -> Self {
+        Self {
+            username: String::new(),
+            password: SecretString::default(),
+            domain: String::from("."),
+            is_ready: false,
+            result_sender: None,
+            tile_status: Arc::new(TileStatus::new()),
+            wake: Arc::new(WakeSignal::new()),
+        }
+    }
+
+    /// 拼接后的用户名 (Domain\User)，本机账户只有用户名
+    pub fn full_username(&self) -> String {
+        if self.domain.is_empty() || self.domain == "." {
+            self.username.clone()
+        } else {
+            format!("{}\\{}", self.domain, self.username)
+        }
+    }
 }
 
-/// 类工厂实现，用于创建凭据提供程序实例
+/// 类工厂实现，用于创建凭据提供程序或过滤器实例
 /// COM规范要求通过类工厂来实例化组件
 #[implement(IClassFactory)]
-struct SampleClassFactory;
+struct SampleClassFactory {
+    // 要创建的组件的CLSID
+    clsid: GUID,
+}
 
 impl IClassFactory_Impl for SampleClassFactory_Impl {
     /// 创建组件实例
@@ -146,7 +202,7 @@ impl IClassFactory_Impl for SampleClassFactory_Impl {
         riid: *const GUID,
         ppv_object: *mut *mut std::ffi::c_void,
     ) -> windows::core::Result<()> {
-        info!("SampleClassFactory::CreateInstance 被调用 - 开始创建凭据提供程序实例");
+        info!("SampleClassFactory::CreateInstance 被调用 - 开始创建组件实例: {:?}", self.clsid);
         
         // 不支持聚合，若提供了外部对象则返回错误
         if punkouter.is_some() {
@@ -161,15 +217,19 @@ impl IClassFactory_Impl for SampleClassFactory_Impl {
                 return Err(E_INVALIDARG.into());
             }
             
-            // 实例化凭据提供程序并转换为ICredentialProvider接口
-            let provider: ICredentialProvider = SampleProvider::new().into();
-            // 查询请求的接口并返回
-            let result = provider.query(riid, ppv_object);
+            // 实例化凭据提供程序或过滤器，查询请求的接口并返回
+            let result = if self.clsid == CLSID_SampleFilter {
+                let filter: ICredentialProviderFilter = SampleFilter::new().into();
+                filter.query(riid, ppv_object)
+            } else {
+                let provider: ICredentialProvider = SampleProvider::new().into();
+                provider.query(riid, ppv_object)
+            };
             if result.is_err() {
                 error!("接口查询失败: {:?}", result.message());
                 Err(E_INVALIDARG.into())
             } else {
-                info!("凭据提供程序实例创建成功");
+                info!("组件实例创建成功");
                 Ok(())
             }
         }
@@ -207,10 +267,11 @@ pub unsafe extern "system" fn DllGetClassObject(
         return E_INVALIDARG;
     }
 
-    // 检查请求的CLSID是否为我们的凭据提供程序
-    if unsafe { *rclsid } == CLSID_SampleProvider {
+    // 检查请求的CLSID是否为我们的凭据提供程序或过滤器
+    let clsid = unsafe { *rclsid };
+    if clsid == CLSID_SampleProvider || clsid == CLSID_SampleFilter {
         info!("请求的CLSID匹配，创建类工厂实例");
-        let factory: IClassFactory = SampleClassFactory.into();
+        let factory: IClassFactory = SampleClassFactory { clsid }.into();
         // 查询请求的接口
         unsafe {
             let result = factory.query(riid, ppv);
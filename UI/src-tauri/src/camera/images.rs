@@ -0,0 +1,162 @@
+// 图片目录，按文件名顺序逐张作为一帧
+use std::{
+    cmp::Ordering,
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+use opencv::{
+    core::{Mat, MatTraitConst},
+    imgcodecs,
+};
+
+use super::FrameSource;
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "bmp"];
+
+pub struct ImageSequence {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    next: usize,
+    // 读完后是否从第一张开始
+    looped: bool,
+}
+
+impl ImageSequence {
+    pub fn open(dir: &Path, looped: bool) -> Result<Self, String> {
+        let files = image_files(dir)?;
+        if files.is_empty() {
+            return Err(format!("目录 {} 中没有图片", dir.display()));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            files,
+            next: 0,
+            looped,
+        })
+    }
+}
+
+impl FrameSource for ImageSequence {
+    fn read(&mut self) -> Result<Option<Mat>, String> {
+        if self.next >= self.files.len() {
+            if !self.looped {
+                return Ok(None);
+            }
+            self.next = 0;
+        }
+        let path = &self.files[self.next];
+        self.next += 1;
+
+        let file = path
+            .to_str()
+            .ok_or_else(|| format!("图片路径无效：{}", path.display()))?;
+        let frame = imgcodecs::imread(file, imgcodecs::IMREAD_COLOR)
+            .map_err(|e| format!("读取图片 {} 失败: {}", path.display(), e))?;
+        if frame.empty() {
+            return Err(format!("无法解码图片 {}", path.display()));
+        }
+        Ok(Some(frame))
+    }
+
+    fn describe(&self) -> String {
+        format!("图片目录 {}（{} 张）", self.dir.display(), self.files.len())
+    }
+}
+
+// 目录中的图片，按文件名自然排序，frame2 在 frame10 之前
+pub fn image_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("读取目录 {} 失败: {}", dir.display(), e))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("读取目录 {} 失败: {}", dir.display(), e))?
+            .path();
+        let is_image = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if is_image && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+    Ok(files)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+// 连续的数字按数值比较，其余字符逐个比较
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                // 去掉前导零后位数多的数值大，位数相同时按字符比较
+                let (tx, ty) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = tx.len().cmp(&ty.len()).then(tx.cmp(ty));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_compared_by_value() {
+        let mut names = vec!["frame10.png", "frame2.png", "frame1.png", "frame002.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["frame1.png", "frame002.jpg", "frame2.png", "frame10.png"]
+        );
+    }
+
+    #[test]
+    fn only_images_are_listed() {
+        let dir = std::env::temp_dir().join(format!("frames-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["3.png", "10.JPG", "1.jpeg", "notes.txt"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names: Vec<String> = image_files(&dir)
+            .unwrap()
+            .iter()
+            .map(|path| file_name(path))
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, vec!["1.jpeg", "3.png", "10.JPG"]);
+    }
+}
@@ -0,0 +1,109 @@
+// 真实摄像头
+use opencv::{
+    core::{Mat, MatTraitConst},
+    videoio::{self, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst},
+};
+use serde::{Deserialize, Serialize};
+use tauri_plugin_log::log::{info, warn};
+
+use super::FrameSource;
+
+// 定义摄像头后端类型枚举
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CameraBackend {
+    Any,   // CAP_ANY
+    DShow, // CAP_DSHOW
+    MSMF,  // CAP_MSMF
+    VFW,   // CAP_VFW
+}
+
+impl From<CameraBackend> for i32 {
+    fn from(backend: CameraBackend) -> Self {
+        match backend {
+            CameraBackend::Any => videoio::CAP_ANY,
+            CameraBackend::DShow => videoio::CAP_DSHOW,
+            CameraBackend::MSMF => videoio::CAP_MSMF,
+            CameraBackend::VFW => videoio::CAP_VFW,
+        }
+    }
+}
+
+pub struct LiveCamera {
+    inner: VideoCapture,
+    index: i32,
+    backend: CameraBackend,
+}
+
+impl LiveCamera {
+    // 使用指定后端尝试打开摄像头并验证读取帧
+    pub fn open(index: i32, backend: CameraBackend) -> Result<Self, String> {
+        let mut cam = VideoCapture::new(index, backend.into())
+            .map_err(|e| format!("后端 {:?} 打开摄像头失败: {}", backend, e))?;
+
+        if !cam.is_opened().unwrap_or(false) {
+            return Err(format!("后端 {:?} 打开摄像头后状态为未激活", backend));
+        }
+
+        // 激活摄像头
+        let mut frame = Mat::default();
+        cam.read(&mut frame)
+            .map_err(|e| format!("后端 {:?} 读取帧失败: {}", backend, e))?;
+        if frame.empty() {
+            return Err(format!("后端 {:?} 读取到空帧", backend));
+        }
+
+        Ok(Self {
+            inner: cam,
+            index,
+            backend,
+        })
+    }
+
+    // 指定了后端时只尝试该后端，未指定时依次尝试常用后端
+    pub fn open_any(index: i32, backend: Option<CameraBackend>) -> Result<Self, String> {
+        if let Some(backend) = backend {
+            let cam = Self::open(index, backend)?;
+            info!("使用指定后端 {:?} 成功打开摄像头", backend);
+            return Ok(cam);
+        }
+
+        let backends_to_try = [
+            CameraBackend::DShow,
+            CameraBackend::Any,
+            CameraBackend::MSMF,
+            CameraBackend::VFW,
+        ];
+        for (idx, backend) in backends_to_try.into_iter().enumerate() {
+            match Self::open(index, backend) {
+                Ok(cam) => {
+                    info!("尝试第{}个后端 {:?} 成功打开摄像头", idx + 1, backend);
+                    return Ok(cam);
+                }
+                // 打印尝试失败日志，继续尝试下一个
+                Err(e) => warn!("尝试后端 {:?} 失败: {}", backend, e),
+            }
+        }
+
+        Err(String::from(
+            "所有摄像头后端均尝试失败，请检查设备是否连接/被占用/有权限",
+        ))
+    }
+}
+
+impl FrameSource for LiveCamera {
+    fn read(&mut self) -> Result<Option<Mat>, String> {
+        let mut frame = Mat::default();
+        self.inner
+            .read(&mut frame)
+            .map_err(|e| format!("摄像头读取失败: {}", e))?;
+        // 摄像头不会结束，空帧视为读取失败
+        if frame.empty() {
+            return Err(String::from("抓取到空帧"));
+        }
+        Ok(Some(frame))
+    }
+
+    fn describe(&self) -> String {
+        format!("摄像头 {}（{:?}）", self.index, self.backend)
+    }
+}
@@ -0,0 +1,152 @@
+// 画面源，识别流程只通过 FrameSource 读取帧
+// 除了真实摄像头，还可以用视频文件、图片目录或内存中生成的帧复现识别过程，不需要摄像头
+use std::{
+    env,
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use opencv::core::Mat;
+
+pub mod images;
+pub mod live;
+pub mod synthetic;
+pub mod video;
+
+pub use images::ImageSequence;
+pub use live::{CameraBackend, LiveCamera};
+pub use synthetic::SyntheticSource;
+pub use video::VideoFile;
+
+// 设置后锁屏识别使用指定的画面源而不是摄像头，格式见 SourceSpec::parse
+pub const FRAME_SOURCE_ENV: &str = "FACEWINUNLOCK_FRAME_SOURCE";
+
+pub trait FrameSource {
+    // 读取下一帧，画面源已经结束（视频播放完、图片读完）时返回 None
+    fn read(&mut self) -> Result<Option<Mat>, String>;
+
+    // 日志中显示的画面源说明
+    fn describe(&self) -> String;
+}
+
+// 读取下一帧，画面源结束时返回错误，识别流程据此退出
+pub fn read_frame(source: &mut dyn FrameSource) -> Result<Mat, String> {
+    source
+        .read()?
+        .ok_or_else(|| format!("{}已结束", source.describe()))
+}
+
+// 按设定的帧率读取画面，距离上次读取不足一帧的间隔时等待，避免空转占满 CPU
+pub struct FramePacer {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1),
+            last: None,
+        }
+    }
+
+    pub fn wait(&mut self) {
+        if let Some(last) = self.last {
+            if let Some(remaining) = self.interval.checked_sub(last.elapsed()) {
+                sleep(remaining);
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+// 可以从配置中打开的画面源，内存中生成的画面源只能在代码中构造
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceSpec {
+    Camera(i32),
+    Video { path: PathBuf, looped: bool },
+    Images { dir: PathBuf, looped: bool },
+}
+
+impl SourceSpec {
+    // camera:0、video:D:\a.mp4、images:D:\frames，类型后加 + 表示读完后从头循环，例如 video+:D:\a.mp4
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (kind, arg) = value
+            .split_once(':')
+            .ok_or_else(|| format!("画面源格式无效：{}，应为 类型:参数", value))?;
+        let (kind, looped) = match kind.strip_suffix('+') {
+            Some(kind) => (kind, true),
+            None => (kind, false),
+        };
+        let arg = arg.trim();
+        if arg.is_empty() {
+            return Err(format!("画面源缺少参数：{}", value));
+        }
+        match kind.trim() {
+            "camera" if !looped => arg
+                .parse()
+                .ok()
+                .filter(|index: &i32| *index >= 0)
+                .map(SourceSpec::Camera)
+                .ok_or_else(|| format!("摄像头索引无效：{}", arg)),
+            "video" => Ok(SourceSpec::Video {
+                path: PathBuf::from(arg),
+                looped,
+            }),
+            "images" => Ok(SourceSpec::Images {
+                dir: PathBuf::from(arg),
+                looped,
+            }),
+            _ => Err(format!("未知的画面源类型：{}", kind)),
+        }
+    }
+
+    pub fn open(&self) -> Result<Box<dyn FrameSource>, String> {
+        Ok(match self {
+            SourceSpec::Camera(index) => Box::new(LiveCamera::open_any(*index, None)?),
+            SourceSpec::Video { path, looped } => Box::new(VideoFile::open(path, *looped)?),
+            SourceSpec::Images { dir, looped } => Box::new(ImageSequence::open(dir, *looped)?),
+        })
+    }
+}
+
+// 锁屏识别使用的画面源，设置了 FRAME_SOURCE_ENV 时优先使用环境变量
+pub fn open_for_unlock(camera_index: i32) -> Result<Box<dyn FrameSource>, String> {
+    let spec = match env::var(FRAME_SOURCE_ENV) {
+        Ok(value) if !value.trim().is_empty() => SourceSpec::parse(&value)?,
+        _ => SourceSpec::Camera(camera_index),
+    };
+    spec.open()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_source_spec() {
+        assert_eq!(SourceSpec::parse("camera:1"), Ok(SourceSpec::Camera(1)));
+        assert_eq!(
+            SourceSpec::parse(r"video:D:\test\unlock.mp4"),
+            Ok(SourceSpec::Video {
+                path: PathBuf::from(r"D:\test\unlock.mp4"),
+                looped: false,
+            })
+        );
+        assert_eq!(
+            SourceSpec::parse("images+: /tmp/frames"),
+            Ok(SourceSpec::Images {
+                dir: PathBuf::from("/tmp/frames"),
+                looped: true,
+            })
+        );
+    }
+
+    #[test]
+    fn reject_invalid_source_spec() {
+        for value in ["camera", "camera:-1", "camera+:0", "video:", "rtsp:x"] {
+            assert!(SourceSpec::parse(value).is_err(), "{}", value);
+        }
+    }
+}
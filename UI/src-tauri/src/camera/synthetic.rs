@@ -0,0 +1,46 @@
+// 内存中生成的画面，测试时不需要任何文件
+use opencv::core::Mat;
+
+use super::FrameSource;
+
+// 参数为帧序号，返回 None 表示结束
+type Generator = Box<dyn FnMut(usize) -> Result<Option<Mat>, String>>;
+
+pub struct SyntheticSource {
+    generator: Generator,
+    index: usize,
+    // 结束后不再调用生成函数
+    finished: bool,
+}
+
+impl SyntheticSource {
+    pub fn new(generator: impl FnMut(usize) -> Result<Option<Mat>, String> + 'static) -> Self {
+        Self {
+            generator: Box::new(generator),
+            index: 0,
+            finished: false,
+        }
+    }
+
+    // 依次返回给定的帧
+    pub fn from_frames(frames: Vec<Mat>) -> Self {
+        let mut frames = frames.into_iter();
+        Self::new(move |_| Ok(frames.next()))
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn read(&mut self) -> Result<Option<Mat>, String> {
+        if self.finished {
+            return Ok(None);
+        }
+        let frame = (self.generator)(self.index)?;
+        self.index += 1;
+        self.finished = frame.is_none();
+        Ok(frame)
+    }
+
+    fn describe(&self) -> String {
+        format!("生成的画面（已读取 {} 帧）", self.index)
+    }
+}
@@ -0,0 +1,63 @@
+// 视频文件，用录好的视频复现一次识别
+use std::path::{Path, PathBuf};
+
+use opencv::{
+    core::{Mat, MatTraitConst},
+    videoio::{self, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst},
+};
+
+use super::FrameSource;
+
+pub struct VideoFile {
+    inner: VideoCapture,
+    path: PathBuf,
+    // 播放完后是否从头开始
+    looped: bool,
+}
+
+impl VideoFile {
+    pub fn open(path: &Path, looped: bool) -> Result<Self, String> {
+        let file = path
+            .to_str()
+            .ok_or_else(|| format!("视频路径无效：{}", path.display()))?;
+        let inner = VideoCapture::from_file(file, videoio::CAP_ANY)
+            .map_err(|e| format!("打开视频 {} 失败: {}", path.display(), e))?;
+        if !inner.is_opened().unwrap_or(false) {
+            return Err(format!("无法打开视频 {}", path.display()));
+        }
+        Ok(Self {
+            inner,
+            path: path.to_path_buf(),
+            looped,
+        })
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Mat>, String> {
+        let mut frame = Mat::default();
+        let has_frame = self
+            .inner
+            .read(&mut frame)
+            .map_err(|e| format!("视频读取失败: {}", e))?;
+        Ok((has_frame && !frame.empty()).then_some(frame))
+    }
+}
+
+impl FrameSource for VideoFile {
+    fn read(&mut self) -> Result<Option<Mat>, String> {
+        if let Some(frame) = self.next_frame()? {
+            return Ok(Some(frame));
+        }
+        if !self.looped {
+            return Ok(None);
+        }
+        self.inner
+            .set(videoio::CAP_PROP_POS_FRAMES, 0.0)
+            .map_err(|e| format!("视频跳转到开头失败: {}", e))?;
+        // 回到开头后仍读不到帧说明视频是空的，结束而不是一直重试
+        self.next_frame()
+    }
+
+    fn describe(&self) -> String {
+        format!("视频 {}", self.path.display())
+    }
+}
@@ -0,0 +1,52 @@
+// 提供给前端的数据库命令
+use serde_json::json;
+
+use super::{get_conn, init_pool, migrations, repo};
+use crate::utils::custom_result::CustomResult;
+
+// 前端连接数据库之前调用，确保表结构已经是最新版本
+#[tauri::command]
+pub fn migrate_database() -> Result<CustomResult, CustomResult> {
+    init_pool().map_err(|e| CustomResult::error(Some(e), None))?;
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let version =
+        migrations::current_version(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({ "version": version })),
+    ))
+}
+
+// 全部面容
+#[tauri::command]
+pub fn get_faces() -> Result<CustomResult, CustomResult> {
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let faces = repo::list_faces(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(faces))))
+}
+
+// 全部设置
+#[tauri::command]
+pub fn get_options() -> Result<CustomResult, CustomResult> {
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let options = repo::list_options(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(options))))
+}
+
+// 最新的解锁日志，不传 limit 返回全部
+#[tauri::command]
+pub fn get_unlock_logs(limit: Option<u32>) -> Result<CustomResult, CustomResult> {
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let logs =
+        repo::list_unlock_logs(&conn, limit).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(logs))))
+}
+
+// 面容的模板自动更新和回滚记录
+#[tauri::command]
+pub fn get_template_audit(face_id: i32) -> Result<CustomResult, CustomResult> {
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let audits = repo::list_template_audit(&conn, face_id)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(audits))))
+}
@@ -0,0 +1,345 @@
+// 数据库结构迁移
+// 每个迁移只执行一次，执行过的版本记录在 schema_migrations 表中
+// 旧版本由前端建表，表可能已经存在，所以迁移必须兼容已有的表结构
+use r2d2_sqlite::rusqlite::{params, Connection, Transaction};
+
+struct Migration {
+    version: u32,
+    name: &'static str,
+    up: fn(&Transaction) -> Result<(), String>,
+}
+
+// 只能在末尾追加，已发布的迁移不要修改
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "基础表结构",
+        up: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        name: "解锁日志记录登录结果",
+        up: add_logon_result,
+    },
+    Migration {
+        version: 3,
+        name: "模板更新审计",
+        up: create_template_audit,
+    },
+    Migration {
+        version: 4,
+        name: "解锁日志记录活体检测结果",
+        up: add_liveness,
+    },
+    Migration {
+        version: 5,
+        name: "面容解锁失败保护",
+        up: create_unlock_guard,
+    },
+    Migration {
+        version: 6,
+        name: "面容密码失效标记",
+        up: add_credentials_stale,
+    },
+];
+
+// 最新的结构版本
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// 执行所有未执行的迁移，返回执行后的结构版本
+pub fn run_migrations(conn: &mut Connection) -> Result<u32, String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            applied_at TEXT DEFAULT (datetime('now', 'localtime'))
+        );",
+    )
+    .map_err(|e| format!("创建迁移记录表失败：{:?}", e))?;
+
+    let current = current_version(conn)?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        // 每个迁移单独一个事务，失败时不会留下一半的结构
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("开启事务失败：{:?}", e))?;
+        (migration.up)(&tx).map_err(|e| {
+            format!(
+                "执行迁移 {} {} 失败：{}",
+                migration.version, migration.name, e
+            )
+        })?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )
+        .map_err(|e| format!("记录迁移版本失败：{:?}", e))?;
+        tx.commit().map_err(|e| format!("提交事务失败：{:?}", e))?;
+    }
+
+    current_version(conn)
+}
+
+// 当前的结构版本，没有执行过任何迁移时为 0
+pub fn current_version(conn: &Connection) -> Result<u32, String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("查询结构版本失败：{:?}", e))
+}
+
+// 判断表中是否存在某一列
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool, String> {
+    let mut stmt = tx
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| format!("查询表结构失败：{:?}", e))?;
+    let names = stmt
+        .query_map([], |row| row.get::<&str, String>("name"))
+        .map_err(|e| format!("查询表结构失败：{:?}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("读取表结构失败：{:?}", e))?;
+    Ok(names.iter().any(|name| name == column))
+}
+
+// 与旧版本前端建表语句一致，已有的表保持不变
+fn create_base_tables(tx: &Transaction) -> Result<(), String> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS options (
+            id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+            -- 设置的key
+            key TEXT NOT NULL UNIQUE,
+            -- 设置key所对应的值
+            val TEXT NOT NULL,
+            -- 上次更新时间
+            lastTime TEXT DEFAULT (datetime('now', 'localtime'))
+        );
+        CREATE TABLE IF NOT EXISTS faces (
+            id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+            -- 对应的Windows用户名
+            user_name TEXT NOT NULL,
+            -- 加密后的Windows密码
+            user_pwd TEXT NOT NULL,
+            -- windows账户类型
+            account_type TEXT NOT NULL,
+            -- 人脸特征标识符和图片的存储路径，特征是 .face 图片是 .faceimg
+            face_token TEXT NOT NULL,
+            -- 其余JSON数据：alias threshold view faceDetectionThreshold
+            json_data TEXT NOT NULL,
+            -- 创建时间
+            createTime TEXT DEFAULT (datetime('now', 'localtime'))
+        );
+        CREATE TABLE IF NOT EXISTS unlock_log (
+            id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+            -- 面容ID，未匹配到面容时为 -1
+            face_id INTEGER,
+            -- 是否成功解锁
+            is_unlock INTEGER NOT NULL,
+            -- 上次更新时间
+            lastTime TEXT DEFAULT (datetime('now', 'localtime'))
+        );",
+    )
+    .map_err(|e| format!("创建基础表失败：{:?}", e))
+}
+
+// DLL 回传的登录结果，success / bad_password / account_locked / NTSTATUS 原值
+// 旧版本前端同步表结构时可能已经加过这一列
+fn add_logon_result(tx: &Transaction) -> Result<(), String> {
+    if has_column(tx, "unlock_log", "logon_result")? {
+        return Ok(());
+    }
+    tx.execute_batch("ALTER TABLE unlock_log ADD COLUMN logon_result TEXT;")
+        .map_err(|e| format!("添加 logon_result 列失败：{:?}", e))
+}
+
+// 解锁后自动更新模板和回滚的记录，同时用于限制自动更新的频率
+fn create_template_audit(tx: &Transaction) -> Result<(), String> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS template_audit (
+            id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+            -- 面容ID
+            face_id INTEGER NOT NULL,
+            -- adaptive_add / rollback
+            action TEXT NOT NULL,
+            -- 新增的模板ID，回滚时为空
+            template_id INTEGER,
+            -- 触发更新的比对得分，回滚时为空
+            score REAL,
+            -- 说明，例如被替换或删除的模板
+            detail TEXT NOT NULL DEFAULT '',
+            -- 创建时间
+            createTime TEXT DEFAULT (datetime('now', 'localtime'))
+        );
+        CREATE INDEX IF NOT EXISTS idx_template_audit_face ON template_audit (face_id, createTime);",
+    )
+    .map_err(|e| format!("创建模板审计表失败：{:?}", e))
+}
+
+// 活体检测各线索的结果，JSON 格式，未进行活体检测时为空
+fn add_liveness(tx: &Transaction) -> Result<(), String> {
+    if has_column(tx, "unlock_log", "liveness")? {
+        return Ok(());
+    }
+    tx.execute_batch("ALTER TABLE unlock_log ADD COLUMN liveness TEXT;")
+        .map_err(|e| format!("添加 liveness 列失败：{:?}", e))
+}
+
+// 按账户记录连续失败次数、冷却和停用状态，见 recognition::lockout
+fn create_unlock_guard(tx: &Transaction) -> Result<(), String> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS unlock_guard (
+            -- 登录用的用户名（小写），未匹配到面容的失败记在 '*' 下
+            account TEXT NOT NULL PRIMARY KEY,
+            -- 连续识别失败次数
+            failures INTEGER NOT NULL DEFAULT 0,
+            -- 连续登录失败次数
+            logon_failures INTEGER NOT NULL DEFAULT 0,
+            -- 冷却结束的时间（Unix 秒）
+            blocked_until INTEGER,
+            -- 是否已停用面容解锁，手动解锁后恢复
+            disabled INTEGER NOT NULL DEFAULT 0,
+            -- 被系统拒绝的密码的指纹，见 secret_store::PasswordFingerprint
+            rejected_fingerprint TEXT,
+            -- 上次更新时间
+            lastTime TEXT DEFAULT (datetime('now', 'localtime'))
+        );",
+    )
+    .map_err(|e| format!("创建面容解锁保护表失败：{:?}", e))
+}
+
+// 系统拒绝了面容保存的密码（例如用户修改了 Windows 密码）时标记为失效
+// 前端通过 SQL 插件修改密码，由触发器在密码变化时清除标记
+fn add_credentials_stale(tx: &Transaction) -> Result<(), String> {
+    if !has_column(tx, "faces", "credentials_stale")? {
+        tx.execute_batch(
+            "ALTER TABLE faces ADD COLUMN credentials_stale INTEGER NOT NULL DEFAULT 0;",
+        )
+        .map_err(|e| format!("添加 credentials_stale 列失败：{:?}", e))?;
+    }
+    tx.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS faces_password_changed
+        AFTER UPDATE OF user_pwd ON faces
+        WHEN NEW.user_pwd <> OLD.user_pwd
+        BEGIN
+            UPDATE faces SET credentials_stale = 0 WHERE id = NEW.id;
+        END;",
+    )
+    .map_err(|e| format!("创建密码变化触发器失败：{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap()
+            .query_map([], |row| row.get::<&str, String>("name"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap_or(0), 0);
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(
+            columns(&conn, "unlock_log"),
+            [
+                "id",
+                "face_id",
+                "is_unlock",
+                "lastTime",
+                "logon_result",
+                "liveness"
+            ]
+        );
+        assert_eq!(
+            columns(&conn, "faces"),
+            [
+                "id",
+                "user_name",
+                "user_pwd",
+                "account_type",
+                "face_token",
+                "json_data",
+                "createTime",
+                "credentials_stale"
+            ]
+        );
+        assert_eq!(
+            columns(&conn, "unlock_guard"),
+            [
+                "account",
+                "failures",
+                "logon_failures",
+                "blocked_until",
+                "disabled",
+                "rejected_fingerprint",
+                "lastTime"
+            ]
+        );
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        let applied: u32 = conn
+            .query_row("SELECT COUNT(*) FROM schema_migrations", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(applied as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn legacy_frontend_schema_keeps_data() {
+        // 旧版本前端建的表，没有 logon_result 列，也没有迁移记录
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE options(id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, key TEXT NOT NULL UNIQUE, val TEXT NOT NULL, lastTime TEXT DEFAULT (datetime('now', 'localtime')));
+            CREATE TABLE unlock_log(id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, face_id INTEGER, is_unlock INTEGER NOT NULL, lastTime TEXT DEFAULT (datetime('now', 'localtime')));
+            INSERT INTO options (key, val) VALUES ('camera', '1');
+            INSERT INTO unlock_log (face_id, is_unlock) VALUES (3, 1);",
+        )
+        .unwrap();
+
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert!(columns(&conn, "unlock_log").contains(&String::from("logon_result")));
+        let val: String = conn
+            .query_row("SELECT val FROM options WHERE key = 'camera'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(val, "1");
+        let (face_id, logon_result): (i32, Option<String>) = conn
+            .query_row("SELECT face_id, logon_result FROM unlock_log", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((face_id, logon_result), (3, None));
+    }
+
+    #[test]
+    fn legacy_schema_with_logon_result_is_accepted() {
+        // 前端已经同步过 logon_result 列的数据库
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE unlock_log(id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT, face_id INTEGER, is_unlock INTEGER NOT NULL, logon_result TEXT, lastTime TEXT DEFAULT (datetime('now', 'localtime')));",
+        )
+        .unwrap();
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        let count = columns(&conn, "unlock_log")
+            .iter()
+            .filter(|name| *name == "logon_result")
+            .count();
+        assert_eq!(count, 1);
+    }
+}
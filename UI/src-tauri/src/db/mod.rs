@@ -0,0 +1,122 @@
+// 数据库模块：表结构和迁移都由 Rust 负责，前端只在迁移完成后读写数据
+pub mod commands;
+pub mod migrations;
+pub mod models;
+pub mod repo;
+
+use std::path::Path;
+
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::{rusqlite, SqliteConnectionManager};
+use tauri_plugin_log::log::info;
+
+use crate::{
+    utils::secret_store::{default_store, migrate_stored_passwords, SecretStore},
+    DB_POOL, ROOT_DIR,
+};
+
+pub type DbPool = Pool<SqliteConnectionManager>;
+pub type DbConnection = PooledConnection<SqliteConnectionManager>;
+
+// 创建全局连接池并执行迁移，已经创建过则直接返回
+// 前端连接数据库之前和加载模型时都会调用，谁先调用谁负责建表
+pub fn init_pool() -> Result<(), String> {
+    let mut pool_guard = DB_POOL
+        .lock()
+        .map_err(|e| format!("获取连接池锁失败 {}", e))?;
+    if pool_guard.is_some() {
+        return Ok(());
+    }
+
+    let db_path = ROOT_DIR.join("database.db");
+    let pool = open_pool(&db_path, &default_store())?;
+    #[cfg(windows)]
+    restrict_db_acl(&db_path)?;
+    *pool_guard = Some(pool);
+    Ok(())
+}
+
+// 数据库里的密码是本机范围的 DPAPI 密文，本机任何进程都能解密，只能靠文件权限保护
+// 每次启动都把 ACL 重设为只有 SYSTEM 和管理员能访问，不继承目录权限
+#[cfg(windows)]
+fn restrict_db_acl(db_path: &Path) -> Result<(), String> {
+    use windows::{
+        core::{w, HSTRING},
+        Win32::{
+            Foundation::{LocalFree, HLOCAL},
+            Security::{
+                Authorization::{
+                    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+                },
+                SetFileSecurityW, DACL_SECURITY_INFORMATION, PROTECTED_DACL_SECURITY_INFORMATION,
+                PSECURITY_DESCRIPTOR,
+            },
+        },
+    };
+
+    unsafe {
+        let mut sd = PSECURITY_DESCRIPTOR::default();
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            w!("D:P(A;;FA;;;SY)(A;;FA;;;BA)"),
+            SDDL_REVISION_1,
+            &mut sd,
+            None,
+        )
+        .map_err(|e| format!("创建安全描述符失败 {}", e))?;
+        let result = SetFileSecurityW(
+            &HSTRING::from(db_path),
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            sd,
+        );
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        result
+            .ok()
+            .map_err(|e| format!("设置数据库文件权限失败 {}", e))
+    }
+}
+
+// 打开指定的数据库文件，执行迁移并用 store 加密旧版本留下的密码
+pub fn open_pool(db_path: &Path, store: &dyn SecretStore) -> Result<DbPool, String> {
+    // 首次启动时数据库文件还不存在，需要允许创建
+    let manager = SqliteConnectionManager::file(db_path).with_flags(
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
+            | rusqlite::OpenFlags::SQLITE_OPEN_CREATE
+            | rusqlite::OpenFlags::SQLITE_OPEN_FULL_MUTEX,
+    );
+    let pool = Pool::builder()
+        .max_size(4) // 回调函数和前端命令使用，不需要太多连接
+        .build(manager)
+        .map_err(|e| format!("创建连接池失败 {}", e))?;
+
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("获取数据库连接失败 {}", e))?;
+    let version = migrations::run_migrations(&mut conn)?;
+    info!("数据库结构版本：{}", version);
+    // 加密旧版本留下的明文密码
+    migrate_stored_passwords(&mut conn, store).map_err(|e| format!("迁移面容密码失败 {}", e))?;
+    drop(conn);
+    Ok(pool)
+}
+
+// 全局连接池，连接池内部共享连接，克隆后可以在锁外使用
+pub fn pool() -> Result<DbPool, String> {
+    let pool_guard = DB_POOL
+        .lock()
+        .map_err(|e| format!("从全局变量获取连接池失败 {}", e))?;
+    pool_guard
+        .clone()
+        .ok_or_else(|| String::from("连接池不存在"))
+}
+
+// 从全局连接池取一个连接，取完立即释放锁，避免长时间占用
+pub fn get_conn() -> Result<DbConnection, String> {
+    let pool_guard = DB_POOL
+        .lock()
+        .map_err(|e| format!("从全局变量获取连接池失败 {}", e))?;
+    let pool = pool_guard
+        .as_ref()
+        .ok_or_else(|| String::from("连接池不存在"))?;
+    pool.get()
+        .map_err(|e| format!("从连接池获取连接失败：{:?}", e))
+}
@@ -0,0 +1,143 @@
+// 数据表对应的结构体，字段名与前端使用的列名保持一致
+use r2d2_sqlite::rusqlite::{Result, Row};
+use serde::{Deserialize, Serialize};
+
+// 面容
+#[derive(Debug, Clone, Serialize)]
+pub struct Face {
+    pub id: i32,
+    // 对应的Windows用户名
+    pub user_name: String,
+    // 加密后的Windows密码，只有准备解锁时才解密
+    pub user_pwd: String,
+    // windows账户类型
+    pub account_type: String,
+    // 人脸特征标识符和图片的存储路径
+    pub face_token: String,
+    // 其余JSON数据，见 FaceExtraData
+    pub json_data: String,
+    #[serde(rename = "createTime")]
+    pub create_time: Option<String>,
+    // 系统拒绝了保存的密码，需要用户重新输入
+    pub credentials_stale: bool,
+}
+
+impl Face {
+    pub fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            user_name: row.get("user_name")?,
+            user_pwd: row.get("user_pwd")?,
+            account_type: row.get("account_type")?,
+            face_token: row.get("face_token")?,
+            json_data: row.get("json_data")?,
+            create_time: row.get("createTime")?,
+            credentials_stale: row.get::<&str, i32>("credentials_stale")? != 0,
+        })
+    }
+
+    // 登录用的用户名，本地账户加上 .\ 前缀
+    pub fn logon_name(&self) -> String {
+        if self.account_type == "local" {
+            format!(".\\{}", self.user_name)
+        } else {
+            self.user_name.clone()
+        }
+    }
+
+    // 解析 json_data
+    pub fn extra(&self) -> std::result::Result<FaceExtraData, String> {
+        serde_json::from_str(&self.json_data)
+            .map_err(|e| format!("解析面容 {} 的附加数据失败：{}", self.id, e))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")] // 适配 JSON 中的驼峰命名
+pub struct FaceExtraData {
+    /// 面容别名
+    pub alias: String,
+    /// 置信度阈值
+    pub threshold: f32,
+    /// 是否在列表页显示图片缩略图
+    pub view: bool,
+    /// 人脸检测置信度阈值
+    pub face_detection_threshold: f32,
+}
+
+// 设置
+#[derive(Debug, Clone, Serialize)]
+pub struct OptionEntry {
+    pub id: i32,
+    pub key: String,
+    pub val: String,
+    #[serde(rename = "lastTime")]
+    pub last_time: Option<String>,
+}
+
+impl OptionEntry {
+    pub fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            key: row.get("key")?,
+            val: row.get("val")?,
+            last_time: row.get("lastTime")?,
+        })
+    }
+}
+
+// 解锁记录
+#[derive(Debug, Clone, Serialize)]
+pub struct UnlockLog {
+    pub id: i32,
+    // 未匹配到面容时为 -1
+    pub face_id: Option<i32>,
+    pub is_unlock: bool,
+    // DLL 回传的登录结果
+    pub logon_result: Option<String>,
+    // 活体检测结果，JSON 格式
+    pub liveness: Option<String>,
+    #[serde(rename = "lastTime")]
+    pub last_time: Option<String>,
+}
+
+impl UnlockLog {
+    pub fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            face_id: row.get("face_id")?,
+            is_unlock: row.get::<&str, i32>("is_unlock")? != 0,
+            logon_result: row.get("logon_result")?,
+            liveness: row.get("liveness")?,
+            last_time: row.get("lastTime")?,
+        })
+    }
+}
+
+// 模板自动更新和回滚的记录
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateAudit {
+    pub id: i32,
+    pub face_id: i32,
+    // adaptive_add / rollback
+    pub action: String,
+    pub template_id: Option<u32>,
+    pub score: Option<f32>,
+    pub detail: String,
+    #[serde(rename = "createTime")]
+    pub create_time: Option<String>,
+}
+
+impl TemplateAudit {
+    pub fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            face_id: row.get("face_id")?,
+            action: row.get("action")?,
+            template_id: row.get("template_id")?,
+            score: row.get::<&str, Option<f64>>("score")?.map(|s| s as f32),
+            detail: row.get("detail")?,
+            create_time: row.get("createTime")?,
+        })
+    }
+}
@@ -0,0 +1,330 @@
+// 数据读写，proc 和前端命令共用
+use r2d2_sqlite::rusqlite::{params, Connection, OptionalExtension};
+
+use super::models::{Face, OptionEntry, TemplateAudit, UnlockLog};
+use crate::recognition::lockout::AccountGuard;
+
+// 模板审计的操作类型
+pub const AUDIT_ADAPTIVE_ADD: &str = "adaptive_add";
+pub const AUDIT_ROLLBACK: &str = "rollback";
+
+// 全部面容，按添加顺序
+pub fn list_faces(conn: &Connection) -> Result<Vec<Face>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM faces ORDER BY id")
+        .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
+    let faces = stmt
+        .query_map([], Face::from_row)
+        .map_err(|e| format!("查询面容数据失败：{:?}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("读取面容数据失败：{:?}", e))?;
+    Ok(faces)
+}
+
+// 按 face_token 查找面容
+pub fn find_face_by_token(conn: &Connection, face_token: &str) -> Result<Option<Face>, String> {
+    conn.query_row(
+        "SELECT * FROM faces WHERE face_token = ?1",
+        params![face_token],
+        Face::from_row,
+    )
+    .optional()
+    .map_err(|e| format!("查询面容数据失败：{:?}", e))
+}
+
+// 标记或清除面容的密码失效标记
+pub fn set_credentials_stale(conn: &Connection, face_id: i32, stale: bool) -> Result<(), String> {
+    conn.execute(
+        "UPDATE faces SET credentials_stale = ?1 WHERE id = ?2",
+        params![if stale { 1 } else { 0 }, face_id],
+    )
+    .map_err(|e| format!("更新面容 {} 的密码失效标记失败：{:?}", face_id, e))?;
+    Ok(())
+}
+
+pub fn count_faces(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT COUNT(id) FROM faces", [], |row| row.get(0))
+        .map_err(|e| format!("查询面容数量失败：{:?}", e))
+}
+
+// 读取一项设置，不存在时返回 None
+pub fn get_option(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT val FROM options WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| format!("从数据库获取设置 {} 失败：{:?}", key, e))
+}
+
+// 写入一项设置，不存在时新增，值没有变化时不更新时间
+pub fn set_option(conn: &Connection, key: &str, val: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO options (key, val) VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET val = excluded.val, lastTime = datetime('now', 'localtime')
+        WHERE val <> excluded.val",
+        params![key, val],
+    )
+    .map_err(|e| format!("保存设置 {} 失败：{:?}", key, e))?;
+    Ok(())
+}
+
+pub fn list_options(conn: &Connection) -> Result<Vec<OptionEntry>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM options ORDER BY id")
+        .map_err(|e| format!("准备查询设置失败：{:?}", e))?;
+    let options = stmt
+        .query_map([], OptionEntry::from_row)
+        .map_err(|e| format!("查询设置失败：{:?}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("读取设置失败：{:?}", e))?;
+    Ok(options)
+}
+
+// 插入解锁日志，返回新记录的ID
+pub fn insert_unlock_log(
+    conn: &Connection,
+    face_id: i32,
+    is_unlock: bool,
+    logon_result: &str,
+    liveness: Option<&str>,
+) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO unlock_log (face_id, is_unlock, logon_result, liveness) VALUES (?1, ?2, ?3, ?4)",
+        params![face_id, if is_unlock { 1 } else { 0 }, logon_result, liveness],
+    )
+    .map_err(|e| format!("插入解锁日志失败：{:?}", e))?;
+    Ok(conn.last_insert_rowid())
+}
+
+// 最新的解锁日志，limit 为 None 时返回全部
+pub fn list_unlock_logs(conn: &Connection, limit: Option<u32>) -> Result<Vec<UnlockLog>, String> {
+    let mut stmt = conn
+        .prepare("SELECT * FROM unlock_log ORDER BY lastTime DESC, id DESC LIMIT ?1")
+        .map_err(|e| format!("准备查询解锁日志失败：{:?}", e))?;
+    // SQLite 中 LIMIT -1 表示不限制
+    let limit = limit.map(i64::from).unwrap_or(-1);
+    let logs = stmt
+        .query_map(params![limit], UnlockLog::from_row)
+        .map_err(|e| format!("查询解锁日志失败：{:?}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("读取解锁日志失败：{:?}", e))?;
+    Ok(logs)
+}
+
+// 插入模板审计记录，返回新记录的ID
+pub fn insert_template_audit(
+    conn: &Connection,
+    face_id: i32,
+    action: &str,
+    template_id: Option<u32>,
+    score: Option<f32>,
+    detail: &str,
+) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO template_audit (face_id, action, template_id, score, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![face_id, action, template_id, score.map(f64::from), detail],
+    )
+    .map_err(|e| format!("插入模板审计记录失败：{:?}", e))?;
+    Ok(conn.last_insert_rowid())
+}
+
+// 距离面容上次自动更新模板的秒数，从未更新过时返回 None
+pub fn secs_since_adaptive_update(conn: &Connection, face_id: i32) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT CAST(strftime('%s', datetime('now', 'localtime')) AS INTEGER) - CAST(strftime('%s', MAX(createTime)) AS INTEGER)
+        FROM template_audit WHERE face_id = ?1 AND action = ?2",
+        params![face_id, AUDIT_ADAPTIVE_ADD],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("查询模板更新时间失败：{:?}", e))
+}
+
+// 面容的模板审计记录，最新的在前
+pub fn list_template_audit(conn: &Connection, face_id: i32) -> Result<Vec<TemplateAudit>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT * FROM template_audit WHERE face_id = ?1 ORDER BY createTime DESC, id DESC",
+        )
+        .map_err(|e| format!("准备查询模板审计记录失败：{:?}", e))?;
+    let audits = stmt
+        .query_map(params![face_id], TemplateAudit::from_row)
+        .map_err(|e| format!("查询模板审计记录失败：{:?}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("读取模板审计记录失败：{:?}", e))?;
+    Ok(audits)
+}
+
+// 账户的失败记录，没有记录时返回空的记录
+pub fn get_unlock_guard(conn: &Connection, account: &str) -> Result<AccountGuard, String> {
+    conn.query_row(
+        "SELECT * FROM unlock_guard WHERE account = ?1",
+        params![account],
+        |row| {
+            Ok(AccountGuard {
+                account: row.get("account")?,
+                failures: row.get("failures")?,
+                logon_failures: row.get("logon_failures")?,
+                blocked_until: row.get("blocked_until")?,
+                disabled: row.get::<&str, i32>("disabled")? != 0,
+                rejected_fingerprint: row.get("rejected_fingerprint")?,
+            })
+        },
+    )
+    .optional()
+    .map(|guard| guard.unwrap_or_else(|| AccountGuard::new(account)))
+    .map_err(|e| format!("查询面容解锁保护记录失败：{:?}", e))
+}
+
+// 保存账户的失败记录，不存在时新增
+pub fn save_unlock_guard(conn: &Connection, guard: &AccountGuard) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO unlock_guard (account, failures, logon_failures, blocked_until, disabled, rejected_fingerprint)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(account) DO UPDATE SET failures = excluded.failures, logon_failures = excluded.logon_failures,
+        blocked_until = excluded.blocked_until, disabled = excluded.disabled, rejected_fingerprint = excluded.rejected_fingerprint,
+        lastTime = datetime('now', 'localtime')",
+        params![
+            guard.account,
+            guard.failures,
+            guard.logon_failures,
+            guard.blocked_until,
+            if guard.disabled { 1 } else { 0 },
+            guard.rejected_fingerprint
+        ],
+    )
+    .map_err(|e| format!("保存面容解锁保护记录失败：{:?}", e))?;
+    Ok(())
+}
+
+// 手动解锁后清除所有账户的失败次数、冷却和停用状态，被拒绝的密码仍然保留
+pub fn reset_unlock_guards(conn: &Connection) -> Result<usize, String> {
+    conn.execute(
+        "UPDATE unlock_guard SET failures = 0, logon_failures = 0, blocked_until = NULL, disabled = 0,
+        lastTime = datetime('now', 'localtime')
+        WHERE failures <> 0 OR logon_failures <> 0 OR blocked_until IS NOT NULL OR disabled <> 0",
+        [],
+    )
+    .map_err(|e| format!("重置面容解锁保护记录失败：{:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn migrated() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn options_roundtrip() {
+        let conn = migrated();
+        assert_eq!(get_option(&conn, "camera").unwrap(), None);
+        set_option(&conn, "camera", "2").unwrap();
+        assert_eq!(get_option(&conn, "camera").unwrap().as_deref(), Some("2"));
+        set_option(&conn, "camera", "3").unwrap();
+        assert_eq!(get_option(&conn, "camera").unwrap().as_deref(), Some("3"));
+        let options = list_options(&conn).unwrap();
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].key, "camera");
+        assert!(options[0].last_time.is_some());
+    }
+
+    #[test]
+    fn faces_roundtrip() {
+        let conn = migrated();
+        assert_eq!(count_faces(&conn).unwrap(), 0);
+        conn.execute(
+            "INSERT INTO faces (user_name, user_pwd, account_type, face_token, json_data) VALUES ('alice', 'dpapi-machine:v1:AAAA', 'local', 'token', ?1)",
+            params![r#"{"alias":"工作","threshold":50.0,"view":true,"faceDetectionThreshold":0.9}"#],
+        )
+        .unwrap();
+        assert_eq!(count_faces(&conn).unwrap(), 1);
+        let faces = list_faces(&conn).unwrap();
+        assert_eq!(faces[0].user_name, "alice");
+        let extra = faces[0].extra().unwrap();
+        assert_eq!(extra.alias, "工作");
+        assert_eq!(extra.face_detection_threshold, 0.9);
+        assert!(!faces[0].credentials_stale);
+    }
+
+    #[test]
+    fn password_change_clears_stale_flag() {
+        let conn = migrated();
+        conn.execute(
+            "INSERT INTO faces (user_name, user_pwd, account_type, face_token, json_data) VALUES ('alice', 'old', 'local', 'token', '{}')",
+            [],
+        )
+        .unwrap();
+        let id = conn.last_insert_rowid() as i32;
+        set_credentials_stale(&conn, id, true).unwrap();
+        // 只修改其他字段时保留标记
+        conn.execute("UPDATE faces SET user_pwd = 'old', json_data = '[]'", [])
+            .unwrap();
+        assert!(list_faces(&conn).unwrap()[0].credentials_stale);
+        conn.execute("UPDATE faces SET user_pwd = 'new'", [])
+            .unwrap();
+        assert!(!list_faces(&conn).unwrap()[0].credentials_stale);
+    }
+
+    #[test]
+    fn unlock_logs_roundtrip() {
+        let conn = migrated();
+        insert_unlock_log(&conn, 1, true, "success", Some(r#"{"frames":3}"#)).unwrap();
+        let last = insert_unlock_log(&conn, -1, false, "bad_password", None).unwrap();
+        let logs = list_unlock_logs(&conn, None).unwrap();
+        assert_eq!(logs.len(), 2);
+        // 同一秒内插入时按ID倒序
+        assert_eq!(logs[0].id as i64, last);
+        assert!(!logs[0].is_unlock);
+        assert_eq!(logs[0].logon_result.as_deref(), Some("bad_password"));
+        assert_eq!(logs[1].face_id, Some(1));
+        assert_eq!(logs[1].liveness.as_deref(), Some(r#"{"frames":3}"#));
+        assert_eq!(logs[0].liveness, None);
+        assert_eq!(list_unlock_logs(&conn, Some(1)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn template_audit_roundtrip() {
+        let conn = migrated();
+        assert_eq!(secs_since_adaptive_update(&conn, 1).unwrap(), None);
+        insert_template_audit(&conn, 1, AUDIT_ADAPTIVE_ADD, Some(2), Some(0.82), "").unwrap();
+        insert_template_audit(&conn, 1, AUDIT_ROLLBACK, None, None, "删除模板 2").unwrap();
+
+        let secs = secs_since_adaptive_update(&conn, 1).unwrap().unwrap();
+        assert!((0..5).contains(&secs));
+        assert_eq!(secs_since_adaptive_update(&conn, 2).unwrap(), None);
+
+        let audits = list_template_audit(&conn, 1).unwrap();
+        assert_eq!(audits.len(), 2);
+        assert_eq!(audits[0].action, AUDIT_ROLLBACK);
+        assert_eq!(audits[1].template_id, Some(2));
+        assert!((audits[1].score.unwrap() - 0.82).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unlock_guard_roundtrip() {
+        let conn = migrated();
+        assert_eq!(
+            get_unlock_guard(&conn, ".\\alice").unwrap(),
+            AccountGuard::new(".\\alice")
+        );
+        let mut guard = AccountGuard::new(".\\alice");
+        guard.record_logon_failure(100, "hmac-sha256:AAAA", true, true);
+        save_unlock_guard(&conn, &guard).unwrap();
+        assert_eq!(get_unlock_guard(&conn, ".\\alice").unwrap(), guard);
+
+        assert_eq!(reset_unlock_guards(&conn).unwrap(), 1);
+        let reset = get_unlock_guard(&conn, ".\\alice").unwrap();
+        assert_eq!(reset.check(100), Ok(()));
+        assert_eq!(
+            reset.rejected_fingerprint.as_deref(),
+            Some("hmac-sha256:AAAA")
+        );
+        assert_eq!(reset_unlock_guards(&conn).unwrap(), 0);
+    }
+}
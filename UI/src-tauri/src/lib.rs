@@ -1,41 +1,54 @@
 use std::{
     env,
     path::{Path, PathBuf},
-    sync::{
-        atomic::{AtomicBool, AtomicI32},
-        Mutex,
-    },
+    sync::{Mutex, OnceLock},
 };
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use tauri::Manager;
+use tauri::{AppHandle, Manager};
+#[cfg(windows)]
 use windows::Win32::{
     Foundation::HWND,
     System::RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
     UI::Shell::SetWindowSubclass,
 };
 
+pub mod camera;
+pub mod db;
 pub mod modules;
 pub mod proc;
+pub mod recognition;
+pub mod session;
 pub mod utils;
+pub mod worker;
+use db::commands::{
+    get_faces, get_options, get_template_audit, get_unlock_logs, migrate_database,
+};
 use modules::faces::{
-    check_face_from_camera, check_face_from_img, save_face_registration, verify_face,
+    add_face_template, check_face_from_camera, check_face_from_img, list_face_templates,
+    remove_face_template, rollback_face_templates, save_face_registration, verify_face,
 };
 use modules::init::{
     check_admin_privileges, check_camera_status, deploy_core_components, uninstall_init,
 };
-use modules::options::write_to_registry;
+use modules::options::{get_settings, update_settings, write_to_registry};
+use modules::tile_accounts::sync_tile_accounts;
+use camera::FrameSource;
 use opencv::{
     core::Ptr,
+    dnn::Net,
     objdetect::{FaceDetectorYN, FaceRecognizerSF},
-    videoio::VideoCapture,
 };
-use proc::wnd_proc_subclass;
+#[cfg(windows)]
+use proc::{watch_credui_prompts, wnd_proc_subclass};
+#[cfg(windows)]
+use worker::UnlockWorker;
 use tauri_plugin_log::{Target, TargetKind};
 use utils::api::{
     check_global_autostart, disable_global_autostart, enable_global_autostart, get_camera,
     get_now_username, init_model, open_camera, open_directory, stop_camera, test_win_logon,
 };
+use utils::secret_store::encrypt_password;
 
 mod tray;
 use tray::create_system_tray;
@@ -52,10 +65,13 @@ unsafe impl<T> Sync for OpenCVResource<T> {}
 pub struct AppState {
     pub detector: Option<OpenCVResource<Ptr<FaceDetectorYN>>>,
     pub recognizer: Option<OpenCVResource<Ptr<FaceRecognizerSF>>>,
-    pub camera: Option<OpenCVResource<VideoCapture>>,
+    // 前端录入时打开的画面源
+    pub camera: Option<OpenCVResource<Box<dyn FrameSource>>>,
+    // 可选的活体检测防伪模型，resources 目录下没有模型文件时为 None
+    pub anti_spoof: Option<OpenCVResource<Net>>,
 }
 
-// 定义全局只读连接池，用来在解锁中对数据库读操作
+// 全局连接池，由 db 模块创建，解锁回调和前端命令共用
 lazy_static::lazy_static! {
     static ref DB_POOL: Mutex<Option<Pool<SqliteConnectionManager>>> = Mutex::new(None);
     // 不在使用状态管理，因为proc获取不到
@@ -63,6 +79,7 @@ lazy_static::lazy_static! {
         detector: None,
         recognizer: None,
         camera: None,
+        anti_spoof: None,
     });
 
     // 全局只读软件根目录
@@ -83,12 +100,12 @@ lazy_static::lazy_static! {
     };
 }
 
-// 计时器，确定何时调用面容识别代码
-static IS_LOCKED: AtomicBool = AtomicBool::new(false);
-const TIMER_ID_LOCK_CHECK: usize = 1001;
+// 面容解锁的后台线程，窗口回调把锁屏和解锁事件发给它
+#[cfg(windows)]
+static UNLOCK_WORKER: OnceLock<UnlockWorker> = OnceLock::new();
 
-// 全局摄像头索引
-static CAMERA_INDEX: AtomicI32 = AtomicI32::new(0);
+// 全局 AppHandle，用于在命令之外向前端发送事件
+pub static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -132,6 +149,7 @@ pub fn run() {
                     .build(),
             )
             .setup(|app| {
+                let _ = APP_HANDLE.set(app.handle().clone());
                 let _ = create_system_tray(app.app_handle());
                 let window = app.get_webview_window("main").unwrap();
                 #[cfg(debug_assertions)] // 仅在调试(debug)版本中包含此代码
@@ -144,6 +162,10 @@ pub fn run() {
                 {
                     let window = app.get_webview_window("main").unwrap();
                     let hwnd = window.hwnd().unwrap();
+                    // 先启动后台线程，再接收锁屏通知
+                    let _ = UNLOCK_WORKER.set(UnlockWorker::spawn());
+                    // UAC 等 CredUI 提示框由 DLL 通过事件通知
+                    watch_credui_prompts();
                     unsafe {
                         // 注册 WTS 通知
                         let _ =
@@ -185,11 +207,25 @@ pub fn run() {
                 check_face_from_camera,
                 verify_face,
                 save_face_registration,
+                list_face_templates,
+                add_face_template,
+                remove_face_template,
+                rollback_face_templates,
                 // 配置模块
+                get_settings,
+                update_settings,
                 write_to_registry,
+                sync_tile_accounts,
+                // 数据库模块
+                migrate_database,
+                get_faces,
+                get_options,
+                get_unlock_logs,
+                get_template_audit,
                 // 通用api
                 get_now_username,
                 test_win_logon,
+                encrypt_password,
                 init_model,
                 open_camera,
                 stop_camera,
@@ -0,0 +1,132 @@
+// 面容保存的 Windows 密码失效检测
+// 用户修改 Windows 密码后，保存的密码会一直被系统拒绝，这里把面容标记为失效，停止发送并提示重新输入
+use facewinunlock_protocol::SecretString;
+use r2d2_sqlite::rusqlite::Connection;
+use serde::Serialize;
+use tauri::Emitter;
+use tauri_plugin_log::log::{info, warn};
+#[cfg(windows)]
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{CloseHandle, ERROR_LOGON_FAILURE, HANDLE},
+        Security::{LogonUserW, LOGON32_LOGON_INTERACTIVE, LOGON32_PROVIDER_DEFAULT},
+    },
+};
+
+use crate::{
+    db::{self, models::Face, repo},
+    tray,
+    utils::secret_store::{default_store, reveal_password},
+    APP_HANDLE,
+};
+
+// 面容的密码被标记为失效时发给前端的事件，载荷为 StalePayload
+pub const CREDENTIALS_STALE_EVENT: &str = "credentials-stale";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StalePayload {
+    face_id: i32,
+    user_name: String,
+}
+
+// 标记面容的密码已失效，通知前端和托盘
+pub fn mark_stale(conn: &Connection, face: &Face) -> Result<(), String> {
+    repo::set_credentials_stale(conn, face.id, true)?;
+    warn!("面容 {} 保存的密码已失效：{}", face.id, face.user_name);
+    if let Some(app) = APP_HANDLE.get() {
+        let payload = StalePayload {
+            face_id: face.id,
+            user_name: face.user_name.clone(),
+        };
+        if let Err(e) = app.emit(CREDENTIALS_STALE_EVENT, payload) {
+            warn!("发送密码失效通知失败：{}", e);
+        }
+        tray::show_stale_credentials(app, &face.user_name);
+    }
+    Ok(())
+}
+
+// 手动解锁后在桌面上用 LogonUserW 检查保存的密码，用户可能刚刚修改了 Windows 密码
+// 已经失效的面容不再检查，同一账户的同一密码只检查一次，避免检查本身把账户推向锁定
+pub fn verify_stored_credentials() -> Result<(), String> {
+    let conn = db::get_conn()?;
+    let store = default_store();
+    let mut checked: Vec<(String, SecretString, bool)> = Vec::new();
+
+    for face in repo::list_faces(&conn)?
+        .iter()
+        .filter(|face| !face.credentials_stale)
+    {
+        let password = match reveal_password(&store, &face.user_pwd) {
+            Ok(password) => password,
+            Err(e) => {
+                warn!("解密面容 {} 的密码失败：{}", face.id, e);
+                continue;
+            }
+        };
+        let account = face.user_name.to_lowercase();
+        let valid = match checked
+            .iter()
+            .find(|(name, pwd, _)| *name == account && *pwd == password)
+        {
+            Some((_, _, valid)) => *valid,
+            None => match check_logon(face, &password) {
+                Ok(valid) => {
+                    checked.push((account, password, valid));
+                    valid
+                }
+                Err(e) => {
+                    warn!("检查面容 {} 的密码失败：{}", face.id, e);
+                    continue;
+                }
+            },
+        };
+        if !valid {
+            mark_stale(&conn, face)?;
+        }
+    }
+    info!("已检查 {} 个账户保存的密码", checked.len());
+    Ok(())
+}
+
+// 用保存的密码登录一次，返回密码是否正确；账户限制等其他错误无法判断密码，返回 Err
+#[cfg(windows)]
+fn check_logon(face: &Face, password: &SecretString) -> Result<bool, String> {
+    let user_name: Vec<u16> = face.user_name.encode_utf16().chain(Some(0)).collect();
+    // 本地账户在本机验证，联机账户的用户名是邮箱，不需要域
+    let domain: Vec<u16> = ".".encode_utf16().chain(Some(0)).collect();
+    let domain = if face.account_type == "local" {
+        PCWSTR(domain.as_ptr())
+    } else {
+        PCWSTR::null()
+    };
+    let password = password.to_wide();
+    let mut token = HANDLE::default();
+    let result = unsafe {
+        LogonUserW(
+            PCWSTR(user_name.as_ptr()),
+            domain,
+            PCWSTR(password.as_ptr()),
+            LOGON32_LOGON_INTERACTIVE,
+            LOGON32_PROVIDER_DEFAULT,
+            &mut token,
+        )
+    };
+    match result {
+        Ok(()) => {
+            unsafe {
+                let _ = CloseHandle(token);
+            }
+            Ok(true)
+        }
+        Err(e) if e.code() == ERROR_LOGON_FAILURE.to_hresult() => Ok(false),
+        Err(e) => Err(format!("{:?}", e)),
+    }
+}
+
+#[cfg(not(windows))]
+fn check_logon(_face: &Face, _password: &SecretString) -> Result<bool, String> {
+    Err(String::from("当前系统无法验证 Windows 密码"))
+}
@@ -4,7 +4,18 @@ use std::{
     path::PathBuf,
 };
 
-use crate::{utils::custom_result::CustomResult, APP_STATE, ROOT_DIR};
+use crate::{
+    camera::read_frame,
+    db::{get_conn, repo},
+    modules::{options::Settings, quality},
+    recognition::{
+        gallery::FaceGallery,
+        quality::{QualityReport, QualityThresholds},
+        selection::{order_by_size, select, FaceBox, FacePolicy, Selection},
+    },
+    utils::custom_result::CustomResult,
+    APP_STATE, ROOT_DIR,
+};
 use base64::{engine::general_purpose, Engine};
 use opencv::{
     core::{Mat, Point, Rect, Scalar, Size, Vector},
@@ -12,49 +23,25 @@ use opencv::{
     objdetect::FaceRecognizerSF_DisType,
     prelude::*,
 };
-use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FaceDescriptor {
-    pub name: String,
-    pub feature: Vec<f32>,
+// 将 OpenCV 的特征 Mat 转换为 Vec，Mat 必须是连续的
+pub fn feature_to_vec(feature_mat: &Mat) -> Result<Vec<f32>, String> {
+    let data = feature_mat
+        .data_typed::<f32>()
+        .map_err(|e| format!("读取特征数据失败: {}", e))?;
+    Ok(data.to_vec())
 }
 
-impl FaceDescriptor {
-    // 将 OpenCV 的 Mat 转换为可序列化的结构
-    pub fn from_mat(name: &str, feature_mat: &Mat) -> Result<Self, Box<dyn std::error::Error>> {
-        // 确保 Mat 是连续的，然后转换为 Vec
-        let mut feature_vec: Vec<f32> = vec![0.0f32; feature_mat.total()];
-        let data = feature_mat.data_typed::<f32>()?;
-        feature_vec.copy_from_slice(data);
-
-        Ok(FaceDescriptor {
-            name: name.to_string(),
-            feature: feature_vec,
-        })
-    }
-
-    // 将特征向量还原回 OpenCV Mat
-    pub fn to_mat(&self) -> Result<Mat, Box<dyn std::error::Error>> {
-        // 从切片创建原始 Mat (默认为 N 行 1 列)
-        let m = Mat::from_slice(&self.feature)?;
-
-        // 变换形状为 1 行 128 列
-        // reshape 返回的是 Result<BoxedRef<Mat>, ...>
-        let m_reshaped = m.reshape(1, 1)?;
-
-        // 使用 try_clone() 进行深拷贝，转回独立的 Mat 对象
-        let final_mat = m_reshaped.try_clone()?;
-
-        Ok(final_mat)
-    }
-}
+// 画面中有多张人脸且策略为拒绝时的错误信息，解锁流程据此与其他错误区分
+pub const MULTIPLE_FACES: &str = "画面中有多张人脸";
 
 struct CaptureResponse {
     display_base64: String, // 带框的
     raw_base64: String,     // 不带框的（仅缩放）
+    // 每张人脸的位置和录入质量，按面积从大到小编号，与 face_index 对应
+    faces: Vec<serde_json::Value>,
 }
 
 // 从图片中检测人脸
@@ -78,14 +65,16 @@ pub fn check_face_from_img(
         ));
     }
 
-    let result = detect_and_format(src, face_detection_threshold)
+    let thresholds = quality_thresholds()?;
+    let result = detect_and_format(src, face_detection_threshold, &thresholds)
         .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
 
     Ok(CustomResult::success(
         None,
         Some(json!({
             "display_base64": result.display_base64,
-            "raw_base64": result.raw_base64
+            "raw_base64": result.raw_base64,
+            "faces": result.faces
         })),
     ))
 }
@@ -96,14 +85,16 @@ pub fn check_face_from_camera(face_detection_threshold: f32) -> Result<CustomRes
     let frame = read_mat_from_camera()
         .map_err(|e| CustomResult::error(Some(format!("摄像头读取失败: {}", e)), None))?;
 
-    let result = detect_and_format(frame, face_detection_threshold)
+    let thresholds = quality_thresholds()?;
+    let result = detect_and_format(frame, face_detection_threshold, &thresholds)
         .map_err(|e| CustomResult::error(Some(format!("OpenCV 检测失败: {}", e)), None))?;
 
     Ok(CustomResult::success(
         None,
         Some(json!({
             "display_base64": result.display_base64,
-            "raw_base64": result.raw_base64
+            "raw_base64": result.raw_base64,
+            "faces": result.faces
         })),
     ))
 }
@@ -113,6 +104,7 @@ pub fn check_face_from_camera(face_detection_threshold: f32) -> Result<CustomRes
 pub async fn verify_face(
     reference_base64: String,
     face_detection_threshold: f32,
+    face_index: Option<usize>,
 ) -> Result<CustomResult, CustomResult> {
     let frame = read_mat_from_camera()
         .map_err(|e| CustomResult::error(Some(format!("摄像头读取失败: {}", e)), None))?;
@@ -124,9 +116,10 @@ pub async fn verify_face(
     let ref_img = imgcodecs::imdecode(&v, opencv::imgcodecs::IMREAD_COLOR)
         .map_err(|e| CustomResult::error(Some(format!("从bse64读取图片失败: {}", e)), None))?;
 
-    let ref_feature = get_feature(&ref_img, face_detection_threshold)
+    let ref_feature = get_feature(&ref_img, face_detection_threshold, face_index.unwrap_or(0))
         .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
-    let cur_feature = get_feature(&frame, face_detection_threshold)
+    // 实时画面中取最大的人脸，与录入的人通常离摄像头最近
+    let cur_feature = get_feature(&frame, face_detection_threshold, 0)
         .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
 
     let app_state = APP_STATE
@@ -170,6 +163,8 @@ pub fn save_face_registration(
     name: String,
     reference_base64: String,
     face_detection_threshold: f32,
+    label: Option<String>,
+    face_index: Option<usize>,
 ) -> Result<CustomResult, CustomResult> {
     // 获取软件数据目录并创建 faces 文件夹
     let path = ROOT_DIR.join("faces");
@@ -180,27 +175,36 @@ pub fn save_face_registration(
         })?;
     }
 
-    // 解码图片
-    let ref_bytes = general_purpose::STANDARD
-        .decode(reference_base64)
-        .map_err(|e| CustomResult::error(Some(format!("图片解码失败: {}", e)), None))?;
-    let v = Vector::<u8>::from_iter(ref_bytes);
-    let ref_img = imgcodecs::imdecode(&v, opencv::imgcodecs::IMREAD_COLOR)
-        .map_err(|e| CustomResult::error(Some(format!("从bse64读取图片失败: {}", e)), None))?;
-
-    let feature_mat = get_feature(&ref_img, face_detection_threshold)
-        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    let ref_img = decode_base64_image(&reference_base64)?;
+    let (face, feature_mat) =
+        detect_face_at(&ref_img, face_detection_threshold, face_index.unwrap_or(0))
+            .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
 
-    let descriptor = FaceDescriptor::from_mat(&name, &feature_mat)
+    // 录入的是面容的第一个模板，质量不合格时拒绝保存
+    let report = quality::assess_image(&ref_img, &face, &quality_thresholds()?)
+        .map_err(|e| CustomResult::error(Some(format!("评估面容质量失败: {}", e)), None))?;
+    if !report.passed {
+        return Err(CustomResult::error(
+            Some(format!("面容质量不合格：{}", report.failures())),
+            Some(json!({"quality": report})),
+        ));
+    }
+    let feature = feature_to_vec(&feature_mat)
         .map_err(|e| CustomResult::error(Some(format!("特征描述失败: {}", e)), None))?;
 
+    // 第一个模板，之后可以通过 add_face_template 继续添加
+    let mut gallery = FaceGallery::new(&name);
+    gallery
+        .add(label.as_deref().unwrap_or("正脸"), feature)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
     let base_name = Uuid::new_v4();
 
     // 保存特征
     let feature_name = format!("{}.face", base_name);
     let mut feature_path = path.clone();
     feature_path.push(feature_name);
-    save_face_data(&feature_path, &descriptor)
+    save_face_data(&feature_path, &gallery)
         .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
 
     // 保存图片
@@ -233,8 +237,224 @@ pub fn save_face_registration(
     ))
 }
 
-// 提取特征点
-pub fn get_feature(img: &Mat, face_detection_threshold: f32) -> Result<Mat, String> {
+// 列出面容的全部模板，不返回特征数据
+#[tauri::command]
+pub fn list_face_templates(face_token: String) -> Result<CustomResult, CustomResult> {
+    let path = face_data_path(&face_token)?;
+    let gallery = load_face_data(&path)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败: {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!(template_summaries(&gallery))),
+    ))
+}
+
+// 给已有的面容添加一个模板，例如侧脸、戴眼镜、弱光
+#[tauri::command]
+pub fn add_face_template(
+    face_token: String,
+    label: String,
+    reference_base64: String,
+    face_detection_threshold: f32,
+    face_index: Option<usize>,
+) -> Result<CustomResult, CustomResult> {
+    let path = face_data_path(&face_token)?;
+    let mut gallery = load_face_data(&path)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败: {}", e)), None))?;
+
+    let ref_img = decode_base64_image(&reference_base64)?;
+    let feature = feature_from_image(&ref_img, face_detection_threshold, face_index.unwrap_or(0))?;
+    let id = gallery
+        .add(&label, feature)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    save_face_data(&path, &gallery)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"id": id, "templates": template_summaries(&gallery)})),
+    ))
+}
+
+// 删除面容的一个模板，至少保留一个
+#[tauri::command]
+pub fn remove_face_template(
+    face_token: String,
+    template_id: u32,
+) -> Result<CustomResult, CustomResult> {
+    let path = face_data_path(&face_token)?;
+    let mut gallery = load_face_data(&path)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败: {}", e)), None))?;
+    gallery
+        .remove(template_id)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    save_face_data(&path, &gallery)
+        .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!(template_summaries(&gallery))),
+    ))
+}
+
+// 删除所有解锁后自动添加的模板，恢复为录入时的模板
+#[tauri::command]
+pub fn rollback_face_templates(face_token: String) -> Result<CustomResult, CustomResult> {
+    let path = face_data_path(&face_token)?;
+    let mut gallery = load_face_data(&path)
+        .map_err(|e| CustomResult::error(Some(format!("加载面容数据失败: {}", e)), None))?;
+    let removed = gallery.rollback_adaptive();
+
+    if !removed.is_empty() {
+        save_face_data(&path, &gallery)
+            .map_err(|e| CustomResult::error(Some(format!("保存特征数据失败: {}", e)), None))?;
+        let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+        let face = repo::find_face_by_token(&conn, &face_token)
+            .map_err(|e| CustomResult::error(Some(e), None))?;
+        if let Some(face) = face {
+            let detail = format!("删除自动更新的模板 {:?}", removed);
+            repo::insert_template_audit(&conn, face.id, repo::AUDIT_ROLLBACK, None, None, &detail)
+                .map_err(|e| CustomResult::error(Some(e), None))?;
+        }
+    }
+    Ok(CustomResult::success(
+        None,
+        Some(json!(template_summaries(&gallery))),
+    ))
+}
+
+// 设置中的录入质量阈值
+fn quality_thresholds() -> Result<QualityThresholds, CustomResult> {
+    let conn = get_conn().map_err(|e| CustomResult::error(Some(e), None))?;
+    let settings = Settings::load(&conn).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(settings.quality)
+}
+
+// 前端传来的 face_token 只能是 uuid，避免拼出 faces 目录以外的路径
+fn face_data_path(face_token: &str) -> Result<PathBuf, CustomResult> {
+    if face_token.is_empty()
+        || !face_token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(CustomResult::error(
+            Some(format!("无效的面容标识: {}", face_token)),
+            None,
+        ));
+    }
+    Ok(ROOT_DIR.join("faces").join(format!("{}.face", face_token)))
+}
+
+fn template_summaries(gallery: &FaceGallery) -> Vec<serde_json::Value> {
+    gallery
+        .templates
+        .iter()
+        .map(|t| json!({"id": t.id, "label": t.label, "adaptive": t.adaptive}))
+        .collect()
+}
+
+// 解码前端传来的 base64 图片
+fn decode_base64_image(reference_base64: &str) -> Result<Mat, CustomResult> {
+    let ref_bytes = general_purpose::STANDARD
+        .decode(reference_base64)
+        .map_err(|e| CustomResult::error(Some(format!("图片解码失败: {}", e)), None))?;
+    let v = Vector::<u8>::from_iter(ref_bytes);
+    imgcodecs::imdecode(&v, opencv::imgcodecs::IMREAD_COLOR)
+        .map_err(|e| CustomResult::error(Some(format!("从bse64读取图片失败: {}", e)), None))
+}
+
+fn feature_from_image(
+    img: &Mat,
+    face_detection_threshold: f32,
+    face_index: usize,
+) -> Result<Vec<f32>, CustomResult> {
+    let feature_mat = get_feature(img, face_detection_threshold, face_index)
+        .map_err(|e| CustomResult::error(Some(format!("特征提取失败: {}", e)), None))?;
+    feature_to_vec(&feature_mat)
+        .map_err(|e| CustomResult::error(Some(format!("特征描述失败: {}", e)), None))
+}
+
+// 提取特征点，face_index 为按面积从大到小的编号
+pub fn get_feature(
+    img: &Mat,
+    face_detection_threshold: f32,
+    face_index: usize,
+) -> Result<Mat, String> {
+    detect_face_at(img, face_detection_threshold, face_index).map(|(_, feature)| feature)
+}
+
+// 检测人脸并提取特征，返回 (YuNet 的检测结果行, 特征)
+// 检测结果行包含人脸框、5 个关键点和置信度，活体检测需要用到
+// 画面中有多张人脸时按 policy 选择，策略拒绝时返回以 MULTIPLE_FACES 开头的错误
+pub fn detect_face(
+    img: &Mat,
+    face_detection_threshold: f32,
+    policy: FacePolicy,
+) -> Result<(Mat, Mat), String> {
+    let size = img.size().map_err(|e| format!("获取Mat尺寸失败: {}", e))?;
+    let frame_size = (size.width as f32, size.height as f32);
+    detect_face_with(img, face_detection_threshold, |boxes| {
+        match select(boxes, frame_size, policy) {
+            Selection::Face(index) => Ok(index),
+            Selection::NoFace => Err(String::from("未检测到人脸")),
+            Selection::Multiple(count) => Err(format!("{}：{} 张", MULTIPLE_FACES, count)),
+        }
+    })
+}
+
+// 解锁流程使用的人脸检测，返回值与 detect_face 相同，测试时可以换成不需要模型的实现
+pub trait FaceDetector {
+    fn detect(
+        &mut self,
+        img: &Mat,
+        face_detection_threshold: f32,
+        policy: FacePolicy,
+    ) -> Result<(Mat, Mat), String>;
+}
+
+// 使用 APP_STATE 中已加载的 YuNet 和 SFace 模型
+pub struct ModelDetector;
+
+impl FaceDetector for ModelDetector {
+    fn detect(
+        &mut self,
+        img: &Mat,
+        face_detection_threshold: f32,
+        policy: FacePolicy,
+    ) -> Result<(Mat, Mat), String> {
+        detect_face(img, face_detection_threshold, policy)
+    }
+}
+
+// 录入时由用户选择人脸，face_index 为按面积从大到小的编号，与 check_face_from_* 返回的顺序一致
+pub fn detect_face_at(
+    img: &Mat,
+    face_detection_threshold: f32,
+    face_index: usize,
+) -> Result<(Mat, Mat), String> {
+    detect_face_with(img, face_detection_threshold, |boxes| {
+        if boxes.is_empty() {
+            return Err(String::from("未检测到人脸"));
+        }
+        order_by_size(boxes)
+            .get(face_index)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "人脸编号 {} 不存在，共检测到 {} 张人脸",
+                    face_index + 1,
+                    boxes.len()
+                )
+            })
+    })
+}
+
+// 检测全部人脸，由 choose 选出一张后提取特征
+fn detect_face_with(
+    img: &Mat,
+    face_detection_threshold: f32,
+    choose: impl FnOnce(&[FaceBox]) -> Result<usize, String>,
+) -> Result<(Mat, Mat), String> {
     let mut app_state = APP_STATE
         .lock()
         .map_err(|e| format!("获取app状态失败 {}", e))?;
@@ -264,29 +484,51 @@ pub fn get_feature(img: &Mat, face_detection_threshold: f32) -> Result<Mat, Stri
         faces
     };
 
-    if faces.rows() > 0 {
-        let mut aligned = Mat::default();
-        let mut feature = Mat::default();
+    let index = choose(&face_boxes(&faces)?)? as i32;
+    let face = faces
+        .row(index)
+        .and_then(|row| row.try_clone())
+        .map_err(|e| format!("读取检测结果失败: {}", e))?;
 
-        let recognizer = app_state.recognizer.as_mut().unwrap();
-        // 人脸对齐与裁剪
-        recognizer
-            .inner
-            .align_crop(img, &faces.row(0).unwrap(), &mut aligned)
-            .map_err(|e| format!("人脸对齐失败: {}", e))?;
-        // 提取特征
-        recognizer
-            .inner
-            .feature(&aligned, &mut feature)
-            .map_err(|e| format!("特征提取失败: {}", e))?;
+    let mut aligned = Mat::default();
+    let mut feature = Mat::default();
 
-        Ok(feature.clone())
-    } else {
-        Err("未检测到人脸".into())
-    }
+    let recognizer = app_state.recognizer.as_mut().unwrap();
+    // 人脸对齐与裁剪
+    recognizer
+        .inner
+        .align_crop(img, &face, &mut aligned)
+        .map_err(|e| format!("人脸对齐失败: {}", e))?;
+    // 提取特征
+    recognizer
+        .inner
+        .feature(&aligned, &mut feature)
+        .map_err(|e| format!("特征提取失败: {}", e))?;
+
+    Ok((face, feature.clone()))
 }
 
-// 从摄像头中读取视频帧
+// 读取检测结果中每张人脸的人脸框
+fn face_boxes(faces: &Mat) -> Result<Vec<FaceBox>, String> {
+    (0..faces.rows())
+        .map(|row| {
+            let value = |col: i32| {
+                faces
+                    .at_2d::<f32>(row, col)
+                    .copied()
+                    .map_err(|e| format!("图片坐标获取失败: {}", e))
+            };
+            Ok(FaceBox {
+                x: value(0)?,
+                y: value(1)?,
+                w: value(2)?,
+                h: value(3)?,
+            })
+        })
+        .collect()
+}
+
+// 从前端打开的画面源中读取视频帧
 pub fn read_mat_from_camera() -> Result<Mat, String> {
     let mut app_state = APP_STATE
         .lock()
@@ -297,18 +539,7 @@ pub fn read_mat_from_camera() -> Result<Mat, String> {
         return Err(String::from("请先打开摄像头"));
     }
 
-    let cam = app_state.camera.as_mut().unwrap();
-    let mut frame = Mat::default();
-
-    cam.inner
-        .read(&mut frame)
-        .map_err(|e| format!("摄像头读取失败: {}", e))?;
-
-    if frame.empty() {
-        return Err(String::from("抓取到空帧"));
-    }
-
-    Ok(frame)
+    read_frame(app_state.camera.as_mut().unwrap().inner.as_mut())
 }
 
 // 等比例缩放Mat
@@ -338,8 +569,12 @@ fn resize_mat(src: &Mat, max_dim: f32) -> Result<Mat, String> {
     Ok(resize_mat)
 }
 
-// 处理人脸特征点
-fn detect_and_format(src: Mat, face_detection_threshold: f32) -> Result<CaptureResponse, String> {
+// 处理人脸特征点，同时评估录入质量
+fn detect_and_format(
+    src: Mat,
+    face_detection_threshold: f32,
+    thresholds: &QualityThresholds,
+) -> Result<CaptureResponse, String> {
     let mut app_state = APP_STATE
         .lock()
         .map_err(|e| format!("获取app状态失败 {}", e))?;
@@ -371,20 +606,23 @@ fn detect_and_format(src: Mat, face_detection_threshold: f32) -> Result<CaptureR
         .detect(&display_mat, &mut faces)
         .map_err(|e| format!("OpenCV 检测失败: {}", e))?;
 
-    if faces.rows() > 0 {
-        let x = *faces
-            .at_2d::<f32>(0, 0)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let y = *faces
-            .at_2d::<f32>(0, 1)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let w = *faces
-            .at_2d::<f32>(0, 2)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
-        let h = *faces
-            .at_2d::<f32>(0, 3)
-            .map_err(|e| format!("图片坐标获取失败: {}", e))?;
+    let boxes = face_boxes(&faces)?;
+    if boxes.is_empty() {
+        return Err(String::from("未检测到人脸"));
+    }
 
+    // 按面积从大到小编号，保存时前端传回编号选择人脸
+    let mut face_list = Vec::with_capacity(boxes.len());
+    for (number, index) in order_by_size(&boxes).into_iter().enumerate() {
+        let row = index as i32;
+        // 在绘制之前的图上评估，与保存时使用的图一致
+        let face = faces
+            .row(row)
+            .and_then(|row| row.try_clone())
+            .map_err(|e| format!("读取检测结果失败: {}", e))?;
+        let quality: QualityReport = quality::assess_image(&raw_mat, &face, thresholds)?;
+
+        let FaceBox { x, y, w, h } = boxes[index];
         let color = Scalar::new(255.0, 242.0, 0.0, 0.0);
         imgproc::rectangle(
             &mut display_mat,
@@ -395,11 +633,26 @@ fn detect_and_format(src: Mat, face_detection_threshold: f32) -> Result<CaptureR
             0,
         )
         .map_err(|e| format!("图片绘制失败: {}", e))?;
+        // 多张人脸时标出编号，编号不影响检测结果，绘制失败可以忽略
+        if boxes.len() > 1 {
+            imgproc::put_text(
+                &mut display_mat,
+                &(number + 1).to_string(),
+                Point::new(x as i32, (y as i32 - 6).max(16)),
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                0.8,
+                color,
+                2,
+                imgproc::LINE_AA,
+                false,
+            )
+            .ok();
+        }
 
         // 绘制五官
         for i in (4..14).step_by(2) {
             // 五官不影响检测结果，所以绘制失败可以忽略
-            if let (Ok(px), Ok(py)) = (faces.at_2d::<f32>(0, i), faces.at_2d::<f32>(0, i + 1)) {
+            if let (Ok(px), Ok(py)) = (faces.at_2d::<f32>(row, i), faces.at_2d::<f32>(row, i + 1)) {
                 imgproc::circle(
                     &mut display_mat,
                     Point::new(*px as i32, *py as i32),
@@ -413,13 +666,18 @@ fn detect_and_format(src: Mat, face_detection_threshold: f32) -> Result<CaptureR
             }
         }
 
-        Ok(CaptureResponse {
-            display_base64: mat_to_base64(&display_mat),
-            raw_base64: mat_to_base64(&raw_mat),
-        })
-    } else {
-        Err(String::from("未检测到人脸"))
+        face_list.push(json!({
+            "index": number,
+            "rect": [x, y, w, h],
+            "quality": quality
+        }));
     }
+
+    Ok(CaptureResponse {
+        display_base64: mat_to_base64(&display_mat),
+        raw_base64: mat_to_base64(&raw_mat),
+        faces: face_list,
+    })
 }
 
 fn mat_to_base64(mat: &Mat) -> String {
@@ -431,22 +689,25 @@ fn mat_to_base64(mat: &Mat) -> String {
     )
 }
 
-// 保存人脸数据到文件
-fn save_face_data(
+// 保存人脸数据到文件，先写临时文件再替换，避免写到一半时损坏原有模板
+pub fn save_face_data(
     path: &std::path::PathBuf,
-    data: &FaceDescriptor,
+    data: &FaceGallery,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let encoded: Vec<u8> = bincode::serialize(data)?;
-    let mut file = std::fs::File::create(path)?;
+    let encoded: Vec<u8> = data.to_bytes()?;
+    let tmp_path = path.with_extension("face.tmp");
+    let mut file = std::fs::File::create(&tmp_path)?;
     file.write_all(&encoded)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path)?;
     Ok(())
 }
 
-// 从文件加载人脸数据
-pub fn load_face_data(path: &PathBuf) -> Result<FaceDescriptor, Box<dyn std::error::Error>> {
+// 从文件加载人脸数据，兼容旧版本只有一个特征的文件
+pub fn load_face_data(path: &PathBuf) -> Result<FaceGallery, Box<dyn std::error::Error>> {
     let mut file = std::fs::File::open(path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
-    let decoded: FaceDescriptor = bincode::deserialize(&buffer)?;
-    Ok(decoded)
+    Ok(FaceGallery::from_bytes(&buffer)?)
 }
@@ -1,50 +1,50 @@
-use crate::modules::options::{write_to_registry, RegistryItem};
+use crate::utils::custom_result::CustomResult;
+use opencv::videoio::{self, VideoCaptureTraitConst};
+// 核心组件是 Windows 的凭据提供程序，部署、卸载和权限检查只在 Windows 上可用
+#[cfg(windows)]
 use crate::{
-    utils::{
-        api::{check_global_autostart, disable_global_autostart},
-        custom_result::CustomResult,
-    },
+    modules::options::write_registry_values,
+    utils::api::{check_global_autostart, disable_global_autostart},
     ROOT_DIR,
 };
-use opencv::videoio::{self, VideoCaptureTraitConst};
+#[cfg(windows)]
+use facewinunlock_protocol::{
+    handshake::{SECRET_REG_PATH, SECRET_REG_VALUE},
+    PipeSecret,
+};
+#[cfg(windows)]
 use serde_json::json;
+#[cfg(windows)]
 use std::fs;
-use windows::Win32::{
-    Foundation::{CloseHandle, HANDLE},
-    Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
-    System::Threading::{GetCurrentProcess, OpenProcessToken},
+#[cfg(windows)]
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{CloseHandle, LocalFree, HANDLE, HLOCAL},
+        Security::{
+            Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            GetTokenInformation, TokenElevation, DACL_SECURITY_INFORMATION,
+            PROTECTED_DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, TOKEN_ELEVATION,
+            TOKEN_QUERY,
+        },
+        System::{
+            Registry::{RegSetKeySecurity, HKEY},
+            Threading::{GetCurrentProcess, OpenProcessToken},
+        },
+    },
 };
+#[cfg(windows)]
 use winreg::enums::*;
+#[cfg(windows)]
 use winreg::RegKey;
 
 // 检查是否具有管理员权限
 #[tauri::command]
 pub fn check_admin_privileges() -> Result<CustomResult, CustomResult> {
-    unsafe {
-        let mut token: HANDLE = HANDLE::default();
-        let success = OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token);
-        if let Ok(_) = success {
-            let mut elevation = TOKEN_ELEVATION::default();
-            let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as u32;
-
-            let result = GetTokenInformation(
-                token,
-                TokenElevation,
-                Some(&mut elevation as *mut _ as *mut _),
-                size,
-                &mut size,
-            );
-
-            let _ = CloseHandle(token);
-            if result.is_ok() && elevation.TokenIsElevated != 0 {
-                return Ok(CustomResult::success(None, None));
-            }
-        } else {
-            return Err(CustomResult::error(
-                Some(format!("打开与进程关联的访问令牌失败：{:?}", success.err())),
-                None,
-            ));
-        }
+    if is_elevated().map_err(|e| CustomResult::error(Some(e), None))? {
+        return Ok(CustomResult::success(None, None));
     }
     return Err(CustomResult::error(
         Some(String::from("无管理员权限，请右键 ‘以管理员身份’ 运行软件")),
@@ -52,6 +52,34 @@ pub fn check_admin_privileges() -> Result<CustomResult, CustomResult> {
     ));
 }
 
+// 当前进程是否以管理员身份运行
+#[cfg(windows)]
+fn is_elevated() -> Result<bool, String> {
+    unsafe {
+        let mut token: HANDLE = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)
+            .map_err(|e| format!("打开与进程关联的访问令牌失败：{:?}", e))?;
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = std::mem::size_of::<TOKEN_ELEVATION>() as u32;
+
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut _ as *mut _),
+            size,
+            &mut size,
+        );
+
+        let _ = CloseHandle(token);
+        Ok(result.is_ok() && elevation.TokenIsElevated != 0)
+    }
+}
+
+#[cfg(not(windows))]
+fn is_elevated() -> Result<bool, String> {
+    Err(String::from("当前系统无法检查管理员权限"))
+}
+
 // 检查摄像头是否可用
 #[tauri::command]
 pub fn check_camera_status() -> Result<CustomResult, CustomResult> {
@@ -79,6 +107,7 @@ pub fn check_camera_status() -> Result<CustomResult, CustomResult> {
 }
 
 // 复制 DLL 并写入注册表
+#[cfg(windows)]
 #[tauri::command]
 pub fn deploy_core_components() -> Result<CustomResult, CustomResult> {
     let dll_name = "FaceWinUnlock-Tauri.dll";
@@ -158,6 +187,50 @@ pub fn deploy_core_components() -> Result<CustomResult, CustomResult> {
             )
         })?;
 
+    // 注册凭据提供程序过滤器，是否隐藏系统密码提供程序由软件设置决定
+    let filter_clsid = "{8a7b9c6d-4e5f-89a0-8b7c-6d5e4f3e2d1d}";
+    let filter_path = format!(
+        "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Authentication\\Credential Provider Filters\\{}",
+        filter_clsid
+    );
+    let (filter_key, _) = hk_lm
+        .create_subkey(filter_path)
+        .map_err(|e| CustomResult::error(Some(format!("无法创建注册表项(Filter): {}", e)), None))?;
+    filter_key
+        .set_value("", &"FaceWinUnlock-Tauri Filter")
+        .map_err(|e| CustomResult::error(Some(format!("无法设置注册表项(Filter): {}", e)), None))?;
+
+    let filter_clsid_path = format!("CLSID\\{}", filter_clsid);
+    let (filter_clsid_key, _) = hk_cr
+        .create_subkey(&filter_clsid_path)
+        .map_err(|e| CustomResult::error(Some(format!("无法创建注册表项(Filter CLSID): {}", e)), None))?;
+    filter_clsid_key
+        .set_value("", &"FaceWinUnlock-Tauri Filter")
+        .map_err(|e| CustomResult::error(Some(format!("无法设置注册表项(Filter CLSID): {}", e)), None))?;
+
+    let (filter_inproc_key, _) = hk_cr
+        .create_subkey(format!("{}\\InprocServer32", filter_clsid_path))
+        .map_err(|e| {
+            CustomResult::error(
+                Some(format!("无法创建注册表项(Filter InprocServer32): {}", e)),
+                None,
+            )
+        })?;
+    filter_inproc_key.set_value("", &target_path).map_err(|e| {
+        CustomResult::error(
+            Some(format!("无法设置注册表项(Filter InprocServer32): {}", e)),
+            None,
+        )
+    })?;
+    filter_inproc_key
+        .set_value("ThreadingModel", &"Apartment")
+        .map_err(|e| {
+            CustomResult::error(
+                Some(format!("无法设置注册表项(Filter ThreadingModel): {}", e)),
+                None,
+            )
+        })?;
+
     // 创建dll日志路径
     let path = ROOT_DIR.join("logs");
     if !path.exists() {
@@ -172,21 +245,79 @@ pub fn deploy_core_components() -> Result<CustomResult, CustomResult> {
             None,
         ));
     }
-    // 写入dll日志路径
-    write_to_registry(vec![RegistryItem {
-        key: String::from("DLL_LOG_PATH"),
-        value: path_str.unwrap().to_string(),
-    }])?;
+    // DLL 只接受该路径的进程发来的凭据
+    let app_path = std::env::current_exe()
+        .map_err(|e| CustomResult::error(Some(format!("获取软件路径失败: {}", e)), None))?;
+    let app_path = app_path.to_str().ok_or(CustomResult::error(
+        Some(String::from("软件路径包含无效字符")),
+        None,
+    ))?;
+
+    // 写入dll日志路径和软件路径
+    write_registry_values(&[("DLL_LOG_PATH", path_str.unwrap()), ("APP_PATH", app_path)])
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+
+    // 每次部署都重新生成管道密钥
+    write_pipe_secret()?;
 
     Ok(CustomResult::success(None, None))
 }
 
+#[cfg(not(windows))]
+#[tauri::command]
+pub fn deploy_core_components() -> Result<CustomResult, CustomResult> {
+    Err(CustomResult::error(
+        Some(String::from("当前系统无法部署核心组件")),
+        None,
+    ))
+}
+
+// 生成管道共享密钥，写入只有 SYSTEM 和管理员可以访问的注册表子项
+#[cfg(windows)]
+fn write_pipe_secret() -> Result<(), CustomResult> {
+    let secret = PipeSecret::generate()
+        .map_err(|e| CustomResult::error(Some(format!("生成管道密钥失败: {}", e)), None))?;
+
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let (secret_key, _) = hklm
+        .create_subkey(SECRET_REG_PATH)
+        .map_err(|e| CustomResult::error(Some(format!("无法创建注册表项(Secret): {}", e)), None))?;
+
+    // 先收紧权限，再写入密钥
+    unsafe {
+        let mut sd = PSECURITY_DESCRIPTOR::default();
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            w!("D:P(A;OICI;KA;;;SY)(A;OICI;KA;;;BA)"),
+            SDDL_REVISION_1,
+            &mut sd,
+            None,
+        )
+        .map_err(|e| CustomResult::error(Some(format!("创建安全描述符失败: {}", e)), None))?;
+        let status = RegSetKeySecurity(
+            HKEY(secret_key.raw_handle() as _),
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            sd,
+        );
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        status.ok().map_err(|e| {
+            CustomResult::error(Some(format!("设置注册表项(Secret)权限失败: {}", e)), None)
+        })?;
+    }
+
+    secret_key
+        .set_value(SECRET_REG_VALUE, &secret.to_hex())
+        .map_err(|e| CustomResult::error(Some(format!("写入管道密钥失败: {}", e)), None))?;
+    Ok(())
+}
+
 // 卸载dll
+#[cfg(windows)]
 #[tauri::command]
 pub fn uninstall_init() -> Result<CustomResult, CustomResult> {
     // 删除注册表
     const MAIN_REG_PATH: &str = "SOFTWARE\\facewinunlock-tauri";
     const CLSID: &str = "{8a7b9c6d-4e5f-89a0-8b7c-6d5e4f3e2d1c}";
+    const FILTER_CLSID: &str = "{8a7b9c6d-4e5f-89a0-8b7c-6d5e4f3e2d1d}";
 
     let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
     let hkcr = RegKey::predef(HKEY_CLASSES_ROOT);
@@ -204,6 +335,20 @@ pub fn uninstall_init() -> Result<CustomResult, CustomResult> {
     hkcr.delete_subkey_all(&clsid_path)
         .map_err(|e| CustomResult::error(Some(format!("删除注册表项(CLSID)失败: {}", e)), None))?;
 
+    // 删除过滤器的注册表项，旧版本部署时没有注册过滤器，不存在时忽略
+    let filter_path = format!(
+        "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\Authentication\\Credential Provider Filters\\{}",
+        FILTER_CLSID
+    );
+    let filter_clsid_path = format!("CLSID\\{}", FILTER_CLSID);
+    for (root, path) in [(&hklm, filter_path), (&hkcr, filter_clsid_path)] {
+        if let Err(e) = root.delete_subkey_all(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(CustomResult::error(Some(format!("删除注册表项(Filter)失败: {}", e)), None));
+            }
+        }
+    }
+
     // 删除程序DLL设置的注册表
     hklm.delete_subkey_all(MAIN_REG_PATH)
         .map_err(|e| CustomResult::error(Some(format!("删除注册表项(DLL)失败: {}", e)), None))?;
@@ -222,3 +367,12 @@ pub fn uninstall_init() -> Result<CustomResult, CustomResult> {
 
     Ok(CustomResult::success(None, None))
 }
+
+#[cfg(not(windows))]
+#[tauri::command]
+pub fn uninstall_init() -> Result<CustomResult, CustomResult> {
+    Err(CustomResult::error(
+        Some(String::from("当前系统没有部署核心组件")),
+        None,
+    ))
+}
@@ -0,0 +1,240 @@
+// 从摄像头帧中计算活体检测需要的观测值，判断逻辑在 recognition::liveness
+use opencv::{
+    core::{self, Mat, Rect, Scalar, Size, Vector},
+    dnn, imgproc,
+    prelude::*,
+};
+
+use crate::{
+    recognition::liveness::{CuePolicy, FrameObservation, Landmarks, LivenessPolicy, TextureStats},
+    APP_STATE,
+};
+
+// 防伪模型文件，放在 resources 目录下时才启用
+// 输入为 80x80 的 BGR 人脸图（人脸框放大 2.7 倍），输出第 1 类为真人，与 MiniFASNet 一致
+pub const ANTI_SPOOF_MODEL: &str = "anti_spoof.onnx";
+const ANTI_SPOOF_INPUT: i32 = 80;
+const ANTI_SPOOF_SCALE: f32 = 2.7;
+
+// 纹理统计使用的人脸尺寸
+const TEXTURE_SIZE: i32 = 128;
+// 灰度超过这个值视为反光
+const SPECULAR_LEVEL: f64 = 250.0;
+
+// 计算一帧的观测值，策略为 Off 的线索不计算
+// face 为 YuNet 的检测结果行：x, y, w, h, 右眼, 左眼, 鼻尖, 右嘴角, 左嘴角, 置信度
+pub fn observe(
+    frame: &Mat,
+    face: &Mat,
+    policy: &LivenessPolicy,
+) -> Result<FrameObservation, String> {
+    let (face_rect, landmarks) = parse_face_row(face)?;
+
+    let gray = if policy.blink != CuePolicy::Off || policy.texture != CuePolicy::Off {
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(frame, &mut gray, imgproc::COLOR_BGR2GRAY)
+            .map_err(|e| format!("转换灰度图失败: {}", e))?;
+        Some(gray)
+    } else {
+        None
+    };
+
+    let eye_openness = match (&gray, policy.blink) {
+        (Some(gray), cue) if cue != CuePolicy::Off => Some(eye_openness(gray, &landmarks)?),
+        _ => None,
+    };
+    let texture = match (&gray, policy.texture) {
+        (Some(gray), cue) if cue != CuePolicy::Off => texture_stats(gray, face_rect)?,
+        _ => None,
+    };
+    let model_score = if policy.model != CuePolicy::Off {
+        anti_spoof_score(frame, face_rect)?
+    } else {
+        None
+    };
+
+    Ok(FrameObservation {
+        landmarks,
+        eye_openness,
+        texture,
+        model_score,
+    })
+}
+
+// 解析 YuNet 的检测结果行，返回人脸框和关键点
+pub(crate) fn parse_face_row(face: &Mat) -> Result<(Rect, Landmarks), String> {
+    let value = |i: i32| -> Result<f32, String> {
+        face.at_2d::<f32>(0, i)
+            .copied()
+            .map_err(|e| format!("读取检测结果失败: {}", e))
+    };
+    let point = |i: i32| -> Result<(f32, f32), String> { Ok((value(i)?, value(i + 1)?)) };
+
+    let landmarks = Landmarks {
+        right_eye: point(4)?,
+        left_eye: point(6)?,
+        nose: point(8)?,
+        mouth_right: point(10)?,
+        mouth_left: point(12)?,
+    };
+    let face_rect = Rect::new(
+        value(0)? as i32,
+        value(1)? as i32,
+        value(2)? as i32,
+        value(3)? as i32,
+    );
+    Ok((face_rect, landmarks))
+}
+
+// 矩形与图像求交集，没有交集时返回 None
+pub(crate) fn clamp_rect(img: &Mat, rect: Rect) -> Option<Rect> {
+    let bounds = Rect::new(0, 0, img.cols(), img.rows());
+    let rect = rect & bounds;
+    (rect.width > 1 && rect.height > 1).then_some(rect)
+}
+
+pub(crate) fn mean_std_dev(img: &impl core::ToInputArray) -> Result<(f64, f64), String> {
+    let mut mean = Vector::<f64>::new();
+    let mut stddev = Vector::<f64>::new();
+    core::mean_std_dev(img, &mut mean, &mut stddev, &core::no_array())
+        .map_err(|e| format!("计算均值失败: {}", e))?;
+    Ok((
+        mean.get(0).unwrap_or_default(),
+        stddev.get(0).unwrap_or_default(),
+    ))
+}
+
+// YuNet 只给出眼睛中心，没有眼睑的关键点，无法计算常用的眼睛纵横比
+// 这里用眼睛周围区域的对比度代替：睁眼时有深色的虹膜和浅色的眼白，闭眼时只有皮肤
+fn eye_openness(gray: &Mat, landmarks: &Landmarks) -> Result<f32, String> {
+    let (rx, ry) = landmarks.right_eye;
+    let (lx, ly) = landmarks.left_eye;
+    let distance = ((lx - rx).powi(2) + (ly - ry).powi(2)).sqrt();
+    let side = (distance * 0.35).max(4.0);
+
+    let mut total = 0.0;
+    let mut count = 0;
+    for (x, y) in [landmarks.right_eye, landmarks.left_eye] {
+        let rect = Rect::new(
+            (x - side / 2.0) as i32,
+            (y - side / 2.0) as i32,
+            side as i32,
+            side as i32,
+        );
+        let Some(rect) = clamp_rect(gray, rect) else {
+            continue;
+        };
+        let roi = Mat::roi(gray, rect).map_err(|e| format!("截取眼睛区域失败: {}", e))?;
+        let (mean, stddev) = mean_std_dev(&roi)?;
+        total += stddev / (mean + 1.0);
+        count += 1;
+    }
+    if count == 0 {
+        return Err(String::from("眼睛不在画面内"));
+    }
+    Ok((total / count as f64) as f32)
+}
+
+// 屏幕翻拍有摩尔纹，高频能量偏高；屏幕和相片表面的反光会产生大片接近饱和的像素
+fn texture_stats(gray: &Mat, face_rect: Rect) -> Result<Option<TextureStats>, String> {
+    let Some(rect) = clamp_rect(gray, face_rect) else {
+        return Ok(None);
+    };
+    let roi = Mat::roi(gray, rect).map_err(|e| format!("截取人脸区域失败: {}", e))?;
+    let mut face = Mat::default();
+    imgproc::resize(
+        &roi,
+        &mut face,
+        Size::new(TEXTURE_SIZE, TEXTURE_SIZE),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )
+    .map_err(|e| format!("缩放人脸区域失败: {}", e))?;
+
+    let mut laplacian = Mat::default();
+    imgproc::laplacian_def(&face, &mut laplacian, core::CV_32F)
+        .map_err(|e| format!("计算拉普拉斯算子失败: {}", e))?;
+    let (_, high_freq) = mean_std_dev(&laplacian)?;
+    let (_, overall) = mean_std_dev(&face)?;
+    let high_freq_energy = high_freq * high_freq;
+    let overall_energy = overall * overall;
+    let high_freq_ratio = if high_freq_energy + overall_energy > 0.0 {
+        high_freq_energy / (high_freq_energy + overall_energy)
+    } else {
+        0.0
+    };
+
+    let mut bright = Mat::default();
+    imgproc::threshold(
+        &face,
+        &mut bright,
+        SPECULAR_LEVEL,
+        255.0,
+        imgproc::THRESH_BINARY,
+    )
+    .map_err(|e| format!("计算反光区域失败: {}", e))?;
+    let bright_pixels =
+        core::count_non_zero(&bright).map_err(|e| format!("统计反光像素失败: {}", e))?;
+    let specular_ratio = bright_pixels as f64 / (TEXTURE_SIZE * TEXTURE_SIZE) as f64;
+
+    Ok(Some(TextureStats {
+        high_freq_ratio: high_freq_ratio as f32,
+        specular_ratio: specular_ratio as f32,
+    }))
+}
+
+// 防伪模型给出的真人概率，模型没有加载时返回 None
+fn anti_spoof_score(frame: &Mat, face_rect: Rect) -> Result<Option<f32>, String> {
+    let mut app_state = APP_STATE
+        .lock()
+        .map_err(|e| format!("获取app状态失败 {}", e))?;
+    let Some(net) = app_state.anti_spoof.as_mut() else {
+        return Ok(None);
+    };
+
+    // 以人脸框中心放大，模型需要包含一部分背景
+    let cx = face_rect.x as f32 + face_rect.width as f32 / 2.0;
+    let cy = face_rect.y as f32 + face_rect.height as f32 / 2.0;
+    let side = face_rect.width.max(face_rect.height) as f32 * ANTI_SPOOF_SCALE;
+    let rect = Rect::new(
+        (cx - side / 2.0) as i32,
+        (cy - side / 2.0) as i32,
+        side as i32,
+        side as i32,
+    );
+    let Some(rect) = clamp_rect(frame, rect) else {
+        return Ok(None);
+    };
+    let roi = Mat::roi(frame, rect).map_err(|e| format!("截取人脸区域失败: {}", e))?;
+
+    let blob = dnn::blob_from_image(
+        &roi,
+        1.0,
+        Size::new(ANTI_SPOOF_INPUT, ANTI_SPOOF_INPUT),
+        Scalar::default(),
+        false,
+        false,
+        core::CV_32F,
+    )
+    .map_err(|e| format!("生成模型输入失败: {}", e))?;
+    net.inner
+        .set_input_def(&blob)
+        .map_err(|e| format!("设置模型输入失败: {}", e))?;
+    let output = net
+        .inner
+        .forward_single_def()
+        .map_err(|e| format!("防伪模型推理失败: {}", e))?;
+
+    // softmax 后取真人类别
+    let logits = output
+        .data_typed::<f32>()
+        .map_err(|e| format!("读取模型输出失败: {}", e))?;
+    if logits.len() < 2 {
+        return Err(format!("防伪模型输出维度不正确: {}", logits.len()));
+    }
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = logits.iter().map(|v| (v - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+    Ok(Some(exp[1] / sum))
+}
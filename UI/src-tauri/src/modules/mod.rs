@@ -1,3 +1,7 @@
+pub mod credentials;
 pub mod faces;
 pub mod init;
+pub mod liveness;
 pub mod options;
+pub mod quality;
+pub mod tile_accounts;
@@ -1,31 +1,521 @@
-use crate::utils::custom_result::CustomResult;
+use std::collections::HashMap;
+
+use r2d2_sqlite::rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::Emitter;
+use tauri_plugin_log::log::warn;
+#[cfg(windows)]
 use winreg::enums::*;
+#[cfg(windows)]
 use winreg::RegKey;
 
-// 注册表结构体
-#[derive(serde::Deserialize)]
-pub struct RegistryItem {
-    pub key: String,
-    pub value: String,
+use crate::{
+    db::{get_conn, repo},
+    modules::tile_accounts::publish_tile_accounts,
+    recognition::{
+        gallery::MAX_TEMPLATES, liveness::LivenessPolicy, matcher::MatchPolicy,
+        quality::QualityThresholds, selection::FacePolicy,
+    },
+    utils::custom_result::CustomResult,
+    APP_HANDLE,
+};
+
+// 设置变化时发给前端的事件，载荷为完整的 Settings
+pub const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+// 锁屏后面容识别延迟的范围（秒），与设置页面的输入框一致
+pub const MIN_FACE_RECOG_DELAY: f32 = 0.1;
+pub const MAX_FACE_RECOG_DELAY: f32 = 120.0;
+// 每次识别持续时间的范围（秒）
+pub const MIN_RECOGNITION_WINDOW: f32 = 2.0;
+pub const MAX_RECOGNITION_WINDOW: f32 = 120.0;
+// 识别时每秒读取帧数的范围
+pub const MIN_RECOGNITION_FPS: u32 = 1;
+pub const MAX_RECOGNITION_FPS: u32 = 30;
+
+// 摄像头列表中的一项，与 get_camera 返回的结构一致
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub camera_name: String,
+    pub capture_index: String,
+    #[serde(default)]
+    pub is_valid: bool,
+}
+
+// 软件设置，保存在 options 表中，每个字段对应一个 key
+// 缺失或无法解析的值使用默认值，锁屏回调、注册表同步和前端都从这里读取
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    // 是否完成初始化，未完成时锁屏不启动面容识别
+    pub is_initialized: bool,
+    // 锁屏后延迟多少秒打开摄像头
+    pub face_recog_delay: f32,
+    // 每次识别持续多少秒，期间没有匹配的面容才算失败，结束后关闭摄像头
+    pub recognition_window: f32,
+    // 识别时每秒读取的帧数
+    pub recognition_fps: u32,
+    // 识别失败后，用户按键、移动鼠标或点击磁贴时是否再次识别
+    // 同步到注册表的 RESCAN_ON_WAKE，关闭时磁贴上不显示“重新识别面容”
+    pub rescan_on_wake: bool,
+    // 面容识别使用的摄像头索引，-1 表示尚未选择
+    pub camera: i32,
+    // 上次获取到的摄像头列表
+    pub camera_list: Vec<CameraInfo>,
+    // 是否在登录界面显示磁贴，同步到注册表的 SHOW_TILE
+    pub show_tile: bool,
+    // 是否包装系统密码提供程序，同步到注册表的 WRAP_PASSWORD_PROVIDER
+    // 开启后面容解锁的状态显示在用户原来的密码磁贴上，不再单独显示磁贴
+    pub wrap_password_provider: bool,
+    // 一个面容有多个模板时的比对策略
+    pub match_policy: MatchPolicy,
+    // 高置信度解锁成功后是否自动把当前人脸加入模板
+    pub adaptive_update: bool,
+    // 活体检测各线索的策略
+    pub liveness: LivenessPolicy,
+    // 识别成功后是否在磁贴上提示随机动作，做对了才解锁
+    pub challenge: bool,
+    // 录入面容时各项质量指标的下限
+    pub quality: QualityThresholds,
+    // 画面中有多张人脸时解锁使用哪一张
+    pub face_policy: FacePolicy,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            is_initialized: false,
+            face_recog_delay: 10.0,
+            recognition_window: 15.0,
+            recognition_fps: 5,
+            rescan_on_wake: true,
+            camera: -1,
+            camera_list: Vec::new(),
+            show_tile: true,
+            wrap_password_provider: false,
+            match_policy: MatchPolicy::default(),
+            adaptive_update: false,
+            liveness: LivenessPolicy::default(),
+            challenge: false,
+            quality: QualityThresholds::default(),
+            face_policy: FacePolicy::default(),
+        }
+    }
+}
+
+impl Settings {
+    // 从 options 表读取设置
+    pub fn load(conn: &Connection) -> Result<Self, String> {
+        let options: HashMap<String, String> = repo::list_options(conn)?
+            .into_iter()
+            .map(|option| (option.key, option.val))
+            .collect();
+        Ok(Self::from_options(&options))
+    }
+
+    // 逐项解析，某一项无效时只回退这一项
+    fn from_options(options: &HashMap<String, String>) -> Self {
+        let mut settings = Self::default();
+
+        if let Some(val) = options.get("is_initialized") {
+            settings.is_initialized = val == "true";
+        }
+        if let Some(val) = options.get("faceRecogDelay") {
+            match val.parse::<f32>() {
+                Ok(delay) if Self::delay_in_range(delay) => settings.face_recog_delay = delay,
+                _ => warn!("faceRecogDelay 设置无效：{}，使用默认值", val),
+            }
+        }
+        if let Some(val) = options.get("recognitionWindow") {
+            match val.parse::<f32>() {
+                Ok(window) if Self::window_in_range(window) => settings.recognition_window = window,
+                _ => warn!("recognitionWindow 设置无效：{}，使用默认值", val),
+            }
+        }
+        if let Some(val) = options.get("recognitionFps") {
+            match val.parse::<u32>() {
+                Ok(fps) if Self::fps_in_range(fps) => settings.recognition_fps = fps,
+                _ => warn!("recognitionFps 设置无效：{}，使用默认值", val),
+            }
+        }
+        if let Some(val) = options.get("rescanOnWake") {
+            settings.rescan_on_wake = val != "false";
+        }
+        if let Some(val) = options.get("camera") {
+            match val.parse::<i32>() {
+                Ok(camera) if camera >= -1 => settings.camera = camera,
+                _ => warn!("camera 设置无效：{}，使用默认值", val),
+            }
+        }
+        if let Some(val) = options.get("cameraList") {
+            match serde_json::from_str(val) {
+                Ok(list) => settings.camera_list = list,
+                Err(e) => warn!("cameraList 设置无效：{}，使用默认值", e),
+            }
+        }
+        if let Some(val) = options.get("showTile") {
+            settings.show_tile = val != "false";
+        }
+        if let Some(val) = options.get("wrapPasswordProvider") {
+            settings.wrap_password_provider = val == "true";
+        }
+        if let Some(val) = options.get("matchPolicy") {
+            match serde_json::from_str(val) {
+                Ok(policy) => settings.match_policy = policy,
+                Err(e) => warn!("matchPolicy 设置无效：{}，使用默认值", e),
+            }
+        }
+        if let Some(val) = options.get("adaptiveUpdate") {
+            settings.adaptive_update = val == "true";
+        }
+        if let Some(val) = options.get("liveness") {
+            match serde_json::from_str(val) {
+                Ok(policy) => settings.liveness = policy,
+                Err(e) => warn!("liveness 设置无效：{}，使用默认值", e),
+            }
+        }
+        if let Some(val) = options.get("challenge") {
+            settings.challenge = val == "true";
+        }
+        if let Some(val) = options.get("quality") {
+            match serde_json::from_str(val) {
+                Ok(thresholds) => settings.quality = thresholds,
+                Err(e) => warn!("quality 设置无效：{}，使用默认值", e),
+            }
+        }
+        if let Some(val) = options.get("facePolicy") {
+            match serde_json::from_str(val) {
+                Ok(policy) => settings.face_policy = policy,
+                Err(e) => warn!("facePolicy 设置无效：{}，使用默认值", e),
+            }
+        }
+
+        settings
+    }
+
+    fn delay_in_range(delay: f32) -> bool {
+        (MIN_FACE_RECOG_DELAY..=MAX_FACE_RECOG_DELAY).contains(&delay)
+    }
+
+    fn window_in_range(window: f32) -> bool {
+        (MIN_RECOGNITION_WINDOW..=MAX_RECOGNITION_WINDOW).contains(&window)
+    }
+
+    fn fps_in_range(fps: u32) -> bool {
+        (MIN_RECOGNITION_FPS..=MAX_RECOGNITION_FPS).contains(&fps)
+    }
+
+    // 保存前校验
+    pub fn validate(&self) -> Result<(), String> {
+        if !Self::delay_in_range(self.face_recog_delay) {
+            return Err(format!(
+                "面容识别延迟必须在 {} 到 {} 秒之间",
+                MIN_FACE_RECOG_DELAY, MAX_FACE_RECOG_DELAY
+            ));
+        }
+        if !Self::window_in_range(self.recognition_window) {
+            return Err(format!(
+                "每次识别的时长必须在 {} 到 {} 秒之间",
+                MIN_RECOGNITION_WINDOW, MAX_RECOGNITION_WINDOW
+            ));
+        }
+        if !Self::fps_in_range(self.recognition_fps) {
+            return Err(format!(
+                "识别帧率必须在 {} 到 {} 之间",
+                MIN_RECOGNITION_FPS, MAX_RECOGNITION_FPS
+            ));
+        }
+        if self.camera < -1 {
+            return Err(format!("摄像头索引无效：{}", self.camera));
+        }
+        // 有摄像头列表时，选择的摄像头必须在列表中
+        if self.camera >= 0
+            && !self.camera_list.is_empty()
+            && !self
+                .camera_list
+                .iter()
+                .any(|item| item.capture_index == self.camera.to_string())
+        {
+            return Err(format!("摄像头 {} 不在摄像头列表中", self.camera));
+        }
+        if let MatchPolicy::MeanTopK { k } = self.match_policy {
+            if !(1..=MAX_TEMPLATES).contains(&k) {
+                return Err(format!("k 必须在 1 到 {} 之间", MAX_TEMPLATES));
+            }
+        }
+        // 动作提示显示在磁贴上，不显示磁贴时用户看不到
+        if self.challenge && !self.show_tile {
+            return Err(String::from("启用动作验证需要显示登录界面磁贴"));
+        }
+        self.quality.validate()?;
+        Ok(())
+    }
+
+    // 锁屏后延迟的毫秒数
+    pub fn face_recog_delay_ms(&self) -> u32 {
+        (self.face_recog_delay * 1000.0) as u32
+    }
+
+    // 每次识别持续的毫秒数
+    pub fn recognition_window_ms(&self) -> u32 {
+        (self.recognition_window * 1000.0) as u32
+    }
+
+    // 写入 options 表，只有值变化的项会更新
+    pub fn save(&self, conn: &mut Connection) -> Result<(), String> {
+        self.validate()?;
+        let camera_list = serde_json::to_string(&self.camera_list)
+            .map_err(|e| format!("序列化摄像头列表失败：{}", e))?;
+        let match_policy = serde_json::to_string(&self.match_policy)
+            .map_err(|e| format!("序列化比对策略失败：{}", e))?;
+        let liveness = serde_json::to_string(&self.liveness)
+            .map_err(|e| format!("序列化活体检测策略失败：{}", e))?;
+        let quality = serde_json::to_string(&self.quality)
+            .map_err(|e| format!("序列化录入质量阈值失败：{}", e))?;
+        let face_policy = serde_json::to_string(&self.face_policy)
+            .map_err(|e| format!("序列化多人脸策略失败：{}", e))?;
+        let items = [
+            ("is_initialized", self.is_initialized.to_string()),
+            ("faceRecogDelay", self.face_recog_delay.to_string()),
+            ("recognitionWindow", self.recognition_window.to_string()),
+            ("recognitionFps", self.recognition_fps.to_string()),
+            ("rescanOnWake", self.rescan_on_wake.to_string()),
+            ("camera", self.camera.to_string()),
+            ("cameraList", camera_list),
+            ("showTile", self.show_tile.to_string()),
+            (
+                "wrapPasswordProvider",
+                self.wrap_password_provider.to_string(),
+            ),
+            ("matchPolicy", match_policy),
+            ("adaptiveUpdate", self.adaptive_update.to_string()),
+            ("liveness", liveness),
+            ("challenge", self.challenge.to_string()),
+            ("quality", quality),
+            ("facePolicy", face_policy),
+        ];
+
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("开启事务失败：{:?}", e))?;
+        for (key, val) in items.iter() {
+            repo::set_option(&tx, key, val)?;
+        }
+        tx.commit().map_err(|e| format!("提交事务失败：{:?}", e))
+    }
+
+    // DLL 需要的设置对应的注册表值，开关写为 "1" 或 "0"
+    fn registry_mirror_values(&self) -> [(&'static str, &'static str); 3] {
+        let flag = |on: bool| if on { "1" } else { "0" };
+        [
+            ("SHOW_TILE", flag(self.show_tile)),
+            ("RESCAN_ON_WAKE", flag(self.rescan_on_wake)),
+            ("WRAP_PASSWORD_PROVIDER", flag(self.wrap_password_provider)),
+        ]
+    }
+
+    // 把 DLL 需要的设置同步到注册表，DLL 运行在 LogonUI 中，读不到数据库
+    pub fn write_registry_mirror(&self) -> Result<(), String> {
+        write_registry_values(&self.registry_mirror_values())
+    }
+}
+
+// 读取当前设置
+pub fn load_settings() -> Result<Settings, String> {
+    let conn = get_conn()?;
+    Settings::load(&conn)
+}
+
+// 保存设置，同步注册表并通知前端
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    settings.validate()?;
+    let mut conn = get_conn()?;
+    let previous = Settings::load(&conn)?;
+    // 只有 DLL 关心的设置变化时才写注册表，避免普通设置也需要管理员权限
+    if previous.show_tile != settings.show_tile
+        || previous.rescan_on_wake != settings.rescan_on_wake
+        || previous.wrap_password_provider != settings.wrap_password_provider
+    {
+        settings.write_registry_mirror()?;
+    }
+    settings.save(&mut conn)?;
+
+    if let Some(app) = APP_HANDLE.get() {
+        if let Err(e) = app.emit(SETTINGS_CHANGED_EVENT, settings) {
+            warn!("发送设置变化通知失败：{}", e);
+        }
+    }
+    Ok(())
 }
 
 // 向注册表写入数据
-#[tauri::command]
-pub fn write_to_registry(items: Vec<RegistryItem>) -> Result<CustomResult, CustomResult> {
+#[cfg(windows)]
+pub fn write_registry_values(items: &[(&str, &str)]) -> Result<(), String> {
     // 向所有用户写入
     let reg_path = format!("SOFTWARE\\{}", "facewinunlock-tauri");
     let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
     let (app_key, _) = hklm
         .create_subkey(&reg_path)
-        .map_err(|e| CustomResult::error(Some(format!("创建子项失败 {}", e)), None))?;
+        .map_err(|e| format!("创建子项失败 {}", e))?;
 
     // 遍历写入每个 key-value
-    for item in items {
+    for (key, value) in items {
         // 写入字符串类型的值
-        app_key.set_value(&item.key, &item.value).map_err(|e| {
-            CustomResult::error(Some(format!("写入 {} 失败：{}", item.key, e)), None)
-        })?;
+        app_key
+            .set_value(key, value)
+            .map_err(|e| format!("写入 {} 失败：{}", key, e))?;
     }
 
+    Ok(())
+}
+
+// 只有 Windows 上的 DLL 读取注册表，其他平台无处可写
+#[cfg(not(windows))]
+pub fn write_registry_values(_items: &[(&str, &str)]) -> Result<(), String> {
+    Err(String::from("当前系统没有注册表"))
+}
+
+// 获取设置
+#[tauri::command]
+pub fn get_settings() -> Result<CustomResult, CustomResult> {
+    let settings = load_settings().map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(settings))))
+}
+
+// 保存设置，返回保存后的设置
+#[tauri::command]
+pub fn update_settings(settings: Settings) -> Result<CustomResult, CustomResult> {
+    save_settings(&settings).map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!(settings))))
+}
+
+// 重新把当前设置同步到注册表，用于修复被改动的注册表
+#[tauri::command]
+pub fn write_to_registry() -> Result<CustomResult, CustomResult> {
+    let settings = load_settings().map_err(|e| CustomResult::error(Some(e), None))?;
+    settings
+        .write_registry_mirror()
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    publish_tile_accounts().map_err(|e| CustomResult::error(Some(e), None))?;
     Ok(CustomResult::success(None, None))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items
+            .iter()
+            .map(|(key, val)| (key.to_string(), val.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn missing_options_use_defaults() {
+        assert_eq!(Settings::from_options(&HashMap::new()), Settings::default());
+    }
+
+    #[test]
+    fn invalid_options_fall_back_per_field() {
+        let settings = Settings::from_options(&options(&[
+            ("faceRecogDelay", "abc"),
+            ("recognitionWindow", "500"),
+            ("recognitionFps", "0"),
+            ("camera", "-5"),
+            ("cameraList", "[不是json"),
+            ("matchPolicy", "{}"),
+            ("quality", "null"),
+            // 有效的项不受其他无效项影响
+            ("showTile", "false"),
+        ]));
+        let defaults = Settings::default();
+        assert_eq!(settings.face_recog_delay, defaults.face_recog_delay);
+        assert_eq!(settings.recognition_window, defaults.recognition_window);
+        assert_eq!(settings.recognition_fps, defaults.recognition_fps);
+        assert_eq!(settings.camera, defaults.camera);
+        assert_eq!(settings.camera_list, defaults.camera_list);
+        assert_eq!(settings.match_policy, defaults.match_policy);
+        assert_eq!(settings.quality, defaults.quality);
+        assert!(!settings.show_tile);
+    }
+
+    #[test]
+    fn valid_options_are_parsed() {
+        let settings = Settings::from_options(&options(&[
+            ("is_initialized", "true"),
+            ("faceRecogDelay", "2.5"),
+            ("camera", "1"),
+            ("wrapPasswordProvider", "true"),
+            ("matchPolicy", r#"{"kind":"mean_top_k","k":2}"#),
+        ]));
+        assert!(settings.is_initialized);
+        assert_eq!(settings.face_recog_delay, 2.5);
+        assert_eq!(settings.face_recog_delay_ms(), 2500);
+        assert_eq!(settings.camera, 1);
+        assert!(settings.wrap_password_provider);
+        assert_eq!(settings.match_policy, MatchPolicy::MeanTopK { k: 2 });
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_values() {
+        assert!(Settings::default().validate().is_ok());
+
+        let cases: [fn(&mut Settings); 7] = [
+            |s| s.face_recog_delay = MAX_FACE_RECOG_DELAY + 1.0,
+            |s| s.recognition_window = MIN_RECOGNITION_WINDOW - 1.0,
+            |s| s.recognition_fps = MAX_RECOGNITION_FPS + 1,
+            |s| s.camera = -2,
+            |s| s.match_policy = MatchPolicy::MeanTopK { k: 0 },
+            |s| {
+                s.match_policy = MatchPolicy::MeanTopK {
+                    k: MAX_TEMPLATES + 1,
+                }
+            },
+            |s| {
+                s.challenge = true;
+                s.show_tile = false;
+            },
+        ];
+        for change in cases {
+            let mut settings = Settings::default();
+            change(&mut settings);
+            assert!(settings.validate().is_err(), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn validate_requires_camera_in_list() {
+        let mut settings = Settings {
+            camera: 1,
+            camera_list: vec![CameraInfo {
+                camera_name: String::from("USB Camera"),
+                capture_index: String::from("0"),
+                is_valid: true,
+            }],
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_err());
+        settings.camera = 0;
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn registry_mirror_maps_switches() {
+        let settings = Settings {
+            show_tile: false,
+            rescan_on_wake: true,
+            wrap_password_provider: true,
+            ..Settings::default()
+        };
+        assert_eq!(
+            settings.registry_mirror_values(),
+            [
+                ("SHOW_TILE", "0"),
+                ("RESCAN_ON_WAKE", "1"),
+                ("WRAP_PASSWORD_PROVIDER", "1"),
+            ]
+        );
+    }
+}
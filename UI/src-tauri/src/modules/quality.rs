@@ -0,0 +1,91 @@
+// 从照片中测量录入质量，判断逻辑在 recognition::quality
+use opencv::{
+    core::{self, Mat, Rect, Size},
+    imgproc,
+    prelude::*,
+};
+
+use crate::{
+    modules::liveness::{clamp_rect, mean_std_dev, parse_face_row},
+    recognition::quality::{assess, QualityMeasurements, QualityReport, QualityThresholds},
+};
+
+// 计算清晰度时人脸缩放到的尺寸，与识别模型的输入一致，不同分辨率的照片可以比较
+const QUALITY_SIZE: i32 = 112;
+// 五官附近区域的灰度标准差低于这个值，视为被纯色的遮挡物（手、口罩）挡住
+const FLAT_STDDEV: f64 = 6.0;
+
+// 测量并评估一张照片，face 为 YuNet 的检测结果行
+pub fn assess_image(
+    img: &Mat,
+    face: &Mat,
+    thresholds: &QualityThresholds,
+) -> Result<QualityReport, String> {
+    Ok(assess(&measure(img, face)?, thresholds))
+}
+
+pub fn measure(img: &Mat, face: &Mat) -> Result<QualityMeasurements, String> {
+    let (face_rect, landmarks) = parse_face_row(face)?;
+
+    let mut gray = Mat::default();
+    imgproc::cvt_color_def(img, &mut gray, imgproc::COLOR_BGR2GRAY)
+        .map_err(|e| format!("转换灰度图失败: {}", e))?;
+    let Some(rect) = clamp_rect(&gray, face_rect) else {
+        return Err(String::from("人脸不在画面内"));
+    };
+    let roi = Mat::roi(&gray, rect).map_err(|e| format!("截取人脸区域失败: {}", e))?;
+
+    let mut resized = Mat::default();
+    imgproc::resize(
+        &roi,
+        &mut resized,
+        Size::new(QUALITY_SIZE, QUALITY_SIZE),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )
+    .map_err(|e| format!("缩放人脸区域失败: {}", e))?;
+    let mut laplacian = Mat::default();
+    imgproc::laplacian_def(&resized, &mut laplacian, core::CV_64F)
+        .map_err(|e| format!("计算拉普拉斯算子失败: {}", e))?;
+    let (_, sharpness) = mean_std_dev(&laplacian)?;
+    let (brightness, _) = mean_std_dev(&roi)?;
+
+    // 五官附近的小块区域，没有细节或者落在人脸框外都算作遮挡
+    let (rx, ry) = landmarks.right_eye;
+    let (lx, ly) = landmarks.left_eye;
+    let side = (((lx - rx).powi(2) + (ly - ry).powi(2)).sqrt() * 0.3).max(4.0);
+    let points = [
+        landmarks.right_eye,
+        landmarks.left_eye,
+        landmarks.nose,
+        landmarks.mouth_right,
+        landmarks.mouth_left,
+    ];
+    let mut occluded = 0;
+    for (x, y) in points {
+        let patch = Rect::new(
+            (x - side / 2.0) as i32,
+            (y - side / 2.0) as i32,
+            side as i32,
+            side as i32,
+        );
+        let Some(patch) = clamp_rect(&gray, patch & face_rect) else {
+            occluded += 1;
+            continue;
+        };
+        let patch = Mat::roi(&gray, patch).map_err(|e| format!("截取五官区域失败: {}", e))?;
+        let (_, stddev) = mean_std_dev(&patch)?;
+        if stddev < FLAT_STDDEV {
+            occluded += 1;
+        }
+    }
+
+    Ok(QualityMeasurements {
+        sharpness: (sharpness * sharpness) as f32,
+        face_size: face_rect.width.min(face_rect.height) as f32,
+        landmarks,
+        brightness: brightness as f32,
+        occlusion: occluded as f32 / points.len() as f32,
+    })
+}
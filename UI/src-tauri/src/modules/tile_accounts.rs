@@ -0,0 +1,116 @@
+// 把录入了面容的 Windows 账户发布到注册表，DLL 为每个账户显示一个磁贴
+use std::collections::HashSet;
+
+use facewinunlock_protocol::accounts::{encode_accounts, ACCOUNTS_REG_VALUE};
+use facewinunlock_protocol::TileAccount;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+#[cfg(windows)]
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{LocalFree, HLOCAL},
+        Security::{Authorization::ConvertSidToStringSidW, LookupAccountNameW, PSID, SID_NAME_USE},
+    },
+};
+
+use crate::{
+    db::{get_conn, models::Face, repo},
+    modules::options::write_registry_values,
+    utils::custom_result::CustomResult,
+};
+
+// 查询账户的 SID 字符串
+#[cfg(windows)]
+fn lookup_sid(account_name: &str) -> Result<String, String> {
+    let name: Vec<u16> = account_name.encode_utf16().chain(Some(0)).collect();
+    let mut sid_size = 0u32;
+    let mut domain_size = 0u32;
+    let mut name_use = SID_NAME_USE::default();
+    unsafe {
+        // 第一次调用只获取缓冲区大小，必然返回 ERROR_INSUFFICIENT_BUFFER
+        let _ = LookupAccountNameW(
+            PCWSTR::null(),
+            PCWSTR(name.as_ptr()),
+            None,
+            &mut sid_size,
+            None,
+            &mut domain_size,
+            &mut name_use,
+        );
+        if sid_size == 0 {
+            return Err(format!("找不到账户 {}", account_name));
+        }
+
+        let mut sid = vec![0u8; sid_size as usize];
+        let mut domain = vec![0u16; domain_size.max(1) as usize];
+        LookupAccountNameW(
+            PCWSTR::null(),
+            PCWSTR(name.as_ptr()),
+            Some(PSID(sid.as_mut_ptr() as _)),
+            &mut sid_size,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_size,
+            &mut name_use,
+        )
+        .map_err(|e| format!("查询账户 {} 的 SID 失败：{}", account_name, e))?;
+
+        let mut sid_string = PWSTR::null();
+        ConvertSidToStringSidW(PSID(sid.as_mut_ptr() as _), &mut sid_string)
+            .map_err(|e| format!("转换账户 {} 的 SID 失败：{}", account_name, e))?;
+        let result = sid_string.to_string().map_err(|e| e.to_string());
+        let _ = LocalFree(Some(HLOCAL(sid_string.0 as _)));
+        result
+    }
+}
+
+// 其他平台没有 Windows 账户，所有面容都不显示磁贴
+#[cfg(not(windows))]
+fn lookup_sid(account_name: &str) -> Result<String, String> {
+    Err(format!("当前系统无法查询账户 {} 的 SID", account_name))
+}
+
+// 面容对应的磁贴账户
+fn tile_account(face: &Face) -> Result<TileAccount, String> {
+    // 联机账户在本机以 MicrosoftAccount\邮箱 的形式登记
+    let account_name = if face.account_type == "local" {
+        face.user_name.clone()
+    } else {
+        format!("MicrosoftAccount\\{}", face.user_name)
+    };
+    Ok(TileAccount {
+        sid: lookup_sid(&account_name)?,
+        user_name: face.logon_name(),
+        display_name: face.user_name.clone(),
+    })
+}
+
+// 把面容对应的账户写入注册表，多个面容属于同一账户时只写一次，返回写入的账户数
+pub fn publish_tile_accounts() -> Result<usize, String> {
+    let conn = get_conn()?;
+    let faces = repo::list_faces(&conn)?;
+
+    let mut seen = HashSet::new();
+    let mut accounts = Vec::new();
+    for face in &faces {
+        if !seen.insert(face.logon_name().to_lowercase()) {
+            continue;
+        }
+        // 查不到 SID 的账户（例如已经删除）跳过，不影响其他账户的磁贴
+        match tile_account(face) {
+            Ok(account) => accounts.push(account),
+            Err(e) => warn!("面容 {} 的账户不显示磁贴：{}", face.id, e),
+        }
+    }
+
+    write_registry_values(&[(ACCOUNTS_REG_VALUE, &encode_accounts(&accounts))])?;
+    info!("已发布 {} 个磁贴账户", accounts.len());
+    Ok(accounts.len())
+}
+
+// 面容增删改之后由前端调用，更新登录界面的磁贴
+#[tauri::command]
+pub fn sync_tile_accounts() -> Result<CustomResult, CustomResult> {
+    let count = publish_tile_accounts().map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!({ "count": count }))))
+}
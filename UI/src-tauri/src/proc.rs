@@ -1,41 +1,92 @@
-use opencv::{objdetect::FaceRecognizerSF_DisType, prelude::FaceRecognizerSFTraitConst};
-use serde::Deserialize;
-use std::{sync::atomic::Ordering, thread::sleep, time::Duration};
+use facewinunlock_protocol::{LogonOutcome, StatusIcon};
+use opencv::core::Mat;
+use r2d2_sqlite::rusqlite::Connection;
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tauri_plugin_log::log::{error, info, warn};
+// 锁屏通知和 CredUI 事件只在 Windows 上接收，识别流程本身与平台无关
+#[cfg(windows)]
+use facewinunlock_protocol::CREDUI_EVENT_NAME;
+#[cfg(windows)]
+use std::thread;
+#[cfg(windows)]
+use windows::core::{w, HSTRING};
+#[cfg(windows)]
 use windows::Win32::{
-    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    Foundation::{LocalFree, HANDLE, HLOCAL, HWND, LPARAM, LRESULT, WAIT_OBJECT_0, WPARAM},
+    Security::{
+        Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+        PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+    },
+    System::Threading::{CreateEventW, WaitForSingleObject, INFINITE},
     UI::{
         Shell::DefSubclassProc,
-        WindowsAndMessaging::{
-            KillTimer, SetTimer, WM_TIMER, WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK,
-            WTS_SESSION_UNLOCK,
-        },
+        WindowsAndMessaging::{WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK},
     },
 };
 
 use crate::{
-    modules::faces::{get_feature, load_face_data, read_mat_from_camera},
-    utils::api::{open_camera, stop_camera, unlock},
-    APP_STATE, CAMERA_INDEX, DB_POOL, IS_LOCKED, ROOT_DIR, TIMER_ID_LOCK_CHECK,
+    camera::{self, read_frame, FramePacer, FrameSource},
+    db::{self, models::Face, repo, DbPool},
+    modules::{
+        credentials,
+        faces::{
+            feature_to_vec, load_face_data, save_face_data, FaceDetector, ModelDetector,
+            MULTIPLE_FACES,
+        },
+        liveness,
+        options::Settings,
+    },
+    recognition::{
+        adaptive,
+        challenge::{Challenge, ChallengeVerdict},
+        liveness::{CuePolicy, LivenessPolicy, LivenessReport, LivenessTracker},
+        lockout::{AccountGuard, ANY_ACCOUNT},
+        matcher::{identify, Candidate, Identity, MatchPolicy, Voter},
+        selection::FacePolicy,
+    },
+    session::{CancelToken, LockSettings, SessionEvent},
+    utils::{
+        api::{PipeError, PipeSession},
+        secret_store::{default_store, reveal_password, PasswordFingerprint, SecretStore},
+    },
+    ROOT_DIR,
 };
+#[cfg(windows)]
+use crate::{utils::api::stop_camera, UNLOCK_WORKER};
 
+// 同一面容需要连续胜出的帧数
 const MAX_SUCCESS: usize = 3;
-const MAX_FAIL: usize = 3;
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")] // 适配 JSON 中的驼峰命名
-pub struct FaceExtraData {
-    /// 面容别名
-    pub alias: String,
-    /// 置信度阈值
-    pub threshold: f32,
-    /// 是否在列表页显示图片缩略图
-    pub view: bool,
-    /// 人脸检测置信度阈值
-    pub face_detection_threshold: f32,
-}
+// 确认身份后，为完成必须通过的活体检测线索最多再采集的帧数
+const MAX_LIVENESS_FRAMES: usize = 60;
+// 未通过活体检测时解锁日志中的登录结果
+const LIVENESS_FAILED: &str = "liveness_failed";
+// 动作验证最多采集的帧数，超过后视为未通过
+const MAX_CHALLENGE_FRAMES: usize = 100;
+// 未通过动作验证时解锁日志中的登录结果
+const CHALLENGE_FAILED: &str = "challenge_failed";
+// 没有匹配到面容时解锁日志中的登录结果
+const UNKNOWN_FACE: &str = "unknown_face";
+// 失败次数过多或密码已被拒绝、没有发送凭据时解锁日志中的登录结果
+const BLOCKED: &str = "blocked";
+// 识别失败时磁贴上的提示
+const UNKNOWN_FACE_STATUS: &str = "未能识别面容，请重试或输入密码解锁";
+const LIVENESS_FAILED_STATUS: &str = "未通过活体检测，请输入密码解锁";
+const CHALLENGE_FAILED_STATUS: &str = "未通过动作验证，请输入密码解锁";
+const STALE_CREDENTIALS_STATUS: &str = "保存的密码已失效，请输入密码解锁并在软件中重新输入密码";
+const NO_FACE_STATUS: &str = "未检测到人脸";
+const CAMERA_UNAVAILABLE_STATUS: &str = "摄像头不可用，请输入密码解锁";
+const RECOGNITION_ERROR_STATUS: &str = "识别出错，请输入密码解锁";
+// 识别过程中磁贴上的提示
+const SCANNING_STATUS: &str = "正在寻找你…";
+const MATCHED_STATUS: &str = "已识别，正在登录…";
+// 识别进度每前进这么多（百分比）才刷新一次磁贴，不必每一帧都写管道
+const PROGRESS_STEP: u8 = 10;
 
 // windows回调
+#[cfg(windows)]
 pub unsafe extern "system" fn wnd_proc_subclass(
     hwnd: HWND,
     msg: u32,
@@ -45,349 +96,749 @@ pub unsafe extern "system" fn wnd_proc_subclass(
     _data: usize,
 ) -> LRESULT {
     if msg == WM_WTSSESSION_CHANGE {
-        let event_type = wparam.0 as u32;
-        let _session_id = lparam.0 as u32;
-
-        match event_type {
-            WTS_SESSION_LOCK => {
-                // 屏幕锁屏，关闭摄像头，因为不确定用户是否开启了摄像头
-                if let Err(e) = stop_camera() {
-                    error!("关闭摄像头失败: {}", e.to_string());
-                } else {
-                    // 摄像头处于关闭状态，可以进行面容识别
-                    // 获取数据库设置
-                    if let Ok(pool_guard) = DB_POOL.lock() {
-                        if let Some(pool) = pool_guard.as_ref() {
-                            // 获取连接
-                            let conn = pool.get();
-                            if conn.is_err() {
-                                error!("从连接池获取连接失败: {:?}", conn.err());
-                            } else {
-                                let conn = conn.unwrap();
-
-                                if let Ok(count) = conn.query_row(
-                                    "SELECT COUNT(id) as count FROM faces;",
-                                    [],
-                                    |row| row.get::<&str, i32>("count"),
-                                ) {
-                                    if count > 0 {
-                                        // 有人脸才进行识别
-                                        let result: Result<String, _> = conn.query_row(
-                                            "SELECT val FROM options WHERE key = 'is_initialized';",
-                                            [],
-                                            |row| row.get::<&str, String>("val"),
-                                        );
-                                        let is_initialized = match result {
-                                            Ok(val) => val,
-                                            Err(
-                                                r2d2_sqlite::rusqlite::Error::QueryReturnedNoRows,
-                                            ) => String::from("false"),
-                                            Err(e) => {
-                                                error!("从数据库获取设置失败: {:?}", e);
-                                                String::new()
-                                            }
-                                        };
-
-                                        // 只有初始化完成才启动
-                                        if is_initialized == "true" {
-                                            let result: Result<String, _> = conn.query_row(
-                                                "SELECT val FROM options WHERE key = 'faceRecogDelay';",
-                                                [],
-                                                |row| row.get::<&str, String>("val"),
-                                            );
-
-                                            let time = match result {
-                                                Ok(val) => val,
-                                                Err(r2d2_sqlite::rusqlite::Error::QueryReturnedNoRows) => {
-                                                    String::from("10.0")
-                                                }
-                                                Err(e) => {
-                                                    error!("从数据库获取设置失败: {:?}", e);
-                                                    String::new()
-                                                }
-                                            };
-
-                                            if !time.is_empty() {
-                                                let time_ms: f32 = match time.parse::<f32>() {
-                                                    Ok(seconds) => seconds * 1000.0,
-                                                    Err(e) => {
-                                                        error!(
-                                                            "秒数字符串转换失败: {}，使用默认值 10000 毫秒",
-                                                            e
-                                                        );
-                                                        10.0 * 1000.0
-                                                    }
-                                                };
-
-                                                // 读取摄像头索引
-                                                let result: Result<String, _> = conn.query_row(
-                                                    "SELECT val FROM options WHERE key = 'camera';",
-                                                    [],
-                                                    |row| row.get::<&str, String>("val"),
-                                                );
-
-                                                let camera_index = match result {
-                                                    Ok(val) => val,
-                                                    Err(r2d2_sqlite::rusqlite::Error::QueryReturnedNoRows) => {
-                                                        String::from("0")
-                                                    }
-                                                    Err(e) => {
-                                                        error!("从数据库获取设置失败: {:?}", e);
-                                                        String::new()
-                                                    }
-                                                };
-
-                                                CAMERA_INDEX.store(
-                                                    camera_index.parse().unwrap_or(0),
-                                                    Ordering::SeqCst,
-                                                );
-
-                                                IS_LOCKED.store(true, Ordering::SeqCst);
-                                                // 设置一个定时器
-                                                // 当时间到达时，系统会发送 WM_TIMER 消息
-                                                unsafe {
-                                                    SetTimer(
-                                                        Some(hwnd),
-                                                        TIMER_ID_LOCK_CHECK,
-                                                        time_ms as u32,
-                                                        None,
-                                                    )
-                                                };
-                                                info!("计时器已设置 {}", time_ms);
-                                            } else {
-                                                error!("未获取到延迟秒，停止启动面容识别");
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        } else {
-                            error!("数据库连接池不存在");
-                        }
-                    } else {
-                        error!("从全局变量获取连接池失败");
+        let event = match wparam.0 as u32 {
+            // 屏幕锁屏，关闭摄像头，因为不确定用户是否开启了摄像头
+            WTS_SESSION_LOCK => match stop_camera() {
+                Ok(_) => match read_lock_settings() {
+                    Ok(settings) => Some(SessionEvent::Lock(settings)),
+                    Err(e) => {
+                        error!("读取面容识别设置失败，停止启动面容识别: {}", e);
+                        Some(SessionEvent::Lock(None))
                     }
+                },
+                Err(e) => {
+                    error!("关闭摄像头失败: {}", e.to_string());
+                    Some(SessionEvent::Lock(None))
                 }
+            },
+            WTS_SESSION_UNLOCK => Some(SessionEvent::Unlock),
+            _ => None,
+        };
+        // 识别在后台线程进行，这里只转发事件
+        if let (Some(event), Some(worker)) = (event, UNLOCK_WORKER.get()) {
+            worker.send(event);
+        }
+    }
+    DefSubclassProc(hwnd, msg, wparam, lparam)
+}
 
-                // println!("[会话{}] 屏幕已锁屏", session_id);
+// 等待 DLL 通知 CredUI 提示框（例如 UAC 提权）需要确认身份，收到后开始识别
+#[cfg(windows)]
+pub fn watch_credui_prompts() {
+    thread::spawn(|| {
+        let event = match create_credui_event() {
+            Ok(event) => event,
+            Err(e) => {
+                error!("创建 CredUI 事件失败，提权提示框不会识别面容：{}", e);
+                return;
             }
-            WTS_SESSION_UNLOCK => {
-                // println!("[会话{}] 屏幕已解锁", session_id);
-                // 解锁取消计时器
-                IS_LOCKED.store(false, Ordering::SeqCst);
-                unsafe {
-                    let _ = KillTimer(Some(hwnd), TIMER_ID_LOCK_CHECK);
-                };
+        };
+        loop {
+            if unsafe { WaitForSingleObject(event, INFINITE) } != WAIT_OBJECT_0 {
+                error!(
+                    "等待 CredUI 事件失败：{}",
+                    windows::core::Error::from_thread()
+                );
+                return;
             }
-            _ => {}
-        }
-    } else if msg == WM_TIMER {
-        if wparam.0 == TIMER_ID_LOCK_CHECK {
-            // 关闭定时器，防止重复触发
-            unsafe {
-                let _ = KillTimer(Some(hwnd), TIMER_ID_LOCK_CHECK);
-            };
-
-            // 二次检查状态
-            if IS_LOCKED.load(Ordering::SeqCst) {
-                // 先打开摄像头
-                let result = open_camera(None, CAMERA_INDEX.load(Ordering::SeqCst));
-                if let Err(e) = result {
-                    error!("打开摄像头失败 {}", e.msg);
-                } else {
-                    // 摄像头成功打开
-                    if let Err(e) = run() {
-                        error!("运行面容解锁失败: {:?}", e);
-                    };
-
-                    if let Err(e) = stop_camera() {
-                        error!("停止摄像头失败: {}", e.msg);
-                    };
+            info!("CredUI 提示框请求确认身份");
+            // 与锁屏相同，先关闭可能正在预览的摄像头
+            let settings = match stop_camera() {
+                Ok(_) => read_lock_settings().unwrap_or_else(|e| {
+                    error!("读取面容识别设置失败，停止启动面容识别: {}", e);
+                    None
+                }),
+                Err(e) => {
+                    error!("关闭摄像头失败: {}", e.to_string());
+                    None
                 }
+            };
+            if let Some(worker) = UNLOCK_WORKER.get() {
+                worker.send(SessionEvent::Prompt(settings));
             }
         }
+    });
+}
+
+// 创建 DLL 通知用的事件，权限与管道相同，只允许 SYSTEM 和管理员打开
+#[cfg(windows)]
+fn create_credui_event() -> windows::core::Result<HANDLE> {
+    let mut sd = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            w!("D:P(A;;GA;;;SY)(A;;GA;;;BA)"),
+            SDDL_REVISION_1,
+            &mut sd,
+            None,
+        )?;
+        let sa = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: sd.0,
+            bInheritHandle: false.into(),
+        };
+        // 自动重置，每次通知只识别一次
+        let event = CreateEventW(Some(&sa), false, false, &HSTRING::from(CREDUI_EVENT_NAME));
+        let _ = LocalFree(Some(HLOCAL(sd.0)));
+        event
     }
-    DefSubclassProc(hwnd, msg, wparam, lparam)
 }
 
-fn run() -> Result<bool, String> {
-    // 从全局变量获取连接并查询
-    if let Ok(pool_guard) = DB_POOL.lock() {
-        if let Some(pool) = pool_guard.as_ref() {
-            // 获取连接
-            let conn = pool
-                .get()
-                .map_err(|e| format!("从连接池获取连接失败：{:?}", e))?;
-            // 获取面容数据
-            let mut faces = conn
-                .prepare("SELECT * FROM faces;")
-                .map_err(|e| format!("准备查询面容数据失败：{:?}", e))?;
-            let rows = faces
-                .query_map([], |row| {
-                    // 读取基础字段
-                    let id = row.get::<&str, i32>("id")?;
-                    let user_name = row.get::<&str, String>("user_name")?;
-                    let user_pwd = row.get::<&str, String>("user_pwd")?;
-                    let account_type = row.get::<&str, String>("account_type")?;
-                    let face_token = row.get::<&str, String>("face_token")?;
-                    let json_data_str = row.get::<&str, String>("json_data")?;
-                    let create_time = row.get::<&str, String>("createTime")?;
-
-                    // 解析 JSON 字符串为结构体
-                    let json_data: FaceExtraData = serde_json::from_str(&json_data_str)
-                        .map_err(|_e| r2d2_sqlite::rusqlite::Error::ExecuteReturnedResults)?;
-
-                    // 返回
-                    Ok((
-                        id,
-                        user_name,
-                        user_pwd,
-                        account_type,
-                        face_token,
-                        json_data,
-                        create_time,
-                    ))
-                })
-                .map_err(|e| format!("查询面容数据失败：{:?}", e))?;
-
-            for row in rows {
-                let (
-                    id,
-                    user_name,
-                    user_pwd,
-                    account_type,
-                    mut face_token,
-                    json_data,
-                    _create_time,
-                ) = row.map_err(|e| format!("获取1条面容数据失败：{:?}", e))?;
-                // 加载数据
-                face_token.push_str(".face");
-                let path = ROOT_DIR.join("faces").join(face_token);
-                // 解析面容数据
-                let face = load_face_data(&path);
-                if face.is_err() {
-                    error!("加载面容数据失败：{:?}", path);
-                    continue;
-                }
+// 识别和解锁依赖的外部资源
+// 锁屏时使用摄像头、模型和 DLL 的管道，测试时可以换成录好的画面、不需要模型的检测和模拟的 DLL
+pub struct UnlockEnv<'a> {
+    pub source: &'a mut dyn FrameSource,
+    pub detector: &'a mut dyn FaceDetector,
+    pub connect: &'a dyn Fn() -> Result<PipeSession, PipeError>,
+    // 面容、设置和解锁记录所在的数据库
+    pub db: &'a DbPool,
+    // 面容数据文件所在的目录
+    pub faces_dir: &'a Path,
+    // 解密面容密码，锁屏时使用 DPAPI
+    pub store: &'a dyn SecretStore,
+    // 按设定的帧率读取画面
+    pub pacer: FramePacer,
+    // 每次识别持续的时间，期间没有确认身份才算失败
+    pub window: Duration,
+    // 手动解锁或超时后停止识别
+    pub cancel: &'a CancelToken,
+    // 向状态机报告进度，例如开始发送凭据
+    pub notify: &'a dyn Fn(SessionEvent),
+}
 
-                let face = face.unwrap();
-                // 参考面容转换失败，跳过当前用户
-                let dst_feature = face.to_mat();
-                if dst_feature.is_err() {
-                    error!("{}, 转换参考面容数据失败：{:?}", json_data.alias, path);
-                    continue;
-                }
-                let dst_feature = dst_feature.unwrap();
-
-                let mut success_count = 0;
-                let mut fail_count = 0;
-
-                loop {
-                    // 读取一帧，摄像头的操作一旦失败，必须退出函数
-                    let frame =
-                        read_mat_from_camera().map_err(|e| format!("摄像头读取失败: {}", e))?;
-                    // 提取特征点
-                    let cur_feature = match get_feature(&frame, json_data.face_detection_threshold)
-                    {
-                        Ok(feature) => feature,
-                        Err(e) => {
-                            let err_msg = format!("特征提取失败: {}", e);
-                            if err_msg.contains("未检测到人脸") {
-                                // 未检测到人脸不动
-                                sleep(Duration::from_millis(200));
-                                continue;
-                            } else {
-                                // 其他错误退出整个函数
-                                return Err(err_msg);
-                            }
-                        }
-                    };
-
-                    let score = {
-                        // 必须实时获取，否则会死锁
-                        let app_state = APP_STATE
-                            .lock()
-                            .map_err(|e| format!("获取app状态失败 {}", e))?;
-
-                        let Some(recognizer) = app_state.recognizer.as_ref() else {
-                            return Err(String::from("人脸识别模型未初始化"));
-                        };
-
-                        recognizer
-                            .inner
-                            .match_(
-                                &dst_feature,
-                                &cur_feature,
-                                FaceRecognizerSF_DisType::FR_COSINE.into(),
-                            )
-                            .map_err(|e| format!("特征匹配失败: {}", e))?
-                    };
-
-                    if score * 100.0 >= json_data.threshold.into() {
-                        // 匹配成功，次数+1
-                        success_count += 1;
-                        if success_count >= MAX_SUCCESS {
-                            // 大于3次，算面容匹配成功
-                            let user_name = if account_type == "local" {
-                                format!(".\\{}", user_name)
-                            } else {
-                                user_name
-                            };
-
-                            if let Err(e) = unlock(user_name, user_pwd) {
-                                return Err(format!("调用解锁函数失败：{}", e));
-                            } else {
-                                if let Err(e) = insert_unlock_log(&conn, id, true) {
-                                    warn!("插入解锁日志失败：{}", e);
-                                };
-                                return Ok(true);
-                            }
-                        }
-                    } else {
-                        success_count = 0;
-                        fail_count += 1;
-                        if fail_count >= MAX_FAIL {
-                            break;
-                        }
+// 打开摄像头识别，返回是否解锁成功
+// 摄像头只在识别期间打开，识别结束后画面源释放时关闭，指示灯不会一直亮着
+pub fn recognize(
+    settings: LockSettings,
+    cancel: &CancelToken,
+    notify: &dyn Fn(SessionEvent),
+) -> bool {
+    let mut source = match camera::open_for_unlock(settings.camera_index) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("打开摄像头失败 {}", e);
+            if cancel.check().is_ok() {
+                match PipeSession::connect() {
+                    Ok(session) => {
+                        notify_failure(session, CAMERA_UNAVAILABLE_STATUS, StatusIcon::Error)
                     }
-
-                    sleep(Duration::from_millis(50));
+                    Err(e) => warn!("显示识别失败提示失败：{}", e),
                 }
             }
-            // 发个假的用户名密码，通知用户解锁失败
-            if let Err(e) = unlock(String::from("null"), String::from("null")) {
-                return Err(format!("调用解锁函数失败：{}", e));
+            return false;
+        }
+    };
+    info!("使用画面源：{}", source.describe());
+    let db = match db::pool() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("获取连接池失败 {}", e);
+            return false;
+        }
+    };
+    let faces_dir = ROOT_DIR.join("faces");
+    let store = default_store();
+    let mut env = UnlockEnv {
+        source: source.as_mut(),
+        detector: &mut ModelDetector,
+        connect: &PipeSession::connect,
+        db: &db,
+        faces_dir: &faces_dir,
+        store: &store,
+        pacer: FramePacer::new(settings.fps),
+        window: Duration::from_millis(settings.window_ms as u64),
+        cancel,
+        notify,
+    };
+    match run(&mut env) {
+        Ok(unlocked) => unlocked,
+        Err(e) => {
+            error!("运行面容解锁失败: {:?}", e);
+            false
+        }
+    }
+}
+
+// 在磁贴上显示失败原因，不发送凭据，提示失败不影响识别结果
+// 假的用户名密码每次都会被系统记为一次登录失败，所以只显示提示
+fn notify_failure(session: PipeSession, reason: &str, icon: StatusIcon) {
+    if let Err(e) = session.report_failure(reason, icon) {
+        warn!("显示识别失败提示失败：{}", e);
+    }
+}
+
+// 刷新磁贴上的识别提示，提示失败不影响识别
+fn show_status(session: &mut PipeSession, text: &str, icon: StatusIcon, progress: Option<u8>) {
+    if let Err(e) = session.show_status(text, icon, progress) {
+        warn!("显示识别提示失败：{}", e);
+    }
+}
+
+// 已用的识别时间占识别时长的百分比，按 PROGRESS_STEP 取整
+fn scan_progress(elapsed: Duration, window: Duration) -> u8 {
+    if window.is_zero() {
+        return 100;
+    }
+    let percent = (elapsed.as_secs_f32() / window.as_secs_f32() * 100.0).min(100.0) as u8;
+    percent / PROGRESS_STEP * PROGRESS_STEP
+}
+
+// 保存失败记录，保存失败不影响识别结果
+fn save_guard(conn: &Connection, guard: &AccountGuard) {
+    if let Err(e) = repo::save_unlock_guard(conn, guard) {
+        warn!("{}", e);
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// 手动解锁后恢复被停用的面容解锁，被系统拒绝的密码仍然不会发送
+pub fn reset_lockouts(conn: &Connection) {
+    match repo::reset_unlock_guards(conn) {
+        Ok(0) => {}
+        Ok(count) => info!("手动解锁，恢复 {} 个账户的面容解锁", count),
+        Err(e) => warn!("{}", e),
+    }
+}
+
+// 完成一次识别和解锁，返回是否解锁成功
+pub fn run(env: &mut UnlockEnv) -> Result<bool, String> {
+    let conn = env
+        .db
+        .get()
+        .map_err(|e| format!("从连接池获取连接失败：{:?}", e))?;
+    // 获取面容数据，密码是加密后的，只有匹配成功准备解锁时才解密
+    let faces = repo::list_faces(&conn)?;
+    let settings = Settings::load(&conn)?;
+    let (gallery, face_detection_threshold) = load_gallery(&faces, env.faces_dir);
+
+    // 识别开始前已经手动解锁时不再连接 DLL
+    env.cancel.check()?;
+    // 识别进度、动作提示、失败原因和解锁请求使用同一个管道连接，期间其他程序无法抢先发送凭据
+    let mut session = (env.connect)().map_err(|e| format!("连接解锁管道失败：{}", e))?;
+
+    // 陌生人脸连续失败时所有账户一起冷却，冷却期间不读取画面
+    let mut any_guard = repo::get_unlock_guard(&conn, ANY_ACCOUNT)?;
+    if let Err(block) = any_guard.check(unix_now()) {
+        info!("暂停面容识别：{}", block);
+        notify_failure(session, &block.to_string(), StatusIcon::Warning);
+        return Ok(false);
+    }
+
+    let scan = identify_from_camera(
+        env,
+        &mut session,
+        &gallery,
+        settings.match_policy,
+        &settings.liveness,
+        settings.face_policy,
+        face_detection_threshold,
+    );
+    let recognition = match scan {
+        Ok(Scan::Matched(recognition)) => Some(recognition),
+        Ok(Scan::Unknown) => None,
+        // 用户不在摄像头前，不记录日志，等待用户唤醒后再识别
+        Ok(Scan::NoFace) => {
+            info!("识别时间内没有检测到人脸");
+            notify_failure(session, NO_FACE_STATUS, StatusIcon::Warning);
+            return Ok(false);
+        }
+        Err(e) => {
+            // 手动解锁或超时时直接断开，DLL 会清除磁贴上的提示
+            if env.cancel.check().is_ok() {
+                notify_failure(session, RECOGNITION_ERROR_STATUS, StatusIcon::Error);
             }
-            if let Err(e) = insert_unlock_log(&conn, -1, false) {
+            return Err(e);
+        }
+    };
+    if let Some(Recognition {
+        candidate,
+        probe,
+        liveness,
+    }) = recognition
+    {
+        let face = faces
+            .iter()
+            .find(|face| face.id == candidate.face_id)
+            .ok_or_else(|| format!("找不到匹配的面容：{}", candidate.face_id))?;
+        let liveness_json = liveness.to_json();
+        // 密码已失效时直接提示，不再发送
+        if face.credentials_stale {
+            warn!("面容 {} 匹配成功，但保存的密码已失效", face.id);
+            notify_failure(session, STALE_CREDENTIALS_STATUS, StatusIcon::Warning);
+            if let Err(e) =
+                repo::insert_unlock_log(&conn, face.id, false, BLOCKED, Some(&liveness_json))
+            {
                 warn!("插入解锁日志失败：{}", e);
             };
             return Ok(false);
+        }
+        let user_name = face.logon_name();
+        let mut guard = repo::get_unlock_guard(&conn, &user_name.to_lowercase())?;
+        let password = reveal_password(env.store, &face.user_pwd)
+            .map_err(|e| format!("解密面容密码失败：{}", e))?;
+        let fingerprint = PasswordFingerprint::load(&conn, env.store)?.of(password.expose());
+        // 冷却中、已停用或者密码已被系统拒绝时不发送凭据
+        if let Err(block) = guard
+            .check(unix_now())
+            .and_then(|_| guard.check_password(&fingerprint))
+        {
+            warn!("面容 {} 匹配成功，但不发送凭据：{}", face.id, block);
+            notify_failure(session, &block.to_string(), StatusIcon::Warning);
+            if let Err(e) =
+                repo::insert_unlock_log(&conn, face.id, false, BLOCKED, Some(&liveness_json))
+            {
+                warn!("插入解锁日志失败：{}", e);
+            };
+            return Ok(false);
+        }
+
+        if !liveness.passed(&settings.liveness) {
+            warn!("面容 {} 未通过活体检测：{}", face.id, liveness_json);
+            guard.record_failure(unix_now());
+            save_guard(&conn, &guard);
+            notify_failure(session, LIVENESS_FAILED_STATUS, StatusIcon::Error);
+            if let Err(e) = repo::insert_unlock_log(
+                &conn,
+                face.id,
+                false,
+                LIVENESS_FAILED,
+                Some(&liveness_json),
+            ) {
+                warn!("插入解锁日志失败：{}", e);
+            };
+            return Ok(false);
+        }
+
+        if settings.challenge {
+            let challenge = random_challenge()?;
+            let target = ChallengeTarget {
+                gallery: &gallery,
+                policy: settings.match_policy,
+                face_id: candidate.face_id,
+                face_policy: settings.face_policy,
+                face_detection_threshold,
+            };
+            let verdict = perform_challenge(env, &mut session, challenge, &target)?;
+            if verdict != ChallengeVerdict::Pass {
+                warn!(
+                    "面容 {} 未通过动作验证 {}：{:?}",
+                    face.id,
+                    challenge.name(),
+                    verdict
+                );
+                guard.record_failure(unix_now());
+                save_guard(&conn, &guard);
+                notify_failure(session, CHALLENGE_FAILED_STATUS, StatusIcon::Error);
+                if let Err(e) = repo::insert_unlock_log(
+                    &conn,
+                    face.id,
+                    false,
+                    CHALLENGE_FAILED,
+                    Some(&liveness_json),
+                ) {
+                    warn!("插入解锁日志失败：{}", e);
+                };
+                return Ok(false);
+            }
+        }
+
+        // 识别期间已经手动解锁时不再发送凭据
+        env.cancel.check()?;
+        show_status(&mut session, MATCHED_STATUS, StatusIcon::Success, None);
+        (env.notify)(SessionEvent::CredentialsSent);
+        let outcome = session
+            .unlock(user_name, password)
+            .map_err(|e| format!("调用解锁函数失败：{}", e))?;
+        // 以 DLL 回传的登录结果为准，管道写入成功不代表解锁成功
+        let is_unlock = outcome.is_success();
+        if let Err(e) = repo::insert_unlock_log(
+            &conn,
+            face.id,
+            is_unlock,
+            &outcome.code(),
+            Some(&liveness_json),
+        ) {
+            warn!("插入解锁日志失败：{}", e);
+        };
+        if is_unlock {
+            guard.record_success();
+            any_guard.record_success();
+            save_guard(&conn, &any_guard);
         } else {
-            return Err(String::from("连接池不存在"));
+            guard.record_logon_failure(
+                unix_now(),
+                &fingerprint,
+                outcome == LogonOutcome::BadPassword,
+                outcome == LogonOutcome::AccountLocked,
+            );
+        }
+        save_guard(&conn, &guard);
+        if outcome == LogonOutcome::BadPassword {
+            // 通常是用户修改了 Windows 密码
+            if let Err(e) = credentials::mark_stale(&conn, face) {
+                warn!("{}", e);
+            }
         }
-    } else {
-        return Err(String::from("从全局变量获取连接池失败"));
+        if !is_unlock {
+            warn!("面容匹配成功，但系统拒绝登录：{}", outcome);
+        } else if settings.adaptive_update {
+            // 只在系统确认解锁成功后更新，更新失败不影响解锁
+            if let Some(identity) = gallery.iter().find(|i| i.face_id == face.id) {
+                if let Err(e) =
+                    update_template(&conn, env.faces_dir, face, identity, candidate.score, probe)
+                {
+                    warn!("自动更新面容模板失败：{}", e);
+                }
+            }
+        }
+        return Ok(is_unlock);
+    }
+
+    any_guard.record_failure(unix_now());
+    save_guard(&conn, &any_guard);
+    notify_failure(session, UNKNOWN_FACE_STATUS, StatusIcon::Error);
+    if let Err(e) = repo::insert_unlock_log(&conn, -1, false, UNKNOWN_FACE, None) {
+        warn!("插入解锁日志失败：{}", e);
+    };
+    Ok(false)
+}
+
+// 加载所有面容的全部模板，返回身份列表和人脸检测置信度
+// 每一帧只检测一次人脸，检测置信度取所有面容中最宽松的一个
+fn load_gallery(faces: &[Face], faces_dir: &Path) -> (Vec<Identity>, f32) {
+    let mut gallery = Vec::with_capacity(faces.len());
+    let mut face_detection_threshold: Option<f32> = None;
+
+    for face in faces {
+        let json_data = match face.extra() {
+            Ok(data) => data,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            }
+        };
+        // 加载数据
+        let path = faces_dir.join(format!("{}.face", face.face_token));
+        // 解析面容数据，失败时跳过当前用户
+        let face_data = match load_face_data(&path) {
+            Ok(data) => data,
+            Err(_) => {
+                error!("{}, 加载面容数据失败：{:?}", json_data.alias, path);
+                continue;
+            }
+        };
+
+        face_detection_threshold = Some(match face_detection_threshold {
+            Some(current) => current.min(json_data.face_detection_threshold),
+            None => json_data.face_detection_threshold,
+        });
+        gallery.push(Identity {
+            face_id: face.id,
+            templates: face_data.features(),
+            // 页面上的阈值是百分比
+            threshold: json_data.threshold / 100.0,
+        });
     }
+
+    (gallery, face_detection_threshold.unwrap_or(0.9))
 }
 
-// 插入解锁日志到数据库
-// 为了统一，这里其实应该前端添加数据，可以实现rust只读，前端读写，并实现响应式数据的同步更新
-// 但是需要包装一个全局变量，存储app，然后向前端发送通知，这里我懒得做了，所以直接后端插入数据了，前端不更新
-fn insert_unlock_log(
-    conn: &r2d2_sqlite::rusqlite::Connection,
-    face_id: i32,
-    is_unlock: bool,
+// 解锁成功后把这次的人脸加入模板，跟踪外貌的缓慢变化
+// 得分必须明显高于阈值，并且限制频率，每次更新都记录到 template_audit
+fn update_template(
+    conn: &Connection,
+    faces_dir: &Path,
+    face: &Face,
+    identity: &Identity,
+    score: f32,
+    probe: Vec<f32>,
 ) -> Result<(), String> {
-    let mut insert_stmt = conn
-        .prepare("INSERT INTO unlock_log (face_id, is_unlock) VALUES (?1, ?2)")
-        .map_err(|e| format!("准备插入解锁日志语句失败：{:?}", e))?;
-
-    // 插入数据
-    insert_stmt
-        .execute(r2d2_sqlite::rusqlite::params![
-            face_id,
-            if is_unlock { 1 } else { 0 }
-        ])
-        .map_err(|e| format!("插入解锁日志失败：{:?}", e))?;
+    let secs_since_last = repo::secs_since_adaptive_update(conn, face.id)?;
+    if let Err(skip) = adaptive::check(
+        &probe,
+        &identity.templates,
+        score,
+        identity.threshold,
+        secs_since_last,
+    ) {
+        info!("面容 {} 不更新模板：{}", face.id, skip);
+        return Ok(());
+    }
+
+    let path = faces_dir.join(format!("{}.face", face.face_token));
+    let mut face_data = load_face_data(&path).map_err(|e| format!("加载面容数据失败：{}", e))?;
+    let (template_id, replaced) = face_data.add_adaptive(probe)?;
+    save_face_data(&path, &face_data).map_err(|e| format!("保存面容数据失败：{}", e))?;
+
+    let detail = replaced
+        .map(|id| format!("替换模板 {}", id))
+        .unwrap_or_default();
+    repo::insert_template_audit(
+        conn,
+        face.id,
+        repo::AUDIT_ADAPTIVE_ADD,
+        Some(template_id),
+        Some(score),
+        &detail,
+    )?;
+    info!("面容 {} 自动添加模板 {} {}", face.id, template_id, detail);
     Ok(())
 }
+
+// 一次识别的结果
+struct Recognition {
+    candidate: Candidate,
+    // 确认身份的最后一帧的特征
+    probe: Vec<f32>,
+    liveness: LivenessReport,
+}
+
+// 一次识别的结果
+enum Scan {
+    Matched(Recognition),
+    // 识别时间内检测到人脸，但没有确认任何面容
+    Unknown,
+    // 识别时间内没有检测到人脸
+    NoFace,
+}
+
+// 在识别时间内持续读取摄像头，每一帧与所有面容比对，连续多帧确认同一面容后再完成活体检测
+// 识别期间在磁贴上显示已用的识别时间
+fn identify_from_camera(
+    env: &mut UnlockEnv,
+    session: &mut PipeSession,
+    gallery: &[Identity],
+    policy: MatchPolicy,
+    liveness_policy: &LivenessPolicy,
+    face_policy: FacePolicy,
+    face_detection_threshold: f32,
+) -> Result<Scan, String> {
+    if gallery.is_empty() {
+        warn!("没有可用的面容数据");
+        return Ok(Scan::Unknown);
+    }
+
+    let mut voter = Voter::new(MAX_SUCCESS);
+    let window_start = Instant::now();
+    let window_end = window_start + env.window;
+    let mut shown_progress = None;
+    let mut seen_face = false;
+    // 只收集同一面容的连续帧，面容变化时重新开始
+    let mut tracker = LivenessTracker::new();
+    let mut tracked_face = None;
+
+    let (candidate, probe) = loop {
+        // 识别时间用完仍没有确认身份
+        let now = Instant::now();
+        if now >= window_end {
+            return Ok(if seen_face {
+                Scan::Unknown
+            } else {
+                Scan::NoFace
+            });
+        }
+        let progress = scan_progress(now - window_start, env.window);
+        if shown_progress != Some(progress) {
+            show_status(
+                session,
+                SCANNING_STATUS,
+                StatusIcon::Scanning,
+                Some(progress),
+            );
+            shown_progress = Some(progress);
+        }
+        let (frame, face, probe) = match capture_face(env, face_detection_threshold, face_policy)? {
+            Capture::Face(frame, face, probe) => (frame, face, probe),
+            Capture::NoFace => continue,
+            // 有旁人时中断连续确认
+            Capture::MultipleFaces => {
+                seen_face = true;
+                voter.observe(None);
+                continue;
+            }
+        };
+        seen_face = true;
+
+        let candidate = identify(&probe, gallery, policy);
+        if let Some(candidate) = candidate {
+            if tracked_face != Some(candidate.face_id) {
+                tracker = LivenessTracker::new();
+                tracked_face = Some(candidate.face_id);
+            }
+            observe_liveness(&mut tracker, &frame, &face, liveness_policy);
+        }
+        if voter.observe(candidate.as_ref()).is_some() {
+            if let Some(candidate) = candidate {
+                break (candidate, probe);
+            }
+        }
+    };
+
+    // 必须通过的线索（例如眨眼、转头）在确认身份的几帧内可能还没出现，继续采集
+    let mut liveness = tracker.evaluate(liveness_policy);
+    let mut extra_frames = 0;
+    while !liveness.passed(liveness_policy) && extra_frames < MAX_LIVENESS_FRAMES {
+        extra_frames += 1;
+        let Capture::Face(frame, face, probe) =
+            capture_face(env, face_detection_threshold, face_policy)?
+        else {
+            continue;
+        };
+        // 画面中换了人的帧不计入
+        if identify(&probe, gallery, policy).map(|c| c.face_id) == Some(candidate.face_id) {
+            observe_liveness(&mut tracker, &frame, &face, liveness_policy);
+            liveness = tracker.evaluate(liveness_policy);
+        }
+    }
+
+    Ok(Scan::Matched(Recognition {
+        candidate,
+        probe,
+        liveness,
+    }))
+}
+
+fn random_challenge() -> Result<Challenge, String> {
+    let mut random = [0u8; 1];
+    getrandom::getrandom(&mut random).map_err(|e| format!("生成随机数失败: {}", e))?;
+    Ok(Challenge::pick(random[0]))
+}
+
+// 动作验证期间要跟踪的面容，以及识别时使用的比对和检测参数
+struct ChallengeTarget<'a> {
+    gallery: &'a [Identity],
+    policy: MatchPolicy,
+    // 识别成功的面容，画面中换了人的帧不计入
+    face_id: i32,
+    face_policy: FacePolicy,
+    face_detection_threshold: f32,
+}
+
+// 在磁贴上提示动作，持续采集同一面容的帧直到动作完成、做错或超过帧数上限
+fn perform_challenge(
+    env: &mut UnlockEnv,
+    session: &mut PipeSession,
+    challenge: Challenge,
+    target: &ChallengeTarget,
+) -> Result<ChallengeVerdict, String> {
+    info!("动作验证：{}", challenge.prompt());
+    session
+        .show_status(challenge.prompt(), StatusIcon::Scanning, None)
+        .map_err(|e| format!("显示动作提示失败：{}", e))?;
+
+    // 转头和点头只需要关键点，眨眼才需要计算睁眼程度
+    let observe_policy = LivenessPolicy {
+        blink: if challenge == Challenge::BlinkTwice {
+            CuePolicy::Observe
+        } else {
+            CuePolicy::Off
+        },
+        head_motion: CuePolicy::Off,
+        texture: CuePolicy::Off,
+        model: CuePolicy::Off,
+    };
+    let mut frames = Vec::new();
+    for _ in 0..MAX_CHALLENGE_FRAMES {
+        let Capture::Face(frame, face, probe) =
+            capture_face(env, target.face_detection_threshold, target.face_policy)?
+        else {
+            continue;
+        };
+        // 画面中换了人的帧不计入
+        if identify(&probe, target.gallery, target.policy).map(|c| c.face_id)
+            != Some(target.face_id)
+        {
+            continue;
+        }
+        match liveness::observe(&frame, &face, &observe_policy) {
+            Ok(observation) => frames.push(observation),
+            Err(e) => {
+                warn!("动作验证计算失败：{}", e);
+                continue;
+            }
+        }
+        match challenge.verify(&frames) {
+            ChallengeVerdict::Pending => {}
+            verdict => return Ok(verdict),
+        }
+    }
+    Ok(ChallengeVerdict::Pending)
+}
+
+// 读取一帧的检测结果
+enum Capture {
+    NoFace,
+    // 策略为拒绝多人时画面中有多张人脸
+    MultipleFaces,
+    // (帧, 检测结果行, 特征)
+    Face(Mat, Mat, Vec<f32>),
+}
+
+// 读取一帧并按多人脸策略选出一张人脸
+// 摄像头的操作一旦失败，必须退出识别
+fn capture_face(
+    env: &mut UnlockEnv,
+    face_detection_threshold: f32,
+    face_policy: FacePolicy,
+) -> Result<Capture, String> {
+    env.cancel.check()?;
+    env.pacer.wait();
+    let frame = read_frame(env.source).map_err(|e| format!("摄像头读取失败: {}", e))?;
+    match env
+        .detector
+        .detect(&frame, face_detection_threshold, face_policy)
+    {
+        Ok((face, feature)) => {
+            let probe = feature_to_vec(&feature)?;
+            Ok(Capture::Face(frame, face, probe))
+        }
+        Err(e) if e.contains("未检测到人脸") => Ok(Capture::NoFace),
+        Err(e) if e.starts_with(MULTIPLE_FACES) => {
+            warn!("{}，不解锁", e);
+            Ok(Capture::MultipleFaces)
+        }
+        // 其他错误退出整个函数
+        Err(e) => Err(format!("特征提取失败: {}", e)),
+    }
+}
+
+// 计算活体检测的观测值，失败时只跳过这一帧
+fn observe_liveness(
+    tracker: &mut LivenessTracker,
+    frame: &Mat,
+    face: &Mat,
+    policy: &LivenessPolicy,
+) {
+    match liveness::observe(frame, face, policy) {
+        Ok(observation) => tracker.push(observation),
+        Err(e) => warn!("活体检测计算失败：{}", e),
+    }
+}
+
+// 读取锁屏后启动面容识别需要的设置
+// 没有面容或未完成初始化时返回 None
+#[cfg(windows)]
+fn read_lock_settings() -> Result<Option<LockSettings>, String> {
+    let conn = db::get_conn()?;
+    if repo::count_faces(&conn)? == 0 {
+        return Ok(None);
+    }
+    let settings = Settings::load(&conn)?;
+    // 只有初始化完成才启动
+    if !settings.is_initialized {
+        return Ok(None);
+    }
+    Ok(Some(LockSettings {
+        delay_ms: settings.face_recog_delay_ms(),
+        // 尚未选择摄像头时使用第一个
+        camera_index: settings.camera.max(0),
+        window_ms: settings.recognition_window_ms(),
+        fps: settings.recognition_fps,
+        rescan_on_wake: settings.rescan_on_wake,
+    }))
+}
@@ -0,0 +1,96 @@
+// 解锁成功后自适应更新模板，跟踪用户外貌的缓慢变化（发型、胡子、眼镜等）
+// 只在高置信度时更新，并且限制频率，避免把误识别的人脸或相似的帧不断加入模板
+use std::fmt;
+
+use super::matcher::cosine_similarity;
+
+// 得分至少要比阈值高出这么多才会更新
+pub const ADAPT_MARGIN: f32 = 0.1;
+// 与已有模板的相似度达到这个值时，新样本没有带来新的信息，不更新
+pub const REDUNDANT_SIMILARITY: f32 = 0.95;
+// 同一面容两次自动更新之间的最短间隔（秒）
+pub const MIN_ADAPT_INTERVAL_SECS: i64 = 24 * 60 * 60;
+
+// 不更新的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Skip {
+    // 得分没有超过阈值足够多
+    LowConfidence,
+    // 距离上次更新时间太短
+    RateLimited,
+    // 与已有模板几乎相同
+    Redundant,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skip::LowConfidence => write!(f, "得分没有比阈值高出 {}", ADAPT_MARGIN),
+            Skip::RateLimited => write!(f, "距离上次更新不足 {} 秒", MIN_ADAPT_INTERVAL_SECS),
+            Skip::Redundant => write!(f, "与已有模板过于相似"),
+        }
+    }
+}
+
+// 判断这次解锁的探针能否作为新模板
+// secs_since_last 为距离上次自动更新的秒数，从未更新过时为 None
+pub fn check(
+    probe: &[f32],
+    templates: &[Vec<f32>],
+    score: f32,
+    threshold: f32,
+    secs_since_last: Option<i64>,
+) -> Result<(), Skip> {
+    if score < threshold + ADAPT_MARGIN {
+        return Err(Skip::LowConfidence);
+    }
+    if secs_since_last.is_some_and(|secs| secs < MIN_ADAPT_INTERVAL_SECS) {
+        return Err(Skip::RateLimited);
+    }
+    if templates
+        .iter()
+        .any(|feature| cosine_similarity(probe, feature) >= REDUNDANT_SIMILARITY)
+    {
+        return Err(Skip::Redundant);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_margin_over_threshold() {
+        let templates = vec![vec![1.0, 0.0]];
+        let probe = [0.8, 0.6];
+        assert_eq!(
+            check(&probe, &templates, 0.65, 0.6, None),
+            Err(Skip::LowConfidence)
+        );
+        assert_eq!(check(&probe, &templates, 0.8, 0.6, None), Ok(()));
+    }
+
+    #[test]
+    fn rate_limited_per_interval() {
+        let templates = vec![vec![1.0, 0.0]];
+        let probe = [0.8, 0.6];
+        assert_eq!(
+            check(&probe, &templates, 0.8, 0.6, Some(60)),
+            Err(Skip::RateLimited)
+        );
+        assert_eq!(
+            check(&probe, &templates, 0.8, 0.6, Some(MIN_ADAPT_INTERVAL_SECS)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn skips_samples_close_to_existing_templates() {
+        let templates = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        assert_eq!(
+            check(&[0.99, 0.01], &templates, 0.99, 0.6, None),
+            Err(Skip::Redundant)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+// 主动式活体检测：识别成功后在磁贴上提示一个随机动作，根据关键点轨迹判断用户是否照做
+// 被动线索可能被视频重放骗过，随机动作要求攻击者实时配合，提高了欺骗的难度
+// 每一帧的观测值与被动检测相同，由 modules::liveness 计算
+use super::liveness::{count_blinks, head_pose, median, FrameObservation};
+
+// 取前几帧的中位数作为动作开始前的基线
+pub const BASELINE_FRAMES: usize = 3;
+// 左右转头时鼻尖偏移（以眼距归一化）相对基线的最小变化
+pub const MIN_TURN: f32 = 0.12;
+// 点头时鼻尖上下偏移相对基线的最小变化，之后需要回到这个值的一半以内
+pub const MIN_NOD: f32 = 0.1;
+// 判断眨眼次数需要的最少帧数
+pub const MIN_BLINK_FRAMES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Challenge {
+    TurnLeft,
+    TurnRight,
+    Nod,
+    BlinkTwice,
+}
+
+// 验证结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChallengeVerdict {
+    // 还没有完成动作，继续采集
+    Pending,
+    Pass,
+    // 做出了相反的动作
+    Fail,
+}
+
+impl Challenge {
+    pub const ALL: [Challenge; 4] = [
+        Challenge::TurnLeft,
+        Challenge::TurnRight,
+        Challenge::Nod,
+        Challenge::BlinkTwice,
+    ];
+
+    // 由随机数选择动作
+    pub fn pick(random: u8) -> Self {
+        Self::ALL[random as usize % Self::ALL.len()]
+    }
+
+    // 显示在磁贴上的提示
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            Challenge::TurnLeft => "请向左转头",
+            Challenge::TurnRight => "请向右转头",
+            Challenge::Nod => "请点头",
+            Challenge::BlinkTwice => "请眨眼两次",
+        }
+    }
+
+    // 记录到解锁日志的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Challenge::TurnLeft => "turn_left",
+            Challenge::TurnRight => "turn_right",
+            Challenge::Nod => "nod",
+            Challenge::BlinkTwice => "blink_twice",
+        }
+    }
+
+    // 根据提示之后采集的帧判断动作是否完成
+    pub fn verify(&self, frames: &[FrameObservation]) -> ChallengeVerdict {
+        match self {
+            Challenge::TurnLeft => verify_turn(frames, 1.0),
+            Challenge::TurnRight => verify_turn(frames, -1.0),
+            Challenge::Nod => verify_nod(frames),
+            Challenge::BlinkTwice => verify_blinks(frames, 2),
+        }
+    }
+}
+
+// 头部姿态 (左右, 上下)，见 liveness::head_pose
+type Pose = (f32, f32);
+
+// 提示之后的头部姿态，以及前几帧的基线
+fn poses(frames: &[FrameObservation]) -> Option<(Pose, Vec<Pose>)> {
+    let poses: Vec<Pose> = frames
+        .iter()
+        .filter_map(|f| head_pose(&f.landmarks))
+        .collect();
+    if poses.len() <= BASELINE_FRAMES {
+        return None;
+    }
+    let head = &poses[..BASELINE_FRAMES];
+    let yaw = median(&mut head.iter().map(|p| p.0).collect::<Vec<_>>());
+    let pitch = median(&mut head.iter().map(|p| p.1).collect::<Vec<_>>());
+    Some(((yaw, pitch), poses))
+}
+
+// direction 为 1 时要求向左转（左右偏移变大），-1 时要求向右转
+fn verify_turn(frames: &[FrameObservation], direction: f32) -> ChallengeVerdict {
+    let Some(((base_yaw, _), poses)) = poses(frames) else {
+        return ChallengeVerdict::Pending;
+    };
+    for (yaw, _) in &poses[BASELINE_FRAMES..] {
+        let delta = (yaw - base_yaw) * direction;
+        if delta >= MIN_TURN {
+            return ChallengeVerdict::Pass;
+        }
+        if delta <= -MIN_TURN {
+            return ChallengeVerdict::Fail;
+        }
+    }
+    ChallengeVerdict::Pending
+}
+
+// 先明显低头或抬头，再回到正视
+fn verify_nod(frames: &[FrameObservation]) -> ChallengeVerdict {
+    let Some(((base_yaw, base_pitch), poses)) = poses(frames) else {
+        return ChallengeVerdict::Pending;
+    };
+    let mut nodded = false;
+    for (yaw, pitch) in &poses[BASELINE_FRAMES..] {
+        // 转头不算点头
+        if (yaw - base_yaw).abs() >= MIN_TURN {
+            return ChallengeVerdict::Fail;
+        }
+        let delta = (pitch - base_pitch).abs();
+        if delta >= MIN_NOD {
+            nodded = true;
+        } else if nodded && delta <= MIN_NOD / 2.0 {
+            return ChallengeVerdict::Pass;
+        }
+    }
+    ChallengeVerdict::Pending
+}
+
+fn verify_blinks(frames: &[FrameObservation], times: usize) -> ChallengeVerdict {
+    let openness: Vec<f32> = frames.iter().filter_map(|f| f.eye_openness).collect();
+    if openness.len() < MIN_BLINK_FRAMES {
+        return ChallengeVerdict::Pending;
+    }
+    match count_blinks(&openness) {
+        Some(count) if count >= times => ChallengeVerdict::Pass,
+        _ => ChallengeVerdict::Pending,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognition::liveness::Landmarks;
+
+    // 录制的关键点轨迹，每行一帧：五个关键点的坐标和睁眼程度
+    fn fixture(csv: &str) -> Vec<FrameObservation> {
+        csv.lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let v: Vec<f32> = line.split(',').map(|s| s.trim().parse().unwrap()).collect();
+                FrameObservation {
+                    landmarks: Landmarks {
+                        right_eye: (v[0], v[1]),
+                        left_eye: (v[2], v[3]),
+                        nose: (v[4], v[5]),
+                        mouth_right: (v[6], v[7]),
+                        mouth_left: (v[8], v[9]),
+                    },
+                    eye_openness: Some(v[10]),
+                    texture: None,
+                    model_score: None,
+                }
+            })
+            .collect()
+    }
+
+    macro_rules! load {
+        ($name:literal) => {
+            fixture(include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/landmarks/",
+                $name,
+                ".csv"
+            )))
+        };
+    }
+
+    #[test]
+    fn turns_are_directional() {
+        let left = load!("turn_left");
+        let right = load!("turn_right");
+        assert_eq!(Challenge::TurnLeft.verify(&left), ChallengeVerdict::Pass);
+        assert_eq!(Challenge::TurnRight.verify(&right), ChallengeVerdict::Pass);
+        assert_eq!(Challenge::TurnRight.verify(&left), ChallengeVerdict::Fail);
+        assert_eq!(Challenge::TurnLeft.verify(&right), ChallengeVerdict::Fail);
+        // 只有基线时还在等待
+        assert_eq!(
+            Challenge::TurnLeft.verify(&left[..BASELINE_FRAMES + 1]),
+            ChallengeVerdict::Pending
+        );
+    }
+
+    #[test]
+    fn nod_requires_return() {
+        let nod = load!("nod");
+        assert_eq!(Challenge::Nod.verify(&nod), ChallengeVerdict::Pass);
+        // 低头到最低处时还没有完成
+        assert_eq!(Challenge::Nod.verify(&nod[..10]), ChallengeVerdict::Pending);
+        assert_eq!(
+            Challenge::Nod.verify(&load!("turn_left")),
+            ChallengeVerdict::Fail
+        );
+    }
+
+    #[test]
+    fn blink_count_must_match() {
+        assert_eq!(
+            Challenge::BlinkTwice.verify(&load!("blink_twice")),
+            ChallengeVerdict::Pass
+        );
+        assert_eq!(
+            Challenge::BlinkTwice.verify(&load!("blink_once")),
+            ChallengeVerdict::Pending
+        );
+    }
+
+    #[test]
+    fn moving_photo_never_passes() {
+        let photo = load!("photo_shift");
+        for challenge in Challenge::ALL {
+            assert_ne!(challenge.verify(&photo), ChallengeVerdict::Pass);
+        }
+    }
+
+    #[test]
+    fn pick_covers_all_challenges() {
+        let picked: Vec<Challenge> = (0..4).map(Challenge::pick).collect();
+        assert_eq!(picked, Challenge::ALL);
+    }
+}
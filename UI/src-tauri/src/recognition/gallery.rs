@@ -0,0 +1,326 @@
+// 面容特征文件 <face_token>.face 的格式，一个身份可以保存多个模板
+// 新格式：魔数 + 版本号 + bincode(FaceGallery)
+// 版本 1 的模板没有 adaptive 字段，读取时视为录入的模板
+// 旧格式：bincode(FaceDescriptor { name, feature })，读取时转为只有一个模板的 FaceGallery
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8; 4] = b"FWUG";
+const VERSION: u8 = 2;
+
+// 每个身份最多保存的模板数
+pub const MAX_TEMPLATES: usize = 10;
+// 其中解锁后自动添加的模板最多几个，超出时替换最早的一个
+pub const MAX_ADAPTIVE_TEMPLATES: usize = 3;
+
+// 一个面容模板，例如正脸、侧脸、戴眼镜、弱光
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FaceTemplate {
+    // 在同一个身份内唯一
+    pub id: u32,
+    // 模板说明，只用于显示
+    pub label: String,
+    // SFace 特征向量
+    pub feature: Vec<f32>,
+    // 是否为解锁成功后自动添加的模板，回滚时只删除这些模板
+    pub adaptive: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FaceGallery {
+    pub name: String,
+    pub templates: Vec<FaceTemplate>,
+}
+
+// 版本 1 的结构
+#[derive(Deserialize)]
+struct FaceTemplateV1 {
+    id: u32,
+    label: String,
+    feature: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct FaceGalleryV1 {
+    name: String,
+    templates: Vec<FaceTemplateV1>,
+}
+
+// 旧版本只保存一个特征
+#[derive(Deserialize)]
+struct LegacyDescriptor {
+    name: String,
+    feature: Vec<f32>,
+}
+
+impl FaceGallery {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            templates: Vec::new(),
+        }
+    }
+
+    // 添加录入的模板，返回新模板的ID
+    pub fn add(&mut self, label: &str, feature: Vec<f32>) -> Result<u32, String> {
+        if self.templates.len() >= MAX_TEMPLATES {
+            return Err(format!("每个面容最多保存 {} 个模板", MAX_TEMPLATES));
+        }
+        self.push(label, feature, false)
+    }
+
+    // 添加自动更新的模板，返回 (新模板的ID, 被替换的模板ID)
+    // 自动模板已满或总数已满时替换最早的自动模板，没有可替换的则失败
+    pub fn add_adaptive(&mut self, feature: Vec<f32>) -> Result<(u32, Option<u32>), String> {
+        let adaptive_count = self.templates.iter().filter(|t| t.adaptive).count();
+        let mut replaced = None;
+        if adaptive_count >= MAX_ADAPTIVE_TEMPLATES || self.templates.len() >= MAX_TEMPLATES {
+            // ID 递增，最小的就是最早的
+            let oldest = self
+                .templates
+                .iter()
+                .filter(|t| t.adaptive)
+                .min_by_key(|t| t.id)
+                .map(|t| t.id)
+                .ok_or_else(|| format!("每个面容最多保存 {} 个模板", MAX_TEMPLATES))?;
+            replaced = Some(oldest);
+        }
+        if let Some(first) = self.templates.first() {
+            if first.feature.len() != feature.len() {
+                return Err(String::from("模板特征长度不一致"));
+            }
+        }
+        if let Some(oldest) = replaced {
+            self.templates.retain(|t| t.id != oldest);
+        }
+        let id = self.push("自动更新", feature, true)?;
+        Ok((id, replaced))
+    }
+
+    fn push(&mut self, label: &str, feature: Vec<f32>, adaptive: bool) -> Result<u32, String> {
+        if let Some(first) = self.templates.first() {
+            if first.feature.len() != feature.len() {
+                return Err(String::from("模板特征长度不一致"));
+            }
+        }
+        let id = self.templates.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+        self.templates.push(FaceTemplate {
+            id,
+            label: label.to_string(),
+            feature,
+            adaptive,
+        });
+        Ok(id)
+    }
+
+    // 删除模板，至少保留一个录入的模板
+    pub fn remove(&mut self, id: u32) -> Result<(), String> {
+        let index = self
+            .templates
+            .iter()
+            .position(|t| t.id == id)
+            .ok_or_else(|| format!("模板 {} 不存在", id))?;
+        if !self.templates[index].adaptive
+            && self.templates.iter().filter(|t| !t.adaptive).count() == 1
+        {
+            return Err(String::from("至少需要保留一个录入的模板"));
+        }
+        self.templates.remove(index);
+        Ok(())
+    }
+
+    // 删除所有自动更新的模板，恢复为录入时的模板，返回删除的模板ID
+    pub fn rollback_adaptive(&mut self) -> Vec<u32> {
+        let removed = self
+            .templates
+            .iter()
+            .filter(|t| t.adaptive)
+            .map(|t| t.id)
+            .collect();
+        self.templates.retain(|t| !t.adaptive);
+        removed
+    }
+
+    // 所有模板的特征，用于比对
+    pub fn features(&self) -> Vec<Vec<f32>> {
+        self.templates.iter().map(|t| t.feature.clone()).collect()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let body = bincode::serialize(self).map_err(|e| format!("序列化面容数据失败：{}", e))?;
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + body.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if let Some(rest) = bytes.strip_prefix(MAGIC) {
+            let (version, body) = rest
+                .split_first()
+                .ok_or_else(|| String::from("面容数据不完整"))?;
+            let gallery: Self = match *version {
+                VERSION => {
+                    bincode::deserialize(body).map_err(|e| format!("解析面容数据失败：{}", e))?
+                }
+                1 => {
+                    let v1: FaceGalleryV1 = bincode::deserialize(body)
+                        .map_err(|e| format!("解析面容数据失败：{}", e))?;
+                    Self {
+                        name: v1.name,
+                        templates: v1
+                            .templates
+                            .into_iter()
+                            .map(|t| FaceTemplate {
+                                id: t.id,
+                                label: t.label,
+                                feature: t.feature,
+                                adaptive: false,
+                            })
+                            .collect(),
+                    }
+                }
+                _ => return Err(format!("不支持的面容数据版本：{}", version)),
+            };
+            if gallery.templates.is_empty() {
+                return Err(String::from("面容数据中没有模板"));
+            }
+            return Ok(gallery);
+        }
+
+        let legacy: LegacyDescriptor =
+            bincode::deserialize(bytes).map_err(|e| format!("解析旧版面容数据失败：{}", e))?;
+        Ok(Self {
+            name: legacy.name,
+            templates: vec![FaceTemplate {
+                id: 1,
+                label: String::from("默认"),
+                feature: legacy.feature,
+                adaptive: false,
+            }],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gallery_roundtrip() {
+        let mut gallery = FaceGallery::new("alice");
+        gallery.add("正脸", vec![1.0, 0.0]).unwrap();
+        gallery.add("戴眼镜", vec![0.0, 1.0]).unwrap();
+        let decoded = FaceGallery::from_bytes(&gallery.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, gallery);
+        assert_eq!(decoded.templates[1].id, 2);
+    }
+
+    #[test]
+    fn legacy_descriptor_becomes_single_template() {
+        #[derive(Serialize)]
+        struct FaceDescriptor {
+            name: String,
+            feature: Vec<f32>,
+        }
+        let bytes = bincode::serialize(&FaceDescriptor {
+            name: String::from("bob"),
+            feature: vec![0.5, 0.25],
+        })
+        .unwrap();
+
+        let gallery = FaceGallery::from_bytes(&bytes).unwrap();
+        assert_eq!(gallery.name, "bob");
+        assert_eq!(gallery.templates.len(), 1);
+        assert_eq!(gallery.templates[0].feature, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn add_and_remove_keep_invariants() {
+        let mut gallery = FaceGallery::new("carol");
+        let first = gallery.add("正脸", vec![1.0, 0.0]).unwrap();
+        assert!(gallery.add("错误", vec![1.0]).is_err());
+        assert!(gallery.remove(first).is_err());
+
+        let second = gallery.add("左侧", vec![0.0, 1.0]).unwrap();
+        gallery.remove(first).unwrap();
+        // 删除后新模板的ID不会与已有的重复
+        let third = gallery.add("右侧", vec![0.6, 0.8]).unwrap();
+        assert!(third > second);
+        assert!(gallery.remove(99).is_err());
+
+        for i in gallery.templates.len()..MAX_TEMPLATES {
+            gallery.add(&i.to_string(), vec![0.0, 1.0]).unwrap();
+        }
+        assert!(gallery.add("超出", vec![0.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn version_1_templates_are_enrolled() {
+        #[derive(Serialize)]
+        struct TemplateV1 {
+            id: u32,
+            label: String,
+            feature: Vec<f32>,
+        }
+        #[derive(Serialize)]
+        struct GalleryV1 {
+            name: String,
+            templates: Vec<TemplateV1>,
+        }
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend(
+            bincode::serialize(&GalleryV1 {
+                name: String::from("dave"),
+                templates: vec![TemplateV1 {
+                    id: 3,
+                    label: String::from("正脸"),
+                    feature: vec![1.0, 0.0],
+                }],
+            })
+            .unwrap(),
+        );
+
+        let gallery = FaceGallery::from_bytes(&bytes).unwrap();
+        assert_eq!(gallery.templates[0].id, 3);
+        assert!(!gallery.templates[0].adaptive);
+    }
+
+    #[test]
+    fn adaptive_templates_are_bounded_and_rolled_back() {
+        let mut gallery = FaceGallery::new("erin");
+        let enrolled = gallery.add("正脸", vec![1.0, 0.0]).unwrap();
+
+        let mut adaptive = Vec::new();
+        for _ in 0..MAX_ADAPTIVE_TEMPLATES {
+            let (id, replaced) = gallery.add_adaptive(vec![0.9, 0.1]).unwrap();
+            assert_eq!(replaced, None);
+            adaptive.push(id);
+        }
+        // 超出上限时替换最早的自动模板
+        let (newest, replaced) = gallery.add_adaptive(vec![0.8, 0.2]).unwrap();
+        assert_eq!(replaced, Some(adaptive[0]));
+        assert_eq!(gallery.templates.len(), 1 + MAX_ADAPTIVE_TEMPLATES);
+
+        // 只剩一个录入的模板时不能删除，自动模板可以删除
+        assert!(gallery.remove(enrolled).is_err());
+        gallery.remove(newest).unwrap();
+
+        let removed = gallery.rollback_adaptive();
+        assert_eq!(removed, adaptive[1..].to_vec());
+        assert_eq!(gallery.templates.len(), 1);
+        assert_eq!(gallery.templates[0].id, enrolled);
+    }
+
+    #[test]
+    fn adaptive_never_evicts_enrolled_templates() {
+        let mut gallery = FaceGallery::new("frank");
+        for i in 0..MAX_TEMPLATES {
+            gallery.add(&i.to_string(), vec![1.0, 0.0]).unwrap();
+        }
+        assert!(gallery.add_adaptive(vec![1.0, 0.0]).is_err());
+        assert!(gallery.add_adaptive(vec![1.0]).is_err());
+        assert_eq!(gallery.templates.len(), MAX_TEMPLATES);
+    }
+}
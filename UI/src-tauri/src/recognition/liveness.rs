@@ -0,0 +1,423 @@
+// 活体检测，在比对成功和调用解锁之间进行
+// 2D 识别可能被照片或视频欺骗，这里利用识别时已经采集的多帧，综合几种线索判断是否为真人：
+// 眨眼、头部转动时五官的视差、屏幕摩尔纹和反光、可选的 ONNX 防伪模型
+// 每一帧的观测值由 modules::liveness 从图像中计算，这里只负责判断
+use serde::{Deserialize, Serialize};
+
+// 每种线索的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CuePolicy {
+    // 不计算
+    Off,
+    // 只计算并记录到解锁日志，不影响解锁
+    #[default]
+    Observe,
+    // 必须通过才能解锁
+    Require,
+}
+
+// 各线索的策略，保存在设置中
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LivenessPolicy {
+    pub blink: CuePolicy,
+    pub head_motion: CuePolicy,
+    pub texture: CuePolicy,
+    // 需要 resources 目录下有防伪模型，默认不启用
+    pub model: CuePolicy,
+}
+
+impl Default for LivenessPolicy {
+    fn default() -> Self {
+        Self {
+            blink: CuePolicy::Observe,
+            head_motion: CuePolicy::Observe,
+            texture: CuePolicy::Observe,
+            model: CuePolicy::Off,
+        }
+    }
+}
+
+impl LivenessPolicy {
+    // 是否有必须通过的线索，有的话识别成功后还需要继续采集帧
+    pub fn has_required(&self) -> bool {
+        [self.blink, self.head_motion, self.texture, self.model].contains(&CuePolicy::Require)
+    }
+}
+
+// 单个线索的结果
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueVerdict {
+    // 策略为 Off，没有计算
+    Skipped,
+    // 帧数不够或缺少数据，无法判断
+    Unknown,
+    Pass,
+    Fail,
+}
+
+// YuNet 输出的 5 个关键点，图像坐标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Landmarks {
+    pub right_eye: (f32, f32),
+    pub left_eye: (f32, f32),
+    pub nose: (f32, f32),
+    pub mouth_right: (f32, f32),
+    pub mouth_left: (f32, f32),
+}
+
+// 人脸区域的纹理统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureStats {
+    // 高频能量与整体能量之比，屏幕翻拍的摩尔纹会使其偏高
+    pub high_freq_ratio: f32,
+    // 接近饱和的像素比例，屏幕和相片的镜面反光会使其偏高
+    pub specular_ratio: f32,
+}
+
+// 一帧的观测值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameObservation {
+    pub landmarks: Landmarks,
+    // 两眼区域的睁眼程度，越大越像睁眼，只有相对变化有意义
+    pub eye_openness: Option<f32>,
+    pub texture: Option<TextureStats>,
+    // 防伪模型给出的真人概率，0 ~ 1
+    pub model_score: Option<f32>,
+}
+
+// 各线索需要的最少帧数
+pub const MIN_BLINK_FRAMES: usize = 6;
+pub const MIN_MOTION_FRAMES: usize = 5;
+pub const MIN_TEXTURE_FRAMES: usize = 3;
+pub const MIN_MODEL_FRAMES: usize = 3;
+
+// 睁眼程度低于基线的这个比例视为闭眼
+pub const BLINK_CLOSED_RATIO: f32 = 0.7;
+// 闭眼之后恢复到基线的这个比例视为重新睁眼
+pub const BLINK_REOPEN_RATIO: f32 = 0.9;
+// 鼻尖相对两眼中点的偏移（以眼距归一化）的变化范围至少要达到这个值
+pub const MIN_PARALLAX: f32 = 0.04;
+// 纹理统计的中位数上限
+pub const MAX_HIGH_FREQ_RATIO: f32 = 0.35;
+pub const MAX_SPECULAR_RATIO: f32 = 0.05;
+// 防伪模型真人概率中位数的下限
+pub const MIN_MODEL_SCORE: f32 = 0.6;
+
+// 活体检测的结果，序列化后记录到解锁日志
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LivenessReport {
+    pub frames: usize,
+    pub blink: CueVerdict,
+    pub head_motion: CueVerdict,
+    pub texture: CueVerdict,
+    pub model: CueVerdict,
+}
+
+impl LivenessReport {
+    fn cues(&self, policy: &LivenessPolicy) -> [(CuePolicy, CueVerdict); 4] {
+        [
+            (policy.blink, self.blink),
+            (policy.head_motion, self.head_motion),
+            (policy.texture, self.texture),
+            (policy.model, self.model),
+        ]
+    }
+
+    // 所有必须通过的线索都已通过
+    pub fn passed(&self, policy: &LivenessPolicy) -> bool {
+        self.cues(policy)
+            .iter()
+            .all(|(p, v)| *p != CuePolicy::Require || *v == CueVerdict::Pass)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+// 收集识别过程中的每一帧，随时可以按策略给出结果
+#[derive(Debug, Default)]
+pub struct LivenessTracker {
+    frames: Vec<FrameObservation>,
+}
+
+impl LivenessTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, observation: FrameObservation) {
+        self.frames.push(observation);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn evaluate(&self, policy: &LivenessPolicy) -> LivenessReport {
+        let run = |cue: CuePolicy, check: fn(&[FrameObservation]) -> CueVerdict| {
+            if cue == CuePolicy::Off {
+                CueVerdict::Skipped
+            } else {
+                check(&self.frames)
+            }
+        };
+        LivenessReport {
+            frames: self.frames.len(),
+            blink: run(policy.blink, check_blink),
+            head_motion: run(policy.head_motion, check_head_motion),
+            texture: run(policy.texture, check_texture),
+            model: run(policy.model, check_model),
+        }
+    }
+}
+
+pub(crate) fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+// 出现一次明显下降并恢复即为眨眼
+fn check_blink(frames: &[FrameObservation]) -> CueVerdict {
+    let openness: Vec<f32> = frames.iter().filter_map(|f| f.eye_openness).collect();
+    if openness.len() < MIN_BLINK_FRAMES {
+        return CueVerdict::Unknown;
+    }
+    match count_blinks(&openness) {
+        None => CueVerdict::Unknown,
+        Some(0) => CueVerdict::Fail,
+        Some(_) => CueVerdict::Pass,
+    }
+}
+
+// 以睁眼程度的中位数为基线，统计明显下降后又恢复的次数，基线无效时返回 None
+pub(crate) fn count_blinks(openness: &[f32]) -> Option<usize> {
+    if openness.is_empty() {
+        return None;
+    }
+    let baseline = median(&mut openness.to_vec());
+    if baseline <= 0.0 {
+        return None;
+    }
+
+    let mut blinks = 0;
+    let mut closed = false;
+    for value in openness {
+        if *value < baseline * BLINK_CLOSED_RATIO {
+            closed = true;
+        } else if closed && *value >= baseline * BLINK_REOPEN_RATIO {
+            closed = false;
+            blinks += 1;
+        }
+    }
+    Some(blinks)
+}
+
+// 鼻尖相对两眼中点的偏移，以眼距归一化，返回 (左右, 上下)
+// 左右方向沿右眼指向左眼，向左转头时变大；上下方向向下为正
+// 两眼距离过近（检测异常）时返回 None
+pub fn head_pose(l: &Landmarks) -> Option<(f32, f32)> {
+    let axis = (l.left_eye.0 - l.right_eye.0, l.left_eye.1 - l.right_eye.1);
+    let distance = (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+    if distance < 1.0 {
+        return None;
+    }
+    let center = (
+        (l.left_eye.0 + l.right_eye.0) / 2.0,
+        (l.left_eye.1 + l.right_eye.1) / 2.0,
+    );
+    let nose = (l.nose.0 - center.0, l.nose.1 - center.1);
+    // 投影到两眼连线方向（左右转头）和垂直方向（抬头低头）
+    let yaw = (nose.0 * axis.0 + nose.1 * axis.1) / (distance * distance);
+    let pitch = (nose.1 * axis.0 - nose.0 * axis.1) / (distance * distance);
+    Some((yaw, pitch))
+}
+
+// 平面的照片无论怎么移动，五官的相对位置都不变；真人转头时鼻尖相对两眼会有偏移
+fn check_head_motion(frames: &[FrameObservation]) -> CueVerdict {
+    if frames.len() < MIN_MOTION_FRAMES {
+        return CueVerdict::Unknown;
+    }
+    let offsets: Vec<(f32, f32)> = frames
+        .iter()
+        .filter_map(|f| head_pose(&f.landmarks))
+        .collect();
+    if offsets.len() < MIN_MOTION_FRAMES {
+        return CueVerdict::Unknown;
+    }
+
+    let range = |values: Vec<f32>| {
+        let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        max - min
+    };
+    let yaw_range = range(offsets.iter().map(|o| o.0).collect());
+    let pitch_range = range(offsets.iter().map(|o| o.1).collect());
+    if yaw_range.max(pitch_range) >= MIN_PARALLAX {
+        CueVerdict::Pass
+    } else {
+        CueVerdict::Fail
+    }
+}
+
+fn check_texture(frames: &[FrameObservation]) -> CueVerdict {
+    let stats: Vec<TextureStats> = frames.iter().filter_map(|f| f.texture).collect();
+    if stats.len() < MIN_TEXTURE_FRAMES {
+        return CueVerdict::Unknown;
+    }
+    let high_freq = median(&mut stats.iter().map(|s| s.high_freq_ratio).collect::<Vec<_>>());
+    let specular = median(&mut stats.iter().map(|s| s.specular_ratio).collect::<Vec<_>>());
+    if high_freq <= MAX_HIGH_FREQ_RATIO && specular <= MAX_SPECULAR_RATIO {
+        CueVerdict::Pass
+    } else {
+        CueVerdict::Fail
+    }
+}
+
+fn check_model(frames: &[FrameObservation]) -> CueVerdict {
+    let mut scores: Vec<f32> = frames.iter().filter_map(|f| f.model_score).collect();
+    if scores.len() < MIN_MODEL_FRAMES {
+        return CueVerdict::Unknown;
+    }
+    if median(&mut scores) >= MIN_MODEL_SCORE {
+        CueVerdict::Pass
+    } else {
+        CueVerdict::Fail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 眼距 100 像素的正脸，nose_dx 为鼻尖的水平偏移
+    fn frame(nose_dx: f32, eye_openness: f32) -> FrameObservation {
+        FrameObservation {
+            landmarks: Landmarks {
+                right_eye: (100.0, 100.0),
+                left_eye: (200.0, 100.0),
+                nose: (150.0 + nose_dx, 150.0),
+                mouth_right: (115.0, 190.0),
+                mouth_left: (185.0, 190.0),
+            },
+            eye_openness: Some(eye_openness),
+            texture: Some(TextureStats {
+                high_freq_ratio: 0.1,
+                specular_ratio: 0.01,
+            }),
+            model_score: None,
+        }
+    }
+
+    fn tracker(frames: impl IntoIterator<Item = FrameObservation>) -> LivenessTracker {
+        let mut tracker = LivenessTracker::new();
+        frames.into_iter().for_each(|f| tracker.push(f));
+        tracker
+    }
+
+    #[test]
+    fn blink_requires_close_and_reopen() {
+        let policy = LivenessPolicy::default();
+        let open = [1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let blink = [1.0, 1.0, 0.4, 0.5, 1.0, 1.0, 1.0];
+        let closing = [1.0, 1.0, 1.0, 1.0, 0.3, 0.3];
+
+        let report = |values: &[f32]| {
+            tracker(values.iter().map(|v| frame(0.0, *v)))
+                .evaluate(&policy)
+                .blink
+        };
+        assert_eq!(report(&open), CueVerdict::Fail);
+        assert_eq!(report(&blink), CueVerdict::Pass);
+        assert_eq!(report(&closing), CueVerdict::Fail);
+        assert_eq!(report(&[1.0, 0.2, 1.0]), CueVerdict::Unknown);
+    }
+
+    #[test]
+    fn rigid_photo_has_no_parallax() {
+        let policy = LivenessPolicy::default();
+        // 照片平移、缩放时所有关键点一起移动
+        let photo = tracker((0..6).map(|i| {
+            let mut f = frame(0.0, 1.0);
+            let shift = i as f32 * 10.0;
+            for point in [
+                &mut f.landmarks.right_eye,
+                &mut f.landmarks.left_eye,
+                &mut f.landmarks.nose,
+            ] {
+                point.0 += shift;
+                point.1 += shift / 2.0;
+            }
+            f
+        }));
+        assert_eq!(photo.evaluate(&policy).head_motion, CueVerdict::Fail);
+
+        let turning = tracker((0..6).map(|i| frame(i as f32 * 2.0, 1.0)));
+        assert_eq!(turning.evaluate(&policy).head_motion, CueVerdict::Pass);
+    }
+
+    #[test]
+    fn required_cues_gate_the_result() {
+        let policy = LivenessPolicy {
+            blink: CuePolicy::Require,
+            head_motion: CuePolicy::Observe,
+            texture: CuePolicy::Require,
+            model: CuePolicy::Off,
+        };
+        assert!(policy.has_required());
+        assert!(!LivenessPolicy::default().has_required());
+
+        let mut frames: Vec<_> = [1.0, 1.0, 0.4, 1.0, 1.0, 1.0]
+            .iter()
+            .map(|v| frame(0.0, *v))
+            .collect();
+        let report = tracker(frames.clone()).evaluate(&policy);
+        // 没有转头不影响结果，模型没有启用
+        assert_eq!(report.head_motion, CueVerdict::Fail);
+        assert_eq!(report.model, CueVerdict::Skipped);
+        assert!(report.passed(&policy));
+
+        // 屏幕翻拍的摩尔纹
+        for f in frames.iter_mut() {
+            f.texture = Some(TextureStats {
+                high_freq_ratio: 0.6,
+                specular_ratio: 0.01,
+            });
+        }
+        let report = tracker(frames).evaluate(&policy);
+        assert_eq!(report.texture, CueVerdict::Fail);
+        assert!(!report.passed(&policy));
+    }
+
+    #[test]
+    fn model_without_scores_is_unknown() {
+        let policy = LivenessPolicy {
+            model: CuePolicy::Require,
+            ..LivenessPolicy::default()
+        };
+        let mut frames: Vec<_> = (0..4).map(|_| frame(0.0, 1.0)).collect();
+        let report = tracker(frames.clone()).evaluate(&policy);
+        assert_eq!(report.model, CueVerdict::Unknown);
+        assert!(!report.passed(&policy));
+
+        frames.iter_mut().for_each(|f| f.model_score = Some(0.9));
+        assert!(tracker(frames).evaluate(&policy).passed(&policy));
+    }
+
+    #[test]
+    fn report_serializes_for_unlock_log() {
+        let report = tracker([frame(0.0, 1.0)]).evaluate(&LivenessPolicy::default());
+        assert_eq!(
+            report.to_json(),
+            r#"{"frames":1,"blink":"unknown","headMotion":"unknown","texture":"unknown","model":"skipped"}"#
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// 暴力尝试和账户锁定保护
+// 连续识别失败和登录失败按账户计数，超过次数后按指数增长冷却，失败太多时停用该账户的面容解锁，
+// 直到用户下一次手动解锁。被系统拒绝过的密码不会再次发送，避免把真实账户推向 Windows 的账户锁定
+use std::fmt;
+
+// 没有匹配到面容的失败不属于任何账户，记在这里并对所有账户生效
+pub const ANY_ACCOUNT: &str = "*";
+// 连续失败这么多次后开始冷却
+pub const COOLDOWN_AFTER: u32 = 3;
+// 第一次冷却的秒数，之后每多失败一次翻倍
+pub const BASE_COOLDOWN_SECS: i64 = 30;
+// 冷却时间的上限
+pub const MAX_COOLDOWN_SECS: i64 = 60 * 60;
+// 连续识别失败这么多次后停用面容解锁
+pub const DISABLE_AFTER: u32 = 10;
+// 连续登录失败这么多次后停用面容解锁
+pub const DISABLE_AFTER_LOGON_FAILURES: u32 = 2;
+
+// 一个账户的失败记录
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountGuard {
+    // 登录用的用户名（小写），未匹配到面容时为 ANY_ACCOUNT
+    pub account: String,
+    // 连续识别失败的次数：陌生人脸、未通过活体检测或动作验证
+    pub failures: u32,
+    // 连续登录失败的次数：凭据已发送但系统拒绝登录
+    pub logon_failures: u32,
+    // 冷却结束的时间（Unix 秒）
+    pub blocked_until: Option<i64>,
+    // 已停用面容解锁，手动解锁后恢复
+    pub disabled: bool,
+    // 被系统拒绝的密码的指纹（见 secret_store::PasswordFingerprint），与面容保存的密码指纹相同时不再发送
+    pub rejected_fingerprint: Option<String>,
+}
+
+// 拒绝识别或发送凭据的原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Block {
+    CoolingDown { remaining_secs: i64 },
+    Disabled,
+    // 密码已经被系统拒绝过
+    Rejected,
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Block::CoolingDown { remaining_secs } => {
+                write!(f, "面容解锁失败次数过多，请 {} 秒后再试", remaining_secs)
+            }
+            Block::Disabled => write!(f, "面容解锁已停用，请输入密码解锁"),
+            Block::Rejected => write!(
+                f,
+                "保存的密码已被系统拒绝，请输入密码解锁并在软件中更新密码"
+            ),
+        }
+    }
+}
+
+impl AccountGuard {
+    pub fn new(account: &str) -> Self {
+        Self {
+            account: account.to_string(),
+            ..Self::default()
+        }
+    }
+
+    // 冷却中或已停用时不识别也不发送凭据
+    pub fn check(&self, now: i64) -> Result<(), Block> {
+        if self.disabled {
+            return Err(Block::Disabled);
+        }
+        match self.blocked_until {
+            Some(until) if until > now => Err(Block::CoolingDown {
+                remaining_secs: until - now,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // 准备发送的密码是否已经被系统拒绝过
+    pub fn check_password(&self, fingerprint: &str) -> Result<(), Block> {
+        if self.rejected_fingerprint.as_deref() == Some(fingerprint) {
+            return Err(Block::Rejected);
+        }
+        Ok(())
+    }
+
+    // 识别失败：陌生人脸、未通过活体检测或动作验证
+    pub fn record_failure(&mut self, now: i64) {
+        self.failures += 1;
+        if self.failures >= DISABLE_AFTER {
+            self.disabled = true;
+        }
+        self.start_cooldown(now);
+    }
+
+    // 凭据已发送但系统拒绝登录
+    // rejected 表示系统明确拒绝了这个密码，之后不再发送；账户被锁定时直接停用
+    pub fn record_logon_failure(
+        &mut self,
+        now: i64,
+        fingerprint: &str,
+        rejected: bool,
+        locked: bool,
+    ) {
+        self.logon_failures += 1;
+        if rejected {
+            self.rejected_fingerprint = Some(fingerprint.to_string());
+        }
+        if locked || self.logon_failures >= DISABLE_AFTER_LOGON_FAILURES {
+            self.disabled = true;
+        }
+        self.start_cooldown(now);
+    }
+
+    // 解锁成功，密码已被系统接受
+    pub fn record_success(&mut self) {
+        *self = Self::new(&self.account);
+    }
+
+    // 失败次数达到 COOLDOWN_AFTER 后开始冷却，之后每多失败一次冷却时间翻倍
+    fn start_cooldown(&mut self, now: i64) {
+        let strikes = self.failures + self.logon_failures;
+        if strikes < COOLDOWN_AFTER {
+            return;
+        }
+        let doublings = (strikes - COOLDOWN_AFTER).min(16);
+        let secs = (BASE_COOLDOWN_SECS << doublings).min(MAX_COOLDOWN_SECS);
+        self.blocked_until = Some(now + secs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cooldown_grows_exponentially() {
+        let mut guard = AccountGuard::new(ANY_ACCOUNT);
+        for _ in 0..COOLDOWN_AFTER - 1 {
+            guard.record_failure(0);
+            assert_eq!(guard.check(0), Ok(()));
+        }
+        guard.record_failure(0);
+        assert_eq!(
+            guard.check(0),
+            Err(Block::CoolingDown {
+                remaining_secs: BASE_COOLDOWN_SECS
+            })
+        );
+        assert_eq!(guard.check(BASE_COOLDOWN_SECS), Ok(()));
+        guard.record_failure(100);
+        assert_eq!(guard.blocked_until, Some(100 + BASE_COOLDOWN_SECS * 2));
+
+        for _ in 0..20 {
+            guard.record_failure(0);
+        }
+        assert_eq!(guard.blocked_until, Some(MAX_COOLDOWN_SECS));
+    }
+
+    #[test]
+    fn too_many_failures_disable() {
+        let mut guard = AccountGuard::new(".\\alice");
+        for _ in 0..DISABLE_AFTER {
+            guard.record_failure(0);
+        }
+        // 冷却结束后仍然停用
+        assert_eq!(guard.check(i64::MAX), Err(Block::Disabled));
+
+        let mut guard = AccountGuard::new(".\\alice");
+        guard.record_logon_failure(0, "pwd", false, false);
+        assert_eq!(guard.check(0), Ok(()));
+        guard.record_logon_failure(0, "pwd", false, false);
+        assert_eq!(guard.check(0), Err(Block::Disabled));
+
+        let mut guard = AccountGuard::new(".\\alice");
+        guard.record_logon_failure(0, "pwd", false, true);
+        assert_eq!(guard.check(0), Err(Block::Disabled));
+    }
+
+    #[test]
+    fn rejected_password_is_not_resent() {
+        let mut guard = AccountGuard::new(".\\alice");
+        guard.record_logon_failure(0, "old", true, false);
+        assert_eq!(guard.check_password("old"), Err(Block::Rejected));
+        // 用户在软件中更新密码后可以再次发送
+        assert_eq!(guard.check_password("new"), Ok(()));
+    }
+
+    #[test]
+    fn success_resets() {
+        let mut guard = AccountGuard::new(".\\alice");
+        guard.record_failure(0);
+        guard.record_logon_failure(0, "old", true, true);
+        guard.record_success();
+        assert_eq!(guard, AccountGuard::new(".\\alice"));
+    }
+}
@@ -0,0 +1,234 @@
+// 1:N 面容比对
+// 每一帧的特征同时与所有已录入的面容（及其全部模板）比对，取超过各自阈值且得分最高的面容，
+// 同一面容需要连续多帧胜出才算识别成功
+
+use serde::{Deserialize, Serialize};
+
+// 已录入的一个身份，可以有多个模板
+#[derive(Debug, Clone)]
+pub struct Identity {
+    // faces 表中的ID
+    pub face_id: i32,
+    // 各个模板的 SFace 特征向量
+    pub templates: Vec<Vec<f32>>,
+    // 余弦相似度阈值，0 ~ 1
+    pub threshold: f32,
+}
+
+// 一个身份有多个模板时，如何把各模板的相似度合成身份的得分
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MatchPolicy {
+    // 取最高的相似度
+    #[default]
+    Max,
+    // 取最高的 k 个相似度的平均值，模板不足 k 个时取全部
+    MeanTopK {
+        k: usize,
+    },
+}
+
+// 一帧的比对结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub face_id: i32,
+    pub score: f32,
+}
+
+// 余弦相似度，与 OpenCV FaceRecognizerSF 的 FR_COSINE 一致
+// 长度不同或存在零向量时返回 0
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let mut dot = 0.0f32;
+    let mut norm_a = 0.0f32;
+    let mut norm_b = 0.0f32;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+// 按策略计算探针与一个身份的得分，没有模板时返回 0
+pub fn identity_score(probe: &[f32], templates: &[Vec<f32>], policy: MatchPolicy) -> f32 {
+    let mut scores: Vec<f32> = templates
+        .iter()
+        .map(|feature| cosine_similarity(probe, feature))
+        .collect();
+    if scores.is_empty() {
+        return 0.0;
+    }
+    scores.sort_by(|a, b| b.total_cmp(a));
+
+    match policy {
+        MatchPolicy::Max => scores[0],
+        MatchPolicy::MeanTopK { k } => {
+            let top = &scores[..k.clamp(1, scores.len())];
+            top.iter().sum::<f32>() / top.len() as f32
+        }
+    }
+}
+
+// 在所有身份中找出超过自身阈值且得分最高的面容，没有则返回 None
+pub fn identify(probe: &[f32], gallery: &[Identity], policy: MatchPolicy) -> Option<Candidate> {
+    gallery
+        .iter()
+        .filter_map(|identity| {
+            let score = identity_score(probe, &identity.templates, policy);
+            (score >= identity.threshold).then_some(Candidate {
+                face_id: identity.face_id,
+                score,
+            })
+        })
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+}
+
+// 连续帧投票，同一面容连续胜出 required 帧才确认身份
+#[derive(Debug)]
+pub struct Voter {
+    required: usize,
+    current: Option<i32>,
+    streak: usize,
+}
+
+impl Voter {
+    pub fn new(required: usize) -> Self {
+        Self {
+            required: required.max(1),
+            current: None,
+            streak: 0,
+        }
+    }
+
+    // 记录一帧的结果，达到连续帧数时返回确认的面容ID
+    // 未匹配或换了一个人都会重新计数
+    pub fn observe(&mut self, candidate: Option<&Candidate>) -> Option<i32> {
+        match candidate {
+            Some(candidate) if self.current == Some(candidate.face_id) => self.streak += 1,
+            Some(candidate) => {
+                self.current = Some(candidate.face_id);
+                self.streak = 1;
+            }
+            None => self.reset(),
+        }
+
+        if self.streak >= self.required {
+            self.current
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.streak = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(face_id: i32, templates: &[&[f32]], threshold: f32) -> Identity {
+        Identity {
+            face_id,
+            templates: templates.iter().map(|t| t.to_vec()).collect(),
+            threshold,
+        }
+    }
+
+    fn candidate(face_id: i32) -> Candidate {
+        Candidate {
+            face_id,
+            score: 0.9,
+        }
+    }
+
+    #[test]
+    fn cosine_similarity_basics() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn identify_picks_best_above_threshold() {
+        let gallery = [
+            identity(1, &[&[1.0, 0.0, 0.0]], 0.5),
+            identity(2, &[&[0.8, 0.6, 0.0]], 0.5),
+            identity(3, &[&[0.0, 0.0, 1.0]], 0.5),
+        ];
+        // 与 1 和 2 都超过阈值，2 更接近
+        let best = identify(&[0.7, 0.7, 0.0], &gallery, MatchPolicy::Max).unwrap();
+        assert_eq!(best.face_id, 2);
+        assert!(best.score > cosine_similarity(&[0.7, 0.7, 0.0], &gallery[0].templates[0]));
+    }
+
+    #[test]
+    fn identify_respects_per_template_threshold() {
+        // 2 得分更高，但没有超过自己更严格的阈值
+        let gallery = [
+            identity(1, &[&[1.0, 0.0]], 0.6),
+            identity(2, &[&[0.8, 0.6]], 0.99),
+        ];
+        let best = identify(&[0.9, 0.436], &gallery, MatchPolicy::Max).unwrap();
+        assert_eq!(best.face_id, 1);
+        assert_eq!(identify(&[0.0, 1.0], &gallery, MatchPolicy::Max), None);
+        assert_eq!(identify(&[1.0, 0.0], &[], MatchPolicy::Max), None);
+    }
+
+    #[test]
+    fn extra_template_matches_changed_appearance() {
+        // 正脸模板不匹配，戴眼镜的模板匹配
+        let single = [identity(1, &[&[1.0, 0.0]], 0.9)];
+        let multi = [identity(1, &[&[1.0, 0.0], &[0.0, 1.0]], 0.9)];
+        assert_eq!(identify(&[0.1, 1.0], &single, MatchPolicy::Max), None);
+        assert_eq!(
+            identify(&[0.1, 1.0], &multi, MatchPolicy::Max).map(|c| c.face_id),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn mean_top_k_averages_best_scores() {
+        let templates = [vec![1.0, 0.0], vec![0.0, 1.0], vec![-1.0, 0.0]];
+        let probe = [1.0, 0.0];
+        assert!((identity_score(&probe, &templates, MatchPolicy::Max) - 1.0).abs() < 1e-6);
+        let top2 = identity_score(&probe, &templates, MatchPolicy::MeanTopK { k: 2 });
+        assert!((top2 - 0.5).abs() < 1e-6);
+        // k 大于模板数时取全部，k 为 0 时按 1 处理
+        let all = identity_score(&probe, &templates, MatchPolicy::MeanTopK { k: 10 });
+        assert!(all.abs() < 1e-6);
+        let zero = identity_score(&probe, &templates, MatchPolicy::MeanTopK { k: 0 });
+        assert!((zero - 1.0).abs() < 1e-6);
+        assert_eq!(identity_score(&probe, &[], MatchPolicy::Max), 0.0);
+    }
+
+    #[test]
+    fn voter_requires_consecutive_agreement() {
+        let mut voter = Voter::new(3);
+        assert_eq!(voter.observe(Some(&candidate(1))), None);
+        assert_eq!(voter.observe(Some(&candidate(1))), None);
+        assert_eq!(voter.observe(Some(&candidate(1))), Some(1));
+    }
+
+    #[test]
+    fn voter_restarts_on_miss_or_identity_change() {
+        let mut voter = Voter::new(2);
+        assert_eq!(voter.observe(Some(&candidate(1))), None);
+        assert_eq!(voter.observe(None), None);
+        assert_eq!(voter.observe(Some(&candidate(1))), None);
+        // 换人后重新计数
+        assert_eq!(voter.observe(Some(&candidate(2))), None);
+        assert_eq!(voter.observe(Some(&candidate(1))), None);
+        assert_eq!(voter.observe(Some(&candidate(1))), Some(1));
+    }
+}
@@ -0,0 +1,9 @@
+// 面容识别的纯逻辑部分，不依赖摄像头和 OpenCV，便于测试
+pub mod adaptive;
+pub mod challenge;
+pub mod gallery;
+pub mod liveness;
+pub mod lockout;
+pub mod matcher;
+pub mod quality;
+pub mod selection;
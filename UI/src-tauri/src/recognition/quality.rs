@@ -0,0 +1,306 @@
+// 录入面容时的质量评估
+// 模糊、过小、侧脸、过暗或被遮挡的照片提取出的特征不稳定，录入后识别效果差
+// 每项的测量值由 modules::quality 从图像中计算，这里只负责和下限比较
+use serde::{Deserialize, Serialize};
+
+use super::liveness::{head_pose, Landmarks};
+
+// 各项指标的下限（或上限），保存在设置中
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QualityThresholds {
+    // 人脸区域拉普拉斯方差的下限
+    pub min_sharpness: f32,
+    // 人脸框短边的最小像素数
+    pub min_face_size: f32,
+    // 左右转头的最大角度（度）
+    pub max_yaw: f32,
+    // 歪头的最大角度（度）
+    pub max_roll: f32,
+    // 人脸区域平均亮度的范围，0 ~ 255
+    pub min_brightness: f32,
+    pub max_brightness: f32,
+    // 被遮挡的五官比例上限，0 ~ 1
+    pub max_occlusion: f32,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        Self {
+            min_sharpness: 40.0,
+            min_face_size: 80.0,
+            max_yaw: 25.0,
+            max_roll: 20.0,
+            min_brightness: 60.0,
+            max_brightness: 200.0,
+            max_occlusion: 0.2,
+        }
+    }
+}
+
+impl QualityThresholds {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [
+            self.min_sharpness,
+            self.min_face_size,
+            self.max_yaw,
+            self.max_roll,
+            self.min_brightness,
+            self.max_brightness,
+            self.max_occlusion,
+        ];
+        if values.iter().any(|v| !v.is_finite() || *v < 0.0) {
+            return Err(String::from("录入质量的阈值不能为负数"));
+        }
+        if self.min_brightness >= self.max_brightness || self.max_brightness > 255.0 {
+            return Err(String::from("亮度范围无效，下限必须小于上限且不超过 255"));
+        }
+        if self.max_yaw > 90.0 || self.max_roll > 90.0 {
+            return Err(String::from("角度上限不能超过 90 度"));
+        }
+        if self.max_occlusion > 1.0 {
+            return Err(String::from("遮挡比例上限必须在 0 到 1 之间"));
+        }
+        Ok(())
+    }
+}
+
+// 一张照片的测量值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityMeasurements {
+    // 人脸区域缩放到固定尺寸后的拉普拉斯方差
+    pub sharpness: f32,
+    // 人脸框短边的像素数
+    pub face_size: f32,
+    pub landmarks: Landmarks,
+    // 人脸区域的平均亮度
+    pub brightness: f32,
+    // 五官附近没有细节（被手、口罩等挡住）的比例
+    pub occlusion: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criterion {
+    Sharpness,
+    FaceSize,
+    Yaw,
+    Roll,
+    Exposure,
+    Occlusion,
+}
+
+impl Criterion {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Criterion::Sharpness => "清晰度",
+            Criterion::FaceSize => "人脸大小",
+            Criterion::Yaw => "左右转头",
+            Criterion::Roll => "歪头",
+            Criterion::Exposure => "亮度",
+            Criterion::Occlusion => "遮挡",
+        }
+    }
+}
+
+// 单项的结果，返回给前端逐项显示
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CriterionResult {
+    pub criterion: Criterion,
+    pub value: f32,
+    pub passed: bool,
+    // 不合格时的提示
+    pub hint: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityReport {
+    pub passed: bool,
+    pub criteria: Vec<CriterionResult>,
+}
+
+impl QualityReport {
+    // 不合格项的说明，拒绝录入时返回给前端
+    pub fn failures(&self) -> String {
+        self.criteria
+            .iter()
+            .filter(|c| !c.passed)
+            .map(|c| {
+                format!(
+                    "{}（{:.1}）{}",
+                    c.criterion.label(),
+                    c.value,
+                    c.hint.unwrap_or("")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("；")
+    }
+}
+
+// 左右转头和歪头的角度（度），两眼距离过近时返回 None
+// 鼻尖在两眼连线上的投影偏离中点 t 个眼距时，到两眼的距离之比为 (0.5 + t) / (0.5 - t)，近似为 sin(yaw) = 2t
+pub fn pose_angles(l: &Landmarks) -> Option<(f32, f32)> {
+    let (offset, _) = head_pose(l)?;
+    let yaw = (offset * 2.0).clamp(-1.0, 1.0).asin().to_degrees();
+    let roll = (l.left_eye.1 - l.right_eye.1)
+        .atan2(l.left_eye.0 - l.right_eye.0)
+        .to_degrees();
+    Some((yaw, roll))
+}
+
+pub fn assess(m: &QualityMeasurements, t: &QualityThresholds) -> QualityReport {
+    let result = |criterion, value: f32, passed: bool, hint| CriterionResult {
+        criterion,
+        value,
+        passed,
+        hint: (!passed).then_some(hint),
+    };
+
+    // 关键点异常时无法判断角度，按不合格处理
+    let (yaw, roll) = pose_angles(&m.landmarks).unwrap_or((90.0, 90.0));
+    let criteria = vec![
+        result(
+            Criterion::Sharpness,
+            m.sharpness,
+            m.sharpness >= t.min_sharpness,
+            "照片模糊，请保持静止并对焦",
+        ),
+        result(
+            Criterion::FaceSize,
+            m.face_size,
+            m.face_size >= t.min_face_size,
+            "人脸太小，请靠近摄像头",
+        ),
+        result(
+            Criterion::Yaw,
+            yaw.abs(),
+            yaw.abs() <= t.max_yaw,
+            "请正对摄像头",
+        ),
+        result(
+            Criterion::Roll,
+            roll.abs(),
+            roll.abs() <= t.max_roll,
+            "请不要歪头",
+        ),
+        result(
+            Criterion::Exposure,
+            m.brightness,
+            (t.min_brightness..=t.max_brightness).contains(&m.brightness),
+            if m.brightness < t.min_brightness {
+                "光线太暗"
+            } else {
+                "光线太亮"
+            },
+        ),
+        result(
+            Criterion::Occlusion,
+            m.occlusion,
+            m.occlusion <= t.max_occlusion,
+            "五官被遮挡，请取下口罩或移开遮挡物",
+        ),
+    ];
+
+    QualityReport {
+        passed: criteria.iter().all(|c| c.passed),
+        criteria,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 眼距 100 像素，nose_dx 为鼻尖的水平偏移，eye_dy 为左眼相对右眼的高度差
+    fn measurements(nose_dx: f32, eye_dy: f32) -> QualityMeasurements {
+        QualityMeasurements {
+            sharpness: 120.0,
+            face_size: 200.0,
+            landmarks: Landmarks {
+                right_eye: (100.0, 100.0),
+                left_eye: (200.0, 100.0 + eye_dy),
+                nose: (150.0 + nose_dx, 150.0),
+                mouth_right: (115.0, 190.0),
+                mouth_left: (185.0, 190.0),
+            },
+            brightness: 128.0,
+            occlusion: 0.0,
+        }
+    }
+
+    fn failed(report: &QualityReport) -> Vec<Criterion> {
+        report
+            .criteria
+            .iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.criterion)
+            .collect()
+    }
+
+    #[test]
+    fn frontal_face_passes() {
+        let report = assess(&measurements(0.0, 0.0), &QualityThresholds::default());
+        assert!(report.passed);
+        assert_eq!(report.criteria.len(), 6);
+        assert!(report.failures().is_empty());
+    }
+
+    #[test]
+    fn pose_angles_from_landmarks() {
+        let (yaw, roll) = pose_angles(&measurements(25.0, 0.0).landmarks).unwrap();
+        assert!((yaw - 30.0).abs() < 0.5, "yaw = {}", yaw);
+        assert!(roll.abs() < 0.01);
+
+        let (_, roll) = pose_angles(&measurements(0.0, 100.0).landmarks).unwrap();
+        assert!((roll - 45.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn each_criterion_is_reported() {
+        let t = QualityThresholds::default();
+
+        let mut m = measurements(40.0, 0.0);
+        m.sharpness = 10.0;
+        m.face_size = 50.0;
+        m.brightness = 30.0;
+        m.occlusion = 0.6;
+        let report = assess(&m, &t);
+        assert!(!report.passed);
+        assert_eq!(
+            failed(&report),
+            vec![
+                Criterion::Sharpness,
+                Criterion::FaceSize,
+                Criterion::Yaw,
+                Criterion::Exposure,
+                Criterion::Occlusion
+            ]
+        );
+        assert!(report.failures().contains("光线太暗"));
+
+        let mut m = measurements(0.0, 60.0);
+        m.brightness = 240.0;
+        let report = assess(&m, &t);
+        assert_eq!(failed(&report), vec![Criterion::Roll, Criterion::Exposure]);
+        assert!(report.failures().contains("光线太亮"));
+    }
+
+    #[test]
+    fn thresholds_are_validated() {
+        assert!(QualityThresholds::default().validate().is_ok());
+        let t = QualityThresholds {
+            min_brightness: 200.0,
+            max_brightness: 100.0,
+            ..Default::default()
+        };
+        assert!(t.validate().is_err());
+        let t = QualityThresholds {
+            max_occlusion: 1.5,
+            ..Default::default()
+        };
+        assert!(t.validate().is_err());
+    }
+}
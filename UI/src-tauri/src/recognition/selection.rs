@@ -0,0 +1,146 @@
+// 画面中有多张人脸时选择哪一张
+// YuNet 输出的顺序不固定，这里按明确的策略选择，录入和解锁使用同样的规则
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FacePolicy {
+    // 面积最大的人脸，通常离摄像头最近
+    #[default]
+    Largest,
+    // 中心离画面中心最近的人脸
+    Central,
+    // 有多张人脸时不解锁，防止旁人窥视时被解锁
+    RefuseMultiple,
+}
+
+// 人脸框，图像坐标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceBox {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl FaceBox {
+    fn area(&self) -> f32 {
+        self.w * self.h
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (self.x + self.w / 2.0, self.y + self.h / 2.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    // 选中的人脸在检测结果中的下标
+    Face(usize),
+    NoFace,
+    // 策略为 RefuseMultiple 且画面中有多张人脸，值为人脸数量
+    Multiple(usize),
+}
+
+// 按面积从大到小排列的下标，面积相同时靠左的在前
+// 录入时给每张人脸编号使用这个顺序，同一张图片每次检测的编号一致
+pub fn order_by_size(boxes: &[FaceBox]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|&a, &b| {
+        boxes[b]
+            .area()
+            .total_cmp(&boxes[a].area())
+            .then(boxes[a].x.total_cmp(&boxes[b].x))
+    });
+    order
+}
+
+// frame_size 为画面的 (宽, 高)
+pub fn select(boxes: &[FaceBox], frame_size: (f32, f32), policy: FacePolicy) -> Selection {
+    if boxes.is_empty() {
+        return Selection::NoFace;
+    }
+    match policy {
+        FacePolicy::Largest => Selection::Face(order_by_size(boxes)[0]),
+        FacePolicy::Central => {
+            let center = (frame_size.0 / 2.0, frame_size.1 / 2.0);
+            let distance = |b: &FaceBox| {
+                let (cx, cy) = b.center();
+                (cx - center.0).powi(2) + (cy - center.1).powi(2)
+            };
+            // 距离相同时取面积较大的
+            let index = order_by_size(boxes)
+                .into_iter()
+                .min_by(|&a, &b| distance(&boxes[a]).total_cmp(&distance(&boxes[b])))
+                .unwrap_or(0);
+            Selection::Face(index)
+        }
+        FacePolicy::RefuseMultiple if boxes.len() > 1 => Selection::Multiple(boxes.len()),
+        FacePolicy::RefuseMultiple => Selection::Face(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face(x: f32, y: f32, size: f32) -> FaceBox {
+        FaceBox {
+            x,
+            y,
+            w: size,
+            h: size,
+        }
+    }
+
+    const FRAME: (f32, f32) = (640.0, 480.0);
+
+    #[test]
+    fn largest_and_central_pick_different_faces() {
+        // 一张大脸在角落，一张小脸在中间
+        let boxes = [
+            face(400.0, 300.0, 40.0),
+            face(0.0, 0.0, 200.0),
+            face(300.0, 220.0, 40.0),
+        ];
+        assert_eq!(
+            select(&boxes, FRAME, FacePolicy::Largest),
+            Selection::Face(1)
+        );
+        assert_eq!(
+            select(&boxes, FRAME, FacePolicy::Central),
+            Selection::Face(2)
+        );
+    }
+
+    #[test]
+    fn refuse_multiple_only_accepts_a_single_face() {
+        let single = [face(200.0, 150.0, 150.0)];
+        let two = [face(200.0, 150.0, 150.0), face(10.0, 10.0, 60.0)];
+        assert_eq!(
+            select(&single, FRAME, FacePolicy::RefuseMultiple),
+            Selection::Face(0)
+        );
+        assert_eq!(
+            select(&two, FRAME, FacePolicy::RefuseMultiple),
+            Selection::Multiple(2)
+        );
+        assert_eq!(select(&[], FRAME, FacePolicy::Largest), Selection::NoFace);
+    }
+
+    #[test]
+    fn order_is_independent_of_detection_order() {
+        let a = face(300.0, 0.0, 100.0);
+        let b = face(0.0, 0.0, 100.0);
+        let c = face(0.0, 200.0, 150.0);
+        let order = order_by_size(&[a, b, c]);
+        assert_eq!(order, vec![2, 1, 0]);
+        // 打乱检测顺序后，选中的仍是同一张人脸
+        let shuffled = [b, c, a];
+        let picked: Vec<FaceBox> = order_by_size(&shuffled)
+            .into_iter()
+            .map(|i| shuffled[i])
+            .collect();
+        assert_eq!(picked, vec![c, b, a]);
+    }
+}
@@ -0,0 +1,476 @@
+// 面容解锁的状态机
+// 后台线程按事件和超时驱动状态变化，窗口回调只负责把系统消息翻译成事件，状态的变化可以脱离窗口测试
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// 锁屏后启动面容识别需要的设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockSettings {
+    // 锁屏后等待多久开始识别
+    pub delay_ms: u32,
+    pub camera_index: i32,
+    // 每次识别持续的时间
+    pub window_ms: u32,
+    // 识别时每秒读取的帧数
+    pub fps: u32,
+    // 识别失败后，用户唤醒登录界面时是否再次识别
+    pub rescan_on_wake: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockState {
+    // 未锁屏
+    Idle,
+    // 已锁屏，等待用户唤醒后再次识别（没有面容或未初始化时不会识别）
+    Armed,
+    // 已锁屏，到 until 后开始识别
+    Delaying { until: Instant },
+    // 正在读取画面识别，摄像头只在这个状态下打开
+    Recognizing,
+    // 已发送凭据，等待 DLL 回传登录结果
+    Unlocking,
+    // 识别失败后的冷却时间，到 until 前不会再次识别
+    Cooldown { until: Instant },
+}
+
+impl UnlockState {
+    // 发给前端的状态名
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnlockState::Idle => "idle",
+            UnlockState::Armed => "armed",
+            UnlockState::Delaying { .. } => "delaying",
+            UnlockState::Recognizing => "recognizing",
+            UnlockState::Unlocking => "unlocking",
+            UnlockState::Cooldown { .. } => "cooldown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    // WTS_SESSION_LOCK，settings 为 None 表示没有面容或未完成初始化
+    Lock(Option<LockSettings>),
+    // WTS_SESSION_UNLOCK
+    Unlock,
+    // 到达 deadline()
+    Timeout,
+    // 用户唤醒了登录界面（按键、移动鼠标或点击磁贴）
+    Wake,
+    // CredUI 提示框（例如 UAC 提权）请求确认身份，settings 为 None 表示没有面容或未完成初始化
+    Prompt(Option<LockSettings>),
+    // 识别通过，开始发送凭据
+    CredentialsSent,
+    // 本次识别结束，unlocked 表示 DLL 确认登录成功
+    Finished { unlocked: bool },
+}
+
+// 需要后台线程执行的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionAction {
+    // 打开摄像头识别，结束后关闭摄像头并发送 Finished
+    Recognize(LockSettings),
+    // 识别期间手动解锁，停止正在进行的识别，之后与 ManualUnlock 相同
+    CancelRecognition,
+    // 用户手动解锁（不是面容解锁的结果），恢复被停用的面容解锁
+    ManualUnlock,
+    // 通过管道等待 DLL 通知用户唤醒，收到后发送 Wake
+    WatchWake,
+}
+
+#[derive(Debug)]
+pub struct UnlockMachine {
+    state: UnlockState,
+    // 本次锁屏的设置，解锁后清除
+    settings: Option<LockSettings>,
+    // 识别失败后的冷却时间
+    cooldown: Duration,
+    // 本次锁屏已经通过面容解锁，之后的解锁事件不是手动解锁
+    face_unlocked: bool,
+    // 正在为 CredUI 提示框识别，结束后回到未锁屏状态
+    prompting: bool,
+    // 识别进行中或冷却中收到的锁屏事件，本次识别结束后按它进入等待唤醒
+    pending_lock: Option<Option<LockSettings>>,
+}
+
+impl UnlockMachine {
+    pub const fn new(cooldown: Duration) -> Self {
+        Self {
+            state: UnlockState::Idle,
+            settings: None,
+            cooldown,
+            face_unlocked: false,
+            prompting: false,
+            pending_lock: None,
+        }
+    }
+
+    pub fn state(&self) -> UnlockState {
+        self.state
+    }
+
+    // 当前状态的超时时间，到达后需要发送 Timeout
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            UnlockState::Delaying { until } | UnlockState::Cooldown { until } => Some(until),
+            _ => None,
+        }
+    }
+
+    pub fn handle(&mut self, event: SessionEvent, now: Instant) -> Option<SessionAction> {
+        use UnlockState::*;
+
+        match event {
+            // 识别进行中或冷却中不打断，记下锁屏事件，结束后再处理
+            SessionEvent::Lock(settings)
+                if matches!(self.state, Recognizing | Unlocking | Cooldown { .. }) =>
+            {
+                self.pending_lock = Some(settings);
+                return None;
+            }
+            SessionEvent::Lock(_) => self.face_unlocked = false,
+            SessionEvent::Unlock => self.pending_lock = None,
+            _ => {}
+        }
+        let (state, action) = match (self.state, event) {
+            // 重复的锁屏事件重新计时
+            (_, SessionEvent::Lock(Some(settings))) => {
+                self.settings = Some(settings);
+                (
+                    Delaying {
+                        until: now + Duration::from_millis(settings.delay_ms as u64),
+                    },
+                    None,
+                )
+            }
+            (_, SessionEvent::Lock(None)) => {
+                self.settings = None;
+                (Armed, None)
+            }
+            (Recognizing, SessionEvent::Unlock) => {
+                self.settings = None;
+                (Idle, Some(SessionAction::CancelRecognition))
+            }
+            // 发送凭据后的解锁就是本次识别的结果，不需要取消
+            (Unlocking, SessionEvent::Unlock) => {
+                self.settings = None;
+                (Idle, None)
+            }
+            (_, SessionEvent::Unlock) => {
+                self.settings = None;
+                let manual = !self.face_unlocked;
+                (Idle, manual.then_some(SessionAction::ManualUnlock))
+            }
+            (Delaying { until }, SessionEvent::Timeout) if now >= until => match self.settings {
+                Some(settings) => (Recognizing, Some(SessionAction::Recognize(settings))),
+                None => (Armed, None),
+            },
+            (Cooldown { until }, SessionEvent::Timeout)
+                if now >= until && self.pending_lock.is_some() =>
+            {
+                self.rearm()
+            }
+            (Cooldown { until }, SessionEvent::Timeout) if now >= until => {
+                let watch = self
+                    .settings
+                    .is_some_and(|settings| settings.rescan_on_wake);
+                (Armed, watch.then_some(SessionAction::WatchWake))
+            }
+            (Armed, SessionEvent::Wake) => match self.settings {
+                Some(settings) => (Recognizing, Some(SessionAction::Recognize(settings))),
+                None => return None,
+            },
+            // 未锁屏时弹出的提示框立即识别，不使用锁屏的延迟、冷却和唤醒
+            (Idle, SessionEvent::Prompt(Some(settings))) => {
+                self.prompting = true;
+                (Recognizing, Some(SessionAction::Recognize(settings)))
+            }
+            (Recognizing | Unlocking, SessionEvent::Finished { .. })
+                if self.pending_lock.is_some() =>
+            {
+                self.rearm()
+            }
+            (Recognizing | Unlocking, SessionEvent::Finished { .. }) if self.prompting => {
+                self.prompting = false;
+                (Idle, None)
+            }
+            (Recognizing, SessionEvent::CredentialsSent) => (Unlocking, None),
+            (Recognizing | Unlocking, SessionEvent::Finished { unlocked: true }) => {
+                self.face_unlocked = true;
+                (Idle, None)
+            }
+            (Recognizing | Unlocking, SessionEvent::Finished { unlocked: false }) => (
+                Cooldown {
+                    until: now + self.cooldown,
+                },
+                None,
+            ),
+            // 提前到达的超时、解锁后才到达的唤醒和取消后才结束的识别不改变状态
+            _ => return None,
+        };
+        self.state = state;
+        action
+    }
+
+    // 识别期间又锁屏了，不论本次结果如何，都按新的锁屏等待唤醒
+    fn rearm(&mut self) -> (UnlockState, Option<SessionAction>) {
+        self.settings = self.pending_lock.take().flatten();
+        self.prompting = false;
+        self.face_unlocked = false;
+        let watch = self
+            .settings
+            .is_some_and(|settings| settings.rescan_on_wake);
+        (
+            UnlockState::Armed,
+            watch.then_some(SessionAction::WatchWake),
+        )
+    }
+}
+
+// 取消一次识别，克隆后共享同一个标记，超过期限也视为取消
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Instant,
+}
+
+impl CancelToken {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    // 已取消或超时时返回原因
+    pub fn check(&self) -> Result<(), String> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            Err(String::from("识别已取消"))
+        } else if Instant::now() >= self.deadline {
+            Err(String::from("识别超时"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(5);
+    const SETTINGS: LockSettings = LockSettings {
+        delay_ms: 2000,
+        camera_index: 1,
+        window_ms: 10000,
+        fps: 5,
+        rescan_on_wake: true,
+    };
+
+    fn locked(now: Instant) -> UnlockMachine {
+        let mut machine = UnlockMachine::new(COOLDOWN);
+        assert_eq!(
+            machine.handle(SessionEvent::Lock(Some(SETTINGS)), now),
+            None
+        );
+        machine
+    }
+
+    #[test]
+    fn lock_delay_recognize_then_cooldown() {
+        let now = Instant::now();
+        let mut machine = locked(now);
+        let until = now + Duration::from_millis(2000);
+        assert_eq!(machine.deadline(), Some(until));
+        // 提前到达的超时不触发识别
+        assert_eq!(machine.handle(SessionEvent::Timeout, now), None);
+        assert_eq!(
+            machine.handle(SessionEvent::Timeout, until),
+            Some(SessionAction::Recognize(SETTINGS))
+        );
+        assert_eq!(machine.state(), UnlockState::Recognizing);
+        assert_eq!(machine.deadline(), None);
+
+        machine.handle(SessionEvent::CredentialsSent, until);
+        assert_eq!(machine.state(), UnlockState::Unlocking);
+        machine.handle(SessionEvent::Finished { unlocked: false }, until);
+        assert_eq!(machine.deadline(), Some(until + COOLDOWN));
+        // 冷却期间的锁屏事件和唤醒不会再次识别
+        assert_eq!(
+            machine.handle(SessionEvent::Lock(Some(SETTINGS)), until),
+            None
+        );
+        assert_eq!(machine.handle(SessionEvent::Wake, until), None);
+        assert_eq!(
+            machine.handle(SessionEvent::Timeout, until + COOLDOWN),
+            Some(SessionAction::WatchWake)
+        );
+        assert_eq!(machine.state(), UnlockState::Armed);
+        assert_eq!(
+            machine.handle(SessionEvent::Timeout, until + COOLDOWN),
+            None
+        );
+    }
+
+    #[test]
+    fn wake_starts_another_window() {
+        let now = Instant::now();
+        let mut machine = locked(now);
+        let until = now + Duration::from_millis(2000);
+        machine.handle(SessionEvent::Timeout, until);
+        machine.handle(SessionEvent::Finished { unlocked: false }, until);
+        machine.handle(SessionEvent::Timeout, until + COOLDOWN);
+        assert_eq!(
+            machine.handle(SessionEvent::Wake, until + COOLDOWN),
+            Some(SessionAction::Recognize(SETTINGS))
+        );
+        assert_eq!(machine.state(), UnlockState::Recognizing);
+        machine.handle(SessionEvent::Finished { unlocked: true }, until + COOLDOWN);
+        assert_eq!(machine.state(), UnlockState::Idle);
+        // 面容解锁的结果不算手动解锁
+        assert_eq!(machine.handle(SessionEvent::Unlock, until + COOLDOWN), None);
+    }
+
+    #[test]
+    fn no_watch_when_rescan_disabled() {
+        let now = Instant::now();
+        let mut machine = UnlockMachine::new(COOLDOWN);
+        let settings = LockSettings {
+            rescan_on_wake: false,
+            ..SETTINGS
+        };
+        machine.handle(SessionEvent::Lock(Some(settings)), now);
+        let until = now + Duration::from_millis(2000);
+        machine.handle(SessionEvent::Timeout, until);
+        machine.handle(SessionEvent::Finished { unlocked: false }, until);
+        assert_eq!(
+            machine.handle(SessionEvent::Timeout, until + COOLDOWN),
+            None
+        );
+        assert_eq!(machine.state(), UnlockState::Armed);
+    }
+
+    #[test]
+    fn unlock_cancels_recognition() {
+        let now = Instant::now();
+        let mut machine = locked(now);
+        machine.handle(SessionEvent::Timeout, now + Duration::from_secs(2));
+        assert_eq!(
+            machine.handle(SessionEvent::Unlock, now),
+            Some(SessionAction::CancelRecognition)
+        );
+        assert_eq!(machine.state(), UnlockState::Idle);
+        // 取消后才结束的识别和解锁后才到达的唤醒不改变状态
+        machine.handle(SessionEvent::Finished { unlocked: false }, now);
+        assert_eq!(machine.handle(SessionEvent::Wake, now), None);
+        assert_eq!(machine.state(), UnlockState::Idle);
+    }
+
+    #[test]
+    fn unlock_before_delay_skips_recognition() {
+        let now = Instant::now();
+        let mut machine = locked(now);
+        assert_eq!(
+            machine.handle(SessionEvent::Unlock, now),
+            Some(SessionAction::ManualUnlock)
+        );
+        assert_eq!(machine.deadline(), None);
+        assert_eq!(
+            machine.handle(SessionEvent::Timeout, now + Duration::from_secs(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn lock_during_recognition_rearms_after_finish() {
+        let now = Instant::now();
+        let mut machine = locked(now);
+        let until = now + Duration::from_millis(2000);
+        machine.handle(SessionEvent::Timeout, until);
+        // 识别期间收到锁屏事件，不打断本次识别
+        assert_eq!(
+            machine.handle(SessionEvent::Lock(Some(SETTINGS)), until),
+            None
+        );
+        assert_eq!(machine.state(), UnlockState::Recognizing);
+        machine.handle(SessionEvent::CredentialsSent, until);
+        assert_eq!(
+            machine.handle(SessionEvent::Finished { unlocked: true }, until),
+            Some(SessionAction::WatchWake)
+        );
+        assert_eq!(machine.state(), UnlockState::Armed);
+        assert_eq!(
+            machine.handle(SessionEvent::Wake, until),
+            Some(SessionAction::Recognize(SETTINGS))
+        );
+        machine.handle(SessionEvent::Finished { unlocked: false }, until);
+        // 这次锁屏没有通过面容解锁，之后的解锁是手动解锁
+        assert_eq!(
+            machine.handle(SessionEvent::Unlock, until),
+            Some(SessionAction::ManualUnlock)
+        );
+    }
+
+    #[test]
+    fn lock_without_faces_ignores_wake() {
+        let now = Instant::now();
+        let mut machine = UnlockMachine::new(COOLDOWN);
+        assert_eq!(machine.handle(SessionEvent::Lock(None), now), None);
+        assert_eq!(machine.state(), UnlockState::Armed);
+        assert_eq!(machine.deadline(), None);
+        assert_eq!(machine.handle(SessionEvent::Wake, now), None);
+    }
+
+    #[test]
+    fn prompt_recognizes_once_while_unlocked() {
+        let now = Instant::now();
+        let mut machine = UnlockMachine::new(COOLDOWN);
+        assert_eq!(
+            machine.handle(SessionEvent::Prompt(Some(SETTINGS)), now),
+            Some(SessionAction::Recognize(SETTINGS))
+        );
+        assert_eq!(machine.state(), UnlockState::Recognizing);
+        // 识别期间再次弹出的提示框不会重复识别
+        assert_eq!(
+            machine.handle(SessionEvent::Prompt(Some(SETTINGS)), now),
+            None
+        );
+        machine.handle(SessionEvent::Finished { unlocked: false }, now);
+        // 提示框的识别失败不进入冷却
+        assert_eq!(machine.state(), UnlockState::Idle);
+        assert_eq!(machine.deadline(), None);
+        // 没有面容时不识别，锁屏期间弹出的提示框也不识别
+        assert_eq!(machine.handle(SessionEvent::Prompt(None), now), None);
+        let mut machine = locked(now);
+        assert_eq!(
+            machine.handle(SessionEvent::Prompt(Some(SETTINGS)), now),
+            None
+        );
+        // 锁屏后的识别按原来的流程进入冷却
+        let until = now + Duration::from_millis(2000);
+        machine.handle(SessionEvent::Timeout, until);
+        machine.handle(SessionEvent::Finished { unlocked: false }, until);
+        assert_eq!(machine.deadline(), Some(until + COOLDOWN));
+    }
+
+    #[test]
+    fn cancel_token_reports_reason() {
+        let token = CancelToken::new(Duration::from_secs(60));
+        assert!(token.check().is_ok());
+        token.clone().cancel();
+        assert_eq!(token.check(), Err(String::from("识别已取消")));
+        assert_eq!(
+            CancelToken::new(Duration::ZERO).check(),
+            Err(String::from("识别超时"))
+        );
+    }
+}
@@ -4,8 +4,12 @@ use tauri::{
     tray::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent},
     AppHandle, Emitter, Manager, Runtime,
 };
+#[cfg(windows)]
 use windows::Win32::{Foundation::HWND, System::RemoteDesktop::WTSUnRegisterSessionNotification};
 
+// 托盘图标的ID，用于在创建之后修改提示文字
+const TRAY_ID: &str = "main";
+
 pub fn create_tray_menu<R: Runtime>(
     app: &AppHandle<R>,
 ) -> Result<Menu<R>, Box<dyn std::error::Error>> {
@@ -24,7 +28,7 @@ pub fn create_system_tray<R: Runtime>(
     let menu = create_tray_menu(app)?;
 
     let tray = Arc::new(
-        TrayIconBuilder::new()
+        TrayIconBuilder::with_id(TRAY_ID)
             .icon(app.default_window_icon().unwrap().clone())
             .menu(&menu)
             .show_menu_on_left_click(false)
@@ -40,10 +44,13 @@ pub fn create_system_tray<R: Runtime>(
             let _ = window.set_focus();
         }
         "quit" => {
-            let hwnd = window.hwnd().unwrap();
-            unsafe {
-                // 注销 WTS 通知
-                let _ = WTSUnRegisterSessionNotification(HWND(hwnd.0));
+            #[cfg(windows)]
+            {
+                let hwnd = window.hwnd().unwrap();
+                unsafe {
+                    // 注销 WTS 通知
+                    let _ = WTSUnRegisterSessionNotification(HWND(hwnd.0));
+                }
             }
             let _ = tray_handle_for_close.set_visible(false);
             app.exit(0);
@@ -69,3 +76,11 @@ pub fn create_system_tray<R: Runtime>(
     });
     Ok(tray)
 }
+
+// 面容保存的密码失效时，在托盘提示中提醒用户重新输入
+pub fn show_stale_credentials<R: Runtime>(app: &AppHandle<R>, user_name: &str) {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let tooltip = format!("{} 的密码已失效，请打开软件重新输入", user_name);
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+}
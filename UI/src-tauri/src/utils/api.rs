@@ -1,39 +1,69 @@
-use std::{ffi::OsStr, os::windows::{ffi::OsStrExt, process::CommandExt}, process::Command};
+use std::{
+    fmt,
+    io::{Read, Write},
+    process::Command,
+};
 
-use crate::{utils::custom_result::CustomResult, OpenCVResource, APP_STATE, DB_POOL, ROOT_DIR};
+use crate::{
+    camera::{CameraBackend, LiveCamera},
+    db::init_pool,
+    modules::liveness::ANTI_SPOOF_MODEL,
+    utils::custom_result::CustomResult,
+    OpenCVResource, APP_STATE, ROOT_DIR,
+};
+use facewinunlock_protocol::{
+    client_handshake, encode, handshake::random_nonce, read_message, LogonOutcome, Message,
+    PipeSecret, SecretString, SecureChannel, StatusIcon,
+};
 use opencv::{
-    core::{Mat, MatTraitConst, Size},
+    core::Size,
+    dnn,
     objdetect::{FaceDetectorYN, FaceRecognizerSF},
-    videoio::{self, VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst},
+    videoio::{VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst},
 };
-use r2d2::Pool;
-use r2d2_sqlite::rusqlite;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::json;
-use tauri_plugin_log::log::{error, info, warn};
+// 管道、注册表和系统账户只在 Windows 上可用，其他平台的命令直接返回错误
+#[cfg(windows)]
+use facewinunlock_protocol::{
+    handshake::{SECRET_REG_PATH, SECRET_REG_VALUE},
+    PIPE_NAME,
+};
+#[cfg(windows)]
+use tauri_plugin_log::log::error;
+#[cfg(windows)]
 use windows::{
-    core::{BSTR, HRESULT, HSTRING, PWSTR},
+    core::{BSTR, HSTRING, PWSTR},
     Win32::{
-        Foundation::{CloseHandle, GetLastError, GENERIC_WRITE, HANDLE},
+        Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE, HANDLE},
         Media::{
             DirectShow::ICreateDevEnum,
             MediaFoundation::{CLSID_SystemDeviceEnum, CLSID_VideoInputDeviceCategory},
         },
+        Security::{
+            GetTokenInformation, IsWellKnownSid, TokenUser, WinLocalSystemSid, TOKEN_QUERY,
+            TOKEN_USER,
+        },
         Storage::FileSystem::{
-            CreateFileW, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_MODE, OPEN_EXISTING,
+            CreateFileW, FlushFileBuffers, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES,
+            FILE_SHARE_MODE, OPEN_EXISTING,
         },
         System::{
             Com::{
                 CoCreateInstance, CoInitializeEx, CoUninitialize, IEnumMoniker,
                 StructuredStorage::IPropertyBag, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
             },
-            Pipes::WaitNamedPipeW,
+            Pipes::{GetNamedPipeServerProcessId, WaitNamedPipeW},
             Shutdown::LockWorkStation,
+            Threading::{OpenProcess, OpenProcessToken, PROCESS_QUERY_LIMITED_INFORMATION},
             Variant::{VariantClear, VARIANT},
             WindowsProgramming::GetUserNameW,
         },
     },
 };
+#[cfg(windows)]
+use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+use zeroize::Zeroizing;
 
 #[derive(Debug, Clone, Serialize)]
 struct ValidCameraInfo {
@@ -42,68 +72,60 @@ struct ValidCameraInfo {
     is_valid: bool,
 }
 
-// 定义摄像头后端类型枚举
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum CameraBackend {
-    Any,   // CAP_ANY
-    DShow, // CAP_DSHOW
-    MSMF,  // CAP_MSMF
-    VFW,   // CAP_VFW
-}
-
-impl From<CameraBackend> for i32 {
-    fn from(backend: CameraBackend) -> Self {
-        match backend {
-            CameraBackend::Any => videoio::CAP_ANY,
-            CameraBackend::DShow => videoio::CAP_DSHOW,
-            CameraBackend::MSMF => videoio::CAP_MSMF,
-            CameraBackend::VFW => videoio::CAP_VFW,
-        }
-    }
-}
-
 // 获取当前用户名
 #[tauri::command]
 pub fn get_now_username() -> Result<CustomResult, CustomResult> {
+    let name = current_user_name().map_err(|e| CustomResult::error(Some(e), None))?;
+    Ok(CustomResult::success(None, Some(json!({"username": name}))))
+}
+
+#[cfg(windows)]
+fn current_user_name() -> Result<String, String> {
     // buffer大小，256应该够了
     let mut buffer = [0u16; 256];
     let mut size = buffer.len() as u32;
     unsafe {
-        let succuess = GetUserNameW(Some(PWSTR(buffer.as_mut_ptr())), &mut size);
-        if succuess.is_err() {
-            return Err(CustomResult::error(
-                Some(format!("获取用户名失败: {:?}", succuess.err())),
-                None,
-            ));
-        }
-
-        let name = String::from_utf16_lossy(&buffer[..size as usize - 1]);
-        return Ok(CustomResult::success(None, Some(json!({"username": name}))));
+        GetUserNameW(Some(PWSTR(buffer.as_mut_ptr())), &mut size)
+            .map_err(|e| format!("获取用户名失败: {:?}", e))?;
     }
+    Ok(String::from_utf16_lossy(&buffer[..size as usize - 1]))
+}
+
+#[cfg(not(windows))]
+fn current_user_name() -> Result<String, String> {
+    Err(String::from("当前系统无法获取 Windows 用户名"))
 }
 
 // 测试 WinLogon 是否加载成功
 #[tauri::command]
 pub fn test_win_logon(user_name: String, password: String) -> Result<CustomResult, CustomResult> {
     // 锁定屏幕
-    unsafe {
-        let succuess = LockWorkStation();
-        if succuess.is_err() {
-            return Err(CustomResult::error(
-                Some(format!("锁定屏幕失败: {:?}", succuess.err())),
-                None,
-            ));
-        }
-
-        // 等待5秒
-        std::thread::sleep(std::time::Duration::from_secs(5));
-        // 解锁
-        unlock(user_name, password)
-            .map_err(|e| CustomResult::error(Some(format!("解锁屏幕失败: {:?}", e)), None))?;
+    lock_workstation().map_err(|e| CustomResult::error(Some(e), None))?;
+
+    // 等待5秒
+    std::thread::sleep(std::time::Duration::from_secs(5));
+    // 解锁
+    let outcome = unlock(user_name, SecretString::from(password))
+        .map_err(|e| CustomResult::error(Some(format!("解锁屏幕失败: {}", e)), None))?;
+    if !outcome.is_success() {
+        return Err(CustomResult::error(
+            Some(format!("解锁屏幕失败: {}", outcome)),
+            None,
+        ));
     }
     return Ok(CustomResult::success(None, None));
 }
 
+#[cfg(windows)]
+fn lock_workstation() -> Result<(), String> {
+    unsafe { LockWorkStation() }.map_err(|e| format!("锁定屏幕失败: {:?}", e))
+}
+
+#[cfg(not(windows))]
+fn lock_workstation() -> Result<(), String> {
+    Err(String::from("当前系统无法锁定 Windows 屏幕"))
+}
+
 // 初始化模型
 #[tauri::command]
 pub fn init_model() -> Result<CustomResult, CustomResult> {
@@ -144,55 +166,28 @@ pub fn init_model() -> Result<CustomResult, CustomResult> {
         app_state.recognizer = Some(OpenCVResource { inner: recognizer });
     }
 
-    let db_path = ROOT_DIR.join("database.db");
-
-    // 创建连接池
-    let mut pool_guard = DB_POOL
-        .lock()
-        .map_err(|e| CustomResult::error(Some(format!("获取连接池锁失败 {}", e)), None))?;
-
-    if pool_guard.as_ref().is_none() {
-        // 如果当前没有SQLite 连接池，则创建一个
-        let manager = r2d2_sqlite::SqliteConnectionManager::file(&db_path).with_flags(
-            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
-                | rusqlite::OpenFlags::SQLITE_OPEN_FULL_MUTEX,
-        );
-
-        let pool = Pool::builder()
-            .max_size(2) // 回调函数使用，不需要太多连接
-            .build(manager)
-            .map_err(|e| CustomResult::error(Some(format!("创建连接池失败 {}", e)), None))?;
-
-        *pool_guard = Some(pool);
+    // 防伪模型是可选的，没有时活体检测不使用模型
+    if app_state.anti_spoof.is_none() {
+        let resource_path = ROOT_DIR.join("resources").join(ANTI_SPOOF_MODEL);
+        if resource_path.exists() {
+            let net =
+                dnn::read_net_from_onnx(resource_path.to_str().unwrap_or("")).map_err(|e| {
+                    CustomResult::error(Some(format!("初始化防伪模型失败: {:?}", e)), None)
+                })?;
+            app_state.anti_spoof = Some(OpenCVResource { inner: net });
+        }
     }
 
+    // 创建连接池，同时完成表结构和密码迁移
+    init_pool().map_err(|e| CustomResult::error(Some(e), None))?;
+
     Ok(CustomResult::success(None, None))
 }
 
 // 获取windows所有摄像头
 #[tauri::command]
 pub fn get_camera() -> Result<CustomResult, CustomResult> {
-    // 初始化COM
-    let com_init_result = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
-    if com_init_result.is_err() {
-        return Err(CustomResult::error(
-            Some(String::from("初始化Com失败")),
-            None,
-        ));
-    }
-
-    let com_operation_result = get_windows_video_devices();
-    // 卸载Com
-    unsafe { CoUninitialize() };
-
-    if let Err(e) = com_operation_result {
-        return Err(CustomResult::error(
-            Some(format!("获取系统摄像头失败 {}", e)),
-            None,
-        ));
-    }
-
-    let video_devices = com_operation_result.unwrap();
+    let video_devices = list_video_devices().map_err(|e| CustomResult::error(Some(e), None))?;
     if video_devices.is_empty() {
         return Err(CustomResult::error(
             Some(String::from("未检测到系统视频设备（摄像头）")),
@@ -233,55 +228,12 @@ pub fn open_camera(
         return Ok(CustomResult::success(None, None));
     }
 
-    // 尝试的列表
-    let backends_to_try = match backend {
-        // 指定了：只尝试该后端
-        Some(backend) => vec![backend],
-        // 未指定：尝试所有常用后端
-        None => vec![
-            CameraBackend::DShow,
-            CameraBackend::Any,
-            CameraBackend::MSMF,
-            CameraBackend::VFW,
-        ],
-    };
-
-    // 循环尝试不同后端
-    for (idx, backend_inner) in backends_to_try.iter().enumerate() {
-        match try_open_camera_with_backend(*backend_inner, camear_index) {
-            Ok(cam) => {
-                // 成功打开
-                app_state.camera = Some(OpenCVResource { inner: cam });
-                let msg = if backend.is_some() {
-                    format!("使用指定后端 {:?} 成功打开摄像头", backend)
-                } else {
-                    format!("尝试第{}个后端 {:?} 成功打开摄像头", idx + 1, backend)
-                };
-                info!("{}", msg);
-                return Ok(CustomResult::success(None, None));
-            }
-            Err(e) => {
-                // 处理失败情况
-                if backend.is_some() {
-                    // 指定了后端但失败：直接返回错误
-                    return Err(CustomResult::error(
-                        Some(format!("使用指定后端 {:?} 打开摄像头失败: {}", backend, e)),
-                        None,
-                    ));
-                } else {
-                    // 未指定后端：打印尝试失败日志，继续尝试下一个
-                    warn!("尝试后端 {:?} 失败: {}", backend, e);
-                    continue;
-                }
-            }
-        }
-    }
-
-    // 所有后端都尝试失败
-    Err(CustomResult::error(
-        Some("所有摄像头后端均尝试失败，请检查设备是否连接/被占用/有权限".to_string()),
-        None,
-    ))
+    let cam = LiveCamera::open_any(camear_index, backend)
+        .map_err(|e| CustomResult::error(Some(e), None))?;
+    app_state.camera = Some(OpenCVResource {
+        inner: Box::new(cam),
+    });
+    Ok(CustomResult::success(None, None))
 }
 
 // 关闭摄像头
@@ -324,7 +276,20 @@ pub fn open_directory(path: String) -> Result<CustomResult, CustomResult> {
 
 // 自启代码由 Google Gemini 3 生成
 // 我写不了出来了，注册表不管用 哭**
+#[cfg(windows)]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+// 执行 schtasks，Windows 上不弹出控制台窗口
+fn schtasks() -> Command {
+    #[allow(unused_mut)]
+    let mut command = Command::new("schtasks");
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
 // 启用全用户自启动 (通过任务计划程序)
 #[tauri::command]
 pub fn enable_global_autostart() -> Result<CustomResult, CustomResult> {
@@ -352,7 +317,7 @@ pub fn enable_global_autostart() -> Result<CustomResult, CustomResult> {
     // /IT: 允许交互（Interactive），对于 GUI 程序非常重要
     // 注意：/RL HIGHEST 和 /RU 同时使用时，有时在非管理员登录时会有限制
     // 但对于需要管理员权限的程序，这是 Windows 允许的最广范围了
-    let output = Command::new("schtasks")
+    let output = schtasks()
         .args(&[
             "/Create",
             "/TN", task_name,
@@ -362,7 +327,6 @@ pub fn enable_global_autostart() -> Result<CustomResult, CustomResult> {
             "/RU", "Users",  // 关键：指定为用户组
             "/F",
         ])
-        .creation_flags(CREATE_NO_WINDOW)
         .output()
         .map_err(|e| CustomResult::error(Some(format!("执行系统命令失败: {}", e)), None))?;
 
@@ -379,9 +343,8 @@ pub fn enable_global_autostart() -> Result<CustomResult, CustomResult> {
 pub fn disable_global_autostart() -> Result<CustomResult, CustomResult> {
     let task_name = "FaceWinUnlockAutoStart";
 
-    let output = Command::new("schtasks")
+    let output = schtasks()
         .args(&["/Delete", "/TN", task_name, "/F"])
-        .creation_flags(CREATE_NO_WINDOW)
         .output()
         .map_err(|e| CustomResult::error(Some(format!("执行系统命令失败: {}", e)), None))?;
 
@@ -400,9 +363,8 @@ pub fn check_global_autostart() -> Result<CustomResult, CustomResult> {
     let task_name = "FaceWinUnlockAutoStart";
 
     // /Query 检查任务是否存在
-    let output = Command::new("schtasks")
+    let output = schtasks()
         .args(&["/Query", "/TN", task_name])
-        .creation_flags(CREATE_NO_WINDOW)
         .output()
         .map_err(|e| CustomResult::error(Some(format!("查询系统命令失败: {}", e)), None))?;
 
@@ -415,35 +377,29 @@ pub fn check_global_autostart() -> Result<CustomResult, CustomResult> {
     ))
 }
 
-// 使用指定后端尝试打开摄像头并验证读取帧
-fn try_open_camera_with_backend(
-    backend: CameraBackend,
-    camear_index: i32,
-) -> Result<VideoCapture, Box<dyn std::error::Error>> {
-    let mut cam = VideoCapture::new(camear_index, backend.into())?;
-
-    if !cam.is_opened()? {
-        return Err(format!("后端 {:?} 打开摄像头后状态为未激活", backend).into());
+// 枚举系统摄像头，返回名称和采集索引
+#[cfg(windows)]
+fn list_video_devices() -> Result<Vec<(String, u32)>, String> {
+    // 初始化COM
+    let com_init_result = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) };
+    if com_init_result.is_err() {
+        return Err(String::from("初始化Com失败"));
     }
 
-    // 激活摄像头
-    let mut frame = Mat::default();
-    let read_result = cam.read(&mut frame);
+    let com_operation_result = get_windows_video_devices();
+    // 卸载Com
+    unsafe { CoUninitialize() };
 
-    match read_result {
-        Ok(_) => {
-            if frame.empty() {
-                return Err(format!("后端 {:?} 读取到空帧", backend).into());
-            }
-        }
-        Err(e) => {
-            return Err(format!("后端 {:?} 读取帧失败: {}", backend, e).into());
-        }
-    }
+    com_operation_result.map_err(|e| format!("获取系统摄像头失败 {}", e))
+}
 
-    Ok(cam)
+#[cfg(not(windows))]
+fn list_video_devices() -> Result<Vec<(String, u32)>, String> {
+    Err(String::from("当前系统不支持枚举摄像头"))
 }
+
 // 获取windows所有摄像头
+#[cfg(windows)]
 fn get_windows_video_devices() -> windows::core::Result<Vec<(String, u32)>> {
     // 存放所有摄像头设备信息
     let mut devices = Vec::new();
@@ -537,83 +493,282 @@ fn is_camera_index_valid(index: u32) -> opencv::Result<bool> {
 }
 
 // 解锁屏幕
-pub fn unlock(user_name: String, password: String) -> windows::core::Result<()> {
-    unsafe {
-        let pipe_name = HSTRING::from("\\\\.\\pipe\\MansonWindowsUnlockRust");
-        // 等待管道连接
-        if !WaitNamedPipeW(&pipe_name.clone(), 5000).as_bool() {
-            return Err(windows::core::Error::new(
-                HRESULT(0),
-                "不能连接到管道: MansonWindowsUnlockRust",
-            ));
+// 返回 DLL 在 ReportResult 中拿到的真实登录结果
+pub fn unlock(user_name: String, password: SecretString) -> Result<LogonOutcome, PipeError> {
+    PipeSession::connect()?.unlock(user_name, password)
+}
+
+// 与 DLL 的管道通信失败的原因，只用于提示和记录日志
+#[derive(Debug)]
+pub struct PipeError(String);
+
+impl PipeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PipeError {}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for PipeError {
+    fn from(e: windows::core::Error) -> Self {
+        Self(format!("{:?}", e))
+    }
+}
+
+// 管道连接的读写端，真实连接是命名管道句柄，测试时可以换成内存中的管道
+pub trait PipeTransport: Read + Write {}
+
+impl<T: Read + Write> PipeTransport for T {}
+
+// 与 DLL 的一次管道连接，完成身份验证后可以先在磁贴上显示提示，再发送解锁请求
+pub struct PipeSession {
+    stream: Box<dyn PipeTransport>,
+    channel: SecureChannel,
+}
+
+impl PipeSession {
+    #[cfg(windows)]
+    pub fn connect() -> Result<Self, PipeError> {
+        let handle = unsafe {
+            let pipe_name = HSTRING::from(PIPE_NAME);
+            // 等待管道连接
+            if !WaitNamedPipeW(&pipe_name.clone(), 5000).as_bool() {
+                return Err(PipeError::new("不能连接到管道: MansonWindowsUnlockRust"));
+            }
+
+            // 打开管道
+            let handle = CreateFileW(
+                &pipe_name.clone(),               // 管道名称
+                GENERIC_READ.0 | GENERIC_WRITE.0, // 双向管道，需要读取确认和登录结果
+                FILE_SHARE_MODE(0), // 阻止对管道的后续打开操作，在我主动关闭之前
+                None,
+                OPEN_EXISTING, // 只在文件存在时才打开，否则返回错误
+                FILE_FLAGS_AND_ATTRIBUTES(0),
+                None,
+            );
+            if handle.is_err() {
+                return Err(PipeError::new(format!("打开管道失败: {:?}", handle.err())));
+            }
+            handle.unwrap()
+        };
+        // 之后任何一步失败，stream 释放时关闭句柄
+        let stream = PipeStream(handle);
+
+        // 先确认对端是 LogonUI 中的 DLL，再发送凭据
+        verify_pipe_server(handle)?;
+        Self::establish(Box::new(stream), &load_pipe_secret()?)
+    }
+
+    // DLL 只运行在 Windows 的登录界面中，其他平台没有可以连接的管道
+    #[cfg(not(windows))]
+    pub fn connect() -> Result<Self, PipeError> {
+        Err(PipeError::new("当前系统没有 DLL 的命名管道"))
+    }
+
+    // 在已连接的流上与 DLL 互相验证身份，返回本次连接的加密通道
+    pub fn establish(
+        mut stream: Box<dyn PipeTransport>,
+        secret: &PipeSecret,
+    ) -> Result<Self, PipeError> {
+        let nonce = random_nonce().map_err(|e| PipeError::new(format!("生成随机数失败: {}", e)))?;
+        let handshake = client_handshake(&mut stream, secret, nonce)
+            .map_err(|e| PipeError::new(format!("管道握手失败: {}", e)))?;
+        Ok(Self {
+            stream,
+            channel: SecureChannel::client(secret, &handshake),
+        })
+    }
+
+    // 在磁贴上显示提示文字、状态图标和识别进度，经过加密通道发送，DLL 不显示明文的状态信息
+    pub fn show_status(
+        &mut self,
+        text: &str,
+        icon: StatusIcon,
+        progress: Option<u8>,
+    ) -> Result<(), PipeError> {
+        let message = self
+            .channel
+            .seal(&Message::Status {
+                text: text.to_string(),
+                icon,
+                progress,
+            })
+            .map_err(|e| PipeError::new(format!("加密状态信息失败: {}", e)))?;
+        send_to_pipe(&message, self.stream.as_mut())
+    }
+
+    // 识别失败时在磁贴上显示原因并取消本次解锁，不发送凭据，之后连接关闭
+    pub fn report_failure(mut self, reason: &str, icon: StatusIcon) -> Result<(), PipeError> {
+        self.show_status(reason, icon, None)?;
+        send_to_pipe(&Message::Cancel, self.stream.as_mut())
+    }
+
+    // 发送解锁请求并等待登录结果，之后连接关闭
+    pub fn unlock(
+        mut self,
+        user_name: String,
+        password: SecretString,
+    ) -> Result<LogonOutcome, PipeError> {
+        exchange_unlock(self.stream.as_mut(), &mut self.channel, user_name, password)
+    }
+
+    // 识别失败后等待用户唤醒登录界面，DLL 回复 Wake 后返回，之后连接关闭
+    // 用户手动解锁时 DLL 会断开管道，这里返回错误
+    pub fn wait_for_wake(mut self) -> Result<(), PipeError> {
+        send_to_pipe(&Message::WatchWake, self.stream.as_mut())?;
+        match read_message(&mut self.stream) {
+            Ok(Message::Wake) => Ok(()),
+            Ok(other) => Err(PipeError::new(format!(
+                "期望唤醒通知，收到: {:?}",
+                other.kind()
+            ))),
+            Err(e) => Err(PipeError::new(format!("等待唤醒通知失败: {}", e))),
         }
+    }
+}
 
-        // 打开管道
-        let handle = CreateFileW(
-            &pipe_name.clone(), // 管道名称
-            GENERIC_WRITE.0,    // 对文件的操作模式，只写
-            FILE_SHARE_MODE(0), // 阻止对管道的后续打开操作，在我主动关闭之前
-            None,
-            OPEN_EXISTING, // 只在文件存在时才打开，否则返回错误
-            FILE_FLAGS_AND_ATTRIBUTES(0),
-            None,
+// 确认管道服务端以 SYSTEM 身份运行，防止连接到其他进程抢先创建的同名管道
+#[cfg(windows)]
+fn verify_pipe_server(handle: HANDLE) -> Result<(), PipeError> {
+    unsafe {
+        let mut pid = 0u32;
+        GetNamedPipeServerProcessId(handle, &mut pid)?;
+
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)?;
+        let mut token = HANDLE::default();
+        let result = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        let _ = CloseHandle(process);
+        result?;
+
+        // TOKEN_USER 后面紧跟 SID，用 u64 数组保证对齐
+        let mut buffer = [0u64; 64];
+        let mut len = 0u32;
+        let result = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as *mut _),
+            std::mem::size_of_val(&buffer) as u32,
+            &mut len,
         );
-        if handle.is_err() {
-            return Err(windows::core::Error::new(
-                HRESULT(0),
-                format!("打开管道失败: {:?}", handle.err()),
-            ));
+        let _ = CloseHandle(token);
+        result?;
+
+        let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+        if !IsWellKnownSid(user.User.Sid, WinLocalSystemSid).as_bool() {
+            return Err(PipeError::new(format!(
+                "管道服务端进程 {} 不是以 SYSTEM 身份运行",
+                pid
+            )));
         }
-        let handle = handle.unwrap();
-
-        // 向管道发送用户名
-        let write_success = send_to_pipe(user_name, handle);
-        if write_success.is_err() {
-            let _ = CloseHandle(handle);
-            return Err(windows::core::Error::new(
-                HRESULT(0),
-                format!(
-                    "发送用户名失败: {:?}, 扩展信息: {:?}",
-                    write_success.err(),
-                    GetLastError()
-                ),
-            ));
+        Ok(())
+    }
+}
+
+// 读取部署时生成的共享密钥
+#[cfg(windows)]
+fn load_pipe_secret() -> Result<PipeSecret, PipeError> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let secret: String = hklm
+        .open_subkey(SECRET_REG_PATH)
+        .and_then(|key| key.get_value(SECRET_REG_VALUE))
+        .map_err(|e| PipeError::new(format!("读取管道密钥失败，请重新部署核心组件: {}", e)))?;
+    PipeSecret::from_hex(&secret).map_err(|e| PipeError::new(format!("管道密钥无效: {}", e)))
+}
+
+// 在已打开的管道上完成一次解锁请求：发送凭据 -> 等待确认 -> 等待登录结果
+fn exchange_unlock(
+    mut stream: &mut dyn PipeTransport,
+    channel: &mut SecureChannel,
+    user_name: String,
+    password: SecretString,
+) -> Result<LogonOutcome, PipeError> {
+    // 用户名和密码放在同一帧中加密发送，DLL 要么收到完整凭据，要么整帧丢弃
+    let message = channel
+        .seal(&Message::Unlock {
+            username: user_name,
+            password,
+        })
+        .map_err(|e| PipeError::new(format!("加密解锁请求失败: {}", e)))?;
+    send_to_pipe(&message, stream)
+        .map_err(|e| PipeError::new(format!("发送解锁请求失败: {}", e)))?;
+
+    match read_message(&mut stream) {
+        Ok(Message::Ack) => {}
+        Ok(other) => {
+            return Err(PipeError::new(format!(
+                "DLL 没有确认解锁请求，收到: {:?}",
+                other.kind()
+            )));
         }
+        Err(e) => {
+            return Err(PipeError::new(format!("读取 DLL 确认失败: {}", e)));
+        }
+    }
 
-        // 向管道发送密码
-        let write_success = send_to_pipe(password, handle);
-        if write_success.is_err() {
-            let _ = CloseHandle(handle);
-            return Err(windows::core::Error::new(
-                HRESULT(0),
-                format!(
-                    "发送密码失败: {:?}, 扩展信息: {:?}",
-                    write_success.err(),
-                    GetLastError()
-                ),
-            ));
+    // DLL 在 ReportResult 之后才会回传，超时会直接断开管道
+    match read_message(&mut stream) {
+        Ok(Message::LogonResult { status, substatus }) => {
+            Ok(LogonOutcome::from_ntstatus(status, substatus))
         }
+        Ok(other) => Err(PipeError::new(format!(
+            "期望登录结果，收到: {:?}",
+            other.kind()
+        ))),
+        Err(e) => Err(PipeError::new(format!("读取登录结果失败: {}", e))),
+    }
+}
 
-        let _ = CloseHandle(handle);
-    };
+// 向管道发送一帧数据
+fn send_to_pipe(message: &Message, stream: &mut dyn PipeTransport) -> Result<(), PipeError> {
+    // 编码为带帧头、长度和校验和的完整一帧，发送后清零
+    let write_buf = Zeroizing::new(
+        encode(message).map_err(|e| PipeError::new(format!("编码消息失败: {}", e)))?,
+    );
+    stream
+        .write_all(&write_buf)
+        .map_err(|e| PipeError::new(format!("管道写入失败: {}", e)))
+}
 
-    Ok(())
+// 把管道句柄包装成 Read + Write，交给协议库读写完整的帧，释放时关闭句柄
+#[cfg(windows)]
+struct PipeStream(HANDLE);
+
+#[cfg(windows)]
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0u32;
+        unsafe { ReadFile(self.0, Some(buf), Some(&mut read), None)? };
+        Ok(read as usize)
+    }
 }
 
-// 向管道发送数据
-fn send_to_pipe(content: String, handle: HANDLE) -> windows::core::Result<()> {
-    unsafe {
-        // 转 UTF-16 含 \0
-        let wide_chars: Vec<u16> = OsStr::new(&content)
-            .encode_wide()
-            .chain(std::iter::once(0))
-            .collect();
-        // 转 &[u8] 切片
-        let write_buf =
-            std::slice::from_raw_parts(wide_chars.as_ptr() as *const u8, wide_chars.len() * 2);
-        // 准备字节数
-        let mut total_bytes = write_buf.len() as u32;
-
-        WriteFile(handle, Some(write_buf), Some(&mut total_bytes), None)
+#[cfg(windows)]
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut written = 0u32;
+        unsafe { WriteFile(self.0, Some(buf), Some(&mut written), None)? };
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        unsafe { FlushFileBuffers(self.0)? };
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
     }
 }
@@ -1,2 +1,3 @@
 pub mod api;
 pub mod custom_result;
+pub mod secret_store;
@@ -0,0 +1,497 @@
+// faces.user_pwd 的加密存储
+// 数据库中保存的格式为 "<方案>:v1:<base64>"，不带前缀的是旧版本留下的明文
+use std::{fs, path::PathBuf};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use facewinunlock_protocol::SecretString;
+use hmac::{Hmac, Mac};
+use r2d2_sqlite::rusqlite::{params, Connection};
+use sha2::Sha256;
+use serde_json::json;
+use tauri_plugin_log::log::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::{db::repo, utils::custom_result::CustomResult};
+
+const DPAPI_MACHINE_PREFIX: &str = "dpapi-machine:v1:";
+const FILE_KEY_PREFIX: &str = "filekey:v1:";
+// 计算密码指纹的密钥，加密后保存在 options 表中
+const FINGERPRINT_KEY_OPTION: &str = "pwdFingerprintKey";
+const FINGERPRINT_PREFIX: &str = "hmac-sha256:";
+
+pub trait SecretStore {
+    // 加密明文密码，返回可以直接写入数据库的字符串
+    fn encrypt(&self, plain: &str) -> Result<String, String>;
+    // 解密数据库中的字符串
+    fn decrypt(&self, stored: &str) -> Result<SecretString, String>;
+    // 是否已经是这个存储当前使用的格式，其他格式在启动时迁移
+    fn is_current(&self, stored: &str) -> bool;
+}
+
+// 是否已经是加密后的格式
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(DPAPI_MACHINE_PREFIX) || stored.starts_with(FILE_KEY_PREFIX)
+}
+
+// 软件默认使用的存储方式
+pub fn default_store() -> DpapiStore {
+    DpapiStore
+}
+
+// 使用 DPAPI（本机范围）加密，密钥由系统托管，换一台电脑无法解密
+// 软件以管理员身份运行，开机自启时可能是任何一个用户，本机范围保证每个用户启动时都能解密所有账户的密码
+// 本机范围下本机任何进程都能解密，保护依赖数据库文件的 ACL：启动时限制为只有 SYSTEM 和管理员能访问（见 db::restrict_db_acl）
+pub struct DpapiStore;
+
+// 额外熵，只用于区分本软件的数据，随程序公开，不是秘密
+#[cfg(windows)]
+const DPAPI_ENTROPY: &[u8] = b"facewinunlock-tauri faces.user_pwd";
+
+#[cfg(windows)]
+impl SecretStore for DpapiStore {
+    fn encrypt(&self, plain: &str) -> Result<String, String> {
+        use windows::Win32::{
+            Foundation::{LocalFree, HLOCAL},
+            Security::Cryptography::{
+                CryptProtectData, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN,
+                CRYPT_INTEGER_BLOB,
+            },
+        };
+
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: plain.len() as u32,
+            pbData: plain.as_ptr() as *mut u8,
+        };
+        let entropy = CRYPT_INTEGER_BLOB {
+            cbData: DPAPI_ENTROPY.len() as u32,
+            pbData: DPAPI_ENTROPY.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptProtectData(
+                &input,
+                windows::core::w!("facewinunlock"),
+                Some(&entropy),
+                None,
+                None,
+                CRYPTPROTECT_LOCAL_MACHINE | CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+            .map_err(|e| format!("DPAPI 加密失败: {:?}", e))?;
+            let data = std::slice::from_raw_parts(output.pbData, output.cbData as usize);
+            let encoded = format!("{}{}", DPAPI_MACHINE_PREFIX, STANDARD.encode(data));
+            let _ = LocalFree(Some(HLOCAL(output.pbData as _)));
+            Ok(encoded)
+        }
+    }
+
+    fn decrypt(&self, stored: &str) -> Result<SecretString, String> {
+        use windows::Win32::{
+            Foundation::{LocalFree, HLOCAL},
+            Security::Cryptography::{
+                CryptUnprotectData, CRYPTPROTECT_LOCAL_MACHINE, CRYPTPROTECT_UI_FORBIDDEN,
+                CRYPT_INTEGER_BLOB,
+            },
+        };
+
+        let data = stored
+            .strip_prefix(DPAPI_MACHINE_PREFIX)
+            .ok_or_else(|| String::from("不是 DPAPI 加密的数据"))?;
+        let data = STANDARD
+            .decode(data)
+            .map_err(|e| format!("密码数据格式错误: {}", e))?;
+
+        let input = CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let entropy = CRYPT_INTEGER_BLOB {
+            cbData: DPAPI_ENTROPY.len() as u32,
+            pbData: DPAPI_ENTROPY.as_ptr() as *mut u8,
+        };
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptUnprotectData(
+                &input,
+                None,
+                Some(&entropy),
+                None,
+                None,
+                CRYPTPROTECT_LOCAL_MACHINE | CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+            .map_err(|e| format!("DPAPI 解密失败: {:?}", e))?;
+            let plain = std::slice::from_raw_parts_mut(output.pbData, output.cbData as usize);
+            let result = std::str::from_utf8(plain)
+                .map(SecretString::from)
+                .map_err(|_| String::from("解密后的密码不是合法的 UTF-8"));
+            // 释放前清零系统分配的明文缓冲区
+            plain.fill(0);
+            let _ = LocalFree(Some(HLOCAL(output.pbData as _)));
+            result
+        }
+    }
+
+    fn is_current(&self, stored: &str) -> bool {
+        stored.starts_with(DPAPI_MACHINE_PREFIX)
+    }
+}
+
+// 其他平台没有 DPAPI，只能使用 FileKeyStore
+#[cfg(not(windows))]
+impl SecretStore for DpapiStore {
+    fn encrypt(&self, _plain: &str) -> Result<String, String> {
+        Err(String::from("当前系统不支持 DPAPI 加密"))
+    }
+
+    fn decrypt(&self, _stored: &str) -> Result<SecretString, String> {
+        Err(String::from("当前系统不支持 DPAPI 解密"))
+    }
+
+    fn is_current(&self, stored: &str) -> bool {
+        stored.starts_with(DPAPI_MACHINE_PREFIX)
+    }
+}
+
+// 使用本地密钥文件加密，不依赖系统账户，用于测试
+pub struct FileKeyStore {
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl FileKeyStore {
+    // 读取密钥文件，不存在时生成一个新的
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        if path.exists() {
+            let data = Zeroizing::new(fs::read(&path).map_err(|e| format!("读取密钥文件失败: {}", e))?);
+            if data.len() != key.len() {
+                return Err(format!("密钥文件长度错误: {}", data.len()));
+            }
+            key.copy_from_slice(&data);
+        } else {
+            getrandom::getrandom(key.as_mut_slice()).map_err(|e| format!("生成密钥失败: {}", e))?;
+            fs::write(&path, key.as_slice()).map_err(|e| format!("写入密钥文件失败: {}", e))?;
+        }
+        Ok(Self { key })
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(self.key.as_slice()))
+    }
+}
+
+impl SecretStore for FileKeyStore {
+    fn encrypt(&self, plain: &str) -> Result<String, String> {
+        let mut nonce = [0u8; 12];
+        getrandom::getrandom(&mut nonce).map_err(|e| format!("生成随机数失败: {}", e))?;
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|_| String::from("加密失败"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", FILE_KEY_PREFIX, STANDARD.encode(data)))
+    }
+
+    fn decrypt(&self, stored: &str) -> Result<SecretString, String> {
+        let data = stored
+            .strip_prefix(FILE_KEY_PREFIX)
+            .ok_or(String::from("不是密钥文件加密的数据"))?;
+        let data = STANDARD
+            .decode(data)
+            .map_err(|e| format!("密码数据格式错误: {}", e))?;
+        if data.len() < 12 {
+            return Err(String::from("密码数据长度错误"));
+        }
+        let (nonce, ciphertext) = data.split_at(12);
+        let plain = Zeroizing::new(
+            self.cipher()
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| String::from("解密失败，数据被篡改或密钥不一致"))?,
+        );
+        std::str::from_utf8(&plain)
+            .map(SecretString::from)
+            .map_err(|_| String::from("解密后的密码不是合法的 UTF-8"))
+    }
+
+    fn is_current(&self, stored: &str) -> bool {
+        stored.starts_with(FILE_KEY_PREFIX)
+    }
+}
+
+// 密码的指纹，用于记录被系统拒绝过的密码
+// 同一个密码每次加密的结果都不同，只有比较明文的 HMAC 才能认出重新保存或迁移后的同一个密码
+pub struct PasswordFingerprint {
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl PasswordFingerprint {
+    // 读取 options 表中的密钥，不存在或无法解密时生成一个新的，之前记录的指纹随之失效
+    pub fn load(conn: &Connection, store: &dyn SecretStore) -> Result<Self, String> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        if let Some(stored) = repo::get_option(conn, FINGERPRINT_KEY_OPTION)? {
+            match store.decrypt(&stored).and_then(|encoded| {
+                STANDARD
+                    .decode(encoded.expose())
+                    .map(Zeroizing::new)
+                    .map_err(|e| format!("密钥格式错误: {}", e))
+            }) {
+                Ok(data) if data.len() == key.len() => {
+                    key.copy_from_slice(&data);
+                    return Ok(Self { key });
+                }
+                Ok(data) => warn!("密码指纹密钥长度错误: {}，重新生成", data.len()),
+                Err(e) => warn!("读取密码指纹密钥失败: {}，重新生成", e),
+            }
+        }
+        getrandom::getrandom(key.as_mut_slice()).map_err(|e| format!("生成密钥失败: {}", e))?;
+        let encoded = Zeroizing::new(STANDARD.encode(key.as_slice()));
+        repo::set_option(conn, FINGERPRINT_KEY_OPTION, &store.encrypt(&encoded)?)?;
+        Ok(Self { key })
+    }
+
+    pub fn of(&self, plain: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key.as_slice())
+            .expect("HMAC 可以使用任意长度的密钥");
+        mac.update(plain.as_bytes());
+        format!(
+            "{}{}",
+            FINGERPRINT_PREFIX,
+            STANDARD.encode(mac.finalize().into_bytes())
+        )
+    }
+}
+
+// 解锁时读取密码，兼容迁移前的明文数据
+pub fn reveal_password(store: &dyn SecretStore, stored: &str) -> Result<SecretString, String> {
+    if is_encrypted(stored) {
+        store.decrypt(stored)
+    } else {
+        warn!("面容密码尚未加密，请重新启动软件完成迁移");
+        Ok(SecretString::from(stored))
+    }
+}
+
+// 把 faces 表中的明文密码和旧格式的密文改写为当前格式，已经是当前格式的数据会跳过，可以重复执行
+// 解不开的密文（例如从其他电脑复制来的数据库）保持不变，解锁时提示重新录入密码
+pub fn migrate_stored_passwords(
+    conn: &mut Connection,
+    store: &dyn SecretStore,
+) -> Result<usize, String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("开启事务失败：{:?}", e))?;
+
+    let rows = {
+        let mut stmt = tx
+            .prepare("SELECT id, user_pwd FROM faces")
+            .map_err(|e| format!("准备查询面容密码失败：{:?}", e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i32>(0)?, Zeroizing::new(row.get::<_, String>(1)?)))
+            })
+            .map_err(|e| format!("查询面容密码失败：{:?}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取面容密码失败：{:?}", e))?;
+        rows
+    };
+
+    let mut migrated = 0;
+    for (id, user_pwd) in rows.iter().filter(|(_, pwd)| !store.is_current(pwd)) {
+        let encrypted = if is_encrypted(user_pwd) {
+            match store.decrypt(user_pwd) {
+                Ok(plain) => store.encrypt(plain.expose())?,
+                Err(e) => {
+                    warn!("面容 {} 的密码无法解密，暂不迁移：{}", id, e);
+                    continue;
+                }
+            }
+        } else {
+            store.encrypt(user_pwd)?
+        };
+        tx.execute(
+            "UPDATE faces SET user_pwd = ?1 WHERE id = ?2",
+            params![encrypted, id],
+        )
+        .map_err(|e| format!("更新面容密码失败：{:?}", e))?;
+        migrated += 1;
+    }
+
+    tx.commit()
+        .map_err(|e| format!("提交事务失败：{:?}", e))?;
+    if migrated > 0 {
+        info!("已迁移 {} 条面容密码", migrated);
+    }
+    Ok(migrated)
+}
+
+// 前端保存面容前调用，把密码加密后再写入数据库
+#[tauri::command]
+pub fn encrypt_password(password: String) -> Result<CustomResult, CustomResult> {
+    let password = Zeroizing::new(password);
+    let encrypted = default_store()
+        .encrypt(&password)
+        .map_err(|e| CustomResult::error(Some(format!("加密密码失败: {}", e)), None))?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({ "encrypted": encrypted })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> (FileKeyStore, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "facewinunlock-{}-{}.key",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        (FileKeyStore::open(path.clone()).unwrap(), path)
+    }
+
+    fn faces_db(passwords: &[&str]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE faces (id INTEGER PRIMARY KEY AUTOINCREMENT, user_pwd TEXT NOT NULL);",
+        )
+        .unwrap();
+        for pwd in passwords {
+            conn.execute("INSERT INTO faces (user_pwd) VALUES (?1)", params![pwd])
+                .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn file_key_roundtrip_and_reopen() {
+        let (store, path) = temp_store("roundtrip");
+        let stored = store.encrypt("p@ssw0rd-密码").unwrap();
+        assert!(is_encrypted(&stored));
+        assert!(!stored.contains("p@ssw0rd"));
+        assert_eq!(store.decrypt(&stored).unwrap().expose(), "p@ssw0rd-密码");
+
+        // 重新打开同一个密钥文件仍然可以解密
+        let reopened = FileKeyStore::open(path.clone()).unwrap();
+        assert_eq!(reopened.decrypt(&stored).unwrap().expose(), "p@ssw0rd-密码");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn file_key_rejects_tampering_and_other_keys() {
+        let (store, path) = temp_store("tamper");
+        let (other, other_path) = temp_store("tamper-other");
+        let stored = store.encrypt("secret").unwrap();
+
+        let mut data = STANDARD
+            .decode(stored.strip_prefix(FILE_KEY_PREFIX).unwrap())
+            .unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let tampered = format!("{}{}", FILE_KEY_PREFIX, STANDARD.encode(data));
+
+        assert!(store.decrypt(&tampered).is_err());
+        assert!(other.decrypt(&stored).is_err());
+        assert!(store.decrypt("secret").is_err());
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(other_path);
+    }
+
+    #[test]
+    fn migration_encrypts_plaintext_once() {
+        let (store, path) = temp_store("migrate");
+        let already = store.encrypt("already").unwrap();
+        let mut conn = faces_db(&["plain-1", &already, "plain-2"]);
+
+        assert_eq!(migrate_stored_passwords(&mut conn, &store).unwrap(), 2);
+        // 再次执行不会重复加密
+        assert_eq!(migrate_stored_passwords(&mut conn, &store).unwrap(), 0);
+
+        let stored: Vec<String> = conn
+            .prepare("SELECT user_pwd FROM faces ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(stored.iter().all(|s| is_encrypted(s)));
+        assert_eq!(stored[1], already);
+        let plain: Vec<String> = stored
+            .iter()
+            .map(|s| reveal_password(&store, s).unwrap().expose().to_string())
+            .collect();
+        assert_eq!(plain, ["plain-1", "already", "plain-2"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn migration_leaves_undecryptable_rows_untouched() {
+        let (store, path) = temp_store("foreign");
+        // 其他电脑上以 DPAPI 加密的数据，当前存储解不开
+        let foreign = format!("{}{}", DPAPI_MACHINE_PREFIX, STANDARD.encode(b"opaque"));
+        let mut conn = faces_db(&["plain", &foreign]);
+
+        assert_eq!(migrate_stored_passwords(&mut conn, &store).unwrap(), 1);
+        let stored: Vec<String> = conn
+            .prepare("SELECT user_pwd FROM faces ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(store.is_current(&stored[0]));
+        assert_eq!(stored[1], foreign);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn fingerprint_survives_reencryption() {
+        let (store, path) = temp_store("fingerprint");
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE options (id INTEGER PRIMARY KEY AUTOINCREMENT, key TEXT NOT NULL UNIQUE, val TEXT NOT NULL, lastTime TEXT);",
+        )
+        .unwrap();
+
+        let fingerprint = PasswordFingerprint::load(&conn, &store).unwrap();
+        let first = store.encrypt("old").unwrap();
+        let second = store.encrypt("old").unwrap();
+        assert_ne!(first, second);
+        let rejected = fingerprint.of(store.decrypt(&first).unwrap().expose());
+        assert_eq!(
+            fingerprint.of(store.decrypt(&second).unwrap().expose()),
+            rejected
+        );
+        assert_ne!(fingerprint.of("new"), rejected);
+        assert!(!rejected.contains("old"));
+
+        // 密钥加密保存，再次读取时指纹不变
+        let stored = repo::get_option(&conn, FINGERPRINT_KEY_OPTION)
+            .unwrap()
+            .unwrap();
+        assert!(store.is_current(&stored));
+        let reloaded = PasswordFingerprint::load(&conn, &store).unwrap();
+        assert_eq!(reloaded.of("old"), rejected);
+
+        // 密钥无法解密时重新生成，旧的指纹不再匹配
+        let (other, other_path) = temp_store("fingerprint-other");
+        let regenerated = PasswordFingerprint::load(&conn, &other).unwrap();
+        assert_ne!(regenerated.of("old"), rejected);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(other_path);
+    }
+
+    #[test]
+    fn reveal_accepts_legacy_plaintext() {
+        let (store, path) = temp_store("legacy");
+        assert_eq!(reveal_password(&store, "legacy").unwrap().expose(), "legacy");
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,155 @@
+// 面容解锁的后台线程
+// 窗口回调只发送事件，延迟和冷却由这里计时，识别和等待唤醒在单独的线程中进行，不阻塞界面线程
+use std::{
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tauri::Emitter;
+use tauri_plugin_log::log::{error, info, warn};
+
+use crate::{
+    db,
+    modules::credentials,
+    proc,
+    session::{CancelToken, SessionAction, SessionEvent, UnlockMachine, UnlockState},
+    utils::api::PipeSession,
+    APP_HANDLE,
+};
+
+// 状态变化时发给前端的事件，载荷为 StatePayload
+pub const UNLOCK_STATE_EVENT: &str = "unlock-state-changed";
+// 识别失败后多久内不再识别
+const COOLDOWN: Duration = Duration::from_secs(5);
+// 识别时间结束后，活体检测、动作验证和解锁最多再用的时间，超时后停止识别并关闭摄像头
+const RECOGNITION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatePayload {
+    state: &'static str,
+    // 距离延迟或冷却结束的毫秒数
+    remaining_ms: Option<u64>,
+}
+
+pub struct UnlockWorker {
+    events: Sender<SessionEvent>,
+}
+
+impl UnlockWorker {
+    pub fn spawn() -> Self {
+        let (events, receiver) = channel();
+        let sender = events.clone();
+        thread::spawn(move || work(sender, receiver));
+        Self { events }
+    }
+
+    pub fn send(&self, event: SessionEvent) {
+        if let Err(e) = self.events.send(event) {
+            error!("面容解锁线程已退出，丢弃事件 {:?}: {}", event, e);
+        }
+    }
+}
+
+fn work(events: Sender<SessionEvent>, receiver: Receiver<SessionEvent>) {
+    let mut machine = UnlockMachine::new(COOLDOWN);
+    // 正在进行的识别
+    let mut running: Option<CancelToken> = None;
+
+    loop {
+        let event = match machine.deadline() {
+            Some(deadline) => {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => SessionEvent::Timeout,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match receiver.recv() {
+                Ok(event) => event,
+                Err(_) => return,
+            },
+        };
+
+        let now = Instant::now();
+        let previous = machine.state();
+        let action = machine.handle(event, now);
+        if machine.state() != previous {
+            info!(
+                "面容解锁状态：{} -> {}（{:?}）",
+                previous.name(),
+                machine.state().name(),
+                event
+            );
+            emit_state(machine.state(), now);
+        }
+
+        match action {
+            Some(SessionAction::Recognize(settings)) => {
+                let window = Duration::from_millis(settings.window_ms as u64);
+                let token = CancelToken::new(window + RECOGNITION_TIMEOUT);
+                running = Some(token.clone());
+                let events = events.clone();
+                thread::spawn(move || {
+                    let unlocked = proc::recognize(settings, &token, &|event| {
+                        let _ = events.send(event);
+                    });
+                    let _ = events.send(SessionEvent::Finished { unlocked });
+                });
+            }
+            Some(SessionAction::CancelRecognition) => {
+                if let Some(token) = running.take() {
+                    token.cancel();
+                }
+                after_manual_unlock();
+            }
+            Some(SessionAction::ManualUnlock) => after_manual_unlock(),
+            Some(SessionAction::WatchWake) => {
+                let events = events.clone();
+                thread::spawn(move || {
+                    match PipeSession::connect().and_then(|session| session.wait_for_wake()) {
+                        Ok(()) => {
+                            let _ = events.send(SessionEvent::Wake);
+                        }
+                        // 手动解锁后 DLL 断开管道，属于正常情况
+                        Err(e) => info!("停止等待唤醒：{}", e),
+                    }
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+// 恢复被停用的面容解锁，并检查保存的密码是否因为用户修改了 Windows 密码而失效
+fn after_manual_unlock() {
+    match db::get_conn() {
+        Ok(conn) => proc::reset_lockouts(&conn),
+        Err(e) => warn!("{}", e),
+    }
+    thread::spawn(|| {
+        if let Err(e) = credentials::verify_stored_credentials() {
+            warn!("检查保存的密码失败：{}", e);
+        }
+    });
+}
+
+fn emit_state(state: UnlockState, now: Instant) {
+    let remaining_ms = match state {
+        UnlockState::Delaying { until, .. } | UnlockState::Cooldown { until } => {
+            Some(until.saturating_duration_since(now).as_millis() as u64)
+        }
+        _ => None,
+    };
+    let payload = StatePayload {
+        state: state.name(),
+        remaining_ms,
+    };
+    if let Some(app) = APP_HANDLE.get() {
+        if let Err(e) = app.emit(UNLOCK_STATE_EVENT, payload) {
+            warn!("发送面容解锁状态失败：{}", e);
+        }
+    }
+}
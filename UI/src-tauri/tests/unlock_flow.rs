@@ -0,0 +1,559 @@
+// 锁屏 → 识别 → 解锁的完整流程，不需要摄像头、模型和 LogonUI
+// 画面由 SyntheticSource 生成，人脸检测换成预设的特征，DLL 由 MockPipeServer 模拟
+// 不依赖 Win32，Linux 上同样可以运行，但需要 OpenCV 和 Tauri 的本机库，见 UI/README.md 的“运行测试”
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use facewinunlock_protocol::{
+    handshake::SECRET_LEN,
+    logon::{STATUS_ACCOUNT_LOCKED_OUT, STATUS_WRONG_PASSWORD},
+    mock::{MockPipeServer, Received},
+    PipeSecret, ProtocolError, SecretString,
+};
+use facewinunlock_tauri_lib::{
+    camera::{FramePacer, SyntheticSource},
+    db::{models::UnlockLog, open_pool, repo, DbPool},
+    modules::{
+        faces::{save_face_data, FaceDetector},
+        options::Settings,
+    },
+    proc::{self, UnlockEnv},
+    recognition::{
+        gallery::FaceGallery,
+        liveness::{CuePolicy, LivenessPolicy},
+        selection::FacePolicy,
+    },
+    session::{CancelToken, LockSettings, SessionAction, SessionEvent, UnlockMachine, UnlockState},
+    utils::{
+        api::PipeSession,
+        secret_store::{FileKeyStore, SecretStore},
+    },
+};
+use opencv::core::{Mat, MatTraitConst, Scalar, CV_8UC3};
+use r2d2_sqlite::rusqlite::params;
+use serde_json::json;
+
+const FACE_TOKEN: &str = "unlock-flow-test";
+// 画面源足够在识别时间内一直提供画面
+const FRAMES: usize = 1000;
+
+// 只有一个维度为 1 的特征，不同维度的特征互不匹配
+fn feature(axis: usize) -> Vec<f32> {
+    let mut feature = vec![0.0; 128];
+    feature[axis] = 1.0;
+    feature
+}
+
+fn mat_from(values: &[f32]) -> Mat {
+    Mat::from_slice(values)
+        .and_then(|row| row.try_clone())
+        .unwrap()
+}
+
+// 每一帧都“检测”到同一张人脸，返回预设的特征
+struct ScriptedDetector {
+    probe: Option<Vec<f32>>,
+}
+
+impl FaceDetector for ScriptedDetector {
+    fn detect(
+        &mut self,
+        img: &Mat,
+        _face_detection_threshold: f32,
+        _policy: FacePolicy,
+    ) -> Result<(Mat, Mat), String> {
+        let Some(probe) = &self.probe else {
+            return Err(String::from("未检测到人脸"));
+        };
+        let size = img.size().map_err(|e| e.to_string())?;
+        // 人脸框居中，关键点的位置不影响关闭的活体线索
+        let (w, h) = (size.width as f32 / 2.0, size.height as f32 / 2.0);
+        let (x, y) = (w / 2.0, h / 2.0);
+        let mut row = vec![x, y, w, h];
+        for (px, py) in [
+            (0.3, 0.4),
+            (0.7, 0.4),
+            (0.5, 0.55),
+            (0.35, 0.75),
+            (0.65, 0.75),
+        ] {
+            row.push(x + w * px);
+            row.push(y + h * py);
+        }
+        row.push(0.99);
+        Ok((mat_from(&row), mat_from(probe)))
+    }
+}
+
+fn frames(count: usize) -> SyntheticSource {
+    SyntheticSource::new(move |index| {
+        if index >= count {
+            return Ok(None);
+        }
+        Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(0.0))
+            .map(Some)
+            .map_err(|e| e.to_string())
+    })
+}
+
+fn secret() -> PipeSecret {
+    PipeSecret::from_bytes([7; SECRET_LEN])
+}
+
+// 每个测试独立的临时目录，测试结束时删除
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "facewinunlock-unlock-flow-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// 一个已初始化、只有一个面容的数据库，密码使用临时密钥加密，不依赖 DPAPI
+struct Fixture {
+    db: DbPool,
+    store: FileKeyStore,
+    faces_dir: PathBuf,
+    face_id: i32,
+    // 最后释放，关闭数据库之后再删除目录
+    _dir: TempDir,
+}
+
+fn setup() -> Fixture {
+    let dir = TempDir::new();
+    let store = FileKeyStore::open(dir.0.join("test.key")).unwrap();
+    let db = open_pool(&dir.0.join("database.db"), &store).unwrap();
+    let mut conn = db.get().unwrap();
+
+    let settings = Settings {
+        is_initialized: true,
+        face_recog_delay: 1.0,
+        camera: 0,
+        // 纯色画面无法计算活体线索
+        liveness: LivenessPolicy {
+            blink: CuePolicy::Off,
+            head_motion: CuePolicy::Off,
+            texture: CuePolicy::Off,
+            model: CuePolicy::Off,
+        },
+        ..Settings::default()
+    };
+    settings.save(&mut conn).unwrap();
+
+    let json_data = json!({
+        "alias": "测试",
+        "threshold": 60.0,
+        "view": false,
+        "faceDetectionThreshold": 0.9,
+    });
+    conn.execute(
+        "INSERT INTO faces (user_name, user_pwd, account_type, face_token, json_data) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            "tester",
+            store.encrypt("p@ssw0rd").unwrap(),
+            "local",
+            FACE_TOKEN,
+            json_data.to_string()
+        ],
+    )
+    .unwrap();
+    let face_id = conn.last_insert_rowid() as i32;
+    drop(conn);
+
+    let faces_dir = dir.0.join("faces");
+    fs::create_dir_all(&faces_dir).unwrap();
+    let mut gallery = FaceGallery::new("测试");
+    gallery.add("正面", feature(0)).unwrap();
+    save_face_data(&faces_dir.join(format!("{}.face", FACE_TOKEN)), &gallery).unwrap();
+    Fixture {
+        db,
+        store,
+        faces_dir,
+        face_id,
+        _dir: dir,
+    }
+}
+
+// 一次锁屏的测试场景
+struct Scenario<'a> {
+    events: &'a [SessionEvent],
+    // 每次识别时检测到的人脸特征，None 表示画面中没有人脸
+    probes: Vec<Option<Vec<f32>>>,
+    // 每次识别时画面源提供的帧数
+    frames: usize,
+    // 模拟识别开始前已经手动解锁
+    cancelled: bool,
+}
+
+impl<'a> Scenario<'a> {
+    fn new(events: &'a [SessionEvent], probes: Vec<Option<Vec<f32>>>) -> Self {
+        Self {
+            events,
+            probes,
+            frames: FRAMES,
+            cancelled: false,
+        }
+    }
+}
+
+// 按锁屏事件驱动状态机，延迟结束和唤醒时识别，返回每次识别的结果、结束时的状态和 DLL 在每个连接上收到的内容
+fn lock_and_recognize(
+    fixture: &Fixture,
+    scenario: Scenario,
+    server: &MockPipeServer,
+) -> (Vec<Result<bool, String>>, UnlockState, Vec<Received>) {
+    let handles: RefCell<Vec<JoinHandle<Result<Received, ProtocolError>>>> =
+        RefCell::new(Vec::new());
+    let connect = || {
+        let (client, handle) = server.accept();
+        handles.borrow_mut().push(handle);
+        PipeSession::establish(Box::new(client), &secret())
+    };
+    let progress = RefCell::new(Vec::new());
+    let notify = |event: SessionEvent| progress.borrow_mut().push(event);
+
+    let mut machine = UnlockMachine::new(Duration::from_secs(5));
+    let mut probes = scenario.probes.into_iter();
+    let mut results = Vec::new();
+    for event in scenario.events {
+        // 动作产生的事件先于下一个锁屏事件处理
+        let mut pending = VecDeque::from([*event]);
+        while let Some(event) = pending.pop_front() {
+            // 超时事件在延迟或冷却结束时到达
+            let now = match event {
+                SessionEvent::Timeout => machine.deadline().unwrap_or_else(Instant::now),
+                _ => Instant::now(),
+            };
+            match machine.handle(event, now) {
+                Some(SessionAction::Recognize(settings)) => {
+                    assert_eq!(settings.camera_index, 0);
+                    let mut source = frames(scenario.frames);
+                    let mut detector = ScriptedDetector {
+                        probe: probes.next().expect("识别次数多于预设的特征"),
+                    };
+                    let cancel = CancelToken::new(Duration::from_secs(30));
+                    if scenario.cancelled {
+                        cancel.cancel();
+                    }
+                    let mut env = UnlockEnv {
+                        source: &mut source,
+                        detector: &mut detector,
+                        connect: &connect,
+                        db: &fixture.db,
+                        faces_dir: &fixture.faces_dir,
+                        store: &fixture.store,
+                        cancel: &cancel,
+                        notify: &notify,
+                        pacer: FramePacer::new(settings.fps),
+                        window: Duration::from_millis(settings.window_ms as u64),
+                    };
+                    let outcome = proc::run(&mut env);
+                    pending.extend(progress.borrow_mut().drain(..));
+                    let unlocked = matches!(outcome, Ok(true));
+                    pending.push_back(SessionEvent::Finished { unlocked });
+                    results.push(outcome);
+                }
+                // 模拟的 DLL 收到 WatchWake 后立即回复唤醒
+                Some(SessionAction::WatchWake) => {
+                    connect()
+                        .and_then(|session| session.wait_for_wake())
+                        .unwrap();
+                    pending.push_back(SessionEvent::Wake);
+                }
+                // 与后台线程一样，手动解锁后恢复面容解锁
+                Some(SessionAction::CancelRecognition | SessionAction::ManualUnlock) => {
+                    proc::reset_lockouts(&fixture.db.get().unwrap())
+                }
+                None => {}
+            }
+        }
+    }
+
+    let received = handles
+        .into_inner()
+        .into_iter()
+        .map(|handle| handle.join().unwrap().unwrap())
+        .collect();
+    (results, machine.state(), received)
+}
+
+fn unlock_logs(fixture: &Fixture) -> Vec<UnlockLog> {
+    let conn = fixture.db.get().unwrap();
+    repo::list_unlock_logs(&conn, None).unwrap()
+}
+
+fn lock_events() -> [SessionEvent; 2] {
+    [
+        SessionEvent::Lock(Some(LockSettings {
+            delay_ms: 1000,
+            camera_index: 0,
+            window_ms: 500,
+            fps: 30,
+            rescan_on_wake: true,
+        })),
+        SessionEvent::Timeout,
+    ]
+}
+
+#[test]
+fn matching_face_sends_credentials() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    let events = lock_events();
+    let (results, state, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(true)]);
+    assert_eq!(state, UnlockState::Idle);
+    assert_eq!(received.len(), 1);
+    assert_eq!(
+        received[0].unlock,
+        Some((String::from(".\\tester"), SecretString::from("p@ssw0rd")))
+    );
+
+    let logs = unlock_logs(&fixture);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].face_id, Some(fixture.face_id));
+    assert!(logs[0].is_unlock);
+    assert_eq!(logs[0].logon_result.as_deref(), Some("success"));
+}
+
+#[test]
+fn unknown_face_notifies_failure() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    let events = lock_events();
+    let started = Instant::now();
+    let (results, state, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(1))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false)]);
+    // 陌生人脸一直识别到识别时间结束才判定失败
+    assert!(started.elapsed() >= Duration::from_millis(500));
+    // 失败后进入冷却，本次锁屏不会立即再次识别
+    assert!(matches!(state, UnlockState::Cooldown { .. }));
+    // 识别期间在磁贴上显示进度，结束后只显示失败原因，不发送假的凭据
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].unlock, None);
+    let statuses = &received[0].statuses;
+    assert!(statuses[..statuses.len() - 1]
+        .iter()
+        .all(|status| status == "正在寻找你…"));
+    assert_eq!(
+        statuses.last().unwrap(),
+        "未能识别面容，请重试或输入密码解锁"
+    );
+
+    let logs = unlock_logs(&fixture);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].face_id, Some(-1));
+    assert!(!logs[0].is_unlock);
+    assert_eq!(logs[0].logon_result.as_deref(), Some("unknown_face"));
+}
+
+#[test]
+fn rejected_password_is_not_resent() {
+    let fixture = setup();
+    let events = lock_events();
+
+    let server = MockPipeServer::new(secret()).with_logon_result(STATUS_WRONG_PASSWORD, 0);
+    let (results, _, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false)]);
+    assert!(received[0].unlock.is_some());
+    // 系统拒绝密码后标记为失效，等待用户重新输入
+    let conn = fixture.db.get().unwrap();
+    assert!(repo::list_faces(&conn).unwrap()[0].credentials_stale);
+
+    // 手动解锁不会恢复已被系统拒绝的密码
+    let [lock, timer] = events;
+    let events = [SessionEvent::Unlock, lock, timer];
+    let server = MockPipeServer::new(secret());
+    let (results, _, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false)]);
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].unlock, None);
+    assert!(received[0]
+        .statuses
+        .last()
+        .unwrap()
+        .starts_with("保存的密码已失效"));
+
+    let logs = unlock_logs(&fixture);
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].face_id, Some(fixture.face_id));
+    assert_eq!(logs[0].logon_result.as_deref(), Some("blocked"));
+    assert_eq!(logs[1].logon_result.as_deref(), Some("bad_password"));
+}
+
+#[test]
+fn manual_unlock_restores_locked_account() {
+    let fixture = setup();
+    let events = lock_events();
+
+    let server = MockPipeServer::new(secret()).with_logon_result(STATUS_ACCOUNT_LOCKED_OUT, 0);
+    let (results, _, _) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false)]);
+
+    // 账户被锁定后停用面容解锁，不再发送凭据
+    let server = MockPipeServer::new(secret());
+    let (results, _, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false)]);
+    assert_eq!(received[0].unlock, None);
+
+    // 用户手动解锁后恢复
+    let [lock, timer] = events;
+    let events = [SessionEvent::Unlock, lock, timer];
+    let (results, state, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(true)]);
+    assert_eq!(state, UnlockState::Idle);
+    assert!(received[0].unlock.is_some());
+}
+
+#[test]
+fn wake_rescans_after_failure() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    // 第一次没有人脸，冷却结束后等待唤醒，唤醒后识别到面容
+    let [lock, timer] = lock_events();
+    let events = [lock, timer, SessionEvent::Timeout];
+    let (results, state, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![None, Some(feature(0))]),
+        &server,
+    );
+    assert_eq!(results, vec![Ok(false), Ok(true)]);
+    assert_eq!(state, UnlockState::Idle);
+    // 第一次识别和等待唤醒各用一个连接
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[0].unlock, None);
+    assert!(received[1].watched_wake);
+    assert_eq!(
+        received[2].unlock,
+        Some((String::from(".\\tester"), SecretString::from("p@ssw0rd")))
+    );
+
+    let logs = unlock_logs(&fixture);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].face_id, Some(fixture.face_id));
+}
+
+#[test]
+fn unlock_before_timer_skips_recognition() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+    let [lock, timer] = lock_events();
+
+    let events = [lock, SessionEvent::Unlock, timer];
+    let (results, state, received) = lock_and_recognize(
+        &fixture,
+        Scenario::new(&events, vec![Some(feature(0))]),
+        &server,
+    );
+    assert!(results.is_empty());
+    assert_eq!(state, UnlockState::Idle);
+    assert!(received.is_empty());
+    assert!(unlock_logs(&fixture).is_empty());
+}
+
+#[test]
+fn no_face_until_window_ends() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    let events = lock_events();
+    let (results, state, received) =
+        lock_and_recognize(&fixture, Scenario::new(&events, vec![None]), &server);
+    assert_eq!(results, vec![Ok(false)]);
+    assert!(matches!(state, UnlockState::Cooldown { .. }));
+    // 没有人脸时只显示提示，不发送假凭据，也不记录解锁日志
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].unlock, None);
+    assert_eq!(received[0].statuses.last().unwrap(), "未检测到人脸");
+    assert!(unlock_logs(&fixture).is_empty());
+}
+
+#[test]
+fn no_face_stops_when_frames_run_out() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    let events = lock_events();
+    let scenario = Scenario {
+        frames: 5,
+        ..Scenario::new(&events, vec![None])
+    };
+    let (results, _, received) = lock_and_recognize(&fixture, scenario, &server);
+    assert!(matches!(&results[..], [Err(e)] if e.contains("已结束")));
+    // 画面源出错时在磁贴上提示，不发送凭据
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].unlock, None);
+    assert_eq!(
+        received[0].statuses.last().unwrap(),
+        "识别出错，请输入密码解锁"
+    );
+    assert!(unlock_logs(&fixture).is_empty());
+}
+
+#[test]
+fn cancelled_recognition_sends_nothing() {
+    let fixture = setup();
+    let server = MockPipeServer::new(secret());
+
+    let events = lock_events();
+    let scenario = Scenario {
+        cancelled: true,
+        ..Scenario::new(&events, vec![Some(feature(0))])
+    };
+    let (results, _, received) = lock_and_recognize(&fixture, scenario, &server);
+    assert_eq!(results, vec![Err(String::from("识别已取消"))]);
+    assert!(received.is_empty());
+    assert!(unlock_logs(&fixture).is_empty());
+}
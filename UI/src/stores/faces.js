@@ -1,4 +1,5 @@
 import { defineStore } from 'pinia';
+import { invoke } from '@tauri-apps/api/core';
 import { select, insert, update, deleteData } from '../utils/sqlite';
 import { formatObjectString, getCurrentDateTime, removeFace } from '../utils/function'
 import { info, error as errorLog, warn } from '@tauri-apps/plugin-log';
@@ -35,6 +36,7 @@ export const useFacesStore = defineStore('faces', {
                         createTime: getCurrentDateTime(),
                         ...data
                     });
+                    this.syncTileAccounts();
                     resolve();
                 }).catch((error)=>{
                     const info = formatObjectString("添加面容到数据库失败：", error);
@@ -72,9 +74,14 @@ export const useFacesStore = defineStore('faces', {
                     this.faceList[faceIndex].json_data = JSON.parse(data.json_data);
 
                     this.faceList[faceIndex].user_name = data.user_name;
+                    // 数据库触发器在密码变化时清除失效标记
+                    if(this.faceList[faceIndex].user_pwd !== data.user_pwd){
+                        this.faceList[faceIndex].credentials_stale = false;
+                    }
                     this.faceList[faceIndex].user_pwd = data.user_pwd;
                     this.faceList[faceIndex].account_type = data.account_type;
                     this.faceList[faceIndex].face_token = data.face_token;
+                    this.syncTileAccounts();
                     resolve();
                 }).catch((error)=>{
                     const info = formatObjectString("修改面容到数据库失败：", error);
@@ -148,7 +155,27 @@ export const useFacesStore = defineStore('faces', {
                 account_type: data.account_type,
                 face_token: data.face_token,
                 json_data: JSON.parse(data.json_data),
-                createTime: data.createTime
+                createTime: data.createTime,
+                credentials_stale: data.credentials_stale == 1
+            });
+        },
+        /**
+         * 标记面容保存的密码已失效，数据库由 Rust 端更新
+         * @param {Number} id 面容ID
+         */
+        markCredentialsStale(id){
+            const face = this.faceList.find(item => item.id == id);
+            if(face){
+                face.credentials_stale = true;
+            }
+        },
+        /**
+         * 把录入了面容的账户同步到注册表，登录界面为每个账户显示一个磁贴
+         * 同步失败不影响面容的保存，下次修复注册表时会重新同步
+         */
+        syncTileAccounts(){
+            invoke('sync_tile_accounts').catch((error)=>{
+                warn(formatObjectString("同步登录界面磁贴账户失败：", error));
             });
         },
         /**
@@ -169,6 +196,7 @@ export const useFacesStore = defineStore('faces', {
                     // 面容特征和图片删除失败不影响系统运行
                     removeFace(this.faceList[faceIndex].face_token);
                     this.faceList.splice(faceIndex, 1);
+                    this.syncTileAccounts();
                     resolve();
                 }).catch((error)=>{
                     const info = formatObjectString("从数据库删除面容失败：", error);
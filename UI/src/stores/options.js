@@ -1,14 +1,25 @@
 import { defineStore } from 'pinia';
-import { select, insert, update } from '../utils/sqlite';
-import { formatObjectString, getCurrentDateTime } from '../utils/function'
-import { info, error as errorLog, warn } from '@tauri-apps/plugin-log';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { formatObjectString } from '../utils/function'
+import { error as errorLog } from '@tauri-apps/plugin-log';
 
+// 设置由 Rust 端的 Settings 负责读写、校验和同步注册表，这里只保存一份副本
 export const useOptionsStore = defineStore('options', {
     actions: {
         init(){
             return new Promise((resolve, reject) => {
-                select('options', ['*']).then((result)=>{
-                    this.list = result.rows;
+                invoke('get_settings').then((result)=>{
+                    this.settings = result.data;
+                    // 其他地方修改设置后同步更新
+                    if(!this.unlisten){
+                        return listen('settings-changed', (event)=>{
+                            this.settings = event.payload;
+                        }).then((unlisten)=>{
+                            this.unlisten = unlisten;
+                        });
+                    }
+                }).then(()=>{
                     resolve();
                 }).catch((error)=>{
                     errorLog(formatObjectString("设置初始化失败", error));
@@ -17,89 +28,52 @@ export const useOptionsStore = defineStore('options', {
             })
         },
         /**
-         * 获取设置项对象从设置名称
-         * @param {String} key 设置名称
-         * @returns {Object<{index: number, data: Object}>}} index 数据下标 data 数据项
-         */
-        getOptionByKey(key){
-            const result = {
-                index: -1,
-                data: null
-            }
-
-            const findIndex = this.list.findIndex(n => n.key == key);
-
-            if(findIndex != -1){
-                result.index = findIndex;
-                result.data = {...this.list[findIndex]};
-            }
-
-            return result;
-        },
-        /**
-         * 获取设置项的值从设置名称
-         * @param {String} key 设置名称 
-         * @returns {null | String} 设置项的值
+         * 保存设置，只需要传入要修改的字段，其余字段保持不变
+         * @param {Object} patch 要修改的设置，字段名与 Rust 端 Settings 一致（驼峰）
+         * @returns {Promise<Object>} 保存后的完整设置，校验或保存失败时 reject
          */
-        getOptionValueByKey(key){
-            const result = this.getOptionByKey(key);
-            return result.data ? result.data.val : null;
+        saveSettings(patch){
+            return new Promise((resolve, reject) => {
+                const settings = { ...this.settings, ...patch };
+                invoke('update_settings', { settings }).then((result)=>{
+                    this.settings = result.data;
+                    resolve(result.data);
+                }).catch((error)=>{
+                    const info = formatObjectString("保存设置失败：", error);
+                    errorLog(info);
+                    reject(error);
+                })
+            })
         },
         /**
-         * 保存设置，如果库中已存在，则会替换，否则会添加
-         * @param {Object} optionObject 要保存的设置对象
-         * @returns {Promise<[]>} 如果修改出错，返回错误信息 该函数不会出发.catch
+         * 获取当前选择的摄像头信息
+         * @returns {null | Object} 摄像头列表中的一项
          */
-        saveOptions(optionObject){
-            return new Promise(async (resolve, reject) => {
-                const errorArray = [];
-
-                for (const key in optionObject) {
-                    const element = optionObject[key];
-                    // 根据key查找配置项
-                    const result = this.getOptionByKey(key);
-    
-                    try {
-                        const lastTime = getCurrentDateTime();
-                        if(result.index == -1){
-                            // 配置不存在，新增
-                            // 因为要获取lastId，这里只能await等待了
-                            const insertResult = await insert('options', ['key', 'val'], [key, element]);
-                            this.list.push({
-                                id: insertResult.lastId,
-                                key: key,
-                                val: element,
-                                lastTime: lastTime
-                            });
-                        }else{
-                            // 配置存在，判断配置是否发生了变化
-                            const item = result.data;
-                            if(element === item.val){
-                                // 如果相同，不执行任何操作
-                                continue;
-                            }else{
-                                // 不相同修改
-                                await update('options', {val: element, lastTime: lastTime}, 'id = ?', [item.id]);
-                                // 修改store中的list
-                                this.list[result.index].val = element;
-                                this.list[result.index].lastTime = lastTime;
-                            }
-                        }
-                    } catch (error) {
-                        const errorInfo = formatObjectString(`配置修改失败，Key: ${key}, val: ${element}, 错误信息：`, error);
-                        errorLog(errorInfo);
-                        errorArray.push(errorInfo);
-                    }
-                    
-                }
-
-                resolve(errorArray);
-            })
+        getCurrentCamera(){
+            const index = String(this.settings.camera);
+            return this.settings.cameraList.find(item => item.capture_index == index) || null;
         },
     },
     state() {
         return{
-            list: []
+            settings: {
+                isInitialized: false,
+                faceRecogDelay: 10.0,
+                recognitionWindow: 15.0,
+                recognitionFps: 5,
+                rescanOnWake: true,
+                camera: -1,
+                cameraList: [],
+                showTile: true,
+                wrapPasswordProvider: false,
+                matchPolicy: { kind: 'max' },
+                adaptiveUpdate: false,
+                facePolicy: 'largest',
+                liveness: { blink: 'observe', headMotion: 'observe', texture: 'observe', model: 'off' },
+                challenge: false,
+                quality: { minSharpness: 40, minFaceSize: 80, maxYaw: 25, maxRoll: 20, minBrightness: 60, maxBrightness: 200, maxOcclusion: 0.2 }
+            },
+            unlisten: null
         }
-    } 
-});
\ No newline at end of file
+    }
+});